@@ -0,0 +1,132 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse, AttributeArgs, ItemFn, Meta, NestedMeta};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct TestOptions {
+    ignore: bool,
+}
+
+impl TestOptions {
+    fn parse(args: AttributeArgs) -> parse::Result<Self> {
+        let mut options = Self::default();
+
+        for arg in args {
+            match arg {
+                NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("ignore") => {
+                    if options.ignore {
+                        return Err(parse::Error::new_spanned(
+                            arg,
+                            "Option specified more than once",
+                        ));
+                    }
+                    options.ignore = true;
+                }
+                arg => {
+                    return Err(parse::Error::new_spanned(
+                        arg,
+                        "Unknown option, expected `ignore`",
+                    ))
+                }
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+pub(crate) fn ctru_test(args: AttributeArgs, test: ItemFn) -> TokenStream {
+    let sig = &test.sig;
+
+    let options = match TestOptions::parse(args) {
+        Ok(options) => options,
+        Err(e) => return e.to_compile_error(),
+    };
+
+    let valid_signature = sig.constness.is_none()
+        && sig.asyncness.is_none()
+        && sig.unsafety.is_none()
+        && sig.abi.is_none()
+        && sig.inputs.is_empty()
+        && sig.generics.params.is_empty()
+        && sig.generics.where_clause.is_none()
+        && sig.variadic.is_none();
+
+    if !valid_signature {
+        return parse::Error::new_spanned(
+            sig,
+            "`#[ctru_test]` function must have signature `fn()` or `fn() -> Result<(), E>`",
+        )
+        .to_compile_error();
+    }
+
+    let ident = &sig.ident;
+    let ignored = options.ignore;
+
+    quote! {
+        #test
+
+        const _: () = {
+            #[used]
+            #[link_section = ".ctru_rt_tests"]
+            static TEST_CASE: ::ctru_rt::testing::TestCase = ::ctru_rt::testing::TestCase {
+                name: ::core::concat!(::core::module_path!(), "::", ::core::stringify!(#ident)),
+                ignored: #ignored,
+                function: || ::ctru_rt::testing::TestResult::into_result(#ident()),
+            };
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use syn::parse_quote;
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(
+            TestOptions::parse(vec![]).ok(),
+            Some(TestOptions::default())
+        );
+        assert_eq!(
+            TestOptions::parse(vec![parse_quote!(ignore)]).ok(),
+            Some(TestOptions { ignore: true })
+        );
+        assert!(TestOptions::parse(vec![parse_quote!(ignore), parse_quote!(ignore)]).is_err());
+        assert!(TestOptions::parse(vec![parse_quote!(should_panic)]).is_err());
+    }
+
+    #[test]
+    fn reject_arguments() {
+        let output = ctru_test(
+            vec![],
+            parse_quote!(
+                fn takes_argument(x: u32) {}
+            ),
+        );
+        assert!(output.to_string().contains("compile_error"));
+    }
+
+    #[test]
+    fn register_test_case() {
+        let output = ctru_test(
+            vec![parse_quote!(ignore)],
+            parse_quote!(
+                fn returns_result() -> Result<(), ()> {
+                    Ok(())
+                }
+            ),
+        )
+        .to_string();
+
+        assert!(output.contains("\".ctru_rt_tests\""));
+        assert!(output.contains("ignored : true"));
+        assert!(output.contains("returns_result ()"));
+    }
+}
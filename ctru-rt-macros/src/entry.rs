@@ -2,13 +2,324 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use proc_macro2::{Span, TokenStream};
-use quote::quote;
-use syn::{parse, AttributeArgs, ItemFn, ReturnType, Type, Visibility};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    parse, AttributeArgs, ItemFn, Lit, Meta, MetaNameValue, NestedMeta, ReturnType, Type,
+    Visibility,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const STACK_ALIGNMENT: usize = 8;
+const MAX_PRIORITY: i32 = 0x3f;
+
+/// Parse a size like `"24MiB"`, `"512 KiB"` or `"0x1000"` into a number of bytes.
+fn parse_size(size: &str) -> Option<usize> {
+    let size = size.trim();
+    let (number, multiplier) = [("KiB", 1 << 10), ("MiB", 1 << 20), ("B", 1)]
+        .iter()
+        .find_map(|(unit, multiplier)| Some((size.strip_suffix(unit)?, *multiplier)))
+        .unwrap_or((size, 1));
+    let number = number.trim();
+
+    let number = match number.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok()?,
+        None => number.parse().ok()?,
+    };
+
+    number.checked_mul(multiplier)
+}
+
+/// What `#[entry(services(...))]` sets up before calling the entry point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Service {
+    Log,
+    Srv,
+    Ac,
+    Cfg,
+    Fs,
+    Gpu,
+    Gfx,
+    Hid,
+    /// The shared [`ctru_rt::Services`], passed instead of a separate `srv` session.
+    Services,
+}
+
+impl Service {
+    const ALL: [(&'static str, Self); 9] = [
+        ("log", Self::Log),
+        ("srv", Self::Srv),
+        ("services", Self::Services),
+        ("ac", Self::Ac),
+        ("cfg", Self::Cfg),
+        ("fs", Self::Fs),
+        ("gpu", Self::Gpu),
+        ("gfx", Self::Gfx),
+        ("hid", Self::Hid),
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find_map(|&(n, service)| (n == name).then_some(service))
+    }
+
+    fn name(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find_map(|&(name, service)| (service == self).then_some(name))
+            .unwrap_or_default()
+    }
+
+    /// Whether the service is passed to the entry point.
+    fn is_passed(self) -> bool {
+        self != Self::Log
+    }
+
+    fn needs_srv(self) -> bool {
+        !matches!(self, Self::Log | Self::Srv | Self::Services)
+    }
+
+    fn binding(self) -> syn::Ident {
+        format_ident!("__ctru_rt_{}", self.name())
+    }
+
+    /// Initialize the service, using the expression `srv` of type `&Srv` if needed.
+    fn emit_init(self, srv: &TokenStream) -> TokenStream {
+        let expect = format!("Failed to initialize {}", self.name());
+
+        let init = match self {
+            Self::Log => return quote! { let _ = ::ctru_rt::debug::init_log(); },
+            Self::Srv => quote! { ::ctru_rt::ports::srv::Srv::init() },
+            Self::Services => quote! { ::ctru_rt::Services::get() },
+            Self::Ac => quote! { ::ctru_rt::services::ac::Ac::init(#srv) },
+            Self::Cfg => quote! { ::ctru_rt::services::cfg::Cfg::init(#srv) },
+            Self::Fs => quote! { ::ctru_rt::services::fs::Fs::init(#srv) },
+            Self::Gpu => quote! { ::ctru_rt::services::gsp::gpu::Gpu::init(#srv) },
+            Self::Gfx => quote! {
+                ::ctru_rt::services::gsp::gpu::Gpu::init(#srv)
+                    .and_then(::ctru_rt::graphics::Grapics::init_default)
+            },
+            Self::Hid => quote! { ::ctru_rt::services::hid::Hid::init(#srv) },
+        };
+
+        let binding = self.binding();
+        quote! { let #binding = #init.expect(#expect); }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct EntryOptions {
+    heap_size: Option<usize>,
+    linear_heap_size: Option<usize>,
+    main_thread_stack: Option<usize>,
+    main_thread_priority: Option<i32>,
+    services: Vec<Service>,
+}
+
+impl EntryOptions {
+    fn parse(args: AttributeArgs) -> parse::Result<Self> {
+        let mut options = Self::default();
+
+        for arg in args {
+            let name_value = match arg {
+                NestedMeta::Meta(Meta::NameValue(name_value)) => name_value,
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("services") => {
+                    options.parse_services(list.nested)?;
+                    continue;
+                }
+                arg => {
+                    return Err(parse::Error::new_spanned(
+                        arg,
+                        "Expected an option of the form `name = value` or `services(...)`",
+                    ))
+                }
+            };
+
+            if name_value.path.is_ident("main_thread_priority") {
+                options.parse_priority(name_value)?;
+                continue;
+            }
+
+            let (option, alignment) = match name_value.path.get_ident() {
+                Some(ident) if ident == "heap_size" => (&mut options.heap_size, PAGE_SIZE),
+                Some(ident) if ident == "linear_heap_size" => {
+                    (&mut options.linear_heap_size, PAGE_SIZE)
+                }
+                Some(ident) if ident == "main_thread_stack" => {
+                    (&mut options.main_thread_stack, STACK_ALIGNMENT)
+                }
+                _ => {
+                    return Err(parse::Error::new_spanned(
+                        name_value.path,
+                        "Unknown option, expected `heap_size`, `linear_heap_size`, \
+                         `main_thread_stack` or `main_thread_priority`",
+                    ))
+                }
+            };
+
+            let size = match &name_value.lit {
+                Lit::Str(size) => parse_size(&size.value()),
+                Lit::Int(size) => size.base10_parse().ok(),
+                _ => None,
+            };
+
+            let size = match size {
+                Some(size) if size > 0 && size % alignment == 0 => size,
+                _ if alignment == PAGE_SIZE => {
+                    return Err(parse::Error::new_spanned(
+                        name_value.lit,
+                        "Expected a multiple of the page size (4KiB), e.g. \"24MiB\"",
+                    ))
+                }
+                _ => {
+                    return Err(parse::Error::new_spanned(
+                        name_value.lit,
+                        "Expected a non-zero multiple of 8 bytes, e.g. \"64KiB\"",
+                    ))
+                }
+            };
+
+            if option.replace(size).is_some() {
+                return Err(parse::Error::new_spanned(
+                    name_value.path,
+                    "Option specified more than once",
+                ));
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn parse_priority(&mut self, name_value: MetaNameValue) -> parse::Result<()> {
+        let priority = match &name_value.lit {
+            Lit::Int(priority) => priority.base10_parse().ok(),
+            _ => None,
+        };
+
+        let priority = match priority {
+            Some(priority) if (0..=MAX_PRIORITY).contains(&priority) => priority,
+            _ => {
+                return Err(parse::Error::new_spanned(
+                    name_value.lit,
+                    "Expected a priority from 0 (highest) to 0x3f (lowest), e.g. 0x30",
+                ))
+            }
+        };
+
+        if self.main_thread_priority.replace(priority).is_some() {
+            return Err(parse::Error::new_spanned(
+                name_value.path,
+                "Option specified more than once",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn parse_services(
+        &mut self,
+        nested: impl IntoIterator<Item = NestedMeta>,
+    ) -> parse::Result<()> {
+        for service in nested {
+            let parsed = match &service {
+                NestedMeta::Meta(Meta::Path(path)) => path
+                    .get_ident()
+                    .and_then(|ident| Service::from_name(&ident.to_string())),
+                _ => None,
+            };
+
+            let parsed = parsed.ok_or_else(|| {
+                parse::Error::new_spanned(
+                    &service,
+                    "Unknown service, expected one of `log`, `srv`, `services`, `ac`, `cfg`, `fs`, \
+                     `gpu`, `gfx` or `hid`",
+                )
+            })?;
+
+            let conflicting = match parsed {
+                Service::Gpu => Some(Service::Gfx),
+                Service::Gfx => Some(Service::Gpu),
+                Service::Srv => Some(Service::Services),
+                Service::Services => Some(Service::Srv),
+                _ => None,
+            };
+
+            if self.services.contains(&parsed) {
+                return Err(parse::Error::new_spanned(
+                    service,
+                    "Service specified more than once",
+                ));
+            }
+
+            if conflicting.is_some_and(|c| self.services.contains(&c)) {
+                let message = match parsed {
+                    Service::Srv | Service::Services => {
+                        "`services` provides `srv`, it can not be combined with `srv`"
+                    }
+                    _ => "`gfx` takes ownership of the GPU, it can not be combined with `gpu`",
+                };
+                return Err(parse::Error::new_spanned(service, message));
+            }
+
+            self.services.push(parsed);
+        }
+
+        Ok(())
+    }
+
+    /// Initialize the services in order, logging first and `srv` before the services needing it.
+    ///
+    /// With `services`, the session with `srv` is the one of the shared `ctru_rt::Services`.
+    fn emit_services(&self) -> TokenStream {
+        let shared = self.services.contains(&Service::Services);
+        let (srv, srv_ref) = if shared {
+            let binding = Service::Services.binding();
+            (Service::Services, quote! { #binding.srv() })
+        } else {
+            let binding = Service::Srv.binding();
+            (Service::Srv, quote! { &#binding })
+        };
+
+        let log = self.services.iter().filter(|&&s| s == Service::Log);
+        let srv = self
+            .services
+            .iter()
+            .any(|s| s.needs_srv() || *s == srv)
+            .then_some(&srv);
+        let others = self.services.iter().filter(|s| s.needs_srv());
+
+        let inits = log.chain(srv).chain(others).map(|s| s.emit_init(&srv_ref));
+
+        quote! { #(#inits)* }
+    }
+
+    /// The arguments passed to the entry point, in the order the services were specified.
+    fn entry_arguments(&self) -> Vec<syn::Ident> {
+        self.services
+            .iter()
+            .filter(|s| s.is_passed())
+            .map(|s| s.binding())
+            .collect()
+    }
+}
+
+fn quote_option<T: quote::ToTokens>(option: Option<T>) -> TokenStream {
+    match option {
+        Some(value) => quote! { ::core::option::Option::Some(#value) },
+        None => quote! { ::core::option::Option::None },
+    }
+}
 
 pub(crate) fn entry(args: AttributeArgs, entry_point: ItemFn) -> TokenStream {
     let sig = &entry_point.sig;
 
+    let options = match EntryOptions::parse(args) {
+        Ok(options) => options,
+        Err(e) => return e.to_compile_error(),
+    };
+    let arguments = options.entry_arguments();
+
     let vis_inherited = matches!(entry_point.vis, Visibility::Inherited);
 
     let valid_return_type = match sig.output {
@@ -23,7 +334,7 @@ pub(crate) fn entry(args: AttributeArgs, entry_point: ItemFn) -> TokenStream {
     let valid_signature = sig.constness.is_none()
         && vis_inherited
         && sig.abi.is_none()
-        && sig.inputs.is_empty()
+        && sig.inputs.len() == arguments.len()
         && sig.generics.params.is_empty()
         && sig.generics.where_clause.is_none()
         && sig.variadic.is_none()
@@ -32,25 +343,147 @@ pub(crate) fn entry(args: AttributeArgs, entry_point: ItemFn) -> TokenStream {
     if !valid_signature {
         return parse::Error::new_spanned(
             sig,
-            "`#[entry]` function must have signature `[unsafe] fn()` or `[unsafe] fn() -> !`",
+            "`#[entry]` function must have signature `[unsafe] fn()` or `[unsafe] fn() -> !`, \
+             taking one argument per service except `log`",
         )
-        .to_compile_error()
-    }
-
-    if !args.is_empty() {
-        return parse::Error::new(Span::call_site(), "This attribute accepts no arguments")
-            .to_compile_error()
+        .to_compile_error();
     }
 
     let ident = &entry_point.sig.ident;
+    let heap_size = quote_option(options.heap_size);
+    let linear_heap_size = quote_option(options.linear_heap_size);
+    let main_thread_stack = quote_option(options.main_thread_stack);
+    let main_thread_priority = quote_option(options.main_thread_priority);
+    let services = options.emit_services();
 
     quote! {
         #[inline(always)]
         #entry_point
 
+        #[export_name = "_ctru_rt_configure"]
+        pub unsafe fn _ctru_rt_configure() {
+            ::ctru_rt::heap::configure(#heap_size, #linear_heap_size);
+            ::ctru_rt::thread::configure_main_thread_stack(#main_thread_stack);
+            ::ctru_rt::thread::configure_main_thread_priority(#main_thread_priority)
+        }
+
         #[export_name = "_ctru_rt_entry"]
         pub unsafe fn _ctru_rt_entry() {
-            #ident()
+            #services
+            #ident(#(#arguments),*)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use syn::parse_quote;
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("24MiB"), Some(24 << 20));
+        assert_eq!(parse_size("512 KiB"), Some(512 << 10));
+        assert_eq!(parse_size("0x1000"), Some(0x1000));
+        assert_eq!(parse_size("4096B"), Some(4096));
+        assert_eq!(parse_size("24 GiB"), None);
+        assert_eq!(parse_size("MiB"), None);
+    }
+
+    #[test]
+    fn parse_options() {
+        let options = EntryOptions::parse(vec![
+            parse_quote!(heap_size = "24MiB"),
+            parse_quote!(linear_heap_size = 8388608),
+        ]);
+
+        assert_eq!(
+            options.ok(),
+            Some(EntryOptions {
+                heap_size: Some(24 << 20),
+                linear_heap_size: Some(8 << 20),
+                ..EntryOptions::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_stack_and_services() {
+        let options = EntryOptions::parse(vec![
+            parse_quote!(main_thread_stack = "64KiB"),
+            parse_quote!(services(hid, log, gfx)),
+        ])
+        .expect("Expected valid options");
+
+        assert_eq!(options.main_thread_stack, Some(64 << 10));
+        assert_eq!(options.main_thread_priority, None);
+        assert_eq!(options.services, [Service::Hid, Service::Log, Service::Gfx]);
+
+        let arguments: Vec<_> = options
+            .entry_arguments()
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert_eq!(arguments, ["__ctru_rt_hid", "__ctru_rt_gfx"]);
+    }
+
+    #[test]
+    fn parse_priority() {
+        let options = EntryOptions::parse(vec![parse_quote!(main_thread_priority = 0x2f)]);
+        assert_eq!(
+            options.ok(),
+            Some(EntryOptions {
+                main_thread_priority: Some(0x2f),
+                ..EntryOptions::default()
+            })
+        );
+
+        assert!(EntryOptions::parse(vec![parse_quote!(main_thread_priority = 0x40)]).is_err());
+        assert!(EntryOptions::parse(vec![parse_quote!(main_thread_priority = "0x30")]).is_err());
+        assert!(EntryOptions::parse(vec![
+            parse_quote!(main_thread_priority = 0x30),
+            parse_quote!(main_thread_priority = 0x30),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn initialize_log_and_srv_first() {
+        let options = EntryOptions::parse(vec![parse_quote!(services(hid, log))]).unwrap();
+
+        let inits = options.emit_services().to_string();
+        let position = |s: &str| inits.find(s).expect(s);
+
+        assert!(position("init_log") < position("Srv :: init"));
+        assert!(position("Srv :: init") < position("Hid :: init"));
+    }
+
+    #[test]
+    fn initialize_with_shared_services() {
+        let options = EntryOptions::parse(vec![parse_quote!(services(hid, services))]).unwrap();
+
+        let inits = options.emit_services().to_string();
+        let position = |s: &str| inits.find(s).expect(s);
+
+        assert!(!inits.contains("Srv :: init"));
+        assert!(position("Services :: get") < position("Hid :: init"));
+        assert!(inits.contains("Hid :: init (__ctru_rt_services . srv ())"));
+    }
+
+    #[test]
+    fn reject_invalid_options() {
+        assert!(EntryOptions::parse(vec![parse_quote!(heap_size = "1000")]).is_err());
+        assert!(EntryOptions::parse(vec![parse_quote!(stack_size = "4KiB")]).is_err());
+        assert!(EntryOptions::parse(vec![parse_quote!(main_thread_stack = "1001")]).is_err());
+        assert!(EntryOptions::parse(vec![parse_quote!(services(hid, nwm))]).is_err());
+        assert!(EntryOptions::parse(vec![parse_quote!(services(hid, hid))]).is_err());
+        assert!(EntryOptions::parse(vec![parse_quote!(services(gpu, gfx))]).is_err());
+        assert!(EntryOptions::parse(vec![parse_quote!(services(services, srv))]).is_err());
+        assert!(EntryOptions::parse(vec![
+            parse_quote!(heap_size = "4KiB"),
+            parse_quote!(heap_size = "8KiB"),
+        ])
+        .is_err());
+    }
+}
@@ -20,7 +20,14 @@ struct ValuedVariant {
 pub struct EnumCast {
     ident: Ident,
     variants: Vec<ValuedVariant>,
+    options: EnumCastOptions,
+}
+
+/// Options passed in `#[enum_cast(...)]`.
+struct EnumCastOptions {
     value_type: Type,
+    /// Whether to implement `TryFrom<value_type>` for the enum and `From<enum>` for the value.
+    conversions: bool,
 }
 
 trait PathExt {
@@ -66,12 +73,12 @@ impl EnumCast {
                 next_value += 1;
             }
 
-            let value_type = Self::parse_value_type(&derive_input.attrs)?;
+            let options = Self::parse_options(&derive_input.attrs)?;
 
             Ok(Self {
                 ident,
                 variants,
-                value_type,
+                options,
             })
         } else {
             Err(Error::new(
@@ -81,7 +88,12 @@ impl EnumCast {
         }
     }
 
-    fn parse_value_type(attributes: &[Attribute]) -> Result<Type> {
+    fn parse_options(attributes: &[Attribute]) -> Result<EnumCastOptions> {
+        let mut options = EnumCastOptions {
+            value_type: syn::parse_quote!(u32),
+            conversions: false,
+        };
+
         for attr in attributes {
             let meta = match attr.parse_meta() {
                 Ok(Meta::List(meta)) => meta,
@@ -95,14 +107,19 @@ impl EnumCast {
                             path,
                             lit: Lit::Str(type_lit),
                             ..
-                        })) if path.is_ident("value_type") => return type_lit.parse(),
+                        })) if path.is_ident("value_type") => {
+                            options.value_type = type_lit.parse()?
+                        }
+                        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("conversions") => {
+                            options.conversions = true
+                        }
                         _ => continue,
                     }
                 }
             }
         }
 
-        Ok(syn::parse_quote!(u32))
+        Ok(options)
     }
 
     fn parse_variant(variant: Variant) -> Result<Variant> {
@@ -135,7 +152,7 @@ impl EnumCast {
     }
 
     fn emit_from_value(&self) -> TokenStream {
-        let value_type = &self.value_type;
+        let value_type = &self.options.value_type;
 
         let (variant_idents, values): (Vec<&Ident>, Vec<&LitInt>) = self
             .variants
@@ -156,7 +173,7 @@ impl EnumCast {
     }
 
     fn emit_to_value(&self) -> TokenStream {
-        let value_type = &self.value_type;
+        let value_type = &self.options.value_type;
 
         let (variant_idents, values): (Vec<&Ident>, Vec<&LitInt>) = self
             .variants
@@ -173,17 +190,77 @@ impl EnumCast {
         }
     }
 
+    fn emit_values(&self) -> TokenStream {
+        let variant_idents = self.variants.iter().map(|v| &v.variant.ident);
+
+        quote! {
+            /// All variants, in order of declaration.
+            pub const VALUES: &'static [Self] = &[#(Self::#variant_idents),*];
+        }
+    }
+
+    fn emit_trait(&self) -> TokenStream {
+        let ident = &self.ident;
+        let value_type = &self.options.value_type;
+
+        quote! {
+            impl ::ctru_rt::util::EnumCast for #ident {
+                type Value = #value_type;
+
+                const VALUES: &'static [Self] = Self::VALUES;
+
+                fn parse_value(value: #value_type) -> ::core::result::Result<Self, #value_type> {
+                    Self::from_value(value)
+                }
+
+                fn as_value(&self) -> #value_type {
+                    self.to_value()
+                }
+            }
+        }
+    }
+
+    fn emit_conversions(&self) -> TokenStream {
+        let ident = &self.ident;
+        let value_type = &self.options.value_type;
+
+        quote! {
+            impl ::core::convert::TryFrom<#value_type> for #ident {
+                type Error = #value_type;
+
+                fn try_from(value: #value_type) -> ::core::result::Result<Self, #value_type> {
+                    Self::from_value(value)
+                }
+            }
+
+            impl ::core::convert::From<#ident> for #value_type {
+                fn from(value: #ident) -> Self {
+                    value.to_value()
+                }
+            }
+        }
+    }
+
     pub fn emit(&self) -> TokenStream {
         let ident = &self.ident;
         let from_value = self.emit_from_value();
         let to_value = self.emit_to_value();
+        let values = self.emit_values();
+        let enum_cast_trait = self.emit_trait();
+        let conversions = self.options.conversions.then(|| self.emit_conversions());
 
         quote! {
             impl #ident {
+                #values
+
                 #from_value
 
                 #to_value
             }
+
+            #enum_cast_trait
+
+            #conversions
         }
     }
 }
@@ -194,3 +271,61 @@ impl Parse for EnumCast {
         Self::new(input)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use syn::parse_quote;
+
+    fn parse(input: DeriveInput) -> EnumCast {
+        EnumCast::new(input).expect("Expected a valid EnumCast input")
+    }
+
+    #[test]
+    fn implicit_values_follow_previous() {
+        let enum_cast = parse(parse_quote! {
+            enum Signal { None, Home = 4, Sleep, Wakeup = 0x10, Shutdown }
+        });
+
+        let values: Vec<u32> = enum_cast
+            .variants
+            .iter()
+            .map(|v| v.value.base10_parse().unwrap())
+            .collect();
+
+        assert_eq!(values, [0, 4, 5, 0x10, 0x11]);
+    }
+
+    #[test]
+    fn parse_options() {
+        let enum_cast = parse(parse_quote! {
+            #[enum_cast(value_type = "u16", conversions)]
+            enum AppId { HomeMenu = 0x101 }
+        });
+
+        let value_type = &enum_cast.options.value_type;
+        assert_eq!(quote!(#value_type).to_string(), "u16");
+        assert!(enum_cast.options.conversions);
+    }
+
+    #[test]
+    fn default_options() {
+        let enum_cast = parse(parse_quote! {
+            enum Domain { AfInet = 2 }
+        });
+
+        let value_type = &enum_cast.options.value_type;
+        assert_eq!(quote!(#value_type).to_string(), "u32");
+        assert!(!enum_cast.options.conversions);
+    }
+
+    #[test]
+    fn reject_fields() {
+        let input: DeriveInput = parse_quote! {
+            enum Message { Text(u32) }
+        };
+
+        assert!(EnumCast::new(input).is_err());
+    }
+}
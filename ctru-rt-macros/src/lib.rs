@@ -4,12 +4,14 @@
 
 #![allow(dead_code)]
 
+mod ctru_test;
 mod entry;
 mod enum_cast;
-mod ipc_call;
+mod service;
 mod svc_spec;
 
 use crate::enum_cast::EnumCast;
+use crate::service::Service;
 use crate::svc_spec::SvcSpec;
 
 use syn::{parse_macro_input, AttributeArgs, ItemFn};
@@ -244,12 +246,34 @@ pub fn svc(tokens: proc_macro::TokenStream) -> proc_macro::TokenStream {
     output.into()
 }
 
+/// Declare the IPC commands of a service, see the `service` module for the syntax.
+#[proc_macro]
+pub fn service(tokens: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let service = parse_macro_input!(tokens as Service);
+    service.emit().into()
+}
+
 #[proc_macro_derive(EnumCast, attributes(enum_cast))]
 pub fn enum_cast_impl(tokens: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let enum_cast = parse_macro_input!(tokens as EnumCast);
     enum_cast.emit().into()
 }
 
+/// Mark the entry point of the application.
+///
+/// Accepts the options `heap_size`, `linear_heap_size` and `main_thread_stack`, given as sizes
+/// like `"24MiB"`, `main_thread_priority`, given as a number from `0` (highest) to `0x3f`, and
+/// `services(...)` naming what to initialize before calling the entry point.
+/// The entry point takes the initialized services as arguments, in the order they are named in,
+/// except for `log`:
+///
+/// ```ignore
+/// #[entry(main_thread_stack = "64KiB", services(log, srv, hid))]
+/// fn main(srv: Srv, hid: Hid) {}
+/// ```
+///
+/// Naming `services` instead of `srv` passes the shared `&'static ctru_rt::Services`, whose
+/// session with `srv` the other services are initialized with.
 #[proc_macro_attribute]
 pub fn entry(
     args: proc_macro::TokenStream,
@@ -260,3 +284,15 @@ pub fn entry(
 
     entry::entry(args, f).into()
 }
+
+/// Register a test function with `ctru_rt::testing`, optionally as `#[ctru_test(ignore)]`.
+#[proc_macro_attribute]
+pub fn ctru_test(
+    args: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let f = parse_macro_input!(item as ItemFn);
+
+    ctru_test::ctru_test(args, f).into()
+}
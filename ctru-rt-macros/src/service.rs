@@ -0,0 +1,676 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Declarative IPC bindings of a service.
+//!
+//! ```ignore
+//! service! {
+//!     impl File {
+//!         #[command(0x803)]
+//!         fn write_raw(
+//!             &self,
+//!             offset: u64,
+//!             size: usize,
+//!             flush: u32,
+//!             #[translate] data: MappedBuffer<'_>,
+//!         ) -> u32;
+//!
+//!         #[command(0x804)]
+//!         pub fn size(&self) -> u64;
+//!     }
+//! }
+//! ```
+//!
+//! Each command becomes a method sending the request to `self.handle`.  Normal parameters precede
+//! the parameters marked `#[translate]`, the same holds for the results.  A command marked
+//! `#[command(id, pid)]` sends the caller's process ID as its first translate parameter.
+//!
+//! Results are returned as a single value, a tuple, or a reply struct declared in place, as in
+//! `-> AcceptReply { fd: u32, #[translate] handle: OwnedHandle }`.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, ToTokens};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{braced, parenthesized, Attribute, Error, Generics, Ident, LitInt, Result, Token};
+use syn::{Type, Visibility};
+
+/// Parse an optional `#[translate]`, marking a translate parameter or result.
+fn parse_translate_attr(input: ParseStream) -> Result<bool> {
+    let attributes = input.call(Attribute::parse_outer)?;
+
+    match attributes.as_slice() {
+        [] => Ok(false),
+        [attr] if attr.path.is_ident("translate") => Ok(true),
+        [attr, ..] => Err(Error::new(
+            attr.span(),
+            r#"Unknown attribute, expected "translate""#,
+        )),
+    }
+}
+
+/// Ensure all values marked `#[translate]` come last.
+fn check_translate_order<'v, V>(values: V, what: &str) -> Result<()>
+where
+    V: IntoIterator<Item = (bool, &'v Type)>,
+{
+    let mut translate = false;
+    for (is_translate, ty) in values {
+        if translate && !is_translate {
+            return Err(Error::new(
+                ty.span(),
+                format!("Normal {} must precede translate {}", what, what),
+            ));
+        }
+        translate = is_translate;
+    }
+
+    Ok(())
+}
+
+/// Whether `ty` is a 64-bit integer, which takes up two words.
+fn is_double_word(ty: &Type) -> bool {
+    matches!(quote!(#ty).to_string().as_str(), "u64" | "i64")
+}
+
+struct Parameter {
+    name: Ident,
+    ty: Type,
+    translate: bool,
+}
+
+impl Parse for Parameter {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let translate = parse_translate_attr(input)?;
+        let name = input.parse()?;
+        let _colon: Token![:] = input.parse()?;
+        let ty = input.parse()?;
+
+        Ok(Self {
+            name,
+            ty,
+            translate,
+        })
+    }
+}
+
+impl Parameter {
+    fn emit_write(&self) -> TokenStream {
+        let Self { name, ty, .. } = self;
+
+        if self.translate {
+            quote! { .translate_parameter(#name) }
+        } else if let Type::Array(_) = ty {
+            quote! { .parameters(&#name) }
+        } else if is_double_word(ty) {
+            quote! { .parameters(&[#name as u32, (#name >> 32) as u32]) }
+        } else {
+            quote! { .parameter(#name) }
+        }
+    }
+
+    /// The number of command buffer words written by [`emit_write`](Self::emit_write).
+    fn emit_words(&self) -> TokenStream {
+        let ty = &self.ty;
+
+        if self.translate {
+            quote! { <#ty as crate::ipc::TranslateParameter>::WORDS }
+        } else if let Type::Array(array) = ty {
+            let len = &array.len;
+            quote! { (#len) }
+        } else if is_double_word(ty) {
+            quote! { 2 }
+        } else {
+            quote! { 1 }
+        }
+    }
+}
+
+struct ReplyValue {
+    ty: Type,
+    translate: bool,
+}
+
+impl Parse for ReplyValue {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let translate = parse_translate_attr(input)?;
+        let ty = input.parse()?;
+
+        Ok(Self { ty, translate })
+    }
+}
+
+impl ReplyValue {
+    fn emit_read(&self) -> TokenStream {
+        let ty = &self.ty;
+
+        if self.translate {
+            // The reply carries what the command was declared to return
+            quote! { unsafe { reply.read_translate_result::<#ty>() } }
+        } else if is_double_word(ty) {
+            quote! {
+                (u64::from(reply.read_word()) | u64::from(reply.read_word()) << 32) as #ty
+            }
+        } else {
+            quote! { reply.read_result::<#ty>() }
+        }
+    }
+}
+
+struct ReplyField {
+    name: Ident,
+    value: ReplyValue,
+}
+
+impl Parse for ReplyField {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let translate = parse_translate_attr(input)?;
+        let name = input.parse()?;
+        let _colon: Token![:] = input.parse()?;
+        let ty = input.parse()?;
+
+        Ok(Self {
+            name,
+            value: ReplyValue { ty, translate },
+        })
+    }
+}
+
+enum Reply {
+    Unit,
+    Single(Box<ReplyValue>),
+    Tuple(Vec<ReplyValue>),
+    Struct {
+        name: Ident,
+        fields: Vec<ReplyField>,
+    },
+}
+
+impl Parse for Reply {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(Token![;]) {
+            return Ok(Self::Unit);
+        }
+
+        let _arrow: Token![->] = input.parse()?;
+
+        if input.peek(syn::token::Paren) {
+            let values;
+            let _paren = parenthesized!(values in input);
+            let values = Punctuated::<ReplyValue, Token![,]>::parse_terminated(&values)?;
+
+            return Ok(match values.len() {
+                0 => Self::Unit,
+                _ => Self::Tuple(values.into_iter().collect()),
+            });
+        }
+
+        if input.peek(Ident) && input.peek2(syn::token::Brace) {
+            let name = input.parse()?;
+            let fields;
+            let _brace = braced!(fields in input);
+            let fields = Punctuated::<ReplyField, Token![,]>::parse_terminated(&fields)?;
+
+            return Ok(Self::Struct {
+                name,
+                fields: fields.into_iter().collect(),
+            });
+        }
+
+        input.parse().map(|value| Self::Single(Box::new(value)))
+    }
+}
+
+impl Reply {
+    fn values(&self) -> Vec<&ReplyValue> {
+        match self {
+            Self::Unit => Vec::new(),
+            Self::Single(value) => vec![&**value],
+            Self::Tuple(values) => values.iter().collect(),
+            Self::Struct { fields, .. } => fields.iter().map(|field| &field.value).collect(),
+        }
+    }
+
+    fn emit_type(&self) -> TokenStream {
+        match self {
+            Self::Unit => quote! { () },
+            Self::Single(value) => value.ty.to_token_stream(),
+            Self::Tuple(values) => {
+                let types = values.iter().map(|value| &value.ty);
+                quote! { (#(#types,)*) }
+            }
+            Self::Struct { name, .. } => quote! { #name },
+        }
+    }
+
+    /// Declare the reply struct, if any.
+    fn emit_declaration(&self, vis: &Visibility, command: &str) -> TokenStream {
+        match self {
+            Self::Struct { name, fields } => {
+                let doc = format!("Reply to [`{}`].", command);
+                let fields = fields.iter().map(|ReplyField { name, value }| {
+                    let ty = &value.ty;
+                    quote! { #vis #name: #ty }
+                });
+
+                quote! {
+                    #[doc = #doc]
+                    #[derive(Debug)]
+                    #vis struct #name {
+                        #(#fields,)*
+                    }
+                }
+            }
+            _ => TokenStream::new(),
+        }
+    }
+
+    /// Read all results from `reply`, returning the statements and the value returned by the
+    /// command.
+    fn emit_reads(&self) -> (TokenStream, TokenStream) {
+        let values = self.values();
+        let bindings: Vec<_> = (0..values.len())
+            .map(|i| format_ident!("__result{}", i))
+            .collect();
+
+        let (normal, translate): (Vec<_>, Vec<_>) = values
+            .iter()
+            .zip(&bindings)
+            .partition(|(value, _)| !value.translate);
+
+        let normal = normal.into_iter().map(|(value, binding)| {
+            let read = value.emit_read();
+            quote! { let #binding = #read; }
+        });
+
+        let translate = match translate.len() {
+            0 => TokenStream::new(),
+            _ => {
+                let reads = translate.into_iter().map(|(value, binding)| {
+                    let read = value.emit_read();
+                    quote! { let #binding = #read; }
+                });
+                quote! {
+                    let mut reply = reply.finish_results();
+                    #(#reads)*
+                }
+            }
+        };
+
+        let value = match self {
+            Self::Unit => quote! { () },
+            Self::Single(_) => quote! { #(#bindings)* },
+            Self::Tuple(_) => quote! { (#(#bindings,)*) },
+            Self::Struct { name, fields } => {
+                let names = fields.iter().map(|field| &field.name);
+                quote! { #name { #(#names: #bindings,)* } }
+            }
+        };
+
+        let reads = quote! {
+            #(#normal)*
+            #translate
+        };
+
+        (reads, value)
+    }
+}
+
+struct Command {
+    attributes: Vec<Attribute>,
+    vis: Visibility,
+    id: u16,
+    pid: bool,
+    name: Ident,
+    parameters: Vec<Parameter>,
+    reply: Reply,
+}
+
+/// Parse the arguments of `#[command(id)]` or `#[command(id, pid)]`.
+fn parse_command_attr(attr: &Attribute) -> Result<(u16, bool)> {
+    attr.parse_args_with(|input: ParseStream| {
+        let id = input.parse::<LitInt>()?.base10_parse()?;
+
+        if input.is_empty() {
+            return Ok((id, false));
+        }
+
+        let _comma: Token![,] = input.parse()?;
+        let flag: Ident = input.parse()?;
+        match flag.to_string().as_str() {
+            "pid" => Ok((id, true)),
+            unknown => Err(Error::new(
+                flag.span(),
+                format!(r#"Unknown flag "{}", expected "pid""#, unknown),
+            )),
+        }
+    })
+}
+
+impl Parse for Command {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut attributes = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        let _fn: Token![fn] = input.parse()?;
+        let name: Ident = input.parse()?;
+
+        let command = attributes
+            .iter()
+            .position(|attr| attr.path.is_ident("command"))
+            .map(|position| attributes.remove(position))
+            .ok_or_else(|| Error::new(name.span(), "Missing `#[command(id)]` attribute"))?;
+        let (id, pid) = parse_command_attr(&command)?;
+
+        let inputs;
+        let _paren = parenthesized!(inputs in input);
+        let _ref: Token![&] = inputs.parse()?;
+        let _self: Token![self] = inputs.parse()?;
+        let parameters = match inputs.is_empty() {
+            true => Vec::new(),
+            false => {
+                let _comma: Token![,] = inputs.parse()?;
+                Punctuated::<Parameter, Token![,]>::parse_terminated(&inputs)?
+                    .into_iter()
+                    .collect()
+            }
+        };
+        check_translate_order(
+            parameters.iter().map(|p| (p.translate, &p.ty)),
+            "parameters",
+        )?;
+
+        let reply: Reply = input.parse()?;
+        check_translate_order(
+            reply.values().into_iter().map(|v| (v.translate, &v.ty)),
+            "results",
+        )?;
+
+        let _semicolon: Token![;] = input.parse()?;
+
+        Ok(Self {
+            attributes,
+            vis,
+            id,
+            pid,
+            name,
+            parameters,
+            reply,
+        })
+    }
+}
+
+impl Command {
+    fn emit(&self, service: &str) -> TokenStream {
+        let Self {
+            attributes,
+            vis,
+            id,
+            name,
+            parameters,
+            reply,
+            ..
+        } = self;
+
+        let label = format!("{}::{}", service, name);
+
+        let inputs = parameters.iter().map(|Parameter { name, ty, .. }| {
+            quote! { #name: #ty }
+        });
+
+        // The size of the request is known, so it is checked against the command buffer once
+        let words = parameters.iter().map(Parameter::emit_words);
+        let pid_words = match self.pid {
+            true => {
+                quote! { + <crate::ipc::ThisProcessId as crate::ipc::TranslateParameter>::WORDS }
+            }
+            false => TokenStream::new(),
+        };
+
+        let (translate, normal): (Vec<_>, Vec<_>) = parameters.iter().partition(|p| p.translate);
+        let normal = normal.iter().map(|p| p.emit_write());
+        let pid = match self.pid {
+            true => quote! { .translate_parameter(crate::ipc::ThisProcessId) },
+            false => TokenStream::new(),
+        };
+        let translate = translate.iter().map(|p| p.emit_write());
+
+        let values = reply.values();
+        let binding = if values.is_empty() {
+            quote! { _ }
+        } else if values.iter().all(|value| value.translate) {
+            quote! { reply }
+        } else {
+            quote! { mut reply }
+        };
+
+        let output = reply.emit_type();
+        let (reads, value) = reply.emit_reads();
+
+        quote! {
+            #(#attributes)*
+            #vis fn #name(&self, #(#inputs),*) -> crate::result::Result<#output> {
+                ::log::trace!("{}: sending command {:#x}", #label, #id);
+
+                let request = unsafe {
+                    crate::ipc::IpcRequest::command_sized::<{ 0 #(+ #words)* #pid_words }>(#id)
+                }
+                    #(#normal)*
+                    #pid
+                    #(#translate)*;
+
+                let #binding = match request.dispatch(&self.handle) {
+                    ::core::result::Result::Ok(reply) => reply,
+                    ::core::result::Result::Err(e) => {
+                        ::log::debug!("{}: command {:#x} failed: {:?}", #label, #id, e);
+                        return ::core::result::Result::Err(e);
+                    }
+                };
+
+                #reads
+
+                ::core::result::Result::Ok(#value)
+            }
+        }
+    }
+}
+
+struct ServiceImpl {
+    generics: Generics,
+    ty: Type,
+    commands: Vec<Command>,
+}
+
+impl Parse for ServiceImpl {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let _impl: Token![impl] = input.parse()?;
+        let mut generics: Generics = input.parse()?;
+        let ty = input.parse()?;
+        generics.where_clause = input.parse()?;
+
+        let body;
+        let _brace = braced!(body in input);
+        let mut commands = Vec::new();
+        while !body.is_empty() {
+            commands.push(body.parse()?);
+        }
+
+        Ok(Self {
+            generics,
+            ty,
+            commands,
+        })
+    }
+}
+
+impl ServiceImpl {
+    /// The name of the service type, without generic arguments.
+    fn name(&self) -> String {
+        match &self.ty {
+            Type::Path(path) => match path.path.segments.last() {
+                Some(segment) => segment.ident.to_string(),
+                None => String::new(),
+            },
+            ty => quote!(#ty).to_string(),
+        }
+    }
+
+    fn emit(&self) -> TokenStream {
+        let Self { generics, ty, .. } = self;
+        let (impl_generics, _, where_clause) = generics.split_for_impl();
+
+        let service = self.name();
+        let replies = self.commands.iter().map(|command| {
+            let label = format!("{}::{}", service, command.name);
+            command.reply.emit_declaration(&command.vis, &label)
+        });
+        let methods = self.commands.iter().map(|command| command.emit(&service));
+
+        quote! {
+            #(#replies)*
+
+            impl #impl_generics #ty #where_clause {
+                #(#methods)*
+            }
+        }
+    }
+}
+
+pub struct Service {
+    impls: Vec<ServiceImpl>,
+}
+
+impl Parse for Service {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut impls = Vec::new();
+        while !input.is_empty() {
+            impls.push(input.parse()?);
+        }
+
+        Ok(Self { impls })
+    }
+}
+
+impl Service {
+    pub fn emit(&self) -> TokenStream {
+        let impls = self.impls.iter().map(ServiceImpl::emit);
+
+        quote! { #(#impls)* }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use syn::parse_quote;
+
+    fn emit(service: Service) -> String {
+        service.emit().to_string()
+    }
+
+    #[test]
+    fn parse_command() {
+        let command: Command = parse_quote! {
+            /// Documented
+            #[command(0x4, pid)]
+            pub fn connect(&self, slot: u32, #[translate] event: BorrowedHandle<'_>) -> u32;
+        };
+
+        assert_eq!(command.id, 4);
+        assert!(command.pid);
+        assert_eq!(command.name, "connect");
+        assert_eq!(command.attributes.len(), 1);
+
+        let translate: Vec<_> = command.parameters.iter().map(|p| p.translate).collect();
+        assert_eq!(translate, [false, true]);
+        assert!(matches!(command.reply, Reply::Single(_)));
+    }
+
+    #[test]
+    fn parse_reply_struct() {
+        let command: Command = parse_quote! {
+            #[command(0x4)]
+            fn accept(&self) -> Accepted { fd: u32, #[translate] handle: OwnedHandle };
+        };
+
+        match command.reply {
+            Reply::Struct { name, fields } => {
+                assert_eq!(name, "Accepted");
+                let translate: Vec<_> = fields.iter().map(|f| f.value.translate).collect();
+                assert_eq!(translate, [false, true]);
+            }
+            _ => panic!("Expected a reply struct"),
+        }
+    }
+
+    #[test]
+    fn reject_missing_command_attr() {
+        let result = syn::parse_str::<Command>("fn size(&self) -> u64;");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn reject_normal_after_translate() {
+        let parameters = syn::parse_str::<Command>(
+            "#[command(0x1)] fn f(&self, #[translate] a: ThisProcessId, b: u32);",
+        );
+        let results = syn::parse_str::<Command>(
+            "#[command(0x1)] fn f(&self) -> (#[translate] OwnedHandle, u32);",
+        );
+
+        assert!(parameters.is_err());
+        assert!(results.is_err());
+    }
+
+    #[test]
+    fn emit_splits_double_words() {
+        let output = emit(parse_quote! {
+            impl File {
+                #[command(0x805)]
+                pub fn set_size(&self, size: u64);
+            }
+        });
+
+        assert!(
+            output.contains(&quote!(.parameters(&[size as u32, (size >> 32) as u32])).to_string())
+        );
+        assert!(output.contains(&quote!(let _ = match).to_string()));
+    }
+
+    #[test]
+    fn emit_pid_before_translate_parameters() {
+        let output = emit(parse_quote! {
+            impl Ac {
+                #[command(0x8, pid)]
+                fn close_async(&self, #[translate] event: BorrowedHandle<'_>);
+            }
+        });
+
+        let expected = quote! {
+            .translate_parameter(crate::ipc::ThisProcessId)
+            .translate_parameter(event);
+        };
+        assert!(output.contains(&expected.to_string()));
+    }
+
+    #[test]
+    fn emit_sums_request_words() {
+        let output = emit(parse_quote! {
+            impl File {
+                #[command(0x803, pid)]
+                fn write(&self, offset: u64, flags: [u32; 2], #[translate] data: MappedBuffer<'_>);
+            }
+        });
+
+        let expected = quote! {
+            crate::ipc::IpcRequest::command_sized::<{
+                0 + 2 + (2) + <MappedBuffer<'_> as crate::ipc::TranslateParameter>::WORDS
+                    + <crate::ipc::ThisProcessId as crate::ipc::TranslateParameter>::WORDS
+            }>(2051u16)
+        };
+        assert!(output.contains(&expected.to_string()));
+    }
+}
@@ -8,47 +8,46 @@ use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 use syn::token::Paren;
-use syn::{parenthesized, Ident, LitInt, LitStr, Result, Token, Type, TypeNever, TypeTuple};
+use syn::{parenthesized, Ident, LitInt, LitStr, Result, Token, Type, TypeNever};
 use syn::{Attribute, Error};
 
 use itertools::MultiUnzip;
 
+/// Parse an optional `#[split]`, marking a 64-bit value passed in two registers.
+fn parse_split_attr(input: ParseStream) -> Result<bool> {
+    let attributes = input.call(Attribute::parse_outer)?;
+
+    let attr = match attributes.first() {
+        Some(attr) => attr,
+        None => return Ok(false), // No attribute specified
+    };
+
+    let name = attr
+        .path
+        .get_ident()
+        .ok_or_else(|| Error::new(attr.span(), "Empty attribute"))?;
+    match name.to_string().as_str() {
+        "split" => Ok(true),
+        unknown => Err(Error::new(
+            name.span(),
+            format!(r#"Unknown attribute "{}", expected "split""#, unknown),
+        )),
+    }
+}
+
 pub enum InputParameterSpec {
     Unused(Token![_]),
     Name(Ident),
     Split(Ident),
 }
 
-impl InputParameterSpec {
-    fn parse_split_attr(input: ParseStream) -> Result<Option<()>> {
-        let attributes = input.call(Attribute::parse_outer)?;
-
-        let attr = match attributes.first() {
-            Some(attr) => attr,
-            None => return Ok(None), // No attribute specified
-        };
-
-        let name = attr
-            .path
-            .get_ident()
-            .ok_or_else(|| Error::new(attr.span(), "Empty attribute"))?;
-        match name.to_string().as_str() {
-            "split" => Ok(Some(())),
-            unknown => Err(Error::new(
-                name.span(),
-                &format!(r#"Unknown attribute "{}", expected "split""#, unknown),
-            )),
-        }
-    }
-}
-
 impl Parse for InputParameterSpec {
     fn parse(input: ParseStream) -> Result<Self> {
         let lookahead = input.lookahead1();
 
         let input_arg = if lookahead.peek(Token![_]) {
             Self::Unused(input.parse()?)
-        } else if (Self::parse_split_attr(input)?).is_some() {
+        } else if parse_split_attr(input)? {
             Self::Split(input.parse()?)
         } else {
             Self::Name(input.parse()?)
@@ -75,15 +74,20 @@ impl Parse for InputSpec {
 impl InputSpec {
     fn parameters(&self) -> Vec<InputParameter> {
         let mut parameters = vec![];
-
-        for (param_spec, register) in self.parameters.iter().zip(0usize..) {
-            let param = match param_spec {
-                InputParameterSpec::Unused(_) => continue,
-                InputParameterSpec::Name(ident) => InputParameter::new(ident.clone(), register),
-                InputParameterSpec::Split(_) => todo!("Argument splitting not yet implemented"),
+        let mut register = 0;
+
+        for param_spec in self.parameters.iter() {
+            match param_spec {
+                InputParameterSpec::Unused(_) => register += 1,
+                InputParameterSpec::Name(ident) => {
+                    parameters.push(InputParameter::new(ident.clone(), register));
+                    register += 1;
+                }
+                InputParameterSpec::Split(ident) => {
+                    parameters.push(InputParameter::split(ident.clone(), register));
+                    register += 2;
+                }
             };
-
-            parameters.push(param);
         }
 
         parameters
@@ -97,19 +101,64 @@ fn register_name(index: usize, span: Span) -> LitStr {
 struct InputParameter {
     name: Ident,
     register: usize,
+    /// Whether the value is split into its low and high half, passed in `register` and the one
+    /// following it.
+    split: bool,
 }
 
 impl InputParameter {
     fn new(name: Ident, register: usize) -> Self {
-        Self { name, register }
+        Self {
+            name,
+            register,
+            split: false,
+        }
+    }
+
+    fn split(name: Ident, register: usize) -> Self {
+        Self {
+            name,
+            register,
+            split: true,
+        }
+    }
+
+    fn halves(&self) -> (Ident, Ident) {
+        (
+            format_ident!("__in_r{}", self.register),
+            format_ident!("__in_r{}", self.register + 1),
+        )
+    }
+
+    /// Statements evaluating the parameter before the call.
+    fn declaration(&self) -> TokenStream {
+        if !self.split {
+            return TokenStream::new();
+        }
+
+        let name = &self.name;
+        let (low, high) = self.halves();
+
+        quote! {
+            let (#low, #high) = crate::svc::IntoRegisterPair::into_register_pair(#name);
+        }
     }
 
     fn register_spec(&self) -> TokenStream {
         let name = &self.name;
         let reg = register_name(self.register, name.span());
 
-        quote! {
-            in(#reg) IntoRegister::into_register(#name)
+        if self.split {
+            let (low, high) = self.halves();
+            let reg_high = register_name(self.register + 1, name.span());
+
+            quote! {
+                in(#reg) #low, in(#reg_high) #high
+            }
+        } else {
+            quote! {
+                in(#reg) IntoRegister::into_register(#name)
+            }
         }
     }
 }
@@ -118,6 +167,8 @@ struct OutputParameter {
     ident: Ident,
     ty: Type,
     register: usize,
+    /// The register holding the high half of a value that is joined from two registers.
+    high_register: Option<(Ident, usize)>,
 }
 
 impl OutputParameter {
@@ -127,6 +178,15 @@ impl OutputParameter {
             ident,
             ty,
             register,
+            high_register: None,
+        }
+    }
+
+    fn joined(register: usize, ty: Type) -> Self {
+        let high = format_ident!("__out_r{}", register + 1);
+        Self {
+            high_register: Some((high, register + 1)),
+            ..Self::new(register, ty)
         }
     }
 
@@ -136,8 +196,10 @@ impl OutputParameter {
 
     fn declaration(&self) -> TokenStream {
         let ident = &self.ident;
+        let high = self.high_register.iter().map(|(high, _)| high);
         quote! {
             let #ident: u32;
+            #(let #high: u32;)*
         }
     }
 
@@ -145,47 +207,89 @@ impl OutputParameter {
         let name = &self.ident;
         let reg = register_name(self.register, name.span());
 
-        quote! {
-            lateout(#reg) #name
+        match &self.high_register {
+            Some((high, register)) => {
+                let reg_high = register_name(*register, name.span());
+                quote! {
+                    lateout(#reg) #name, lateout(#reg_high) #high
+                }
+            }
+            None => quote! {
+                lateout(#reg) #name
+            },
+        }
+    }
+
+    fn conversion(&self) -> TokenStream {
+        let (name, ty) = (&self.ident, &self.ty);
+
+        match &self.high_register {
+            Some((high, _)) => quote! {
+                <#ty as crate::svc::FromRegisterPair>::from_register_pair(#name, #high)
+            },
+            None => quote! {
+                <#ty as FromRegister>::from_register(#name)
+            },
         }
     }
 
-    fn unzip(zipped: Vec<Self>) -> (Vec<Ident>, Vec<Type>, Vec<TokenStream>, Vec<TokenStream>) {
+    fn unzip(zipped: Vec<Self>) -> (Vec<TokenStream>, Vec<TokenStream>, Vec<TokenStream>) {
         zipped
             .into_iter()
             .map(|param| {
-                let decl = param.declaration();
-                let reg = param.register_spec();
-                (param.ident, param.ty, decl, reg)
+                (
+                    param.declaration(),
+                    param.register_spec(),
+                    param.conversion(),
+                )
             })
             .multiunzip()
     }
 }
 
+/// The type of an output, which is joined from two registers if marked `#[split]`.
+pub struct OutputTypeSpec {
+    split: bool,
+    ty: Type,
+}
+
+impl Parse for OutputTypeSpec {
+    fn parse(input: ParseStream) -> Result<Self> {
+        Ok(Self {
+            split: parse_split_attr(input)?,
+            ty: input.parse()?,
+        })
+    }
+}
+
 pub enum OutputSpec {
     NoReturn(TypeNever),
     Unit,
-    Single(Box<Type>),
-    Multiple(TypeTuple),
+    Single(Box<OutputTypeSpec>),
+    Multiple(Punctuated<OutputTypeSpec, Token![,]>),
 }
 
 impl OutputSpec {
     fn parameters(&self) -> Option<(OutputParameter, Vec<OutputParameter>)> {
-        let params = match self {
+        let specs: Vec<&OutputTypeSpec> = match self {
             Self::NoReturn(_) => return None,
             Self::Unit => vec![],
-            Self::Single(ty) => {
-                vec![OutputParameter::new(1, (**ty).clone())]
-            }
-            Self::Multiple(types) => types
-                .elems
-                .iter()
-                .cloned()
-                .zip(1usize..)
-                .map(|(ty, register_index)| OutputParameter::new(register_index, ty))
-                .collect(),
+            Self::Single(spec) => vec![spec],
+            Self::Multiple(specs) => specs.iter().collect(),
         };
 
+        let mut params = vec![];
+        let mut register = 1;
+        for spec in specs {
+            if spec.split {
+                params.push(OutputParameter::joined(register, spec.ty.clone()));
+                register += 2;
+            } else {
+                params.push(OutputParameter::new(register, spec.ty.clone()));
+                register += 1;
+            }
+        }
+
         Some((OutputParameter::result(), params))
     }
 }
@@ -200,14 +304,16 @@ impl Parse for OutputSpec {
 
         let lookahead = input.lookahead1();
         if lookahead.peek(Paren) {
-            let types = input.parse()?;
+            let types;
+            let _in_paren = parenthesized!(types in input);
+            let types = types.parse_terminated(OutputTypeSpec::parse)?;
             Ok(Self::Multiple(types))
         } else if lookahead.peek(Token![!]) {
             let never = input.parse()?;
             Ok(Self::NoReturn(never))
         } else {
             let tyype = input.parse()?;
-            Ok(Self::Single(tyype))
+            Ok(Self::Single(Box::new(tyype)))
         }
     }
 }
@@ -222,43 +328,66 @@ impl SvcSpec {
     pub fn to_asm_call(&self) -> TokenStream {
         let svc_mnemonic = LitStr::new(&format!("svc 0x{:02x}", self.svc_num), self.svc_num.span());
 
-        let inputs = self
-            .input
-            .parameters()
-            .into_iter()
-            .map(|p| p.register_spec());
+        let svc_num = self.svc_num;
+        let parameters = self.input.parameters();
+        let names: Vec<_> = parameters.iter().map(|p| &p.name).collect();
+        let (input_decl, inputs): (Vec<_>, Vec<_>) = parameters
+            .iter()
+            .map(|p| (p.declaration(), p.register_spec()))
+            .unzip();
 
         let asm_call = if let Some((result, output)) = self.output.parameters() {
             let result_code = result.ident.clone();
             let result_decl = result.declaration();
             let result_register = result.register_spec();
 
-            let (output_idents, output_types, output_decl, output_spec) =
-                OutputParameter::unzip(output);
+            let output_types: Vec<_> = output.iter().map(|o| o.ty.clone()).collect();
+            let (output_decl, output_spec, output_conversion) = OutputParameter::unzip(output);
 
             quote! {
                 {
-                    use crate::result::ResultCode;
-                    use crate::svc::{FromRegister, IntoRegister};
-
-                    #result_decl
-                    #(#output_decl)*
-
-                    core::arch::asm!(
-                        #svc_mnemonic,
-                        #(#inputs,)*
-                        #result_register,
-                        #(#output_spec,)*
-                        options(nostack)
-                    );
-
-                    ResultCode::from(#result_code).and_then(||
-                        (#(<#output_types as FromRegister>::from_register(#output_idents)),*)
-                    )
+                    #[cfg(target_arch = "arm")]
+                    let __result = {
+                        use crate::result::ResultCode;
+                        use crate::svc::{FromRegister, IntoRegister};
+
+                        #(#input_decl)*
+                        #result_decl
+                        #(#output_decl)*
+
+                        core::arch::asm!(
+                            #svc_mnemonic,
+                            #(#inputs,)*
+                            #result_register,
+                            #(#output_spec,)*
+                            options(nostack)
+                        );
+
+                        ResultCode::from(#result_code).and_then(|| (#(#output_conversion),*))
+                    };
+                    #[cfg(not(target_arch = "arm"))]
+                    let __result = {
+                        let _ = (#(&#names,)*);
+                        crate::svc::unsupported::<crate::result::Result<(#(#output_types),*)>>(#svc_num)
+                    };
+                    __result
                 }
             }
         } else {
-            quote! { core::arch::asm!(#svc_mnemonic, #(#inputs,)* options(noreturn, nostack)) }
+            quote! {
+                {
+                    #[cfg(target_arch = "arm")]
+                    {
+                        #(#input_decl)*
+                        core::arch::asm!(#svc_mnemonic, #(#inputs,)* options(noreturn, nostack))
+                    }
+                    #[cfg(not(target_arch = "arm"))]
+                    {
+                        let _ = (#(&#names,)*);
+                        crate::svc::unsupported(#svc_num)
+                    }
+                }
+            }
         };
 
         asm_call
@@ -314,9 +443,9 @@ mod tests {
                 Self::NoReturn(_) => f.debug_tuple("NoReturn").field(&"_").finish(),
                 Self::Unit => write!(f, "Unit"),
                 Self::Single(_) => f.debug_tuple("Single").field(&"_").finish(),
-                Self::Multiple(tuple) => f
+                Self::Multiple(types) => f
                     .debug_tuple("Multiple")
-                    .field(&format!("[_; {}]", tuple.elems.len()))
+                    .field(&format!("[_; {}]", types.len()))
                     .finish(),
             }
         }
@@ -368,8 +497,43 @@ mod tests {
         parse_output_spec_single:
             [-> u32] => OutputSpec::Single(_),
         parse_output_spec_multiple:
-            [-> (u32, u32)] => OutputSpec::Multiple(tuple) if tuple.elems.len() == 2,
+            [-> (u32, u32)] => OutputSpec::Multiple(types) if types.len() == 2,
         parse_output_spec_multiple_empty:
-            [-> ()] => OutputSpec::Multiple(tuple) if tuple.elems.is_empty(),
+            [-> ()] => OutputSpec::Multiple(types) if types.is_empty(),
+        parse_output_spec_single_split:
+            [-> #[split] i64] => OutputSpec::Single(spec) if spec.split,
+        parse_output_spec_multiple_split:
+            [-> (#[split] i64, u32)] => OutputSpec::Multiple(types) if types[0].split && !types[1].split,
+    }
+
+    #[test]
+    fn input_split_takes_two_registers() {
+        let spec: InputSpec = parse_quote! { (#[split] timeout, _, handle) };
+
+        let registers: Vec<_> = spec
+            .parameters()
+            .iter()
+            .map(|param| (param.name.to_string(), param.register, param.split))
+            .collect();
+
+        assert_eq!(
+            registers,
+            [("timeout".into(), 0, true), ("handle".into(), 3, false)]
+        );
+    }
+
+    #[test]
+    fn output_split_takes_two_registers() {
+        let spec: OutputSpec = parse_quote! { -> (#[split] i64, u32) };
+
+        let (result, params) = spec.parameters().expect("Expected a returning call");
+
+        let registers: Vec<_> = params
+            .iter()
+            .map(|param| (param.register, param.high_register.as_ref().map(|h| h.1)))
+            .collect();
+
+        assert_eq!(result.register, 0);
+        assert_eq!(registers, [(1, Some(2)), (3, None)]);
     }
 }
@@ -7,15 +7,8 @@
 
 use core::{fmt::Write, panic::PanicInfo};
 
-use ctru_rt::{
-    entry,
-    graphics::Grapics,
-    ports::srv::Srv,
-    result::Result,
-    services::{gsp::gpu::Gpu, hid::Hid},
-    svc,
-};
-use log::{error, info};
+use ctru_rt::{entry, graphics::Grapics, services::hid::Hid, svc};
+use log::info;
 
 #[panic_handler]
 fn panic_handler(info: &PanicInfo) -> ! {
@@ -26,18 +19,10 @@ fn panic_handler(info: &PanicInfo) -> ! {
     svc::user_break(UserBreakReason::Panic)
 }
 
-fn run() -> Result<()> {
-    let srv = Srv::init()?;
-    info!("Initialized `srv`: {:#0x?}", srv);
-
-    let mut gpu = Gpu::init(&srv)?;
-    info!("Initialized `gsp::Gpu`: {:#0x?}", gpu);
-
-    let mut gfx = Grapics::init_default(&mut gpu)?;
+#[entry(main_thread_stack = "64KiB", services(log, gfx, hid))]
+fn main(gfx: Grapics, hid: Hid) {
     info!("Initialized graphics: {:#0x?}", gfx);
 
-    let hid = Hid::init(&srv)?;
-
     let mut runs = 0..5;
     info!("Press START to exit");
     while !hid.last_keypad().start() {
@@ -50,19 +35,4 @@ fn run() -> Result<()> {
     }
 
     info!("Exiting...");
-
-    Ok(())
-}
-
-#[entry]
-fn main() {
-    let _ = ctru_rt::debug::init_log();
-
-    match run() {
-        Ok(_) => {}
-        Err(e) => {
-            let _ = error!("Failed to run: {:?}", e);
-            svc::exit_process()
-        }
-    }
 }
@@ -40,7 +40,7 @@ fn main() {
     let rc = ResultCode::from(0x2a07);
     info!("Some result code: {:?}", rc);
 
-    let _ = ctru_rt::svc::sleep_thread(Duration::from_secs(2).into());
+    ctru_rt::thread::sleep(Duration::from_secs(2));
 
     info!("Bye-bye!");
 }
@@ -14,9 +14,10 @@ use ctru_rt::{
     entry,
     heap::{PageAlignError, PageAlignedBuffer},
     ports::srv::Srv,
-    result::{ErrorCode, Level, Module, Result, Summary},
-    svc::{sleep_thread, UserBreakReason},
-    services::soc::{Domain, Protocol, Soc, Type},
+    result::{ErrorCode, Level, Module, Summary},
+    svc::UserBreakReason,
+    services::soc::{Domain, Protocol, Soc, SocketError, Type},
+    thread,
 };
 
 #[panic_handler]
@@ -27,7 +28,7 @@ fn panic_handler(info: &PanicInfo) -> ! {
     ctru_rt::svc::user_break(UserBreakReason::Panic)
 }
 
-fn run() -> Result<()> {
+fn run() -> Result<(), SocketError> {
     let srv = Srv::init()?;
 
     let _ = info!("Initialized srv: {:#0x?}", srv);
@@ -50,7 +51,7 @@ fn run() -> Result<()> {
 
     loop {
         info!("Done...");
-        sleep_thread(Duration::from_secs(1).into());
+        thread::sleep(Duration::from_secs(1));
     }
 }
 
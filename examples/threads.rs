@@ -17,7 +17,7 @@ use ctru_rt::{
     debug::{init_log, SvcDebugLog},
     entry,
     result::Result,
-    svc, sync, thread,
+    sync, thread,
 };
 
 use log::{error, info, warn};
@@ -27,7 +27,8 @@ fn panic_handler(info: &PanicInfo) -> ! {
     let mut log = SvcDebugLog::default();
     let _ = writeln!(log, "{}", info);
 
-    svc::exit_thread()
+    // Let the main thread know about the panic when it joins this thread.
+    thread::exit_panicking()
 }
 
 /// Spawn a bunch of worker threads which slowly count to five and return some value when done.
@@ -59,7 +60,7 @@ fn run() -> Result<()> {
                 // Slowly count to five
                 for i in 0..5 {
                     info!("\x1b[{}mThread {}\x1b[0m: Counting {}...", color, id, 5 - i);
-                    svc::sleep_thread(timeout.into());
+                    thread::sleep(timeout);
                 }
 
                 // Signal the main thread that we're done here.
@@ -90,9 +91,12 @@ fn run() -> Result<()> {
         };
 
         match thread.join() {
-            Ok(val) => {
+            Ok(Ok(val)) => {
                 info!("Thread {} returned {}.", signaled_idx, val);
             }
+            Ok(Err(panicked)) => {
+                error!("Thread {}: {}", signaled_idx, panicked);
+            }
             Err(e) => {
                 error!("Failed to join thread {}: {:?}", signaled_idx, e);
             }
@@ -102,9 +106,12 @@ fn run() -> Result<()> {
     if !threads.is_empty() {
         for (idx, thread) in threads.into_iter() {
             match thread.join() {
-                Ok(val) => {
+                Ok(Ok(val)) => {
                     info!("Lingering thread {} returned {}.", idx, val);
                 }
+                Ok(Err(panicked)) => {
+                    error!("Lingering thread {}: {}", idx, panicked);
+                }
                 Err(e) => {
                     error!("Failed to join lingering thread {}: {:?}", idx, e);
                 }
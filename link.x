@@ -49,6 +49,31 @@ SECTIONS
     ARM.exidx : { *(.ARM.exidx* .gnu.linkonce.armexidx.*) } : RODATA
     __exidx_end = .;
 
+    /* Constructors and destructors, run by `_ctru_rt_start` and `ctru_rt::exit` */
+    .init_array : ALIGN(4)
+    {
+        PROVIDE_HIDDEN(__init_array_start = .);
+        KEEP( *(SORT_BY_INIT_PRIORITY(.init_array.*)) )
+        KEEP( *(.init_array) )
+        PROVIDE_HIDDEN(__init_array_end = .);
+    } : RODATA
+
+    .fini_array : ALIGN(4)
+    {
+        PROVIDE_HIDDEN(__fini_array_start = .);
+        KEEP( *(SORT_BY_INIT_PRIORITY(.fini_array.*)) )
+        KEEP( *(.fini_array) )
+        PROVIDE_HIDDEN(__fini_array_end = .);
+    } : RODATA
+
+    /* Tests registered with `#[ctru_test]`, see `ctru_rt::testing` */
+    .ctru_rt_tests : ALIGN(4)
+    {
+        PROVIDE_HIDDEN(__ctru_rt_tests_start = .);
+        KEEP( *(.ctru_rt_tests) )
+        PROVIDE_HIDDEN(__ctru_rt_tests_end = .);
+    } : RODATA
+
     .data : ALIGN(4K)
     {
         __data_start__ = .;
@@ -0,0 +1,124 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Showing errors to the user with the system's error applet.
+//!
+//! ```ignore
+//! if let Err(e) = load_save_data() {
+//!     applets::error::display(&apt, &mut gpu, e)?;
+//!     return;
+//! }
+//! ```
+
+use crate::result::{ErrorCode, Result, ResultValue};
+use crate::services::apt::{AppId, AppState, AptLock};
+use crate::services::gsp::gpu::Gpu;
+
+/// Maximum length of a message in UTF-16 code units, excluding the NUL terminator.
+pub const MAX_TEXT_LENGTH: usize = 1900 - 1;
+
+const TYPE_CODE: u32 = 0x000;
+const TYPE_TEXT: u32 = 0x001;
+const TYPE_WORD_WRAP: u32 = 0x200;
+
+const UPPER_SCREEN_NORMAL: u32 = 0;
+const RETURN_UNKNOWN: i32 = -1;
+
+/// What the error applet displays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorMessage<'a> {
+    /// A message, word-wrapped and truncated to [`MAX_TEXT_LENGTH`].
+    Text(&'a str),
+    /// The error code, formatted like `012-3456`.
+    Code(ErrorCode),
+}
+
+impl<'a> From<&'a str> for ErrorMessage<'a> {
+    fn from(text: &'a str) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<ErrorCode> for ErrorMessage<'_> {
+    fn from(code: ErrorCode) -> Self {
+        Self::Code(code)
+    }
+}
+
+/// Parameter of the error applet, which it overwrites with its result.
+///
+/// Padding is spelled out, so the whole struct can be sent as bytes.
+#[repr(C)]
+struct Config {
+    kind: u32,
+    code: u32,
+    upper_screen: u32,
+    language: u16,
+    text: [u16; MAX_TEXT_LENGTH + 1],
+    home_button: u8,
+    software_reset: u8,
+    app_jump: u8,
+    _reserved0: [u8; 3],
+    return_code: i32,
+    eula_version: u16,
+    _reserved1: u16,
+}
+
+impl Config {
+    fn new(message: ErrorMessage) -> Self {
+        let mut config = Self {
+            kind: TYPE_CODE,
+            code: 0,
+            upper_screen: UPPER_SCREEN_NORMAL,
+            language: 0,
+            text: [0; MAX_TEXT_LENGTH + 1],
+            home_button: 1,
+            software_reset: 0,
+            app_jump: 0,
+            _reserved0: [0; 3],
+            return_code: RETURN_UNKNOWN,
+            eula_version: 0,
+            _reserved1: 0,
+        };
+
+        match message {
+            ErrorMessage::Text(text) => {
+                config.kind = TYPE_TEXT | TYPE_WORD_WRAP;
+                for (unit, c) in config.text[..MAX_TEXT_LENGTH]
+                    .iter_mut()
+                    .zip(text.encode_utf16())
+                {
+                    *unit = c;
+                }
+            }
+            ErrorMessage::Code(code) => config.code = code.value(),
+        }
+
+        config
+    }
+
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self as *mut Self as *mut u8,
+                core::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+const _: () = assert!(core::mem::size_of::<Config>() == 0xef4);
+
+/// Show `message` with the error applet, returning once the user dismissed it.
+///
+/// The application is suspended while the applet runs, like when
+/// [jumping to the HOME menu](AptLock::jump_to_home).
+pub fn display<'a>(
+    apt: &AptLock,
+    gpu: &mut Gpu,
+    message: impl Into<ErrorMessage<'a>>,
+) -> Result<AppState> {
+    let mut config = Config::new(message.into());
+    apt.run_library_applet(gpu, AppId::Error, config.as_bytes_mut())
+}
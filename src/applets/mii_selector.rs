@@ -0,0 +1,280 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Letting the user pick one of their Miis.
+//!
+//! ```ignore
+//! let selection = MiiSelector::new()
+//!     .title("Who is playing?")
+//!     .launch(&apt, &mut gpu)?;
+//!
+//! if let Selection::User(mii) = selection {
+//!     info!("Hello, {}!", mii.name);
+//! }
+//! ```
+
+use crate::result::{CommonDescription, ErrorCode, Level, Module, Result, Summary};
+use crate::services::apt::{AppId, AptLock};
+use crate::services::gsp::gpu::Gpu;
+
+use alloc::string::String;
+
+/// Maximum length of the title in UTF-16 code units, excluding the NUL terminator.
+pub const MAX_TITLE_LENGTH: usize = 64 - 1;
+
+const CONFIG_SIZE: usize = 0x104;
+const CONFIG_MAGIC: u32 = 0x13de28cf;
+
+/// Size of the data of a [`Mii`].
+pub const MII_DATA_SIZE: usize = 0x5c;
+
+const GUEST_SLOTS: usize = 6;
+const USER_SLOTS: usize = 100;
+
+/// The checksum of the returned Mii does not match its data.
+pub const ERROR_CHECKSUM_MISMATCH: ErrorCode = ErrorCode::new(
+    Level::Permanent,
+    Summary::InvalidResultValue,
+    Module::Applet,
+    CommonDescription::InvalidResultValue.to_value(),
+);
+
+/// Offsets into the parameter, which is overwritten with the result by the applet.
+mod offset {
+    pub const ENABLE_CANCEL: usize = 0x00;
+    pub const ENABLE_GUESTS: usize = 0x01;
+    pub const SHOW_ON_TOP_SCREEN: usize = 0x02;
+    pub const TITLE: usize = 0x08;
+    pub const INITIAL_INDEX: usize = 0x90;
+    pub const GUEST_WHITELIST: usize = 0x94;
+    pub const USER_WHITELIST: usize = 0x9a;
+    pub const MAGIC: usize = 0x100;
+
+    pub const NO_MII_SELECTED: usize = 0x00;
+    pub const GUEST_SELECTED: usize = 0x04;
+    pub const GUEST_INDEX: usize = 0x08;
+    pub const MII: usize = 0x0c;
+    pub const CHECKSUM: usize = 0x6a;
+    pub const GUEST_NAME: usize = 0x6c;
+}
+
+/// Offsets into the Mii data.
+mod mii_offset {
+    pub const SYSTEM_ID: usize = 0x04;
+    pub const ID: usize = 0x0c;
+    pub const MAC: usize = 0x10;
+    pub const DETAILS: usize = 0x18;
+    pub const NAME: usize = 0x1a;
+    pub const AUTHOR: usize = 0x48;
+}
+
+const NAME_LENGTH: usize = 10;
+const GUEST_NAME_LENGTH: usize = 12;
+
+/// Configures and launches the Mii selector applet.
+#[derive(Debug, Clone)]
+pub struct MiiSelector<'a> {
+    title: Option<&'a str>,
+    allow_cancel: bool,
+    allow_guests: bool,
+    on_top_screen: bool,
+    initial_index: u32,
+}
+
+impl Default for MiiSelector<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> MiiSelector<'a> {
+    pub const fn new() -> Self {
+        Self {
+            title: None,
+            allow_cancel: true,
+            allow_guests: false,
+            on_top_screen: false,
+            initial_index: 0,
+        }
+    }
+
+    /// Show `title` above the Miis, truncated to [`MAX_TITLE_LENGTH`].
+    pub const fn title(mut self, title: &'a str) -> Self {
+        self.title = Some(title);
+        self
+    }
+
+    pub const fn allow_cancel(mut self, allow: bool) -> Self {
+        self.allow_cancel = allow;
+        self
+    }
+
+    /// Also offer the six guest Miis built into the system.
+    pub const fn allow_guests(mut self, allow: bool) -> Self {
+        self.allow_guests = allow;
+        self
+    }
+
+    pub const fn on_top_screen(mut self) -> Self {
+        self.on_top_screen = true;
+        self
+    }
+
+    /// Start with the user's Mii at `index` selected.
+    pub const fn initial_index(mut self, index: u32) -> Self {
+        self.initial_index = index;
+        self
+    }
+
+    fn encode(&self) -> [u8; CONFIG_SIZE] {
+        let mut config = [0; CONFIG_SIZE];
+
+        config[offset::ENABLE_CANCEL] = self.allow_cancel.into();
+        config[offset::ENABLE_GUESTS] = self.allow_guests.into();
+        config[offset::SHOW_ON_TOP_SCREEN] = self.on_top_screen.into();
+
+        if let Some(title) = self.title {
+            let units = title.encode_utf16().take(MAX_TITLE_LENGTH);
+            for (i, unit) in units.enumerate() {
+                let at = offset::TITLE + 2 * i;
+                config[at..at + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+        }
+
+        config[offset::INITIAL_INDEX..offset::INITIAL_INDEX + 4]
+            .copy_from_slice(&self.initial_index.to_le_bytes());
+        config[offset::GUEST_WHITELIST..offset::GUEST_WHITELIST + GUEST_SLOTS].fill(1);
+        config[offset::USER_WHITELIST..offset::USER_WHITELIST + USER_SLOTS].fill(1);
+        config[offset::MAGIC..offset::MAGIC + 4].copy_from_slice(&CONFIG_MAGIC.to_le_bytes());
+
+        config
+    }
+
+    /// Show the selector, returning once the user picked a Mii or cancelled.
+    ///
+    /// The application is suspended while the applet runs, see [`AptLock::jump_to_home`].  If the
+    /// user requested the HOME menu meanwhile, the next [`AptLock::main_loop_step`] reports it.
+    pub fn launch(&self, apt: &AptLock, gpu: &mut Gpu) -> Result<Selection> {
+        let mut buffer = self.encode();
+        apt.run_library_applet(gpu, AppId::AppletEd, &mut buffer)?;
+
+        Selection::decode(&buffer)
+    }
+}
+
+/// What the user picked in the [`MiiSelector`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    Cancelled,
+    /// One of the user's Miis.
+    User(Mii),
+    /// One of the guest Miis built into the system.
+    Guest {
+        index: u32,
+        name: String,
+        mii: Mii,
+    },
+}
+
+impl Selection {
+    fn decode(result: &[u8; CONFIG_SIZE]) -> Result<Self> {
+        if read_u32(result, offset::NO_MII_SELECTED) != 0 {
+            return Ok(Self::Cancelled);
+        }
+
+        let mut data = [0; MII_DATA_SIZE];
+        data.copy_from_slice(&result[offset::MII..offset::MII + MII_DATA_SIZE]);
+
+        // Covers the Mii and the padding following it
+        let checksum = u16::from_be_bytes([result[offset::CHECKSUM], result[offset::CHECKSUM + 1]]);
+        if crc16(&result[offset::MII..offset::CHECKSUM]) != checksum {
+            return Err(ERROR_CHECKSUM_MISMATCH);
+        }
+
+        let mii = Mii::parse(data);
+
+        if read_u32(result, offset::GUEST_SELECTED) != 0 {
+            Ok(Self::Guest {
+                index: read_u32(result, offset::GUEST_INDEX),
+                name: read_utf16(result, offset::GUEST_NAME, GUEST_NAME_LENGTH),
+                mii,
+            })
+        } else {
+            Ok(Self::User(mii))
+        }
+    }
+}
+
+/// A Mii as stored in the system's Mii database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mii {
+    pub name: String,
+    pub author: String,
+    pub id: u32,
+    /// Identifies the console the Mii was created on.
+    pub system_id: u64,
+    pub mac: [u8; 6],
+    pub is_favorite: bool,
+    /// The undecoded data, e.g. for rendering the Mii's face.
+    pub data: [u8; MII_DATA_SIZE],
+}
+
+impl Mii {
+    const DETAILS_FAVORITE: u16 = 1 << 14;
+
+    fn parse(data: [u8; MII_DATA_SIZE]) -> Self {
+        let mut system_id = [0; 8];
+        system_id.copy_from_slice(&data[mii_offset::SYSTEM_ID..mii_offset::SYSTEM_ID + 8]);
+
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&data[mii_offset::MAC..mii_offset::MAC + 6]);
+
+        let details =
+            u16::from_le_bytes([data[mii_offset::DETAILS], data[mii_offset::DETAILS + 1]]);
+
+        Self {
+            name: read_utf16(&data, mii_offset::NAME, NAME_LENGTH),
+            author: read_utf16(&data, mii_offset::AUTHOR, NAME_LENGTH),
+            id: u32::from_be_bytes([
+                data[mii_offset::ID],
+                data[mii_offset::ID + 1],
+                data[mii_offset::ID + 2],
+                data[mii_offset::ID + 3],
+            ]),
+            system_id: u64::from_be_bytes(system_id),
+            mac,
+            is_favorite: details & Self::DETAILS_FAVORITE != 0,
+            data,
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// Decode the NUL-terminated UTF-16 string of at most `length` units at `at`.
+fn read_utf16(bytes: &[u8], at: usize, length: usize) -> String {
+    let units = bytes[at..at + 2 * length]
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|&unit| unit != 0);
+
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// CRC-16/XMODEM, as used for Mii data.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte) << 8, |crc, _| {
+            if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
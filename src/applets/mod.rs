@@ -0,0 +1,9 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! System applets that run on top of the application, started through
+//! [`AptLock`](crate::services::apt::AptLock).
+
+pub mod error;
+pub mod mii_selector;
@@ -0,0 +1,57 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The session with the service manager, shared by all parts of the application.
+//!
+//! On startup, the runtime sets up the main thread, applies the configuration of
+//! [`#[entry]`](crate::entry), maps the heap and VRAM and runs the constructors in `.init_array`.
+//! Only then the entry point initializes the services it named, so constructors must not use
+//! services.  [`Services`] connects to the service manager on first use, either through
+//! `#[entry(services(services))]` or by calling [`Services::get`].
+
+use crate::os::sharedmem::SharedMemoryMapper;
+use crate::ports::srv::Srv;
+use crate::result::Result;
+use crate::sync::OnceCell;
+
+static SERVICES: OnceCell<Services> = OnceCell::new();
+
+/// The session with the service manager, which all services are initialized with.
+///
+/// ```ignore
+/// #[entry(services(log, services))]
+/// fn main(services: &'static Services) {
+///     let hid = Hid::init(services.srv()).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Services {
+    srv: Srv,
+}
+
+impl Services {
+    /// The shared services, connecting to the service manager on the first call.
+    ///
+    /// If connecting fails, the next call tries again.
+    pub fn get() -> Result<&'static Self> {
+        SERVICES.get_or_try_init(|| Ok(Self { srv: Srv::init()? }))
+    }
+
+    /// The services, if [`get`](Self::get) succeeded before.
+    pub fn try_get() -> Option<&'static Self> {
+        SERVICES.get()
+    }
+
+    pub fn srv(&self) -> &Srv {
+        &self.srv
+    }
+
+    /// The mapper placing the shared memory blocks of all services.
+    ///
+    /// This is the process-wide [`SharedMemoryMapper::global`], which is not owned by the
+    /// context.
+    pub fn shared_memory(&self) -> &'static SharedMemoryMapper {
+        SharedMemoryMapper::global()
+    }
+}
@@ -0,0 +1,208 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::result::{FsDescription, KnownDescription, Result, ResultValue};
+use crate::services::fs::{Archive, ArchiveId, File, Fs, OpenFlags};
+use crate::sync::LightMutex;
+use crate::tls;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{format, string::String, vec::Vec};
+
+use log::{Level, Log, Metadata, Record};
+
+const LOG_DIRECTORY: &str = "/ctru-rt";
+
+/// Records are written to the file once this many bytes are buffered.
+const BUFFER_CAPACITY: usize = 0x1000;
+
+/// Writes log records to `sdmc:/ctru-rt/<app>.log`.
+///
+/// Records are buffered, call [`log::logger().flush()`](Log::flush) to write them out.  The
+/// panic handler of the `panic-handler` feature does this before reporting a panic.  Once the log
+/// exceeds its [maximum size](FileLogger::with_max_size), it is moved to `<app>.log.1`, replacing
+/// the previous one.
+pub struct FileLogger {
+    state: LightMutex<State>,
+    /// The thread currently writing, whose records are dropped to not deadlock on the state.
+    writer: AtomicUsize,
+}
+
+struct State {
+    archive: Archive<'static>,
+    path: String,
+    rotated_path: String,
+    file: Option<File>,
+    position: u64,
+    buffer: Vec<u8>,
+    max_size: u64,
+}
+
+impl FileLogger {
+    pub const DEFAULT_MAX_SIZE: u64 = 1 << 20;
+
+    /// Open the log of `app`, appending to it if it exists.
+    pub fn open(fs: &'static Fs, app: &str) -> Result<Self> {
+        let archive = fs.open_archive(ArchiveId::Sdmc)?;
+
+        match archive.create_directory(LOG_DIRECTORY) {
+            Err(e) if e.description() != Ok(KnownDescription::Fs(FsDescription::AlreadyExists)) => {
+                return Err(e)
+            }
+            _ => {}
+        }
+
+        let path = format!("{}/{}.log", LOG_DIRECTORY, app);
+        let rotated_path = format!("{}.1", path);
+
+        let file = archive.open_file(&path, OpenFlags::WRITE | OpenFlags::CREATE)?;
+        let position = file.size()?;
+
+        Ok(Self {
+            state: LightMutex::new(State {
+                archive,
+                path,
+                rotated_path,
+                file: Some(file),
+                position,
+                buffer: Vec::with_capacity(BUFFER_CAPACITY),
+                max_size: Self::DEFAULT_MAX_SIZE,
+            }),
+            writer: AtomicUsize::new(0),
+        })
+    }
+
+    /// Rotate the log once it would grow beyond `max_size` bytes.
+    pub fn with_max_size(self, max_size: u64) -> Self {
+        self.state.lock().max_size = max_size;
+        self
+    }
+
+    fn with_state<F: FnOnce(&mut State)>(&self, f: F) {
+        // Failing requests to the file system log errors themselves
+        let thread = tls::get_thread_local_storage().thread_vars() as usize;
+        if self.writer.load(Ordering::Relaxed) == thread {
+            return;
+        }
+
+        let mut state = self.state.lock();
+        self.writer.store(thread, Ordering::Relaxed);
+        f(&mut state);
+        self.writer.store(0, Ordering::Relaxed);
+    }
+}
+
+impl State {
+    fn open(&self) -> Result<File> {
+        self.archive
+            .open_file(&self.path, OpenFlags::WRITE | OpenFlags::CREATE)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        if self.position > 0 && self.position + self.buffer.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        let file = match &self.file {
+            Some(file) => file,
+            None => self.file.insert(self.open()?),
+        };
+
+        let mut written = 0;
+        while written < self.buffer.len() {
+            match file.write(self.position, &self.buffer[written..], true)? {
+                0 => break,
+                length => {
+                    written += length;
+                    self.position += length as u64;
+                }
+            }
+        }
+
+        self.buffer.clear();
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        self.file = None;
+
+        let _ = self.archive.delete_file(&self.rotated_path);
+        let renamed = self.archive.rename_file(&self.path, &self.rotated_path);
+
+        let file = self.open()?;
+        if renamed.is_err() {
+            // Start over if the full log can not be kept
+            file.set_size(0)?;
+        }
+
+        self.file = Some(file);
+        self.position = 0;
+
+        Ok(())
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Trace
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format!(
+            "[{:<5}] {} - {}\n",
+            record.level(),
+            record.module_path_static().unwrap_or(""),
+            record.args()
+        );
+
+        self.with_state(|state| {
+            state.buffer.extend_from_slice(line.as_bytes());
+            if state.buffer.len() >= BUFFER_CAPACITY {
+                let _ = state.flush();
+            }
+        })
+    }
+
+    fn flush(&self) {
+        self.with_state(|state| {
+            let _ = state.flush();
+        })
+    }
+}
+
+impl Drop for FileLogger {
+    fn drop(&mut self) {
+        let _ = self.state.get_mut().flush();
+    }
+}
+
+/// Log to both loggers, e.g. to a [`FileLogger`] and the [`SvcDebugLog`](super::SvcDebugLog).
+#[derive(Debug)]
+pub struct Tee<A, B>(pub A, pub B);
+
+impl<A: Log, B: Log> Log for Tee<A, B> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata) || self.1.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.0.log(record);
+        self.1.log(record);
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+        self.1.flush();
+    }
+}
@@ -0,0 +1,342 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Debugging with the GDB stub built into Luma3DS.
+//!
+//! While GDB is attached, the stub forwards host I/O ("HIO") requests to it, which gives access to
+//! files on the host and to the GDB console.  Call [`route_debug_output`] to have
+//! [`SvcDebugLog`](super::SvcDebugLog) and the logger print to the GDB console instead of the
+//! kernel's debug output, which GDB does not display.
+
+use crate::svc::{self, UserBreakReason};
+
+use core::fmt;
+use core::ops::BitOr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::ffi::CString;
+
+const HIO_MAGIC: [u8; 4] = *b"GDB\0";
+const HIO_VERSION: u32 = 1;
+
+/// Placed in the result of a request, the stub overwrites it once the request was handled.
+const RESULT_PENDING: i64 = i64::MIN;
+
+const STDOUT: i32 = 1;
+
+/// GDB's `EINTR`, reported when a request was interrupted with Ctrl-C.
+const EINTR: i32 = 4;
+
+/// Layout of a host I/O request as expected by the stub.
+#[repr(C)]
+struct Request {
+    magic: [u8; 4],
+    version: u32,
+    function: [u8; 16 + 1],
+    format: [u8; 8 + 1],
+    parameters: [u64; 8],
+    string_lengths: [u32; 8],
+    result: i64,
+    errno: i32,
+    ctrl_c: bool,
+}
+
+/// A parameter of a host I/O request.
+#[derive(Debug, Clone, Copy)]
+enum Parameter<'a> {
+    Int(i32),
+    UInt(u32),
+    Long(i64),
+    Pointer(*const u8, usize),
+    String(&'a CString),
+}
+
+impl Parameter<'_> {
+    fn format(&self) -> u8 {
+        match self {
+            Self::Int(_) => b'i',
+            Self::UInt(_) => b'I',
+            Self::Long(_) => b'l',
+            Self::Pointer(..) => b'p',
+            Self::String(_) => b's',
+        }
+    }
+}
+
+fn call(function: &str, parameters: &[Parameter]) -> Result<i64, HioError> {
+    let mut request = Request {
+        magic: HIO_MAGIC,
+        version: HIO_VERSION,
+        function: [0; 17],
+        format: [0; 9],
+        parameters: [0; 8],
+        string_lengths: [0; 8],
+        result: RESULT_PENDING,
+        errno: 0,
+        ctrl_c: false,
+    };
+
+    request.function[..function.len()].copy_from_slice(function.as_bytes());
+    for (i, parameter) in parameters.iter().enumerate() {
+        request.format[i] = parameter.format();
+        request.parameters[i] = match *parameter {
+            Parameter::Int(value) => value as u64,
+            Parameter::UInt(value) => value.into(),
+            Parameter::Long(value) => value as u64,
+            Parameter::Pointer(ptr, _) => ptr as u64,
+            Parameter::String(string) => string.as_ptr() as u64,
+        };
+        request.string_lengths[i] = match *parameter {
+            Parameter::Pointer(_, len) => len as u32,
+            // Including the NUL terminator
+            Parameter::String(string) => string.as_bytes_with_nul().len() as u32,
+            _ => 0,
+        };
+    }
+
+    // A message of length 0 is ignored by the kernel, but is handled by the stub if attached.
+    let bytes = unsafe { core::slice::from_raw_parts(&request as *const Request as *const u8, 0) };
+    svc::output_debug_bytes(bytes);
+
+    let request = unsafe { core::ptr::read_volatile(&request) };
+    match request.result {
+        RESULT_PENDING => Err(HioError::NotAttached),
+        _ if request.ctrl_c => Err(HioError::Interrupted),
+        -1 if request.errno == EINTR => Err(HioError::Interrupted),
+        -1 => Err(HioError::Errno(request.errno)),
+        result => Ok(result),
+    }
+}
+
+fn path(path: &str) -> Result<CString, HioError> {
+    CString::new(path).map_err(|_| HioError::InvalidPath)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HioError {
+    /// No debugger is attached through the GDB stub.
+    NotAttached,
+    /// The user pressed Ctrl-C in GDB.
+    Interrupted,
+    /// The path contained a NUL byte.
+    InvalidPath,
+    /// The request failed on the host, with one of the error numbers of GDB's File-I/O protocol.
+    Errno(i32),
+}
+
+impl fmt::Display for HioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotAttached => write!(f, "no debugger attached"),
+            Self::Interrupted => write!(f, "interrupted by the debugger"),
+            Self::InvalidPath => write!(f, "path contains a NUL byte"),
+            Self::Errno(errno) => write!(f, "host I/O failed with errno {}", errno),
+        }
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl core::error::Error for HioError {}
+
+/// Whether GDB is attached through the stub of Luma3DS.
+pub fn is_attached() -> bool {
+    !matches!(
+        call("isatty", &[Parameter::Int(STDOUT)]),
+        Err(HioError::NotAttached)
+    )
+}
+
+/// Stop in the attached debugger.
+///
+/// Does nothing if no debugger is attached, instead of terminating the process like
+/// [`svc::user_break`].
+pub fn breakpoint() {
+    if is_attached() {
+        svc::user_break_resumable(UserBreakReason::User)
+    }
+}
+
+static ROUTE_DEBUG_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Print debug output to the GDB console while it is attached.
+///
+/// Returns whether GDB is attached right now.  Output falls back to the kernel's debug output
+/// whenever GDB is detached.
+pub fn route_debug_output() -> bool {
+    ROUTE_DEBUG_OUTPUT.store(true, Ordering::Relaxed);
+    is_attached()
+}
+
+fn is_routed() -> bool {
+    ROUTE_DEBUG_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Write `bytes` to the GDB console if debug output is routed there.
+pub(crate) fn write_routed(bytes: &[u8]) -> bool {
+    is_routed() && write_all(STDOUT, bytes).is_ok()
+}
+
+fn write(fd: i32, bytes: &[u8]) -> Result<usize, HioError> {
+    let written = call(
+        "write",
+        &[
+            Parameter::Int(fd),
+            Parameter::Pointer(bytes.as_ptr(), bytes.len()),
+            Parameter::UInt(bytes.len() as u32),
+        ],
+    )?;
+    Ok(written as usize)
+}
+
+fn write_all(fd: i32, mut bytes: &[u8]) -> Result<(), HioError> {
+    while !bytes.is_empty() {
+        match write(fd, bytes)? {
+            0 => return Err(HioError::Errno(0)),
+            written => bytes = &bytes[written.min(bytes.len())..],
+        }
+    }
+    Ok(())
+}
+
+/// How to open a [`File`], combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OpenFlags(u32);
+
+impl OpenFlags {
+    pub const READ_ONLY: Self = Self(0x0);
+    pub const WRITE_ONLY: Self = Self(0x1);
+    pub const READ_WRITE: Self = Self(0x2);
+    pub const APPEND: Self = Self(0x8);
+    pub const CREATE: Self = Self(0x200);
+    pub const TRUNCATE: Self = Self(0x400);
+    pub const EXCLUSIVE: Self = Self(0x800);
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(other)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u32),
+    Current(i32),
+    End(i32),
+}
+
+/// A file on the host GDB runs on.
+///
+/// Relative paths are resolved against the working directory of GDB.
+#[derive(Debug)]
+pub struct File {
+    fd: i32,
+}
+
+impl File {
+    /// Open `path` on the host, creating it with mode `0644` if requested.
+    pub fn open(path: &str, flags: OpenFlags) -> Result<Self, HioError> {
+        const MODE: u32 = 0o644;
+
+        let path = self::path(path)?;
+        let fd = call(
+            "open",
+            &[
+                Parameter::String(&path),
+                Parameter::Int(flags.bits() as i32),
+                Parameter::Int(MODE as i32),
+            ],
+        )?;
+        Ok(Self { fd: fd as i32 })
+    }
+
+    /// Create or truncate `path` for writing.
+    pub fn create(path: &str) -> Result<Self, HioError> {
+        Self::open(
+            path,
+            OpenFlags::WRITE_ONLY | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+        )
+    }
+
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, HioError> {
+        let read = call(
+            "read",
+            &[
+                Parameter::Int(self.fd),
+                Parameter::Pointer(buffer.as_mut_ptr(), buffer.len()),
+                Parameter::UInt(buffer.len() as u32),
+            ],
+        )?;
+        Ok(read as usize)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> Result<usize, HioError> {
+        write(self.fd, bytes)
+    }
+
+    pub fn write_all(&mut self, bytes: &[u8]) -> Result<(), HioError> {
+        write_all(self.fd, bytes)
+    }
+
+    /// Move the file position, returning the new position from the start of the file.
+    pub fn seek(&mut self, position: SeekFrom) -> Result<u64, HioError> {
+        const SEEK_SET: i32 = 0;
+        const SEEK_CUR: i32 = 1;
+        const SEEK_END: i32 = 2;
+
+        let (offset, whence) = match position {
+            SeekFrom::Start(offset) => (offset.into(), SEEK_SET),
+            SeekFrom::Current(offset) => (offset.into(), SEEK_CUR),
+            SeekFrom::End(offset) => (offset.into(), SEEK_END),
+        };
+
+        let position = call(
+            "lseek",
+            &[
+                Parameter::Int(self.fd),
+                Parameter::Long(offset),
+                Parameter::Int(whence),
+            ],
+        )?;
+        Ok(position as u64)
+    }
+}
+
+impl fmt::Write for File {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let _ = call("close", &[Parameter::Int(self.fd)]);
+    }
+}
+
+/// Delete the file at `path` on the host.
+pub fn remove(path: &str) -> Result<(), HioError> {
+    let path = self::path(path)?;
+    call("unlink", &[Parameter::String(&path)]).map(drop)
+}
+
+/// Rename the file at `from` to `to` on the host.
+pub fn rename(from: &str, to: &str) -> Result<(), HioError> {
+    let (from, to) = (path(from)?, path(to)?);
+    call(
+        "rename",
+        &[Parameter::String(&from), Parameter::String(&to)],
+    )
+    .map(drop)
+}
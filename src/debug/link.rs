@@ -0,0 +1,80 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Streaming debug output to the host that sent the application with `3dslink`.
+//!
+//! Run `3dslink -s app.3dsx` to keep its console open after sending, then [`connect`] once the
+//! socket service is up.  [`SvcDebugLog`](super::SvcDebugLog) and the logger write to the host
+//! from then on, and fall back to the kernel's debug output if the connection is lost.
+
+use crate::env;
+use crate::services::soc::{Domain, OwnedSocketFd, Protocol, Soc, SocketError, Type};
+use crate::sync::LightMutex;
+
+use core::net::SocketAddrV4;
+
+/// Port the console of `3dslink` listens on.
+pub const LINK_PORT: u16 = 17491;
+
+struct Link {
+    soc: &'static Soc,
+    fd: OwnedSocketFd<'static>,
+}
+
+// Requests to the socket service can be sent from any thread.
+unsafe impl Send for Link {}
+
+static LINK: LightMutex<Option<Link>> = LightMutex::new(None);
+
+/// Connect to the console of `3dslink` on the host.
+///
+/// Returns `false` if the application was not started by `3dslink`.
+pub fn connect(soc: &'static Soc) -> Result<bool, SocketError> {
+    let host = match env::link_host() {
+        Some(host) => host,
+        None => return Ok(false),
+    };
+
+    let fd = soc.socket(Domain::AfInet, Type::Stream, Protocol::Default)?;
+    soc.connect(&fd, SocketAddrV4::new(host, LINK_PORT))?;
+
+    *LINK.lock() = Some(Link { soc, fd });
+
+    Ok(true)
+}
+
+/// Stop writing debug output to the host.
+pub fn disconnect() {
+    LINK.lock().take();
+}
+
+pub fn is_connected() -> bool {
+    LINK.lock().is_some()
+}
+
+/// Send `bytes` to the host if connected, dropping the connection on failure.
+pub(crate) fn write_linked(mut bytes: &[u8]) -> bool {
+    // Do not wait on, or recurse into, a write in progress
+    let mut link = match LINK.try_lock() {
+        Some(link) => link,
+        None => return false,
+    };
+
+    let Link { soc, fd } = match link.as_ref() {
+        Some(link) => link,
+        None => return false,
+    };
+
+    while !bytes.is_empty() {
+        match soc.send(fd, bytes) {
+            Ok(sent) if sent > 0 => bytes = &bytes[sent.min(bytes.len())..],
+            _ => {
+                *link = None;
+                return false;
+            }
+        }
+    }
+
+    true
+}
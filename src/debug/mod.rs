@@ -0,0 +1,305 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod file;
+pub mod gdb;
+pub mod link;
+pub mod profile;
+
+pub use file::{FileLogger, Tee};
+
+use crate::svc::{output_debug_bytes, output_debug_string};
+
+use log::{Level, Log, Metadata, Record};
+
+use alloc::fmt;
+
+/// Write to GDB once [routed](gdb::route_debug_output), or to [`link`] once connected.
+fn write_streams(bytes: &[u8]) -> bool {
+    gdb::write_routed(bytes) || link::write_linked(bytes)
+}
+
+/// Write to a stream, or else to the kernel's debug output.
+fn output(message: &str) {
+    if !write_streams(message.as_bytes()) {
+        output_debug_string(message)
+    }
+}
+
+/// Write a line to a stream, or else as a single message to the kernel's debug output.
+fn output_line(line: &str) {
+    if !write_streams(line.as_bytes()) {
+        output_debug_string(line.strip_suffix('\n').unwrap_or(line))
+    }
+}
+
+#[derive(Default)]
+pub struct SvcDebugLog;
+
+impl SvcDebugLog {}
+
+#[allow(clippy::unit_arg)]
+impl fmt::Write for SvcDebugLog {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        Ok(output(s))
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> fmt::Result {
+        if crate::heap::initialized() {
+            Ok(output(&alloc::fmt::format(args)))
+        } else {
+            write_string_fallback(args)
+        }
+    }
+}
+
+/// Format a string to a fixed size buffer, then write it to the debug log.
+///
+/// This is `#[inline(never)]` so the stack allocation for the fixed size buffer is not necessary
+/// in the presence of a heap.
+#[inline(never)]
+fn write_string_fallback(args: fmt::Arguments) -> fmt::Result {
+    output_debug_string("[INTERNAL] Falling back to heap-less debug write");
+
+    let mut buffer = FixedSizeBufferWriter::<512>::new();
+    fmt::write(&mut buffer, args)?;
+
+    output_debug_bytes(buffer.occupied());
+    Ok(())
+}
+
+#[derive(Debug)]
+#[doc(hidden)]
+pub struct FixedSizeBufferWriter<const N: usize> {
+    buffer: [u8; N],
+    pos: usize,
+}
+
+impl<const N: usize> FixedSizeBufferWriter<N> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            pos: 0,
+        }
+    }
+
+    fn remaining(&mut self) -> &mut [u8] {
+        &mut self.buffer[self.pos..]
+    }
+
+    pub fn occupied(&self) -> &[u8] {
+        &self.buffer[..self.pos]
+    }
+}
+
+impl<const N: usize> fmt::Write for FixedSizeBufferWriter<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let bytes = s.as_bytes();
+        let remaining = self.remaining();
+
+        let printed = &bytes[..bytes.len().min(remaining.len())];
+
+        remaining[..printed.len()].copy_from_slice(printed);
+
+        self.pos += printed.len();
+        self.pos = self.pos.min(N);
+
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! early_debug {
+    ($fmt: expr, $($args: expr,)*) => {
+        #[cfg(debug_assertions)]
+        {
+            let write = || {
+                use alloc::fmt::Write;
+                use $crate::{debug::FixedSizeBufferWriter, svc::output_debug_bytes};
+
+                let mut buffer = FixedSizeBufferWriter::<256>::new();
+
+                let _ = write!(&mut buffer, $fmt, $($args),*);
+
+                output_debug_bytes(buffer.occupied())
+            };
+
+            write()
+        }
+    };
+
+    ($fmt: expr, $($args: expr),*) => {
+        early_debug!($fmt, $($args,)*)
+    };
+
+    ($fmt: expr) => {
+        $crate::svc::output_debug_string($fmt);
+    }
+}
+
+static LOGGER: SvcDebugLog = SvcDebugLog;
+
+impl Log for SvcDebugLog {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Trace
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let level = record.level();
+            let color = match level {
+                Level::Trace => "0",
+                Level::Debug => "37",
+                Level::Info => "32",
+                Level::Warn => "33",
+                Level::Error => "31",
+            };
+            output_line(&alloc::fmt::format(format_args!(
+                "\x1b[0m[\x1b[{};1m{:<5}\x1b[0m] {} - {}\n",
+                color,
+                level,
+                record.module_path_static().unwrap_or(""),
+                record.args(),
+            )))
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(debug_assertions)]
+const LOG_FILTER: log::LevelFilter = log::LevelFilter::Debug;
+#[cfg(not(debug_assertions))]
+const LOG_FILTER: log::LevelFilter = log::LevelFilter::Info;
+
+/// Log to the [`SvcDebugLog`].
+pub fn init_log() -> Result<(), log::SetLoggerError> {
+    init_log_with(&LOGGER)
+}
+
+/// Log to `logger`, e.g. a [`FileLogger`] leaked with [`Box::leak`](alloc::boxed::Box::leak).
+pub fn init_log_with(logger: &'static dyn Log) -> Result<(), log::SetLoggerError> {
+    log::set_logger(logger).map(|()| log::set_max_level(LOG_FILTER))
+}
+
+/// Maximum number of frames recorded by [`backtrace`].
+const BACKTRACE_DEPTH: usize = 32;
+
+/// Return addresses on the call stack, innermost first.
+///
+/// Printing it lists the addresses with their offsets from the image base.  Look them up with
+/// `addr2line -e <app.elf>`.
+#[derive(Clone)]
+pub struct Backtrace {
+    addresses: [usize; BACKTRACE_DEPTH],
+    len: usize,
+}
+
+impl Backtrace {
+    pub fn addresses(&self) -> &[usize] {
+        &self.addresses[..self.len]
+    }
+}
+
+fn image_base() -> usize {
+    extern "C" {
+        static __start__: u8;
+    }
+
+    core::ptr::addr_of!(__start__) as usize
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let base = image_base();
+        writeln!(f, "stack backtrace (image base {:#010x}):", base)?;
+        for (index, &address) in self.addresses().iter().enumerate() {
+            writeln!(
+                f,
+                "{:4}: {:#010x} (+{:#x})",
+                index,
+                address,
+                address.wrapping_sub(base)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.addresses()).finish()
+    }
+}
+
+/// Record the call stack of the caller.
+///
+/// With the `unwind` feature, this uses the unwind tables.  Otherwise frame pointers are followed,
+/// which requires building with `-C force-frame-pointers=yes`.
+#[inline(never)]
+pub fn backtrace() -> Backtrace {
+    let mut backtrace = Backtrace {
+        addresses: [0; BACKTRACE_DEPTH],
+        len: 0,
+    };
+
+    // The first address returns into this function
+    let mut skip = 1;
+    walk_stack(|address| {
+        if skip > 0 {
+            skip -= 1;
+            return true;
+        }
+
+        backtrace.addresses[backtrace.len] = address;
+        backtrace.len += 1;
+        backtrace.len < BACKTRACE_DEPTH
+    });
+
+    backtrace
+}
+
+/// Report the return address of every frame, starting with the one into the caller.
+#[cfg(feature = "unwind")]
+#[inline(never)]
+fn walk_stack(mut record: impl FnMut(usize) -> bool) {
+    // The first address returns into this function
+    let mut skip = true;
+    crate::unwind::ehabi::trace(|address| core::mem::take(&mut skip) || record(address as usize))
+}
+
+/// Follow the chain of frame records, each holding the caller's frame pointer and return address.
+#[cfg(all(not(feature = "unwind"), target_arch = "arm"))]
+#[inline(never)]
+fn walk_stack(mut record: impl FnMut(usize) -> bool) {
+    use crate::os::mem::MemoryPermission;
+
+    let mut frame: usize;
+    unsafe { core::arch::asm!("mov {}, r11", out(reg) frame) };
+
+    // Stay within the memory block of the stack, so a broken chain can not fault.
+    let stack = match unsafe { crate::svc::query_memory(frame) } {
+        Ok(info) if info.permission.contains(MemoryPermission::RW) => {
+            info.base_process_virtual_address..info.base_process_virtual_address + info.size
+        }
+        _ => return,
+    };
+
+    while frame & 0x3 == 0 && stack.contains(&frame) && stack.contains(&(frame + 4)) {
+        let (caller_frame, address) =
+            unsafe { (*(frame as *const usize), *((frame + 4) as *const usize)) };
+
+        if address == 0 || !record(address) || caller_frame <= frame {
+            break;
+        }
+        frame = caller_frame;
+    }
+}
+
+/// There are no frames to report off the 3DS.
+#[cfg(all(not(feature = "unwind"), not(target_arch = "arm")))]
+fn walk_stack(_record: impl FnMut(usize) -> bool) {}
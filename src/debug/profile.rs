@@ -0,0 +1,322 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Measuring time spent in scopes, and counting events.
+//!
+//! ```ignore
+//! fn draw_frame() {
+//!     ctru_rt::scope!("draw_frame");
+//!     ctru_rt::count!("frames");
+//!     // ...
+//! }
+//!
+//! // Once per frame, or every few seconds:
+//! log::info!("{}", profile::report());
+//! profile::reset();
+//! ```
+//!
+//! Scopes and counters show up in the [`report`] after they were first entered.
+
+use crate::os::{duration_from_ticks, SystemTick};
+use crate::sync::LightMutex;
+
+use core::fmt;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering};
+use core::time::Duration;
+
+/// Statistics of the time spent in a [`Scope`], in [`SystemTick`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub calls: u32,
+    pub total: u64,
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Stats {
+    const EMPTY: Self = Self {
+        calls: 0,
+        total: 0,
+        min: u64::MAX,
+        max: 0,
+    };
+
+    fn record(&mut self, ticks: u64) {
+        self.calls = self.calls.saturating_add(1);
+        self.total = self.total.saturating_add(ticks);
+        self.min = self.min.min(ticks);
+        self.max = self.max.max(ticks);
+    }
+
+    pub fn total_duration(&self) -> Duration {
+        duration_from_ticks(self.total)
+    }
+
+    pub fn mean_duration(&self) -> Duration {
+        match self.calls {
+            0 => Duration::ZERO,
+            calls => duration_from_ticks(self.total / u64::from(calls)),
+        }
+    }
+
+    pub fn min_duration(&self) -> Duration {
+        match self.calls {
+            0 => Duration::ZERO,
+            _ => duration_from_ticks(self.min),
+        }
+    }
+
+    pub fn max_duration(&self) -> Duration {
+        duration_from_ticks(self.max)
+    }
+}
+
+/// An intrusive list of everything that was used at least once.
+struct Registry<T: 'static> {
+    head: AtomicPtr<T>,
+}
+
+trait Entry: Sized + Sync + 'static {
+    fn registered(&self) -> &AtomicBool;
+    fn next(&self) -> &AtomicPtr<Self>;
+}
+
+impl<T: Entry> Registry<T> {
+    const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    fn register(&self, entry: &'static T) {
+        if entry.registered().swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let entry_ptr = entry as *const T as *mut T;
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            entry.next().store(head, Ordering::Relaxed);
+            match self.head.compare_exchange_weak(
+                head,
+                entry_ptr,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &'static T> {
+        let head = NonNull::new(self.head.load(Ordering::Acquire));
+        core::iter::successors(head, |entry| {
+            NonNull::new(unsafe { entry.as_ref() }.next().load(Ordering::Acquire))
+        })
+        .map(|entry| unsafe { &*entry.as_ptr() })
+    }
+}
+
+static SCOPES: Registry<Scope> = Registry::new();
+static COUNTERS: Registry<Counter> = Registry::new();
+
+/// A named section of code whose run time is measured, see [`scope!`](crate::scope).
+pub struct Scope {
+    name: &'static str,
+    stats: LightMutex<Stats>,
+    registered: AtomicBool,
+    next: AtomicPtr<Scope>,
+}
+
+impl Entry for Scope {
+    fn registered(&self) -> &AtomicBool {
+        &self.registered
+    }
+
+    fn next(&self) -> &AtomicPtr<Self> {
+        &self.next
+    }
+}
+
+impl Scope {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            stats: LightMutex::new(Stats::EMPTY),
+            registered: AtomicBool::new(false),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Start measuring until the returned guard is dropped.
+    pub fn enter(&'static self) -> ScopeGuard {
+        ScopeGuard {
+            scope: self,
+            start: SystemTick::now(),
+        }
+    }
+
+    /// Add a measurement of `ticks`.
+    pub fn record(&'static self, ticks: u64) {
+        SCOPES.register(self);
+        self.stats.lock().record(ticks)
+    }
+
+    pub fn stats(&self) -> Stats {
+        *self.stats.lock()
+    }
+
+    pub fn reset(&self) {
+        *self.stats.lock() = Stats::EMPTY
+    }
+}
+
+/// Measures the time until it is dropped, see [`Scope::enter`].
+#[must_use = "the scope ends when the guard is dropped"]
+pub struct ScopeGuard {
+    scope: &'static Scope,
+    start: SystemTick,
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let ticks = SystemTick::now().count().saturating_sub(self.start.count());
+        self.scope.record(ticks)
+    }
+}
+
+/// A named count of events, see [`count!`](crate::count).
+pub struct Counter {
+    name: &'static str,
+    count: AtomicU32,
+    registered: AtomicBool,
+    next: AtomicPtr<Counter>,
+}
+
+impl Entry for Counter {
+    fn registered(&self) -> &AtomicBool {
+        &self.registered
+    }
+
+    fn next(&self) -> &AtomicPtr<Self> {
+        &self.next
+    }
+}
+
+impl Counter {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            count: AtomicU32::new(0),
+            registered: AtomicBool::new(false),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn add(&'static self, count: u32) {
+        COUNTERS.register(self);
+        self.count.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed)
+    }
+}
+
+/// All scopes and counters used so far.
+pub fn scopes() -> impl Iterator<Item = &'static Scope> {
+    SCOPES.iter()
+}
+
+pub fn counters() -> impl Iterator<Item = &'static Counter> {
+    COUNTERS.iter()
+}
+
+/// Clear the statistics of all scopes and counters, e.g. at the start of each frame.
+pub fn reset() {
+    scopes().for_each(Scope::reset);
+    counters().for_each(Counter::reset);
+}
+
+/// A table of all scopes and counters, printed with `{}`.
+pub fn report() -> Report {
+    Report
+}
+
+#[derive(Debug)]
+pub struct Report;
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<24} {:>8} {:>10} {:>10} {:>10} {:>10}",
+            "scope", "calls", "total us", "mean us", "min us", "max us"
+        )?;
+        for scope in scopes() {
+            let stats = scope.stats();
+            writeln!(
+                f,
+                "{:<24} {:>8} {:>10} {:>10} {:>10} {:>10}",
+                scope.name(),
+                stats.calls,
+                stats.total_duration().as_micros(),
+                stats.mean_duration().as_micros(),
+                stats.min_duration().as_micros(),
+                stats.max_duration().as_micros(),
+            )?;
+        }
+
+        for counter in counters() {
+            writeln!(f, "{:<24} {:>8}", counter.name(), counter.count())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Measure the time until the end of the enclosing block.
+///
+/// ```ignore
+/// fn update() {
+///     ctru_rt::scope!("update");
+///     // ...
+/// }
+/// ```
+#[macro_export]
+macro_rules! scope {
+    ($name: expr) => {
+        let _scope_guard = {
+            static SCOPE: $crate::debug::profile::Scope = $crate::debug::profile::Scope::new($name);
+            SCOPE.enter()
+        };
+    };
+}
+
+/// Count an event, or add a number of events.
+#[macro_export]
+macro_rules! count {
+    ($name: expr) => {
+        $crate::count!($name, 1)
+    };
+    ($name: expr, $count: expr) => {{
+        static COUNTER: $crate::debug::profile::Counter =
+            $crate::debug::profile::Counter::new($name);
+        COUNTER.add($count)
+    }};
+}
@@ -2,26 +2,131 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::os::{BorrowedHandle, RawHandle};
+use crate::svc;
+
+use core::net::Ipv4Addr;
+use core::ops::BitOr;
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+
+#[cfg(target_arch = "arm")]
 extern "C" {
     static __apt_appid: u32;
     static __heap_size: u32;
     static __service_ptr: *const u8;
     static __system_arglist: *const u8;
     static __system_runflags: u32;
+    static __main_thread_priority: i32;
+    static __main_thread_stack_size: u32;
+}
+
+/// Off the 3DS, no loader fills in the header, so it reads as if the application was started
+/// without one.
+#[cfg(not(target_arch = "arm"))]
+#[allow(non_upper_case_globals)]
+mod header {
+    pub static mut __apt_appid: u32 = 0;
+    pub static mut __heap_size: u32 = 0;
+    pub static mut __service_ptr: *const u8 = core::ptr::null();
+    pub static mut __system_arglist: *const u8 = core::ptr::null();
+    pub static mut __system_runflags: u32 = 0;
+    pub static mut __main_thread_priority: i32 = -1;
+    pub static mut __main_thread_stack_size: u32 = 0;
 }
 
+#[cfg(not(target_arch = "arm"))]
+use header::*;
+
 pub fn is_homebrew() -> bool {
     unsafe { !__service_ptr.is_null() }
 }
 
+/// Entry of the list of services the homebrew loader acquired for this application.
+#[repr(C)]
+struct ServiceOverride {
+    name: [u8; 8],
+    handle: RawHandle,
+}
+
+/// A handle to `service_name` acquired by the homebrew loader.
+///
+/// The loader passes handles to services the application itself may not be allowed to access,
+/// like `cfg:i`.
+pub(crate) fn service_override(service_name: &str) -> Option<BorrowedHandle<'static>> {
+    if !is_homebrew() {
+        return None;
+    }
+
+    let overrides = unsafe {
+        let list = __service_ptr as *const u32;
+        let length = list.read() as usize;
+        core::slice::from_raw_parts(list.add(1) as *const ServiceOverride, length)
+    };
+
+    // Names are at most 8 bytes long, and NUL-terminated if shorter
+    let name = &service_name.as_bytes()[..service_name.len().min(8)];
+    overrides
+        .iter()
+        .find(|entry| {
+            let length = entry.name.iter().position(|&b| b == 0).unwrap_or(8);
+            entry.name[..length] == *name
+        })
+        .map(|entry| BorrowedHandle::new(entry.handle))
+}
+
 pub fn app_id() -> u32 {
     unsafe { __apt_appid }
 }
 
-pub fn system_runflags() -> u32 {
-    unsafe { __system_runflags }
+/// How the homebrew loader expects the application to behave, combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RunFlags(u32);
+
+impl RunFlags {
+    /// The loader can not provide a working APT session, e.g. when running under a
+    /// ROP-based exploit.  The application must neither wait for APT nor close itself on exit.
+    pub const APT_WORKAROUND: Self = Self(1 << 0);
+    /// The loader reuses the APT registration of this application, so instead of closing it,
+    /// the application only unregisters from APT on exit.
+    pub const APT_REINIT: Self = Self(1 << 1);
+    /// The loader is restarted by restarting the current title, so the application does that on
+    /// exit unless it chainloads something else.
+    pub const APT_CHAINLOAD: Self = Self(1 << 2);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for RunFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(other)
+    }
+}
+
+/// The run flags passed by the homebrew loader, empty when not launched from one.
+pub fn system_runflags() -> RunFlags {
+    RunFlags(unsafe { __system_runflags })
 }
 
+/// The raw arguments passed by the homebrew loader, see [`args`] for decoded ones.
+#[derive(Debug, Clone)]
 pub struct SystemArgList {
     length: usize,
     arguments: *const u8,
@@ -40,10 +145,12 @@ impl core::iter::Iterator for SystemArgList {
                         i += 1
                     }
 
+                    let argument = core::slice::from_raw_parts(self.arguments, i as usize);
+
                     self.arguments = self.arguments.offset(i + 1);
                     self.length -= 1;
 
-                    core::slice::from_raw_parts(self.arguments, i as usize)
+                    argument
                 };
 
                 Some(slice)
@@ -60,11 +167,147 @@ impl core::iter::ExactSizeIterator for SystemArgList {}
 
 pub fn system_arglist() -> SystemArgList {
     let length_ptr = unsafe { __system_arglist } as *const u32;
+
+    // Not set when launched without a homebrew loader
+    if length_ptr.is_null() {
+        return SystemArgList {
+            length: 0,
+            arguments: core::ptr::null(),
+        };
+    }
+
     let (length, arguments) = unsafe { (*length_ptr as usize, length_ptr.offset(1) as *const u8) };
 
     SystemArgList { length, arguments }
 }
 
+/// The arguments the application was started with, see [`args`].
+#[derive(Debug, Clone)]
+pub struct Args {
+    arguments: SystemArgList,
+}
+
+impl Args {
+    /// The argument at `index`, where index 0 is the path of the executable.
+    pub fn get(&self, index: usize) -> Option<Cow<'static, str>> {
+        self.clone().nth(index)
+    }
+}
+
+impl Iterator for Args {
+    type Item = Cow<'static, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.arguments.next().map(String::from_utf8_lossy)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.arguments.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Args {}
+
+/// The arguments the application was started with, decoded as UTF-8.
+///
+/// Like on other platforms, the homebrew loader passes the path of the executable (e.g.
+/// `sdmc:/3ds/app.3dsx`) as the first argument.  Invalid UTF-8 is replaced with `U+FFFD`.
+pub fn args() -> Args {
+    Args {
+        arguments: system_arglist(),
+    }
+}
+
+/// The path the executable was loaded from, if the loader passed it.
+pub fn executable_path() -> Option<Cow<'static, str>> {
+    args().next()
+}
+
 pub fn heap_size() -> usize {
     unsafe { __heap_size as usize }
 }
+
+/// The main thread's priority linked into the executable's header, if any.
+///
+/// Loaders do not patch this word, so it only changes when linking with a different default.
+pub(crate) fn requested_main_thread_priority() -> Option<i32> {
+    let priority = unsafe { __main_thread_priority };
+    (priority >= 0).then_some(priority)
+}
+
+/// The size of the main thread's stack linked into the executable's header, 0 for the loader's.
+///
+/// Loaders do not patch this word, so it only changes when linking with a different default.
+pub(crate) fn requested_main_thread_stack_size() -> usize {
+    unsafe { __main_thread_stack_size as usize }
+}
+
+/// The priority the main thread runs at.
+///
+/// On startup, the main thread switches to the priority set with
+/// `#[entry(main_thread_priority = ...)]`, or linked into the executable's header.  Without
+/// either, or if the kernel rejects it, the main thread keeps the priority the loader started it
+/// with.
+pub fn main_thread_priority() -> i32 {
+    crate::thread::main_thread_priority()
+}
+
+/// Address of the host that sent this application with `3dslink`.
+///
+/// The netloader passes `3dslink:/<name>.3dsx` as the first argument and stores the address of
+/// the host directly after the argument list.
+pub fn link_host() -> Option<Ipv4Addr> {
+    const LINK_PREFIX: &[u8] = b"3dslink:/";
+
+    let mut arguments = system_arglist();
+    let first = arguments.next()?;
+    if !first.starts_with(LINK_PREFIX) {
+        return None;
+    }
+
+    let last = arguments.last().unwrap_or(first);
+
+    // Skip the NUL terminator of the last argument
+    let address = unsafe { (last.as_ptr_range().end.add(1) as *const [u8; 4]).read_unaligned() };
+    Some(Ipv4Addr::from(address))
+}
+
+/// An emulator this application runs in, see [`emulator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Emulator {
+    Citra,
+    Panda3ds,
+}
+
+/// The emulator this application runs in, or `None` on hardware.
+///
+/// Emulators are detected through the "Citra information" extension of `svcGetSystemInfo`,
+/// which Panda3DS implements as well.  Emulators without it are taken for hardware.
+pub fn emulator() -> Option<Emulator> {
+    const CITRA_INFORMATION: u32 = 0x20000;
+    const IS_CITRA: i32 = 0;
+    const BUILD_NAME: i32 = 10;
+
+    // The kernel rejects unknown system info types
+    let is_citra = unsafe { svc::get_system_info(CITRA_INFORMATION, IS_CITRA) };
+    if is_citra != Ok(1) {
+        return None;
+    }
+
+    // The first eight bytes of the name of the build
+    let build_name = unsafe { svc::get_system_info(CITRA_INFORMATION, BUILD_NAME) }
+        .unwrap_or(0)
+        .to_le_bytes();
+
+    if build_name.starts_with(b"Panda") {
+        Some(Emulator::Panda3ds)
+    } else {
+        Some(Emulator::Citra)
+    }
+}
+
+/// Whether this application runs in an emulator, to skip hardware-only initialization.
+pub fn is_emulated() -> bool {
+    emulator().is_some()
+}
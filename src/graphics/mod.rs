@@ -2,16 +2,28 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use core::alloc::Allocator;
 use core::ptr::NonNull;
 
 use crate::result::{ErrorCode, Result};
-use crate::services::gsp::gpu::{FramebufferIndex, Gpu, InterruptEvent, Screen, ScreenDimensions};
+use crate::services::gsp::gpu::{
+    ForceBlank, FramebufferIndex, Gpu, InterruptEvent, Screen, ScreenDimensions,
+};
+use crate::services::gsp::gx::{FillBuffer, FillWidth, TransferFormat};
+use crate::sync::{LightMutex, LightMutexGuard};
 
 use ctru_rt_macros::EnumCast;
 
 use alloc::alloc::Layout;
+use alloc::sync::Arc;
 use log::{debug, info};
 
+mod pacer;
+pub mod vram;
+
+pub use pacer::{FramePacer, FrameStats, TargetFrameRate};
+pub use vram::VramAllocator;
+
 #[derive(Debug, EnumCast, Clone, Copy, PartialEq, Eq)]
 #[enum_cast(value_type = "u8")]
 pub enum FramebufferColorFormat {
@@ -23,7 +35,7 @@ pub enum FramebufferColorFormat {
 }
 
 impl FramebufferColorFormat {
-    pub(crate) const fn bytes_per_pixel(&self) -> usize {
+    pub const fn bytes_per_pixel(&self) -> usize {
         match self {
             Self::RGBA8 => 4,
             Self::BGR8 => 3,
@@ -32,6 +44,62 @@ impl FramebufferColorFormat {
     }
 }
 
+impl From<FramebufferColorFormat> for TransferFormat {
+    fn from(format: FramebufferColorFormat) -> Self {
+        match format {
+            FramebufferColorFormat::RGBA8 => Self::RGBA8,
+            FramebufferColorFormat::BGR8 => Self::RGB8,
+            FramebufferColorFormat::RGB565 => Self::RGB565,
+            FramebufferColorFormat::RGB5A1 => Self::RGB5A1,
+            FramebufferColorFormat::RGBA4 => Self::RGBA4,
+        }
+    }
+}
+
+/// A color, converted to the framebuffer's [`FramebufferColorFormat`] when drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Pixel {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Pixel {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::rgba(r, g, b, 0xff)
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Write this pixel to `out` in the memory layout of `format`.
+    ///
+    /// `out` must be exactly [`FramebufferColorFormat::bytes_per_pixel`] bytes long.
+    pub fn encode(self, format: FramebufferColorFormat, out: &mut [u8]) {
+        let Self { r, g, b, a } = self;
+        let (r16, g16, b16, a16) = (u16::from(r), u16::from(g), u16::from(b), u16::from(a));
+
+        match format {
+            FramebufferColorFormat::RGBA8 => out.copy_from_slice(&[a, b, g, r]),
+            FramebufferColorFormat::BGR8 => out.copy_from_slice(&[b, g, r]),
+            FramebufferColorFormat::RGB565 => {
+                let packed = (r16 >> 3) << 11 | (g16 >> 2) << 5 | (b16 >> 3);
+                out.copy_from_slice(&packed.to_le_bytes())
+            }
+            FramebufferColorFormat::RGB5A1 => {
+                let packed = (r16 >> 3) << 11 | (g16 >> 3) << 6 | (b16 >> 3) << 1 | (a16 >> 7);
+                out.copy_from_slice(&packed.to_le_bytes())
+            }
+            FramebufferColorFormat::RGBA4 => {
+                let packed = (r16 >> 4) << 12 | (g16 >> 4) << 8 | (b16 >> 4) << 4 | (a16 >> 4);
+                out.copy_from_slice(&packed.to_le_bytes())
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 struct FramebufferFormat(u16);
 
@@ -55,7 +123,10 @@ impl Framebuffer {
             * usize::from(dimensions.height)
             * format.bytes_per_pixel();
         debug!("Allocating new framebuffer (size = {:#0x})", size);
-        let buffer = vram::ALLOCATOR.allocate(Self::layout_for_size(size))?;
+        let buffer = VramAllocator::any()
+            .allocate(Self::layout_for_size(size))
+            .map_err(drop)?
+            .cast();
 
         debug!("New framebuffer: {:p}", buffer);
 
@@ -74,12 +145,23 @@ impl Framebuffer {
             None => core::ptr::null(),
         }
     }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        match self.buffer {
+            // SAFETY: `buffer` points to `size` bytes of VRAM that we exclusively own.
+            Some(buffer) => unsafe { core::slice::from_raw_parts_mut(buffer.as_ptr(), self.size) },
+            None => &mut [],
+        }
+    }
 }
 
+// SAFETY: the framebuffer exclusively owns its VRAM allocation.
+unsafe impl Send for Framebuffer {}
+
 impl Drop for Framebuffer {
     fn drop(&mut self) {
         if let Some(buffer) = self.buffer.take() {
-            unsafe { vram::ALLOCATOR.deallocate(buffer, Self::layout_for_size(self.size)) }
+            unsafe { VramAllocator::any().deallocate(buffer, Self::layout_for_size(self.size)) }
         }
     }
 }
@@ -124,34 +206,181 @@ impl ScreenConfiguration {
         mode
     }
 
+    fn framebuffer_mut(&mut self, index: FramebufferIndex) -> &mut Framebuffer {
+        match index {
+            FramebufferIndex::First => &mut self.fb0,
+            FramebufferIndex::Second => &mut self.fb1,
+        }
+    }
+
+    fn framebuffer(&self, index: FramebufferIndex) -> &Framebuffer {
+        match index {
+            FramebufferIndex::First => &self.fb0,
+            FramebufferIndex::Second => &self.fb1,
+        }
+    }
+
     fn present_buffer(&self, screen: Screen, gpu: &mut Gpu) {
+        let displayed = self.framebuffer(self.active_fb).as_ptr();
+
         gpu.present_buffer(
             screen,
             self.active_fb,
-            self.fb0.as_ptr(),
-            self.fb0.as_ptr(), // not a typo, only 2D mode for now
+            displayed,
+            displayed, // not a typo, only 2D mode for now
             self.stride(),
             self.mode(screen),
         )
     }
+
+    fn back_buffer_mut(&mut self) -> &mut [u8] {
+        self.framebuffer_mut(!self.active_fb).as_mut_slice()
+    }
+
+    fn swap_buffers(&mut self, screen: Screen, gpu: &mut Gpu) -> Result<&mut [u8]> {
+        let back_buffer = self.framebuffer(!self.active_fb);
+        gpu.flush_data_cache(back_buffer.as_ptr(), back_buffer.size)?;
+
+        self.active_fb = !self.active_fb;
+        self.present_buffer(screen, gpu);
+
+        Ok(self.back_buffer_mut())
+    }
+}
+
+/// Exclusive access to one of the screens.
+///
+/// Handles own their framebuffers and share the [`Gpu`] with each other, so the screens can be
+/// drawn to and presented independently, e.g. from different threads.
+#[derive(Debug)]
+pub struct ScreenHandle {
+    gpu: Arc<LightMutex<Gpu>>,
+    screen: Screen,
+    config: ScreenConfiguration,
+}
+
+impl ScreenHandle {
+    pub fn screen(&self) -> Screen {
+        self.screen
+    }
+
+    pub fn format(&self) -> FramebufferColorFormat {
+        self.config.format
+    }
+
+    /// Width of the screen in pixels, as seen by the user holding the console.
+    pub fn width(&self) -> usize {
+        usize::from(self.config.dimensions.height)
+    }
+
+    /// Height of the screen in pixels, as seen by the user holding the console.
+    pub fn height(&self) -> usize {
+        usize::from(self.config.dimensions.width)
+    }
+
+    /// Lock the GPU shared by all screens.
+    pub fn gpu(&self) -> LightMutexGuard<'_, Gpu> {
+        self.gpu.lock()
+    }
+
+    /// Wait for the next VBlank of this screen.
+    pub fn wait_vblank(&self) -> Result<()> {
+        let mut gpu = self.gpu();
+        match self.screen {
+            Screen::Top => gpu.wait_vblank_top(),
+            Screen::Bottom => gpu.wait_vblank_bottom(),
+        }
+    }
+
+    /// The buffer that is drawn to while the other one is displayed.
+    ///
+    /// The screens are mounted sideways, so pixels are stored column by column, starting at the
+    /// bottom left corner.  Use [`set_pixel`](Self::set_pixel) to draw in screen coordinates.
+    pub fn back_buffer_mut(&mut self) -> &mut [u8] {
+        self.config.back_buffer_mut()
+    }
+
+    /// Draw `pixel` to the back buffer, with `(0, 0)` in the top left corner of the screen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `(x, y)` is outside of the screen.
+    pub fn set_pixel(&mut self, x: usize, y: usize, pixel: Pixel) {
+        let (width, height) = (self.width(), self.height());
+        assert!(
+            x < width && y < height,
+            "Pixel ({}, {}) is outside of the {}x{} screen",
+            x,
+            y,
+            width,
+            height
+        );
+
+        let format = self.format();
+        let bytes_per_pixel = format.bytes_per_pixel();
+        let offset = (x * height + (height - 1 - y)) * bytes_per_pixel;
+
+        pixel.encode(
+            format,
+            &mut self.back_buffer_mut()[offset..offset + bytes_per_pixel],
+        )
+    }
+
+    /// Fill the whole back buffer with `pixel` using the GPU.
+    pub fn clear(&mut self, pixel: Pixel) -> Result<()> {
+        let format = self.format();
+
+        let mut encoded = [0u8; 4];
+        pixel.encode(format, &mut encoded[..format.bytes_per_pixel()]);
+        let value = u32::from_le_bytes(encoded);
+
+        let width = match format.bytes_per_pixel() {
+            4 => FillWidth::Bits32,
+            3 => FillWidth::Bits24,
+            _ => FillWidth::Bits16,
+        };
+
+        let buffer = self.config.back_buffer_mut();
+        self.gpu
+            .lock()
+            .memory_fill(Some(FillBuffer::new(buffer, value, width)), None)
+    }
+
+    /// Fill the whole back buffer with `pixel`.
+    pub fn fill(&mut self, pixel: Pixel) {
+        let format = self.format();
+
+        self.back_buffer_mut()
+            .chunks_exact_mut(format.bytes_per_pixel())
+            .for_each(|out| pixel.encode(format, out))
+    }
+
+    /// Display the back buffer, and return the new back buffer for drawing the next frame.
+    ///
+    /// The back buffer is flushed from the data cache before it is displayed.  The new back buffer
+    /// still contains the frame before the one that is now displayed.
+    pub fn swap_buffers(&mut self) -> Result<&mut [u8]> {
+        let mut gpu = self.gpu.lock();
+        self.config.swap_buffers(self.screen, &mut gpu)
+    }
 }
 
 #[derive(Debug)]
-pub struct Grapics<'g> {
-    gpu: &'g mut Gpu,
+pub struct Grapics {
+    gpu: Arc<LightMutex<Gpu>>,
     stereoscopic: bool,
-    top: ScreenConfiguration,
-    bottom: ScreenConfiguration,
+    top: ScreenHandle,
+    bottom: ScreenHandle,
 }
 
-impl<'g> Grapics<'g> {
-    pub fn init_default(gpu: &'g mut Gpu) -> Result<Self> {
+impl Grapics {
+    pub fn init_default(gpu: Gpu) -> Result<Self> {
         use FramebufferColorFormat::BGR8;
         Self::init(gpu, BGR8, BGR8)
     }
 
     pub fn init(
-        gpu: &'g mut Gpu,
+        mut gpu: Gpu,
         format_top: FramebufferColorFormat,
         format_bottom: FramebufferColorFormat,
     ) -> Result<Self> {
@@ -171,67 +400,79 @@ impl<'g> Grapics<'g> {
         let bottom = ScreenConfiguration::new(Bottom.dimensions(), format_bottom)
             .map_err(|_| ERR_SCREEN_ALLOC)?;
 
-        top.present_buffer(Top, gpu);
-        bottom.present_buffer(Bottom, gpu);
+        top.present_buffer(Top, &mut gpu);
+        bottom.present_buffer(Bottom, &mut gpu);
 
         while !gpu.next_event()?.contains(InterruptEvent::VBlank0) {}
 
         info!("Turning on LCD...");
-        gpu.set_lcd_force_blank(0x00)?;
+        gpu.set_lcd_force_blank(ForceBlank::none())?;
+
+        let gpu = Arc::new(LightMutex::new(gpu));
 
         Ok(Self {
+            top: ScreenHandle {
+                gpu: gpu.clone(),
+                screen: Top,
+                config: top,
+            },
+            bottom: ScreenHandle {
+                gpu: gpu.clone(),
+                screen: Bottom,
+                config: bottom,
+            },
             gpu,
             stereoscopic: false,
-            top,
-            bottom,
         })
     }
 
-    pub fn gpu(&'g mut self) -> &'g mut Gpu {
-        &mut self.gpu
+    pub fn top(&mut self) -> &mut ScreenHandle {
+        &mut self.top
     }
 
-    pub fn wait_vblank0(&mut self) -> Result<()> {
-        while !self.gpu.next_event()?.contains(InterruptEvent::VBlank0) {}
-
-        Ok(())
+    pub fn bottom(&mut self) -> &mut ScreenHandle {
+        &mut self.bottom
     }
-}
 
-pub(crate) mod vram {
-    use core::ptr::NonNull;
-
-    use alloc::alloc::Layout;
-
-    use linked_list_allocator::LockedHeap;
+    /// Borrow both screens at once, as `(top, bottom)`.
+    pub fn screens_mut(&mut self) -> (&mut ScreenHandle, &mut ScreenHandle) {
+        (&mut self.top, &mut self.bottom)
+    }
 
-    pub(crate) struct VramAllocator {
-        inner: LockedHeap,
+    /// Split into handles for the top and bottom screen, e.g. to move them to different threads.
+    pub fn into_screens(self) -> (ScreenHandle, ScreenHandle) {
+        (self.top, self.bottom)
     }
 
-    pub(crate) static ALLOCATOR: VramAllocator = VramAllocator {
-        inner: LockedHeap::empty(),
-    };
+    /// Lock the GPU shared by all screens.
+    pub fn gpu(&self) -> LightMutexGuard<'_, Gpu> {
+        self.gpu.lock()
+    }
 
-    pub(crate) fn init() {
-        ALLOCATOR.init()
+    /// Save VRAM and release the GPU before the system enters sleep mode.
+    ///
+    /// See [`Gpu::prepare_for_sleep`].
+    pub fn prepare_for_sleep(&self) -> Result<()> {
+        self.gpu().prepare_for_sleep()
     }
 
-    impl VramAllocator {
-        pub(crate) fn init(&self) {
-            const VRAM_START: usize = 0x1F00_0000;
-            const VRAM_SIZE: usize = 0x60_0000;
-            unsafe { self.inner.lock().init(VRAM_START, VRAM_SIZE) }
-        }
+    /// Restore the GPU after sleep mode, and present the screens' framebuffers again.
+    pub fn resume_after_sleep(&self) -> Result<()> {
+        let mut gpu = self.gpu();
+        gpu.resume_after_sleep()?;
 
-        pub fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, ()> {
-            let layout = layout.align_to(16).map_err(drop)?;
+        self.top.config.present_buffer(Screen::Top, &mut gpu);
+        self.bottom.config.present_buffer(Screen::Bottom, &mut gpu);
 
-            self.inner.lock().allocate_first_fit(layout)
-        }
+        Ok(())
+    }
 
-        pub unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
-            self.inner.lock().deallocate(ptr, layout)
-        }
+    pub fn wait_vblank0(&self) -> Result<()> {
+        self.gpu().wait_vblank_top()
     }
 }
+
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<ScreenHandle>();
+};
@@ -0,0 +1,125 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use core::time::Duration;
+
+use crate::os::{duration_from_ticks, SystemTick};
+use crate::result::Result;
+use crate::services::gsp::gpu::Gpu;
+
+/// Number of frames the statistics of a [`FramePacer`] are computed over.
+const WINDOW: usize = 120;
+
+/// Ticks between two VBlanks, at a refresh rate of about 59.83 Hz.
+const REFRESH_PERIOD_TICKS: u64 = 4_481_134;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetFrameRate {
+    Fps60,
+    Fps30,
+}
+
+impl TargetFrameRate {
+    const fn vblanks_per_frame(self) -> u64 {
+        match self {
+            Self::Fps60 => 1,
+            Self::Fps30 => 2,
+        }
+    }
+}
+
+/// Frame time statistics over the last frames, see [`FramePacer::stats`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    pub fps: f32,
+    pub average: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub worst: Duration,
+}
+
+/// Caps the frame rate by waiting for VBlanks of the top screen, and keeps track of frame times.
+#[derive(Debug)]
+pub struct FramePacer {
+    target: TargetFrameRate,
+    last_frame: SystemTick,
+    frame_times: [u64; WINDOW],
+    next: usize,
+    recorded: usize,
+}
+
+impl FramePacer {
+    pub fn new(target: TargetFrameRate) -> Self {
+        Self {
+            target,
+            last_frame: SystemTick::now(),
+            frame_times: [0; WINDOW],
+            next: 0,
+            recorded: 0,
+        }
+    }
+
+    pub fn target(&self) -> TargetFrameRate {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: TargetFrameRate) {
+        self.target = target
+    }
+
+    /// Wait until the next frame should start, and return the duration of the frame that ended.
+    ///
+    /// Frames that took too long end at the next VBlank.
+    pub fn wait_next_frame(&mut self, gpu: &mut Gpu) -> Result<Duration> {
+        // Stop halfway into the last refresh period of this frame, so that the VBlank ending it
+        // is the next one.
+        let min_ticks =
+            (self.target.vblanks_per_frame() - 1) * REFRESH_PERIOD_TICKS + REFRESH_PERIOD_TICKS / 2;
+
+        let now = loop {
+            gpu.wait_vblank_top()?;
+
+            let now = SystemTick::now();
+            if now.count().wrapping_sub(self.last_frame.count()) >= min_ticks {
+                break now;
+            }
+        };
+
+        let frame_ticks = now.count().wrapping_sub(self.last_frame.count());
+        self.last_frame = now;
+
+        self.frame_times[self.next] = frame_ticks;
+        self.next = (self.next + 1) % WINDOW;
+        self.recorded = (self.recorded + 1).min(WINDOW);
+
+        Ok(duration_from_ticks(frame_ticks))
+    }
+
+    /// Statistics over the last (up to 120) frames, or `None` if no frame has been recorded yet.
+    pub fn stats(&self) -> Option<FrameStats> {
+        if self.recorded == 0 {
+            return None;
+        }
+
+        let mut sorted = [0u64; WINDOW];
+        let sorted = &mut sorted[..self.recorded];
+        sorted.copy_from_slice(&self.frame_times[..self.recorded]);
+        sorted.sort_unstable();
+
+        let percentile = |p: usize| sorted[((sorted.len() - 1) * p) / 100];
+
+        let total: u64 = sorted.iter().sum();
+        let average = total / sorted.len() as u64;
+
+        Some(FrameStats {
+            fps: SystemTick::TICKS_PER_SECOND as f32 / average.max(1) as f32,
+            average: duration_from_ticks(average),
+            median: duration_from_ticks(percentile(50)),
+            p95: duration_from_ticks(percentile(95)),
+            p99: duration_from_ticks(percentile(99)),
+            worst: duration_from_ticks(sorted[sorted.len() - 1]),
+        })
+    }
+}
@@ -0,0 +1,148 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! # Video memory
+//!
+//! VRAM is split into two banks of 3 MiB, which the GPU can access in parallel.  Placing e.g. a
+//! render target and the textures sampled while rendering to it in different banks avoids
+//! stalls.
+
+use core::alloc::{AllocError, Allocator, Layout};
+use core::ptr::NonNull;
+
+use linked_list_allocator::LockedHeap;
+
+const VRAM_START: usize = 0x1F00_0000;
+const BANK_SIZE: usize = 0x30_0000;
+const VRAM_ALIGN: usize = 16;
+
+static BANKS: [LockedHeap; 2] = [LockedHeap::empty(), LockedHeap::empty()];
+
+pub(crate) fn init() {
+    for bank in VramBank::ALL {
+        unsafe { BANKS[bank as usize].lock().init(bank.start(), BANK_SIZE) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VramBank {
+    A = 0,
+    B = 1,
+}
+
+impl VramBank {
+    const ALL: [Self; 2] = [Self::A, Self::B];
+
+    pub const fn start(self) -> usize {
+        VRAM_START + self as usize * BANK_SIZE
+    }
+
+    pub const fn size(self) -> usize {
+        BANK_SIZE
+    }
+
+    /// The bank containing `address`, if it is in VRAM at all.
+    pub const fn containing(address: usize) -> Option<Self> {
+        match address.checked_sub(VRAM_START) {
+            Some(offset) if offset < BANK_SIZE => Some(Self::A),
+            Some(offset) if offset < 2 * BANK_SIZE => Some(Self::B),
+            _ => None,
+        }
+    }
+
+    fn heap(self) -> &'static LockedHeap {
+        &BANKS[self as usize]
+    }
+}
+
+/// Allocates from VRAM, optionally restricted to a single bank.
+///
+/// Use with e.g. [`Box::new_in`](alloc::boxed::Box::new_in) or
+/// [`Vec::with_capacity_in`](alloc::vec::Vec::with_capacity_in).  All allocations are aligned to
+/// at least 16 bytes, as required by the GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VramAllocator {
+    bank: Option<VramBank>,
+}
+
+impl VramAllocator {
+    /// Allocate from bank A first, then from bank B.
+    pub const fn any() -> Self {
+        Self { bank: None }
+    }
+
+    pub const fn bank(bank: VramBank) -> Self {
+        Self { bank: Some(bank) }
+    }
+
+    fn banks(&self) -> &'static [VramBank] {
+        match self.bank {
+            None => &VramBank::ALL,
+            Some(VramBank::A) => &VramBank::ALL[..1],
+            Some(VramBank::B) => &VramBank::ALL[1..],
+        }
+    }
+}
+
+unsafe impl Allocator for VramAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let layout = layout.align_to(VRAM_ALIGN).map_err(|_| AllocError)?;
+
+        self.banks()
+            .iter()
+            .find_map(|bank| bank.heap().lock().allocate_first_fit(layout).ok())
+            .map(|ptr| NonNull::slice_from_raw_parts(ptr, layout.size()))
+            .ok_or(AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let layout = layout
+            .align_to(VRAM_ALIGN)
+            .expect("Layout was valid when allocating");
+        let bank = VramBank::containing(ptr.as_ptr() as usize)
+            .expect("Deallocating memory that is not in VRAM");
+
+        bank.heap().lock().deallocate(ptr, layout)
+    }
+}
+
+/// Memory usage of a [`VramBank`], in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BankUsage {
+    pub used: usize,
+    pub free: usize,
+}
+
+/// Memory usage of both VRAM banks, see [`usage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VramUsage {
+    pub bank_a: BankUsage,
+    pub bank_b: BankUsage,
+}
+
+impl VramUsage {
+    pub const fn used(&self) -> usize {
+        self.bank_a.used + self.bank_b.used
+    }
+
+    pub const fn free(&self) -> usize {
+        self.bank_a.free + self.bank_b.free
+    }
+}
+
+pub fn bank_usage(bank: VramBank) -> BankUsage {
+    let heap = bank.heap().lock();
+
+    BankUsage {
+        used: heap.used(),
+        free: heap.free(),
+    }
+}
+
+pub fn usage() -> VramUsage {
+    VramUsage {
+        bank_a: bank_usage(VramBank::A),
+        bank_b: bank_usage(VramBank::B),
+    }
+}
@@ -0,0 +1,111 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A first-fit heap that grows into its memory as needed.
+//!
+//! The memory the heap has not grown into yet is its free tail, which can be returned to the
+//! system without searching the allocator's free list.
+
+use crate::sync::{LightLock, LightMutex, LightMutexGuard};
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+
+use linked_list_allocator::Heap;
+
+/// The heap grows by at least this many bytes at a time.
+const GROWTH_STEP: usize = 0x10000;
+
+pub struct FirstFit {
+    heap: Heap,
+    /// End of the memory the heap may grow into.
+    end: usize,
+}
+
+impl FirstFit {
+    pub const fn empty() -> Self {
+        Self {
+            heap: Heap::empty(),
+            end: 0,
+        }
+    }
+
+    /// Manage the `heap_size` bytes at `heap_bottom`.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be valid for reads and writes, unused otherwise, and this heap must be
+    /// empty.
+    pub unsafe fn init(&mut self, heap_bottom: usize, heap_size: usize) {
+        self.heap.init(heap_bottom, heap_size.min(GROWTH_STEP));
+        self.end = heap_bottom + heap_size;
+    }
+
+    pub fn bottom(&self) -> usize {
+        self.heap.bottom()
+    }
+
+    /// Start of the memory the heap has not grown into.
+    pub fn free_tail(&self) -> usize {
+        self.heap.top()
+    }
+
+    /// Never grow past `end` again.
+    ///
+    /// # Safety
+    ///
+    /// `end` must not be below [`free_tail`](Self::free_tail).
+    pub unsafe fn truncate(&mut self, end: usize) {
+        self.end = end;
+    }
+
+    pub fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        if let Ok(ptr) = self.heap.allocate_first_fit(layout) {
+            return Some(ptr);
+        }
+
+        let wanted = layout.size().checked_add(layout.align())?;
+        let growth = (wanted.max(GROWTH_STEP) + 0xfff) & !0xfff;
+        let growth = growth.min(self.end - self.heap.top());
+        if growth < wanted {
+            return None;
+        }
+
+        // SAFETY: The memory up to `end` is mapped and not used by anything else.
+        unsafe { self.heap.extend(growth) };
+        self.heap.allocate_first_fit(layout).ok()
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`allocate`](Self::allocate) of this heap, with `layout`.
+    pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        self.heap.deallocate(ptr, layout)
+    }
+}
+
+/// A [`FirstFit`] heap behind a lock, usable as the global allocator.
+pub struct LockedFirstFit(LightMutex<FirstFit>);
+
+impl LockedFirstFit {
+    pub const fn empty() -> Self {
+        Self(LightMutex::const_new(LightLock::new(), FirstFit::empty()))
+    }
+
+    pub fn lock(&self) -> LightMutexGuard<'_, FirstFit> {
+        self.0.lock()
+    }
+}
+
+unsafe impl GlobalAlloc for LockedFirstFit {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock()
+            .allocate(layout)
+            .map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.lock().deallocate(NonNull::new_unchecked(ptr), layout)
+    }
+}
@@ -0,0 +1,443 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::os::Process;
+use crate::result::ERROR_OUT_OF_MEMORY;
+use crate::{early_debug, os::mem, result::Result, svc};
+
+use core::num::NonZeroUsize;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::{
+    alloc::{AllocError, Allocator, Layout, LayoutError},
+    fmt,
+    ptr::NonNull,
+};
+
+use linked_list_allocator::LockedHeap;
+
+#[cfg(not(feature = "tlsf"))]
+mod first_fit;
+#[cfg(feature = "tlsf")]
+mod tlsf;
+
+#[cfg(target_arch = "arm")]
+#[alloc_error_handler]
+fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
+    panic!("allocation error: {:?}", layout)
+}
+
+/// The allocator of the main heap, first-fit unless the `tlsf` feature is enabled.
+#[cfg(not(feature = "tlsf"))]
+type MainHeap = first_fit::LockedFirstFit;
+#[cfg(feature = "tlsf")]
+type MainHeap = tlsf::LockedTlsf;
+
+/// Off the 3DS, the heap is never mapped, so allocations go to the host's allocator.
+#[cfg_attr(target_arch = "arm", global_allocator)]
+pub(crate) static ALLOCATOR: MainHeap = MainHeap::empty();
+
+static LINEAR_ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+/// End of the part of the heap that may still be mapped, see [`trim`].
+static HEAP_BOUNDARY: AtomicUsize = AtomicUsize::new(0);
+
+const HEAP_START: usize = 0x0800_0000;
+const HEAP_SPLIT_CAP: usize = 24 << 20; // 24 MiB
+const LINEAR_HEAP_SPLIT_CAP: usize = 32 << 20; // 32 MiB
+
+extern "C" {
+    static __heap_size: AtomicUsize;
+    static __linear_heap_size: AtomicUsize;
+}
+
+#[inline]
+pub fn heap_size() -> usize {
+    unsafe { &__heap_size }.load(Ordering::Acquire)
+}
+
+#[inline]
+pub(crate) fn linear_heap_size() -> usize {
+    unsafe { &__linear_heap_size }.load(Ordering::Acquire)
+}
+
+unsafe fn set_heap_size(heap_size: usize) {
+    __heap_size.store(heap_size, Ordering::Release)
+}
+
+unsafe fn set_linear_heap_size(linear_heap_size: usize) {
+    __linear_heap_size.store(linear_heap_size, Ordering::Release)
+}
+
+#[inline]
+fn page_align(size: usize) -> usize {
+    size & !0xfff
+}
+
+#[inline]
+fn page_align_up(size: usize) -> usize {
+    page_align(size + 0xfff)
+}
+
+/// Override the sizes of the heap and linear heap mapped on startup.
+///
+/// Called by [`#[entry]`](crate::entry) before the heap is initialized.  If only one size is
+/// given, the other heap receives all remaining memory.
+#[doc(hidden)]
+pub unsafe fn configure(heap_size: Option<usize>, linear_heap_size: Option<usize>) {
+    if heap_size.is_some() || linear_heap_size.is_some() {
+        set_heap_size(heap_size.unwrap_or(0));
+        set_linear_heap_size(linear_heap_size.unwrap_or(0));
+    }
+}
+
+pub(crate) fn init() -> Result<()> {
+    early_debug!("Initializing heap...",);
+
+    let memory_remaining = {
+        let process = Process::current();
+        let limits = process.resource_limits()?;
+        limits.memory_allocatable().remaining()?
+    };
+
+    let memory_remaining = if memory_remaining < 0 {
+        return Err(ERROR_OUT_OF_MEMORY);
+    } else {
+        page_align(memory_remaining as usize)
+    };
+
+    let heap_size = heap_size();
+    let linear_heap_size = linear_heap_size();
+
+    let total_memory = heap_size + linear_heap_size;
+
+    if total_memory > memory_remaining {
+        return Err(ERROR_OUT_OF_MEMORY);
+    }
+
+    let (heap_size, linear_heap_size) = match (
+        NonZeroUsize::new(heap_size),
+        NonZeroUsize::new(linear_heap_size),
+    ) {
+        (None, None) => {
+            let half = page_align(memory_remaining / 2);
+            let heap_size = (memory_remaining - half).min(HEAP_SPLIT_CAP);
+            let linear_heap_size = memory_remaining - heap_size;
+
+            if linear_heap_size > LINEAR_HEAP_SPLIT_CAP {
+                (
+                    memory_remaining - LINEAR_HEAP_SPLIT_CAP,
+                    LINEAR_HEAP_SPLIT_CAP,
+                )
+            } else {
+                (heap_size, linear_heap_size)
+            }
+        }
+        (Some(_heap_size), None) => (heap_size, memory_remaining - heap_size),
+        (None, Some(_linear_heap_size)) => (memory_remaining - linear_heap_size, linear_heap_size),
+        _ => (heap_size, linear_heap_size),
+    };
+
+    {
+        let heap_start = unsafe {
+            svc::control_memory(
+                mem::MemoryOperation::allocate(),
+                HEAP_START,
+                0x0,
+                heap_size,
+                mem::MemoryPermission::RW,
+            )?
+        };
+
+        crate::svc::output_debug_string("Mapped heap");
+
+        unsafe { ALLOCATOR.lock().init(heap_start, heap_size) };
+        HEAP_BOUNDARY.store(heap_start + heap_size, Ordering::Release);
+
+        early_debug!(
+            "Initialized heap at {:p}, size = 0x{:08x}",
+            heap_start as *const (),
+            heap_size,
+        );
+
+        unsafe { set_heap_size(heap_size) };
+
+        heap_start
+    };
+
+    {
+        const ADDR_DONT_CARE: usize = 0x0;
+        let linear_heap_start = unsafe {
+            svc::control_memory(
+                mem::MemoryOperation::allocate().linear(),
+                ADDR_DONT_CARE,
+                ADDR_DONT_CARE,
+                linear_heap_size,
+                mem::MemoryPermission::RW,
+            )?
+        };
+
+        early_debug!(
+            "Initialized linear heap at {:p}, size = 0x{:08x}",
+            linear_heap_start as *const (),
+            linear_heap_size
+        );
+
+        unsafe {
+            LINEAR_ALLOCATOR
+                .lock()
+                .init(linear_heap_start, linear_heap_size)
+        };
+        unsafe { set_linear_heap_size(linear_heap_size) };
+    }
+
+    Ok(())
+}
+
+/// Return unused pages at the end of the heap to the system, and return the number of bytes freed.
+///
+/// Only memory at the end of the heap can be returned.  The default first-fit allocator grows into
+/// the heap as needed, so this returns the memory it has not used yet.  With the `tlsf` feature,
+/// this succeeds if the free block at the end of the heap spans pages, as is usually the case
+/// after freeing large temporary buffers.  Freed memory is not handed out by the allocator again.
+pub fn trim() -> Result<usize> {
+    let mut heap = ALLOCATOR.lock();
+    let boundary = HEAP_BOUNDARY.load(Ordering::Acquire);
+    let mapped_end = page_align_up(boundary);
+
+    let tail = match FreeTail::reserve(&mut heap, boundary) {
+        Some(tail) => tail,
+        None => return Ok(0),
+    };
+
+    let trim_start = page_align_up(tail.start);
+    if trim_start >= mapped_end {
+        unsafe { tail.release(&mut heap) };
+        return Ok(0);
+    }
+
+    let freed = mapped_end - trim_start;
+    let result = unsafe {
+        svc::control_memory(
+            mem::MemoryOperation::free(),
+            trim_start,
+            0x0,
+            freed,
+            mem::MemoryPermission::NONE,
+        )
+    };
+
+    if let Err(e) = result {
+        unsafe { tail.release(&mut heap) };
+        return Err(e);
+    }
+
+    HEAP_BOUNDARY.store(tail.start, Ordering::Release);
+    unsafe { set_heap_size(heap_size() - freed) };
+    drop(heap);
+
+    early_debug!(
+        "Trimmed heap: freed 0x{:08x} bytes at {:p}",
+        freed,
+        trim_start as *const ()
+    );
+
+    Ok(freed)
+}
+
+/// Free memory at the end of the heap, kept from the allocator while it is returned to the system.
+struct FreeTail {
+    start: usize,
+    /// The free block spanning the tail, which stays allocated once the tail is returned.
+    #[cfg(feature = "tlsf")]
+    block: (NonNull<u8>, Layout),
+}
+
+impl FreeTail {
+    #[cfg(feature = "tlsf")]
+    fn reserve(heap: &mut tlsf::Tlsf, boundary: usize) -> Option<Self> {
+        let block = heap.allocate_free_block_before(boundary)?;
+
+        Some(Self {
+            start: block.0.as_ptr() as usize,
+            block,
+        })
+    }
+
+    #[cfg(not(feature = "tlsf"))]
+    fn reserve(heap: &mut first_fit::FirstFit, _boundary: usize) -> Option<Self> {
+        let start = heap.free_tail();
+        // SAFETY: The heap has not grown past its free tail.
+        unsafe { heap.truncate(start) };
+
+        Some(Self { start })
+    }
+
+    /// Hand the tail back to the allocator.
+    ///
+    /// # Safety
+    ///
+    /// `heap` must be the heap the tail was reserved from.
+    #[cfg(feature = "tlsf")]
+    unsafe fn release(self, heap: &mut tlsf::Tlsf) {
+        heap.deallocate(self.block.0, self.block.1)
+    }
+
+    #[cfg(not(feature = "tlsf"))]
+    unsafe fn release(self, heap: &mut first_fit::FirstFit) {
+        heap.truncate(HEAP_BOUNDARY.load(Ordering::Acquire))
+    }
+}
+
+/// Return the heap and the linear heap to the system, before returning to the loader.
+///
+/// # Safety
+///
+/// Nothing allocated may be used, and nothing may be allocated afterwards.
+pub(crate) unsafe fn unmap() -> Result<()> {
+    let heap_end = page_align_up(HEAP_BOUNDARY.load(Ordering::Acquire));
+    if heap_end > HEAP_START {
+        svc::control_memory(
+            mem::MemoryOperation::free(),
+            HEAP_START,
+            0x0,
+            heap_end - HEAP_START,
+            mem::MemoryPermission::NONE,
+        )?;
+    }
+
+    let linear_heap_start = LINEAR_ALLOCATOR.lock().bottom();
+    if linear_heap_start != 0 {
+        svc::control_memory(
+            mem::MemoryOperation::free(),
+            linear_heap_start,
+            0x0,
+            linear_heap_size(),
+            mem::MemoryPermission::NONE,
+        )?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn initialized() -> bool {
+    ALLOCATOR.lock().bottom() != 0
+}
+
+/// Allocates from the linear heap, which is physically contiguous.
+///
+/// Buffers accessed by hardware, like sources and targets of GX commands or DSP buffers, have to
+/// be allocated here, e.g. with [`Box::new_in`](alloc::boxed::Box::new_in).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LinearAllocator;
+
+impl LinearAllocator {
+    /// The physical address of `ptr`, if it points into the linear heap.
+    pub fn physical_address<T: ?Sized>(ptr: *const T) -> Option<usize> {
+        let address = ptr as *const u8 as usize;
+        let heap = LINEAR_ALLOCATOR.lock();
+
+        if (heap.bottom()..heap.top()).contains(&address) {
+            mem::virtual_to_physical(address)
+        } else {
+            None
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        LINEAR_ALLOCATOR.lock().used()
+    }
+
+    pub fn free(&self) -> usize {
+        LINEAR_ALLOCATOR.lock().free()
+    }
+}
+
+unsafe impl Allocator for LinearAllocator {
+    fn allocate(&self, layout: Layout) -> ::core::result::Result<NonNull<[u8]>, AllocError> {
+        LINEAR_ALLOCATOR
+            .lock()
+            .allocate_first_fit(layout)
+            .map(|ptr| NonNull::slice_from_raw_parts(ptr, layout.size()))
+            .map_err(|_| AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        LINEAR_ALLOCATOR.lock().deallocate(ptr, layout)
+    }
+}
+
+#[derive(Debug)]
+pub enum PageAlignError {
+    Alloc,
+    Layout(#[allow(deprecated)] LayoutError),
+}
+
+impl fmt::Display for PageAlignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Alloc => write!(f, "failed to allocate page aligned memory"),
+            Self::Layout(_) => write!(f, "size was not suitable for a page aligned allocation"),
+        }
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl core::error::Error for PageAlignError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::Alloc => None,
+            Self::Layout(e) => Some(e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PageAlignedBuffer {
+    buffer: Option<NonNull<u8>>,
+    layout: Layout,
+}
+
+impl PageAlignedBuffer {
+    pub const fn null() -> Self {
+        Self {
+            buffer: None,
+            layout: Layout::new::<()>(),
+        }
+    }
+
+    pub fn allocate(size: usize) -> ::core::result::Result<Self, PageAlignError> {
+        let layout = Self::layout_for_size(size).map_err(PageAlignError::Layout)?;
+        let buffer = Some(
+            NonNull::new(unsafe { alloc::alloc::alloc(layout) }).ok_or(PageAlignError::Alloc)?,
+        );
+        Ok(PageAlignedBuffer { buffer, layout })
+    }
+
+    pub const fn as_ptr(&self) -> Option<NonNull<u8>> {
+        self.buffer
+    }
+
+    pub fn size(&self) -> usize {
+        self.layout.size()
+    }
+
+    #[allow(deprecated)]
+    pub fn layout_for_size(size: usize) -> ::core::result::Result<Layout, LayoutError> {
+        Layout::from_size_align(size, 0x1000)
+    }
+}
+
+impl Default for PageAlignedBuffer {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+impl Drop for PageAlignedBuffer {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer {
+            unsafe { alloc::alloc::dealloc(buffer.as_ptr(), self.layout) }
+        }
+    }
+}
@@ -0,0 +1,431 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A two-level segregated fit (TLSF) allocator.
+//!
+//! Free blocks are kept in lists by size class: the first level splits sizes by powers of two,
+//! the second level splits each of those into [`SL_COUNT`] linear ranges.  Bitmaps of the
+//! non-empty lists let allocation find a fitting block with a few bit scans, and freed blocks are
+//! merged with their free neighbours immediately.  Both take constant time, independent of how
+//! fragmented the heap is.
+
+use crate::sync::{LightLock, LightMutex, LightMutexGuard};
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::size_of;
+use core::ptr::{self, NonNull};
+
+const ALIGN: usize = 8;
+const HEADER_SIZE: usize = size_of::<Header>();
+/// Free blocks hold the links of their list.
+const MIN_BLOCK_SIZE: usize = size_of::<FreeLinks>();
+
+const SL_LOG2: u32 = 4;
+const SL_COUNT: usize = 1 << SL_LOG2;
+/// Blocks smaller than this are all in the first first-level list, split linearly.
+const SMALL_BLOCK_SIZE: usize = 1 << FL_SHIFT;
+const FL_SHIFT: u32 = SL_LOG2 + ALIGN.trailing_zeros();
+const FL_MAX: u32 = 30;
+const FL_COUNT: usize = (FL_MAX - FL_SHIFT + 1) as usize;
+const MAX_BLOCK_SIZE: usize = 1 << FL_MAX;
+
+const FLAG_FREE: usize = 1 << 0;
+
+#[repr(C)]
+struct Header {
+    /// The block physically preceding this one, null for the first block.
+    prev: *mut Header,
+    /// Size of the payload following the header, with [`FLAG_FREE`] in the lowest bit.
+    size: usize,
+}
+
+/// Links of a free block's list, stored in its payload.
+#[repr(C)]
+struct FreeLinks {
+    next: *mut Header,
+    prev: *mut Header,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Block(*mut Header);
+
+impl Block {
+    const NULL: Self = Self(ptr::null_mut());
+
+    unsafe fn from_payload(payload: NonNull<u8>) -> Self {
+        Self(payload.as_ptr().sub(HEADER_SIZE) as *mut Header)
+    }
+
+    fn is_null(self) -> bool {
+        self.0.is_null()
+    }
+
+    unsafe fn init(self, prev: Block, size: usize) {
+        self.0.write(Header {
+            prev: prev.0,
+            size: size | FLAG_FREE,
+        })
+    }
+
+    unsafe fn size(self) -> usize {
+        (*self.0).size & !FLAG_FREE
+    }
+
+    unsafe fn set_size(self, size: usize) {
+        (*self.0).size = size | ((*self.0).size & FLAG_FREE)
+    }
+
+    unsafe fn is_free(self) -> bool {
+        (*self.0).size & FLAG_FREE != 0
+    }
+
+    unsafe fn set_free(self, free: bool) {
+        match free {
+            true => (*self.0).size |= FLAG_FREE,
+            false => (*self.0).size &= !FLAG_FREE,
+        }
+    }
+
+    unsafe fn prev(self) -> Block {
+        Block((*self.0).prev)
+    }
+
+    unsafe fn set_prev(self, prev: Block) {
+        (*self.0).prev = prev.0
+    }
+
+    /// The block physically following this one.
+    unsafe fn next(self) -> Block {
+        Block((self.payload() as usize + self.size()) as *mut Header)
+    }
+
+    fn payload(self) -> *mut u8 {
+        (self.0 as usize + HEADER_SIZE) as *mut u8
+    }
+
+    fn links(self) -> *mut FreeLinks {
+        self.payload() as *mut FreeLinks
+    }
+}
+
+const fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+/// Index of the highest set bit of `size`.
+const fn fls(size: usize) -> u32 {
+    usize::BITS - 1 - size.leading_zeros()
+}
+
+/// The list a free block of `size` bytes is kept in.
+fn mapping_insert(size: usize) -> (usize, usize) {
+    if size < SMALL_BLOCK_SIZE {
+        (0, size / (SMALL_BLOCK_SIZE / SL_COUNT))
+    } else {
+        let fl = fls(size);
+        let sl = (size >> (fl - SL_LOG2)) ^ SL_COUNT;
+        ((fl - FL_SHIFT + 1) as usize, sl)
+    }
+}
+
+/// The first list whose blocks all fit `size` bytes.
+fn mapping_search(size: usize) -> (usize, usize) {
+    if size < SMALL_BLOCK_SIZE {
+        mapping_insert(size)
+    } else {
+        mapping_insert(size + (1 << (fls(size) - SL_LOG2)) - 1)
+    }
+}
+
+/// A heap managed by two-level segregated fit, see the [module documentation](self).
+pub struct Tlsf {
+    fl_bitmap: u32,
+    sl_bitmap: [u32; FL_COUNT],
+    lists: [[Block; SL_COUNT]; FL_COUNT],
+    bottom: usize,
+    size: usize,
+    free: usize,
+}
+
+unsafe impl Send for Tlsf {}
+
+impl Tlsf {
+    pub const fn empty() -> Self {
+        Self {
+            fl_bitmap: 0,
+            sl_bitmap: [0; FL_COUNT],
+            lists: [[Block::NULL; SL_COUNT]; FL_COUNT],
+            bottom: 0,
+            size: 0,
+            free: 0,
+        }
+    }
+
+    /// Manage the `heap_size` bytes at `heap_bottom`.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be valid for reads and writes, unused otherwise, and this heap must be
+    /// empty.
+    pub unsafe fn init(&mut self, heap_bottom: usize, heap_size: usize) {
+        let start = align_up(heap_bottom, ALIGN);
+        let end = (heap_bottom + heap_size) & !(ALIGN - 1);
+
+        // One free block spanning the heap, followed by an empty block that is never free
+        let block = Block(start as *mut Header);
+        let sentinel = Block((end - HEADER_SIZE) as *mut Header);
+        let size = sentinel.0 as usize - block.payload() as usize;
+        assert!(
+            (MIN_BLOCK_SIZE..MAX_BLOCK_SIZE).contains(&size),
+            "Heap size {:#x} is not supported",
+            heap_size
+        );
+
+        block.init(Block::NULL, size);
+        sentinel.init(block, 0);
+        sentinel.set_free(false);
+
+        self.bottom = heap_bottom;
+        self.size = heap_size;
+        self.insert(block);
+    }
+
+    pub fn bottom(&self) -> usize {
+        self.bottom
+    }
+
+    pub fn top(&self) -> usize {
+        self.bottom + self.size
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Bytes handed out, including the bookkeeping of the allocator.
+    pub fn used(&self) -> usize {
+        self.size - self.free
+    }
+
+    pub fn free(&self) -> usize {
+        self.free
+    }
+
+    pub fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let size = align_up(layout.size().max(MIN_BLOCK_SIZE), ALIGN);
+        let align = layout.align();
+
+        // Leave room to split off a free block in front of a payload with larger alignment
+        let search = match align {
+            ..=ALIGN => size,
+            _ => size.checked_add(align + HEADER_SIZE + MIN_BLOCK_SIZE)?,
+        };
+        if search >= MAX_BLOCK_SIZE {
+            return None;
+        }
+
+        unsafe {
+            let block = self.find(search)?;
+            self.remove(block);
+
+            let block = match align {
+                ..=ALIGN => block,
+                _ => self.split_front(block, align),
+            };
+            self.split_back(block, size);
+            block.set_free(false);
+
+            NonNull::new(block.payload())
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`allocate`](Self::allocate) of this heap.
+    pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>, _layout: Layout) {
+        let mut block = Block::from_payload(ptr);
+        block.set_free(true);
+
+        let prev = block.prev();
+        if !prev.is_null() && prev.is_free() {
+            self.remove(prev);
+            prev.set_size(prev.size() + HEADER_SIZE + block.size());
+            prev.next().set_prev(prev);
+            block = prev;
+        }
+
+        let next = block.next();
+        if next.is_free() {
+            self.remove(next);
+            block.set_size(block.size() + HEADER_SIZE + next.size());
+            block.next().set_prev(block);
+        }
+
+        self.insert(block)
+    }
+
+    /// Allocate the free block ending at `end`, e.g. to return its memory to the system.
+    ///
+    /// `end` is either the top of the heap, or the start of an allocation.
+    pub fn allocate_free_block_before(&mut self, end: usize) -> Option<(NonNull<u8>, Layout)> {
+        if end < self.bottom + 2 * HEADER_SIZE || end > self.top() {
+            return None;
+        }
+
+        unsafe {
+            let next = Block(((end & !(ALIGN - 1)) - HEADER_SIZE) as *mut Header);
+            let block = next.prev();
+            if block.is_null() || !block.is_free() {
+                return None;
+            }
+
+            self.remove(block);
+            block.set_free(false);
+
+            let layout = Layout::from_size_align(block.size(), ALIGN).ok()?;
+            Some((NonNull::new(block.payload())?, layout))
+        }
+    }
+
+    /// A free block of at least `size` bytes.
+    unsafe fn find(&self, size: usize) -> Option<Block> {
+        let (fl, sl) = mapping_search(size);
+        if let Some(block) = self.find_from(fl, sl) {
+            return Some(block);
+        }
+
+        // Only the blocks of the list `size` falls into may still fit, e.g. a block spanning
+        // the whole heap
+        let (fl, sl) = mapping_insert(size);
+        let mut block = self.lists[fl][sl];
+        while !block.is_null() && block.size() < size {
+            block = Block((*block.links()).next);
+        }
+
+        (!block.is_null()).then_some(block)
+    }
+
+    /// The first free block in the list `(fl, sl)` or any list of larger blocks.
+    fn find_from(&self, mut fl: usize, sl: usize) -> Option<Block> {
+        if fl >= FL_COUNT {
+            return None;
+        }
+
+        let mut sl_map = self.sl_bitmap[fl] & (!0 << sl);
+        if sl_map == 0 {
+            let fl_map = self.fl_bitmap & (!0u32).checked_shl(fl as u32 + 1).unwrap_or(0);
+            if fl_map == 0 {
+                return None;
+            }
+
+            fl = fl_map.trailing_zeros() as usize;
+            sl_map = self.sl_bitmap[fl];
+        }
+
+        Some(self.lists[fl][sl_map.trailing_zeros() as usize])
+    }
+
+    unsafe fn insert(&mut self, block: Block) {
+        let (fl, sl) = mapping_insert(block.size());
+        let head = self.lists[fl][sl];
+
+        block.links().write(FreeLinks {
+            next: head.0,
+            prev: ptr::null_mut(),
+        });
+        if !head.is_null() {
+            (*head.links()).prev = block.0;
+        }
+
+        self.lists[fl][sl] = block;
+        self.fl_bitmap |= 1 << fl;
+        self.sl_bitmap[fl] |= 1 << sl;
+        self.free += block.size();
+    }
+
+    unsafe fn remove(&mut self, block: Block) {
+        let (fl, sl) = mapping_insert(block.size());
+        let FreeLinks { next, prev } = block.links().read();
+
+        if !next.is_null() {
+            (*Block(next).links()).prev = prev;
+        }
+        if !prev.is_null() {
+            (*Block(prev).links()).next = next;
+        } else {
+            self.lists[fl][sl] = Block(next);
+            if next.is_null() {
+                self.sl_bitmap[fl] &= !(1 << sl);
+                if self.sl_bitmap[fl] == 0 {
+                    self.fl_bitmap &= !(1 << fl);
+                }
+            }
+        }
+
+        self.free -= block.size();
+    }
+
+    /// Split off a free block in front of `block`, so that the payload of the rest is aligned to
+    /// `align`.
+    unsafe fn split_front(&mut self, block: Block, align: usize) -> Block {
+        let payload = block.payload() as usize;
+        let mut aligned = align_up(payload, align);
+        if aligned == payload {
+            return block;
+        }
+        while aligned - payload < HEADER_SIZE + MIN_BLOCK_SIZE {
+            aligned += align;
+        }
+
+        let gap = aligned - payload;
+        let rest = Block((aligned - HEADER_SIZE) as *mut Header);
+        rest.init(block, block.size() - gap);
+        rest.next().set_prev(rest);
+
+        block.set_size(gap - HEADER_SIZE);
+        self.insert(block);
+
+        rest
+    }
+
+    /// Split off the part of `block` beyond `size` bytes as a free block, if it is large enough.
+    unsafe fn split_back(&mut self, block: Block, size: usize) {
+        let remaining = block.size() - size;
+        if remaining < HEADER_SIZE + MIN_BLOCK_SIZE {
+            return;
+        }
+
+        let rest = Block((block.payload() as usize + size) as *mut Header);
+        rest.init(block, remaining - HEADER_SIZE);
+        rest.next().set_prev(rest);
+
+        block.set_size(size);
+        self.insert(rest);
+    }
+}
+
+/// A [`Tlsf`] heap behind a lock, usable as the global allocator.
+pub struct LockedTlsf(LightMutex<Tlsf>);
+
+impl LockedTlsf {
+    pub const fn empty() -> Self {
+        Self(LightMutex::const_new(LightLock::new(), Tlsf::empty()))
+    }
+
+    pub fn lock(&self) -> LightMutexGuard<'_, Tlsf> {
+        self.0.lock()
+    }
+}
+
+unsafe impl GlobalAlloc for LockedTlsf {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock()
+            .allocate(layout)
+            .map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.lock().deallocate(NonNull::new_unchecked(ptr), layout)
+    }
+}
@@ -0,0 +1,226 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Scripted replies to IPC requests, for testing service bindings without the service.
+//!
+//! With the `mock-ipc` feature, requests are not sent to the kernel.  Instead, each request is
+//! recorded and answered with the next reply scripted with [`reply`] or [`expect`]:
+//!
+//! ```ignore
+//! mock::expect(0x5, Reply::success().handle(0x1234));
+//! let cfg = srv.get_service_handle("cfg:u")?;
+//!
+//! let requests = mock::take_requests();
+//! assert_eq!(requests[0].parameters[2], "cfg:u".len() as u32);
+//! assert_eq!(mock::pending_replies(), 0);
+//! ```
+//!
+//! The crate builds for the host too, so the tests of the bindings run without a 3DS:
+//!
+//! ```text
+//! cargo test --lib --features mock-ipc --target x86_64-unknown-linux-gnu -Zbuild-std=std,panic_unwind
+//! ```
+//!
+//! Off the 3DS, the command buffer lives in memory shared by all threads, see
+//! [`get_thread_local_storage`](crate::tls::get_thread_local_storage), so only one thread may
+//! send requests at a time.
+
+use super::{IpcHeader, COMMAND_BUFFER_LENGTH, FLAG_MOVE_HANDLE, TYPE_HANDLE};
+
+use crate::os::{BorrowedHandle, RawHandle};
+use crate::result::{ErrorCode, Result, ResultCode, ResultValue};
+use crate::sync::{LightLock, LightMutex};
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// A request sent while mocking, see [`take_requests`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// The session the request was sent to.
+    pub handle: RawHandle,
+    pub command_id: u16,
+    pub parameters: Vec<u32>,
+    /// The translate parameters, including their descriptors.
+    pub translate_parameters: Vec<u32>,
+}
+
+/// The reply to a request, built like the reply of a service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    result: u32,
+    parameters: Vec<u32>,
+    translate_parameters: Vec<u32>,
+}
+
+impl Reply {
+    pub fn success() -> Self {
+        Self {
+            result: 0,
+            parameters: Vec::new(),
+            translate_parameters: Vec::new(),
+        }
+    }
+
+    /// A reply failing with `code`, without parameters.
+    pub fn error(code: ErrorCode) -> Self {
+        Self {
+            result: ResultCode::from(code).value(),
+            ..Self::success()
+        }
+    }
+
+    /// Append a normal parameter.
+    pub fn word(mut self, word: u32) -> Self {
+        self.parameters.push(word);
+        self
+    }
+
+    pub fn words(mut self, words: &[u32]) -> Self {
+        self.parameters.extend_from_slice(words);
+        self
+    }
+
+    /// Append a handle moved to the requesting process.
+    ///
+    /// The handle is closed when the binding under test drops it, so this should be a handle the
+    /// test owns, or one that is never dropped.
+    pub fn handle(mut self, handle: RawHandle) -> Self {
+        self.translate_parameters
+            .extend_from_slice(&[FLAG_MOVE_HANDLE | TYPE_HANDLE, handle]);
+        self
+    }
+
+    /// Write the reply to `command_id` into `buffer`.
+    unsafe fn write(&self, command_id: u16, buffer: *mut u32) {
+        let normal_words = 1 + self.parameters.len();
+        let translate_words = self.translate_parameters.len();
+        assert!(
+            1 + normal_words + translate_words <= COMMAND_BUFFER_LENGTH,
+            "Scripted reply does not fit the command buffer"
+        );
+
+        let header = IpcHeader::new(command_id, normal_words, translate_words);
+        let words = [u32::from(header), self.result]
+            .into_iter()
+            .chain(self.parameters.iter().copied())
+            .chain(self.translate_parameters.iter().copied());
+
+        for (i, word) in words.enumerate() {
+            buffer.add(i).write(word)
+        }
+    }
+}
+
+struct Scripted {
+    command_id: Option<u16>,
+    reply: Reply,
+}
+
+struct Mock {
+    replies: VecDeque<Scripted>,
+    requests: Vec<Request>,
+}
+
+static MOCK: LightMutex<Mock> = LightMutex::const_new(
+    LightLock::new(),
+    Mock {
+        replies: VecDeque::new(),
+        requests: Vec::new(),
+    },
+);
+
+/// Answer the next request with `reply`, whatever command it is.
+pub fn reply(reply: Reply) {
+    MOCK.lock().replies.push_back(Scripted {
+        command_id: None,
+        reply,
+    })
+}
+
+/// Answer the next request with `reply`, panicking if it is not command `command_id`.
+pub fn expect(command_id: u16, reply: Reply) {
+    MOCK.lock().replies.push_back(Scripted {
+        command_id: Some(command_id),
+        reply,
+    })
+}
+
+/// Number of scripted replies no request was sent for yet.
+pub fn pending_replies() -> usize {
+    MOCK.lock().replies.len()
+}
+
+/// The requests sent since the last call, oldest first.
+pub fn take_requests() -> Vec<Request> {
+    core::mem::take(&mut MOCK.lock().requests)
+}
+
+/// Forget all scripted replies and recorded requests.
+pub fn reset() {
+    let mut mock = MOCK.lock();
+    mock.replies.clear();
+    mock.requests.clear();
+}
+
+/// Take the mock for a test, with no replies scripted.
+///
+/// Tests run in parallel, but share the scripted replies and the command buffer.
+#[cfg(test)]
+pub(crate) fn lock() -> std::sync::MutexGuard<'static, ()> {
+    static TESTS: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    let guard = TESTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    reset();
+    guard
+}
+
+/// Record the request in `command_buffer` and replace it with the next scripted reply.
+///
+/// # Panics
+///
+/// If no reply is scripted, or the request is not the expected command.
+pub unsafe fn send_sync_request(
+    handle: BorrowedHandle,
+    command_buffer: *mut u32,
+) -> Result<*mut u32> {
+    let header = IpcHeader::from(command_buffer.read());
+    let normal_words = header.normal_param_words();
+    let words = core::slice::from_raw_parts(
+        command_buffer.add(1),
+        normal_words + header.translate_param_words(),
+    );
+
+    let request = Request {
+        handle: handle.handle,
+        command_id: header.command_id(),
+        parameters: words[..normal_words].to_vec(),
+        translate_parameters: words[normal_words..].to_vec(),
+    };
+
+    let scripted = {
+        let mut mock = MOCK.lock();
+        mock.requests.push(request.clone());
+        mock.replies.pop_front()
+    };
+
+    let scripted = match scripted {
+        Some(scripted) => scripted,
+        None => panic!("No reply scripted for IPC request {:x?}", request),
+    };
+
+    if let Some(command_id) = scripted.command_id {
+        assert_eq!(
+            command_id, request.command_id,
+            "Expected IPC command {:#x}, got {:x?}",
+            command_id, request
+        );
+    }
+
+    scripted.reply.write(request.command_id, command_buffer);
+
+    Ok(command_buffer)
+}
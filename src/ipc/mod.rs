@@ -3,18 +3,22 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! # Inter-process communication
+#[cfg(feature = "mock-ipc")]
+pub mod mock;
 mod reply;
 mod request;
+pub mod server;
 
 use self::reply::CommandBufferReader;
 use self::request::CommandBufferWriter;
 pub(crate) use self::request::IpcRequest;
 
-use crate::os::{BorrowedHandle, OwnedHandle, RawHandle};
+use crate::os::{BorrowedHandle, OwnedHandle, RawHandle, CLOSED_HANDLE};
 use crate::result::{ResultCode, ResultValue};
 use crate::tls;
 
 use core::convert::TryFrom;
+use core::marker::PhantomData;
 use core::mem::MaybeUninit;
 use core::{fmt, ops::Range};
 
@@ -126,8 +130,15 @@ pub(crate) trait IpcResult {
 }
 
 pub(crate) trait TranslateParameter {
+    /// Number of words written by [`encode`](Self::encode).
     #[doc(hidden)]
-    fn encode(self, cmdbuf: &mut CommandBufferWriter);
+    const WORDS: usize;
+
+    /// # Safety
+    ///
+    /// Room for [`WORDS`](Self::WORDS) words must be reserved in `cmdbuf`.
+    #[doc(hidden)]
+    unsafe fn encode(self, cmdbuf: &mut CommandBufferWriter);
 }
 
 pub(crate) trait TranslateResult {
@@ -172,21 +183,26 @@ impl IpcResult for ResultCode {
 
 const TYPE_HANDLE: u32 = 0 << 1;
 const TYPE_STATIC_BUFFER: u32 = 1 << 1;
+const TYPE_MAPPED_BUFFER: u32 = 1 << 3;
 
 const FLAG_MOVE_HANDLE: u32 = 1 << 4;
 const FLAG_REPLACE_PID: u32 = 1 << 5;
 
 impl TranslateParameter for OwnedHandle {
+    const WORDS: usize = 2;
+
     #[inline(always)]
-    fn encode(self, cmdbuf: &mut CommandBufferWriter) {
+    unsafe fn encode(self, cmdbuf: &mut CommandBufferWriter) {
         let handle: [OwnedHandle; 1] = unsafe { core::mem::transmute(self) };
         handle.encode(cmdbuf)
     }
 }
 
 impl<const N: usize> TranslateParameter for [OwnedHandle; N] {
+    const WORDS: usize = if N == 0 { 0 } else { N + 1 };
+
     #[inline]
-    fn encode(self, cmdbuf: &mut CommandBufferWriter) {
+    unsafe fn encode(self, cmdbuf: &mut CommandBufferWriter) {
         if N == 0 {
             return;
         }
@@ -239,8 +255,10 @@ impl TranslateResult for OwnedHandle {
 }
 
 impl<'h, const N: usize> TranslateParameter for [BorrowedHandle<'h>; N] {
+    const WORDS: usize = if N == 0 { 0 } else { N + 1 };
+
     #[inline(always)]
-    fn encode(self, cmdbuf: &mut CommandBufferWriter) {
+    unsafe fn encode(self, cmdbuf: &mut CommandBufferWriter) {
         if N == 0 {
             return;
         }
@@ -255,20 +273,46 @@ impl<'h, const N: usize> TranslateParameter for [BorrowedHandle<'h>; N] {
 }
 
 impl<'h> TranslateParameter for BorrowedHandle<'h> {
+    const WORDS: usize = 2;
+
     #[inline(always)]
-    fn encode(self, cmdbuf: &mut CommandBufferWriter) {
+    unsafe fn encode(self, cmdbuf: &mut CommandBufferWriter) {
         let header = TYPE_HANDLE;
         cmdbuf.write(header);
         cmdbuf.write(self.handle)
     }
 }
 
+impl TranslateParameter for Option<BorrowedHandle<'_>> {
+    const WORDS: usize = 2;
+
+    #[inline(always)]
+    unsafe fn encode(self, cmdbuf: &mut CommandBufferWriter) {
+        cmdbuf.write(TYPE_HANDLE);
+        cmdbuf.write(self.map_or(CLOSED_HANDLE, |handle| handle.handle))
+    }
+}
+
+/// A handle that may have been sent as the null handle.
+impl TranslateResult for Option<OwnedHandle> {
+    #[inline(always)]
+    unsafe fn decode(cmdbuf: &mut CommandBufferReader) -> Self {
+        let header = cmdbuf.read();
+        let num_handles = (header >> 26) + 1;
+        debug_assert_eq!(num_handles, 1);
+
+        OwnedHandle::new(cmdbuf.read())
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct ThisProcessId;
 
 impl TranslateParameter for ThisProcessId {
+    const WORDS: usize = 2;
+
     #[inline]
-    fn encode(self, cmdbuf: &mut CommandBufferWriter) {
+    unsafe fn encode(self, cmdbuf: &mut CommandBufferWriter) {
         const HEADER: u32 = FLAG_REPLACE_PID | TYPE_HANDLE;
         const PLACEHOLDER: u32 = 0x0;
         cmdbuf.write(HEADER);
@@ -278,31 +322,98 @@ impl TranslateParameter for ThisProcessId {
 
 #[derive(Debug)]
 pub(crate) struct StaticBuffer<'buf> {
-    source: &'buf [u32],
+    source: *const u8,
+    size: usize,
     target_id: u8,
+    _buffer: PhantomData<&'buf [u8]>,
 }
 
 impl<'buf> StaticBuffer<'buf> {
     pub(crate) fn new(source: &'buf [u32], target_id: u8) -> Self {
-        Self { source, target_id }
+        Self {
+            source: source.as_ptr() as *const u8,
+            size: core::mem::size_of_val(source),
+            target_id,
+            _buffer: PhantomData,
+        }
+    }
+
+    pub(crate) fn from_bytes(source: &'buf [u8], target_id: u8) -> Self {
+        Self {
+            source: source.as_ptr(),
+            size: source.len(),
+            target_id,
+            _buffer: PhantomData,
+        }
     }
 }
 
 impl TranslateParameter for StaticBuffer<'_> {
+    const WORDS: usize = 2;
+
     #[inline]
-    fn encode(self, cmdbuf: &mut CommandBufferWriter) {
+    unsafe fn encode(self, cmdbuf: &mut CommandBufferWriter) {
         let index = self.target_id as u32;
         if index >= 16 {
             panic!("Static buffer target index must be in 0..16, not {}", index);
         }
 
-        let size: u32 = u16::try_from(self.source.len())
+        let size: u32 = u16::try_from(self.size)
             .expect("Static buffer length must fit 16 bits")
             .into();
 
         let header = (size << 14) | (index << 10) | TYPE_STATIC_BUFFER;
 
         cmdbuf.write(header);
-        cmdbuf.write(self.source.as_ptr() as u32)
+        cmdbuf.write(self.source as u32)
+    }
+}
+
+/// A buffer mapped into the receiving process for the duration of the request.
+#[derive(Debug)]
+pub(crate) struct MappedBuffer<'buf> {
+    buffer: *const u8,
+    size: usize,
+    permission: u32,
+    _buffer: PhantomData<&'buf [u8]>,
+}
+
+impl<'buf> MappedBuffer<'buf> {
+    const PERMISSION_READ: u32 = 1 << 1;
+    const PERMISSION_WRITE: u32 = 2 << 1;
+
+    /// Let the receiver read `source`.
+    pub(crate) fn read(source: &'buf [u8]) -> Self {
+        Self {
+            buffer: source.as_ptr(),
+            size: source.len(),
+            permission: Self::PERMISSION_READ,
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Let the receiver write to `target`.
+    pub(crate) fn write(target: &'buf mut [u8]) -> Self {
+        Self {
+            buffer: target.as_mut_ptr(),
+            size: target.len(),
+            permission: Self::PERMISSION_WRITE,
+            _buffer: PhantomData,
+        }
+    }
+}
+
+impl TranslateParameter for MappedBuffer<'_> {
+    const WORDS: usize = 2;
+
+    #[inline]
+    unsafe fn encode(self, cmdbuf: &mut CommandBufferWriter) {
+        let size = u32::try_from(self.size)
+            .ok()
+            .filter(|&size| size < 1 << 28)
+            .expect("Mapped buffer length must fit 28 bits");
+
+        cmdbuf.write((size << 4) | TYPE_MAPPED_BUFFER | self.permission);
+        cmdbuf.write(self.buffer as u32)
     }
 }
@@ -7,51 +7,112 @@ use crate::result::{Result, ResultCode};
 use crate::svc;
 
 use super::reply::IpcReply;
-use super::{state, CommandBuffer, IpcHeader, IpcParameter, TranslateParameter};
+use super::{
+    state, CommandBuffer, IpcHeader, IpcParameter, TranslateParameter, COMMAND_BUFFER_LENGTH,
+};
 
 use core::marker::PhantomData;
 
 use log::{error, trace};
 
+/// Writes a request or reply into the command buffer, leaving room for the header.
+///
+/// Instead of checking the bounds on each word, room for all words of a parameter is reserved at
+/// once.  Requests of a known size reserve room for all of their words up front, see
+/// [`IpcRequest::command_sized`].
 pub(crate) struct CommandBufferWriter {
     buf: CommandBuffer,
-    end_ptr: *mut u32,
+    /// Index of the next word to write.
+    pos: usize,
 }
 
 impl CommandBufferWriter {
     #[inline(always)]
-    pub(crate) fn write(&mut self, arg: u32) {
-        if self.buf.range().contains(&(self.end_ptr as *const u32)) {
-            unsafe { self.end_ptr.write(arg) };
-            unsafe { self.advance() };
-        } else {
-            panic!(
-                "Detected attempt to access command buffer out of bounds: {:?} is outside of {:?}",
-                self.end_ptr,
-                self.buf.range()
-            )
+    pub(super) fn new(buf: CommandBuffer) -> Self {
+        // The header is written last
+        Self { buf, pos: 1 }
+    }
+
+    #[inline(always)]
+    fn reserve(&self, words: usize) {
+        if self.pos + words > COMMAND_BUFFER_LENGTH {
+            overflow(self.pos, words)
         }
     }
 
-    pub(crate) unsafe fn advance(&mut self) {
-        self.end_ptr = self.end_ptr.add(1);
+    /// Write a word into the reserved space.
+    ///
+    /// # Safety
+    ///
+    /// Room for the word must have been reserved.
+    #[inline(always)]
+    pub(crate) unsafe fn write(&mut self, word: u32) {
+        debug_assert!(self.pos < COMMAND_BUFFER_LENGTH);
+        self.buf.start().add(self.pos).write(word);
+        self.pos += 1;
     }
 
-    pub(crate) fn pos(&self) -> usize {
-        unsafe { self.end_ptr.offset_from(self.buf.start()) as usize }
+    /// Write all `words`, returning how many were written.
+    #[inline(always)]
+    pub(super) fn write_words<const N: usize>(&mut self, words: [u32; N]) -> usize {
+        self.reserve(N);
+        unsafe { self.write_words_unchecked(words) }
     }
 
-    pub(super) const fn new(buf: CommandBuffer) -> Self {
-        let end_ptr = buf.start();
-        Self { buf, end_ptr }
+    /// # Safety
+    ///
+    /// Room for all `words` must have been reserved.
+    #[inline(always)]
+    unsafe fn write_words_unchecked<const N: usize>(&mut self, words: [u32; N]) -> usize {
+        for word in words {
+            self.write(word)
+        }
+        N
     }
 
-    pub(super) const fn finish(self) -> CommandBuffer {
+    /// Write the translate parameter `parameter`, returning how many words were written.
+    #[inline(always)]
+    pub(super) fn write_translate<P: TranslateParameter>(&mut self, parameter: P) -> usize {
+        self.reserve(P::WORDS);
+        unsafe { self.write_translate_unchecked(parameter) }
+    }
+
+    /// # Safety
+    ///
+    /// Room for the words of `parameter` must have been reserved.
+    #[inline(always)]
+    unsafe fn write_translate_unchecked<P: TranslateParameter>(&mut self, parameter: P) -> usize {
+        let before = self.pos;
+        parameter.encode(self);
+        debug_assert_eq!(self.pos - before, P::WORDS);
+        P::WORDS
+    }
+
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub(super) fn finish(self, header: IpcHeader) -> CommandBuffer {
+        unsafe { self.buf.start().write(header.into()) };
         self.buf
     }
 }
 
-pub(crate) struct IpcRequest<S: state::State = state::Normal> {
+#[cold]
+#[inline(never)]
+fn overflow(pos: usize, words: usize) -> ! {
+    panic!(
+        "IPC message of {} words does not fit the command buffer of {} words",
+        pos + words,
+        COMMAND_BUFFER_LENGTH
+    )
+}
+
+/// A request being built in the command buffer.
+///
+/// Each parameter checks that it fits the command buffer, unless room for all of them was
+/// `RESERVED` up front by [`command_sized`](Self::command_sized).
+pub(crate) struct IpcRequest<S: state::State = state::Normal, const RESERVED: bool = false> {
     cmdbuf: CommandBufferWriter,
     param_words: u32,
     translate_param_words: u32,
@@ -62,27 +123,50 @@ pub(crate) struct IpcRequest<S: state::State = state::Normal> {
 impl IpcRequest<state::Normal> {
     #[inline]
     pub fn command(id: u16) -> Self {
-        let mut cmdbuf = CommandBufferWriter::new(CommandBuffer::get());
-        // # Safety
-        // `end_ptr` points inside of command buffer
-        unsafe { cmdbuf.advance() }; // write the header last
         Self {
-            cmdbuf,
+            cmdbuf: CommandBufferWriter::new(CommandBuffer::get()),
+            param_words: 0,
+            translate_param_words: 0,
+            id,
+            _state: PhantomData,
+        }
+    }
+
+    /// Start a request whose parameters take up `WORDS` words in total.
+    ///
+    /// Whether they fit the command buffer is checked once at compile time, so writing the
+    /// parameters skips the checks.
+    ///
+    /// # Safety
+    ///
+    /// At most `WORDS` words of parameters may be written to the request.
+    #[inline]
+    pub unsafe fn command_sized<const WORDS: usize>(id: u16) -> IpcRequest<state::Normal, true> {
+        const {
+            // One word is taken by the header
+            assert!(
+                WORDS < COMMAND_BUFFER_LENGTH,
+                "IPC request does not fit the command buffer"
+            )
+        };
+
+        IpcRequest {
+            cmdbuf: CommandBufferWriter::new(CommandBuffer::get()),
             param_words: 0,
             translate_param_words: 0,
             id,
             _state: PhantomData,
         }
     }
+}
 
+impl<const RESERVED: bool> IpcRequest<state::Normal, RESERVED> {
     #[inline]
-    pub fn parameter<P>(mut self, parameter: P) -> Self
+    pub fn parameter<P>(self, parameter: P) -> Self
     where
         P: IpcParameter,
     {
-        self.cmdbuf.write(parameter.encode());
-        self.param_words += 1;
-        self
+        self.parameters(&[parameter])
     }
 
     #[inline]
@@ -90,25 +174,29 @@ impl IpcRequest<state::Normal> {
     where
         P: IpcParameter,
     {
-        for parameter in parameters {
-            self.cmdbuf.write(parameter.encode());
-        }
-        self.param_words += parameters.len() as u32;
+        let words = parameters.each_ref().map(|parameter| parameter.encode());
+        let written = match RESERVED {
+            // SAFETY: Room for all parameters was reserved by `command_sized`
+            true => unsafe { self.cmdbuf.write_words_unchecked(words) },
+            false => self.cmdbuf.write_words(words),
+        };
+        self.param_words += written as u32;
         self
     }
 }
 
-impl<S: state::State> IpcRequest<S> {
+impl<S: state::State, const RESERVED: bool> IpcRequest<S, RESERVED> {
     #[inline]
-    pub fn translate_parameter<P>(mut self, parameter: P) -> IpcRequest<state::Translate>
+    pub fn translate_parameter<P>(mut self, parameter: P) -> IpcRequest<state::Translate, RESERVED>
     where
         P: TranslateParameter,
     {
         let pos = self.cmdbuf.pos();
-        let before = self.cmdbuf.end_ptr;
-        parameter.encode(&mut self.cmdbuf);
-
-        let size = unsafe { self.cmdbuf.end_ptr.offset_from(before) as u32 };
+        let size = match RESERVED {
+            // SAFETY: Room for all parameters was reserved by `command_sized`
+            true => unsafe { self.cmdbuf.write_translate_unchecked(parameter) },
+            false => self.cmdbuf.write_translate(parameter),
+        } as u32;
 
         trace!("request[{}] = <size: {}>", pos, size);
 
@@ -123,7 +211,6 @@ impl<S: state::State> IpcRequest<S> {
 
     #[inline]
     pub fn dispatch_impl(self, receiver: BorrowedHandle) -> Result<(ResultCode, IpcReply)> {
-        let cmdbuf = self.cmdbuf.finish();
         let header = IpcHeader::new(
             self.id,
             self.param_words as usize,
@@ -132,8 +219,7 @@ impl<S: state::State> IpcRequest<S> {
 
         trace!("Dispatching IPC command: header = {:#x?}", header);
 
-        // Write IPC header
-        unsafe { cmdbuf.start().write(header.into()) }
+        let cmdbuf = self.cmdbuf.finish(header);
 
         let mut reply = match unsafe { svc::send_sync_request(receiver, cmdbuf.into_inner()) } {
             Ok(reply_buffer) => unsafe { IpcReply::new(reply_buffer) },
@@ -0,0 +1,313 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! # Server side of inter-process communication
+//!
+//! [`Command`] parses a request that was received into this thread's command buffer (e.g. by
+//! [`svc::reply_and_receive`](crate::svc::reply_and_receive)), and [`ReplyBuilder`] composes the
+//! response in place.
+
+use super::request::CommandBufferWriter;
+use super::{
+    CommandBuffer, IpcHeader, TranslateParameter, COMMAND_BUFFER_LENGTH, FLAG_MOVE_HANDLE,
+    FLAG_REPLACE_PID, TYPE_HANDLE, TYPE_STATIC_BUFFER,
+};
+use crate::os::{BorrowedHandle, OwnedHandle};
+use crate::result::{ResultCode, ResultValue};
+
+use alloc::vec::Vec;
+
+use core::fmt;
+
+use log::{trace, warn};
+
+const TYPE_MASK: u32 = 0b1110;
+const FLAG_MAPPED_BUFFER: u32 = 1 << 3;
+
+/// Access rights granted to the receiver of a mapped buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferPermission {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl BufferPermission {
+    fn from_descriptor(descriptor: u32) -> Option<Self> {
+        match (descriptor >> 1) & 0b11 {
+            1 => Some(Self::Read),
+            2 => Some(Self::Write),
+            3 => Some(Self::ReadWrite),
+            _ => None,
+        }
+    }
+}
+
+/// A translate parameter sent along with a [`Command`].
+///
+/// Handles sent by the client (moved or copied) are installed into this process by the kernel, so
+/// they are owned by the receiver either way.
+#[derive(Debug)]
+pub enum TranslatedParameter {
+    Handles {
+        moved: bool,
+        handles: Vec<Option<OwnedHandle>>,
+    },
+    ProcessId(u32),
+    StaticBuffer {
+        index: u8,
+        buffer: *const u8,
+        size: usize,
+    },
+    MappedBuffer {
+        permission: BufferPermission,
+        buffer: *mut u8,
+        size: usize,
+    },
+}
+
+/// Error returned when a translate descriptor is malformed or of an unsupported kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidDescriptor(pub u32);
+
+impl fmt::Display for InvalidDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid translate descriptor {:#010x}", self.0)
+    }
+}
+
+/// A request received from a client.
+pub struct Command {
+    header: IpcHeader,
+    buffer: *const u32,
+    translate_pos: usize,
+}
+
+impl Command {
+    /// Parse the command currently stored in this thread's command buffer.
+    pub fn receive() -> Self {
+        let buffer = CommandBuffer::get();
+
+        unsafe { Self::from_buffer(buffer.start()) }
+    }
+
+    /// Parse the command stored at `buffer`.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must point to a command buffer of `0x80` words that stays valid and unmodified
+    /// for the lifetime of the returned `Command`.
+    pub unsafe fn from_buffer(buffer: *const u32) -> Self {
+        let header = IpcHeader::from(buffer.read());
+
+        trace!("Received IPC command: header = {:#x?}", header);
+
+        Self {
+            header,
+            buffer,
+            translate_pos: 1 + header.normal_param_words(),
+        }
+    }
+
+    pub const fn header(&self) -> IpcHeader {
+        self.header
+    }
+
+    pub const fn id(&self) -> u16 {
+        self.header.command_id()
+    }
+
+    fn translate_end(&self) -> usize {
+        (1 + self.header.normal_param_words() + self.header.translate_param_words())
+            .min(COMMAND_BUFFER_LENGTH)
+    }
+
+    /// The normal parameter words of this command.
+    pub fn parameters(&self) -> &[u32] {
+        let len = self
+            .header
+            .normal_param_words()
+            .min(COMMAND_BUFFER_LENGTH - 1);
+
+        unsafe { core::slice::from_raw_parts(self.buffer.add(1), len) }
+    }
+
+    #[inline]
+    pub fn parameter(&self, index: usize) -> Option<u32> {
+        self.parameters().get(index).copied()
+    }
+
+    fn read_translate_word(&mut self) -> Option<u32> {
+        if self.translate_pos < self.translate_end() {
+            let word = unsafe { self.buffer.add(self.translate_pos).read() };
+            self.translate_pos += 1;
+            Some(word)
+        } else {
+            None
+        }
+    }
+
+    /// Parse the next translate parameter.
+    ///
+    /// Returns `None` once all translate parameters have been consumed.  Handles are taken out of
+    /// the command buffer, so each translate parameter can only be read once.
+    pub fn next_translate_parameter(
+        &mut self,
+    ) -> Option<Result<TranslatedParameter, InvalidDescriptor>> {
+        let descriptor = self.read_translate_word()?;
+
+        let parameter = if descriptor & FLAG_MAPPED_BUFFER != 0 {
+            self.read_mapped_buffer(descriptor)
+        } else {
+            match descriptor & TYPE_MASK {
+                TYPE_HANDLE => self.read_handles(descriptor),
+                TYPE_STATIC_BUFFER => self.read_static_buffer(descriptor),
+                _ => None,
+            }
+        };
+
+        Some(parameter.ok_or_else(|| {
+            warn!(
+                "Received invalid translate descriptor: {:#010x}",
+                descriptor
+            );
+            InvalidDescriptor(descriptor)
+        }))
+    }
+
+    fn read_handles(&mut self, descriptor: u32) -> Option<TranslatedParameter> {
+        let count = (descriptor >> 26) as usize + 1;
+
+        if descriptor & FLAG_REPLACE_PID != 0 {
+            return self
+                .read_translate_word()
+                .map(TranslatedParameter::ProcessId);
+        }
+
+        let mut handles = Vec::with_capacity(count);
+        for _ in 0..count {
+            let raw_handle = self.read_translate_word()?;
+            handles.push(unsafe { OwnedHandle::new(raw_handle) });
+        }
+
+        Some(TranslatedParameter::Handles {
+            moved: descriptor & FLAG_MOVE_HANDLE != 0,
+            handles,
+        })
+    }
+
+    fn read_static_buffer(&mut self, descriptor: u32) -> Option<TranslatedParameter> {
+        let buffer = self.read_translate_word()? as *const u8;
+
+        Some(TranslatedParameter::StaticBuffer {
+            index: ((descriptor >> 10) & 0b1111) as u8,
+            buffer,
+            size: (descriptor >> 14) as usize,
+        })
+    }
+
+    fn read_mapped_buffer(&mut self, descriptor: u32) -> Option<TranslatedParameter> {
+        let permission = BufferPermission::from_descriptor(descriptor)?;
+        let buffer = self.read_translate_word()? as *mut u8;
+
+        Some(TranslatedParameter::MappedBuffer {
+            permission,
+            buffer,
+            size: (descriptor >> 4) as usize,
+        })
+    }
+}
+
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Command")
+            .field("header", &self.header)
+            .field("parameters", &self.parameters())
+            .finish()
+    }
+}
+
+/// Compose a reply to a [`Command`] in this thread's command buffer.
+///
+/// The result code is always the first normal parameter.  All normal parameters have to be
+/// written before any translate parameters.
+pub struct ReplyBuilder {
+    cmdbuf: CommandBufferWriter,
+    id: u16,
+    param_words: usize,
+    translate_param_words: usize,
+}
+
+impl ReplyBuilder {
+    pub fn new(command_id: u16, result: ResultCode) -> Self {
+        let reply = Self {
+            cmdbuf: CommandBufferWriter::new(CommandBuffer::get()),
+            id: command_id,
+            param_words: 0,
+            translate_param_words: 0,
+        };
+
+        reply.parameter(result.value())
+    }
+
+    #[inline]
+    pub fn parameter(mut self, word: u32) -> Self {
+        if self.translate_param_words != 0 {
+            panic!("Normal reply parameters must be written before translate parameters");
+        }
+
+        self.param_words += self.cmdbuf.write_words([word]);
+        self
+    }
+
+    #[inline]
+    pub fn parameters<const N: usize>(self, words: &[u32; N]) -> Self {
+        words
+            .iter()
+            .fold(self, |reply, word| reply.parameter(*word))
+    }
+
+    #[inline]
+    fn translate<P: TranslateParameter>(mut self, parameter: P) -> Self {
+        self.translate_param_words += self.cmdbuf.write_translate(parameter);
+        self
+    }
+
+    /// Transfer ownership of `handles` to the client.
+    pub fn move_handles<const N: usize>(self, handles: [OwnedHandle; N]) -> Self {
+        self.translate(handles)
+    }
+
+    /// Send copies of `handles` to the client.
+    pub fn copy_handles<const N: usize>(self, handles: [BorrowedHandle<'_>; N]) -> Self {
+        self.translate(handles)
+    }
+
+    /// Copy `data` into the client's static buffer `target_id`.
+    pub fn static_buffer(self, data: &[u32], target_id: u8) -> Self {
+        self.translate(super::StaticBuffer::new(data, target_id))
+    }
+
+    /// Write the reply header.
+    ///
+    /// Afterwards, the command buffer is ready to be sent with
+    /// [`svc::reply_and_receive`](crate::svc::reply_and_receive).
+    pub fn finish(self) {
+        let header = IpcHeader::new(self.id, self.param_words, self.translate_param_words);
+
+        trace!("Prepared IPC reply: header = {:#x?}", header);
+
+        let _ = self.cmdbuf.finish(header);
+    }
+}
+
+impl fmt::Debug for ReplyBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReplyBuilder")
+            .field("id", &self.id)
+            .field("param_words", &self.param_words)
+            .field("translate_param_words", &self.translate_param_words)
+            .finish()
+    }
+}
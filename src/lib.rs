@@ -10,31 +10,52 @@
 #![feature(new_uninit, maybe_uninit_array_assume_init)]
 #![feature(atomic_from_mut)]
 #![feature(link_llvm_intrinsics)]
+#![cfg_attr(
+    feature = "unwind",
+    feature(lang_items, core_intrinsics, panic_can_unwind)
+)]
+#![cfg_attr(feature = "unwind", allow(internal_features))]
 // Allow dead code for now
 #![allow(dead_code)]
 #![allow(clippy::missing_safety_doc)]
 
+pub mod applets;
+mod context;
 pub mod debug;
 pub mod env;
 pub mod graphics;
 pub mod heap;
 pub mod ipc;
 pub mod os;
+#[cfg(feature = "panic-handler")]
+mod panic;
 pub mod ports;
 pub mod result;
+pub mod rt;
 pub mod services;
 pub mod svc;
 pub mod sync;
+pub mod testing;
 pub mod thread;
 pub mod tls;
+#[cfg(feature = "unwind")]
+pub mod unwind;
 
 extern crate alloc;
 extern crate core;
+#[cfg(test)]
+extern crate std;
+// Lets derives refer to this crate as `::ctru_rt` from within it
+extern crate self as ctru_rt;
 
-pub use ctru_rt_macros::entry;
+pub use context::Services;
+pub use ctru_rt_macros::{ctru_test, entry};
+pub use rt::exit;
 
+#[cfg(target_arch = "arm")]
 use core::arch::global_asm;
 
+#[cfg(target_arch = "arm")]
 global_asm! {
     include_str!("../rsrt0.S"),
     options(raw),
@@ -52,18 +73,28 @@ global_asm! {
 //     };
 // }
 
+#[cfg(target_arch = "arm")]
 #[no_mangle]
 unsafe extern "C" fn _ctru_rt_start() {
+    extern "Rust" {
+        fn _ctru_rt_configure();
+        fn _ctru_rt_entry();
+    }
+
+    crate::thread::init_main_thread();
+    _ctru_rt_configure();
     crate::heap::init().expect("Failed to initialize heap");
     crate::early_debug!("Mapped heap.");
     crate::graphics::vram::init();
     crate::early_debug!("Mapped VRAM linear memory.");
+    crate::rt::run_init_array();
 
-    extern "Rust" {
-        fn _ctru_rt_entry();
+    unsafe extern "C" fn entry() {
+        _ctru_rt_entry()
     }
 
-    _ctru_rt_entry();
+    crate::thread::run_main(entry);
+    crate::rt::exit(0)
 }
 
 #[doc(hidden)]
@@ -72,15 +103,30 @@ unsafe extern "C" fn _ctru_rt_start() {
 /// Until we implement stack unwinding, this shouldn't be necessary.  But trying to use the alloc
 /// crate requires this symbol and the linker gets angry if it can't find it.  So some time in the
 /// future we should figure out how and under which circumstances we can get rid of it.
+///
+/// With the `unwind` feature, [`unwind`] provides the real personality routines.
+#[cfg(not(feature = "unwind"))]
 #[no_mangle]
 pub fn __aeabi_unwind_cpp_pr1() {}
 
+#[doc(hidden)]
+#[cfg(not(feature = "unwind"))]
 #[no_mangle]
 pub fn __aeabi_unwind_cpp_pr0() {}
 
 pub mod util {
-    trait EnumCast: Sized {
+    /// Conversion of field-less enums from and to integer values, implemented by
+    /// `#[derive(EnumCast)]`.
+    ///
+    /// The derive also provides `const fn`s `from_value` and `to_value` and a `VALUES` constant on
+    /// the enum itself.  With `#[enum_cast(conversions)]`, it implements `TryFrom` for the enum and
+    /// `From` for the value type, too.
+    pub trait EnumCast: Sized + 'static {
         type Value;
+
+        /// All variants, in order of declaration.
+        const VALUES: &'static [Self];
+
         fn parse_value(value: Self::Value) -> core::result::Result<Self, Self::Value>;
 
         fn as_value(&self) -> Self::Value;
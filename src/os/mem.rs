@@ -3,9 +3,21 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use super::MemoryRegion;
+use crate::result::{CommonDescription, ErrorCode, Level, Module, Result, Summary};
+use crate::svc;
+
+use core::ops::{BitAnd, BitOr, BitOrAssign};
 
 use ctru_rt_macros::EnumCast;
 
+/// Returned when the kernel reports a memory permission or state that is not known.
+pub(crate) const ERROR_INVALID_MEMORY_INFO: ErrorCode = ErrorCode::new(
+    Level::Fatal,
+    Summary::InvalidResultValue,
+    Module::Os,
+    CommonDescription::InvalidResultValue.to_value(),
+);
+
 #[repr(u32)]
 #[derive(Debug, Clone, Copy)]
 pub enum MemoryOperationTarget {
@@ -50,28 +62,87 @@ impl MemoryOperation {
         Self(MemoryOperationAction::Allocate as u32)
     }
 
+    #[inline]
+    pub const fn free() -> Self {
+        Self(MemoryOperationAction::Free as u32)
+    }
+
     #[inline]
     pub const fn linear(self) -> Self {
         Self(self.0 | MemoryOperationTarget::Linear as u32)
     }
 }
 
-#[repr(u32)]
-#[derive(Debug, Clone, Copy, EnumCast)]
-pub enum MemoryPermission {
-    None = 0,
-    R = 1,
-    W = 2,
-    Rw = 3,
-    X = 4,
-    Rx = 5,
-    Wx = 6,
-    Rwx = 7,
-    DontCare = 0x1000_0000,
+/// Access permissions of a memory mapping, combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MemoryPermission(u32);
+
+impl MemoryPermission {
+    pub const NONE: Self = Self(0);
+    pub const R: Self = Self(1);
+    pub const W: Self = Self(2);
+    pub const X: Self = Self(4);
+    pub const RW: Self = Self::R.union(Self::W);
+    pub const RX: Self = Self::R.union(Self::X);
+    /// Let the kernel pick the permission, only valid as the permission of the other process when
+    /// mapping shared memory.
+    pub const DONT_CARE: Self = Self(0x1000_0000);
+
+    const ALL: Self = Self(Self::R.0 | Self::W.0 | Self::X.0 | Self::DONT_CARE.0);
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn from_bits(bits: u32) -> Option<Self> {
+        if bits & !Self::ALL.0 == 0 {
+            Some(Self(bits))
+        } else {
+            None
+        }
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
 }
 
-#[repr(u32)]
-#[derive(Debug, Clone, Copy)]
+impl BitOr for MemoryPermission {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(other)
+    }
+}
+
+impl BitOrAssign for MemoryPermission {
+    fn bitor_assign(&mut self, other: Self) {
+        *self = self.union(other)
+    }
+}
+
+impl BitAnd for MemoryPermission {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+impl TryFrom<u32> for MemoryPermission {
+    type Error = ErrorCode;
+
+    fn try_from(bits: u32) -> Result<Self> {
+        Self::from_bits(bits).ok_or(ERROR_INVALID_MEMORY_INFO)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[enum_cast(value_type = "u32", conversions)]
 pub enum MemoryState {
     Free = 0,
     Reserved = 1,
@@ -95,3 +166,76 @@ pub struct QueryResult {
     pub state: MemoryState,
     pub page_flags: u32,
 }
+
+/// Translate a virtual address in linear memory, VRAM or DSP memory to the physical address seen
+/// by hardware like the GPU or DSP.
+///
+/// Returns `None` for addresses outside of these regions.
+pub const fn virtual_to_physical(address: usize) -> Option<usize> {
+    const REGIONS: [(usize, usize, usize); 4] = [
+        // (virtual start, size, physical start)
+        (0x1400_0000, 0x0800_0000, 0x2000_0000), // linear heap, before system version 8.0
+        (0x3000_0000, 0x1000_0000, 0x2000_0000), // linear heap
+        (0x1F00_0000, 0x0060_0000, 0x1800_0000), // VRAM
+        (0x1FF0_0000, 0x0008_0000, 0x1FF0_0000), // DSP memory
+    ];
+
+    let mut i = 0;
+    while i < REGIONS.len() {
+        let (start, size, physical) = REGIONS[i];
+        if address >= start && address - start < size {
+            return Some(address - start + physical);
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// End of the address space accessible to user processes.
+const USER_ADDRESS_SPACE_END: usize = 0x4000_0000;
+
+/// Iterator over the memory blocks of this process, see [`regions`].
+#[derive(Debug, Clone)]
+pub struct Regions {
+    next: Option<usize>,
+    end: usize,
+}
+
+impl Iterator for Regions {
+    type Item = Result<QueryResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let address = self.next.filter(|&address| address < self.end)?;
+
+        let block = match unsafe { svc::query_memory(address) } {
+            Ok(block) => block,
+            Err(e) => {
+                self.next = None;
+                return Some(Err(e));
+            }
+        };
+
+        self.next = block
+            .base_process_virtual_address
+            .checked_add(block.size)
+            .filter(|&next| next > address);
+
+        Some(Ok(block))
+    }
+}
+
+/// Iterate over all memory blocks in the address space of this process, including free ones.
+pub fn regions() -> Regions {
+    regions_within(0, USER_ADDRESS_SPACE_END)
+}
+
+/// Iterate over the memory blocks overlapping the addresses from `start` up to `end`.
+///
+/// The first block may begin before `start`, and the last block may extend beyond `end`.
+pub fn regions_within(start: usize, end: usize) -> Regions {
+    Regions {
+        next: Some(start),
+        end,
+    }
+}
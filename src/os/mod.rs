@@ -2,16 +2,26 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{result::Result, svc};
+use crate::ports::srv::Srv;
+use crate::services::cfg::{Cfg, SystemModel};
+use crate::services::ptm::{New3dsCpuConfig, PtmSysm};
+use crate::{result::Result, svc, sync::OnceCell};
 
+use core::ops::{Add, AddAssign, Sub};
+use core::time::Duration;
 use core::{fmt, marker::PhantomData, num::NonZeroU32};
 
 use log::debug;
 
 pub mod cfgmem;
 pub mod mem;
+pub mod process;
 pub mod reslimit;
 pub mod sharedmem;
+pub mod sharedpage;
+pub mod time;
+
+pub use process::Process;
 
 pub type RawHandle = u32;
 pub type ValidRawHandle = NonZeroU32;
@@ -176,10 +186,45 @@ impl<T: AsHandle> AsHandle for &mut T {
     }
 }
 
+/// Frequency of the SoC clock, in Hz.
+pub const SYSCLOCK_SOC: u64 = 16_756_991;
+/// Frequency of the ARM9 clock, in Hz.
+pub const SYSCLOCK_ARM9: u64 = SYSCLOCK_SOC * 8;
+/// Frequency of the ARM11 clock, in Hz.
+pub const SYSCLOCK_ARM11: u64 = SYSCLOCK_ARM9 * 2;
+/// Frequency of the ARM11 clock of a New 3DS in high performance mode, in Hz.
+pub const SYSCLOCK_ARM11_NEW: u64 = SYSCLOCK_ARM11 * 3;
+
+/// Convert a number of [`SystemTick`]s to a duration.
+pub const fn duration_from_ticks(ticks: u64) -> Duration {
+    let seconds = ticks / SystemTick::TICKS_PER_SECOND;
+    let sub_second_ticks = ticks % SystemTick::TICKS_PER_SECOND;
+    let nanos = sub_second_ticks * 1_000_000_000 / SystemTick::TICKS_PER_SECOND;
+
+    Duration::new(seconds, nanos as u32)
+}
+
+/// Convert a duration to a number of [`SystemTick`]s, rounding down and saturating at
+/// `u64::MAX`.
+pub const fn ticks_from_duration(duration: Duration) -> u64 {
+    let seconds = duration
+        .as_secs()
+        .saturating_mul(SystemTick::TICKS_PER_SECOND);
+    let sub_second_ticks =
+        duration.subsec_nanos() as u64 * SystemTick::TICKS_PER_SECOND / 1_000_000_000;
+
+    seconds.saturating_add(sub_second_ticks)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SystemTick(u64);
 
 impl SystemTick {
+    /// Frequency of the system tick counter, i.e. the ARM11 clock, in Hz.
+    ///
+    /// The counter runs at this rate even when a New 3DS runs at a higher clock.
+    pub const TICKS_PER_SECOND: u64 = SYSCLOCK_ARM11;
+
     pub fn new(ticks: u64) -> Self {
         Self(ticks)
     }
@@ -192,3 +237,147 @@ impl SystemTick {
         self.0
     }
 }
+
+/// Version of the kernel or FIRM, ordered by `major`, then `minor`, then `revision`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub revision: u8,
+}
+
+impl SystemVersion {
+    pub const fn new(major: u8, minor: u8, revision: u8) -> Self {
+        Self {
+            major,
+            minor,
+            revision,
+        }
+    }
+
+    pub fn is_at_least(&self, major: u8, minor: u8) -> bool {
+        (self.major, self.minor) >= (major, minor)
+    }
+}
+
+impl fmt::Display for SystemVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}-{}", self.major, self.minor, self.revision)
+    }
+}
+
+/// Version of the running kernel.
+pub fn version() -> SystemVersion {
+    SystemVersion::new(
+        cfgmem::KERNEL_VERSIONMAJOR.read(),
+        cfgmem::KERNEL_VERSIONMINOR.read(),
+        cfgmem::KERNEL_VERSIONREVISION.read(),
+    )
+}
+
+/// Version of the FIRM the system booted from.
+pub fn firm_version() -> SystemVersion {
+    SystemVersion::new(
+        cfgmem::FIRM_VERSIONMAJOR.read(),
+        cfgmem::FIRM_VERSIONMINOR.read(),
+        cfgmem::FIRM_VERSIONREVISION.read(),
+    )
+}
+
+/// Check whether this is running on a New 3DS, New 3DS XL or New 2DS XL.
+///
+/// The model is read from `cfg` once and cached.
+pub fn is_new_3ds(srv: &Srv) -> Result<bool> {
+    static SYSTEM_MODEL: OnceCell<SystemModel> = OnceCell::new();
+
+    let model = SYSTEM_MODEL.get_or_try_init(|| Cfg::init(srv)?.system_model())?;
+    Ok(model.is_new_3ds())
+}
+
+/// Enable or disable the 804 MHz clock and L2 cache of a New 3DS.
+///
+/// Does nothing on other models.
+pub fn set_high_performance(srv: &Srv, enabled: bool) -> Result<()> {
+    if !is_new_3ds(srv)? {
+        return Ok(());
+    }
+
+    let config = match enabled {
+        true => New3dsCpuConfig::legacy().high_clock().l2_cache(),
+        false => New3dsCpuConfig::legacy(),
+    };
+
+    PtmSysm::init(srv)?.configure_new_3ds_cpu(config)
+}
+
+/// A measurement of the monotonic [`SystemTick`] counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Self {
+        Self(SystemTick::now().count())
+    }
+
+    pub const fn from_tick(tick: SystemTick) -> Self {
+        Self(tick.count())
+    }
+
+    pub const fn tick(&self) -> SystemTick {
+        SystemTick(self.0)
+    }
+
+    /// Time passed since `self`.
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    /// Time passed from `earlier` to `self`, or zero if `earlier` is later than `self`.
+    pub fn duration_since(&self, earlier: Self) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+
+    pub fn checked_duration_since(&self, earlier: Self) -> Option<Duration> {
+        self.0.checked_sub(earlier.0).map(duration_from_ticks)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_add(ticks_from_duration(duration)).map(Self)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_sub(ticks_from_duration(duration)).map(Self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Self;
+
+    fn add(self, duration: Duration) -> Self {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Self;
+
+    fn sub(self, duration: Duration) -> Self {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from instant")
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Self) -> Duration {
+        self.duration_since(earlier)
+    }
+}
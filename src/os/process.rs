@@ -0,0 +1,74 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::result::Result;
+use crate::svc::{self, Timeout, WaitOutcome};
+
+use super::reslimit::{process_limits, ProcessLimits};
+use super::{AsHandle, BorrowedHandle, OwnedHandle};
+
+/// A handle to a process, either the current one or one opened by ID.
+#[derive(Debug)]
+pub struct Process {
+    handle: Option<OwnedHandle>,
+}
+
+impl Process {
+    /// The process this code runs in.
+    pub const fn current() -> Self {
+        Self { handle: None }
+    }
+
+    /// Open the process with ID `id`.
+    ///
+    /// This requires the `OpenProcess` system call to be enabled for this process.
+    pub fn open(id: u32) -> Result<Self> {
+        let handle = svc::open_process(id)?;
+        Ok(Self {
+            handle: Some(handle),
+        })
+    }
+
+    pub unsafe fn from_handle(handle: OwnedHandle) -> Self {
+        Self {
+            handle: Some(handle),
+        }
+    }
+
+    pub fn is_current(&self) -> bool {
+        self.handle.is_none()
+    }
+
+    pub fn id(&self) -> Result<u32> {
+        svc::get_process_id(self.as_handle())
+    }
+
+    pub fn resource_limits(&self) -> Result<ProcessLimits<'_>> {
+        process_limits(self.as_handle())
+    }
+
+    /// Wait until the process has exited.
+    ///
+    /// Waiting for the current process never returns successfully.
+    pub fn wait_for_exit(&self, timeout: Timeout) -> Result<WaitOutcome> {
+        svc::wait_synchronization(self.as_handle(), timeout)
+    }
+
+    /// Terminate the process.
+    ///
+    /// This requires the `TerminateProcess` system call to be enabled for this process.  To end
+    /// the current process, use [`svc::exit_process`] instead.
+    pub fn terminate(&self) -> Result<()> {
+        svc::terminate_process(self.as_handle())
+    }
+}
+
+impl AsHandle for Process {
+    fn as_handle(&self) -> BorrowedHandle<'_> {
+        match &self.handle {
+            Some(handle) => handle.as_handle(),
+            None => BorrowedHandle::active_process(),
+        }
+    }
+}
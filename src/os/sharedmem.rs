@@ -2,14 +2,17 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use core::sync::atomic::AtomicU32;
 
 use super::{
-    mem::{MemoryPermission, MemoryState, QueryResult},
+    mem::{self, MemoryPermission, MemoryState, QueryResult},
     OwnedHandle,
 };
 use crate::result::{Result, ERROR_OUT_OF_MEMORY};
 use crate::svc;
+use crate::sync::LightMutex;
+
+use alloc::vec::Vec;
 
 use log::debug;
 
@@ -51,28 +54,30 @@ impl MappedBlock {
     }
 }
 
+/// Maps shared memory blocks into the shared memory region of the address space.
+///
+/// The mapper keeps track of the blocks it mapped, so blocks can be unmapped in any order without
+/// losing address space, and checks with the kernel that a gap is actually free before using it.
 #[derive(Debug)]
 pub struct SharedMemoryMapper {
-    next_candidate: AtomicUsize,
+    /// `(start, size)` of every mapped block, sorted by `start`.
+    mapped: LightMutex<Vec<(usize, usize)>>,
 }
 
 const SHAREDMEM_START: usize = 0x1000_0000;
 const SHAREDMEM_END: usize = 0x1400_0000;
 
-static mut GLOBAL_SHAREDMEMORY_MAPPER: SharedMemoryMapper = SharedMemoryMapper::new();
+static GLOBAL_SHAREDMEMORY_MAPPER: SharedMemoryMapper = SharedMemoryMapper::new();
 
 impl SharedMemoryMapper {
     pub const fn new() -> Self {
         Self {
-            next_candidate: AtomicUsize::new(SHAREDMEM_START),
+            mapped: LightMutex::new(Vec::new()),
         }
     }
 
     pub(crate) fn global() -> &'static Self {
-        // (UN)SAFETY: I *know* global statics are bad.
-        //
-        // This will get a proper implementation once there's support for `RwLock`s.
-        unsafe { &GLOBAL_SHAREDMEMORY_MAPPER }
+        &GLOBAL_SHAREDMEMORY_MAPPER
     }
 
     pub fn map(
@@ -84,11 +89,8 @@ impl SharedMemoryMapper {
     ) -> Result<MappedBlock> {
         let size = (size + 0xFFF) & !0xFFF;
 
-        let candidate = self.next_candidate.load(Ordering::Acquire);
-        let address = Self::find_gap(candidate, size)?.ok_or(ERROR_OUT_OF_MEMORY)?;
-
-        let next = candidate.saturating_add(size).min(SHAREDMEM_END);
-        self.next_candidate.store(next, Ordering::Release);
+        let mut mapped = self.mapped.lock();
+        let address = Self::find_gap(&mapped, size)?.ok_or(ERROR_OUT_OF_MEMORY)?;
 
         debug!(
             "Mapping memory block at {:p}, size = 0x{:x}, handle = {:?}",
@@ -107,6 +109,9 @@ impl SharedMemoryMapper {
             )?
         };
 
+        let index = mapped.partition_point(|&(start, _)| start < address);
+        mapped.insert(index, (address, size));
+
         Ok(MappedBlock {
             start: address,
             size,
@@ -115,9 +120,11 @@ impl SharedMemoryMapper {
     }
 
     pub fn unmap(&self, block: MappedBlock) -> Result<OwnedHandle> {
+        let mut mapped = self.mapped.lock();
+
         unsafe { svc::unmap_memory_block(block.handle.handle(), block.start as usize)? }
 
-        self.next_candidate.store(block.start, Ordering::Release);
+        mapped.retain(|&(start, _)| start != block.start);
 
         Ok(block.handle)
     }
@@ -128,36 +135,39 @@ impl SharedMemoryMapper {
     }
 
     fn find_gap_within(start: usize, end: usize, size: usize) -> Result<Option<usize>> {
-        let mut candidate_addr = start;
-
-        while candidate_addr
-            .checked_add(size)
-            .map(|chunk_end| chunk_end < end)
-            .unwrap_or(false)
-        {
-            let block: QueryResult = unsafe { svc::query_memory(candidate_addr)? };
+        for block in mem::regions_within(start, end) {
+            let block = block?;
 
             if let MemoryState::Free = block.state {
+                let candidate_addr = block.base_process_virtual_address.max(start);
+                let fits_before_end = candidate_addr
+                    .checked_add(size)
+                    .map(|chunk_end| chunk_end <= end)
+                    .unwrap_or(false);
+
                 if let Some(free) = Self::remaining_free(&block, candidate_addr) {
-                    if free >= size {
+                    if free >= size && fits_before_end {
                         return Ok(Some(candidate_addr));
                     }
                 }
             };
-
-            match candidate_addr.checked_add(block.size) {
-                Some(next) => candidate_addr = next,
-                None => return Ok(None),
-            }
         }
 
         Ok(None)
     }
 
-    fn find_gap(candidate: usize, size: usize) -> Result<Option<usize>> {
-        match Self::find_gap_within(candidate, SHAREDMEM_END, size)? {
-            Some(address) => Ok(Some(address)),
-            None => Self::find_gap_within(SHAREDMEM_START, candidate, size),
+    fn find_gap(mapped: &[(usize, usize)], size: usize) -> Result<Option<usize>> {
+        let mut gap_start = SHAREDMEM_START;
+
+        for &(start, block_size) in mapped {
+            if start.saturating_sub(gap_start) >= size {
+                if let Some(address) = Self::find_gap_within(gap_start, start, size)? {
+                    return Ok(Some(address));
+                }
+            }
+            gap_start = start + block_size;
         }
+
+        Self::find_gap_within(gap_start, SHAREDMEM_END, size)
     }
 }
@@ -0,0 +1,129 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! # The shared page
+//!
+//! The kernel maps a page of system state at `0x1FF81000` into every process, which is kept up to
+//! date by system modules and can be polled without IPC.
+
+use core::sync::atomic::{fence, Ordering};
+
+use super::cfgmem::CfgMemPointer;
+
+macro_rules! sharedpage_entry {
+    ($addr: expr, $name: ident, $width: ty) => {
+        pub const $name: CfgMemPointer<$width> = unsafe { CfgMemPointer::new($addr) };
+    };
+}
+
+sharedpage_entry!(0x1FF81000, DATETIME_SELECTOR, u32);
+sharedpage_entry!(0x1FF81004, RUNNING_HW, u8);
+sharedpage_entry!(0x1FF81005, MCU_HW_INFO, u8);
+sharedpage_entry!(0x1FF81020, DATETIME_0, RawDateTime);
+sharedpage_entry!(0x1FF81040, DATETIME_1, RawDateTime);
+sharedpage_entry!(0x1FF81060, WIFI_MACADDR, [u8; 6]);
+sharedpage_entry!(0x1FF81066, WIFI_LINK_LEVEL, u8);
+sharedpage_entry!(0x1FF81067, NETWORK_STATE, u8);
+sharedpage_entry!(0x1FF81080, SLIDER_3D_STATE, f32);
+sharedpage_entry!(0x1FF81084, LED_3D_STATE, u8);
+sharedpage_entry!(0x1FF81085, LED_BATTERY_STATE, u8);
+sharedpage_entry!(0x1FF810A0, MENU_TITLE_ID, u64);
+sharedpage_entry!(0x1FF810A8, ACTIVE_MENU_TITLE_ID, u64);
+sharedpage_entry!(0x1FF810C0, HEADSET_CONNECTED, u8);
+
+/// Date and time as last written by the RTC, see [`date_time`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct RawDateTime {
+    /// Milliseconds since 1900-01-01 00:00:00.
+    pub milliseconds_since_1900: u64,
+    /// [`SystemTick`](super::SystemTick) count at the time of the update.
+    pub update_tick: u64,
+    pub tick_to_second_coefficient: u64,
+    pub tick_offset: u64,
+}
+
+/// Read the current date and time entry.
+///
+/// The entry is only updated about once per second, see [`RawDateTime::update_tick`].
+pub fn date_time() -> RawDateTime {
+    // The kernel writes to the inactive entry and flips the selector afterwards; retry if it
+    // flipped while reading.
+    let mut selector = DATETIME_SELECTOR.read();
+    loop {
+        fence(Ordering::Acquire);
+        let date_time = match selector & 1 {
+            0 => DATETIME_0.read(),
+            _ => DATETIME_1.read(),
+        };
+        fence(Ordering::Acquire);
+
+        let current = DATETIME_SELECTOR.read();
+        if current == selector {
+            return date_time;
+        }
+        selector = current;
+    }
+}
+
+/// Position of the 3D slider, from `0.0` (off) to `1.0`.
+pub fn slider_3d() -> f32 {
+    SLIDER_3D_STATE.read()
+}
+
+/// Whether the 3D LED is lit.
+pub fn led_3d() -> bool {
+    LED_3D_STATE.read() != 0
+}
+
+/// Charging and battery state, as shown by the power LED.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryState {
+    pub adapter_connected: bool,
+    pub charging: bool,
+    /// Battery level from `0` (empty) to `5` (full).
+    pub level: u8,
+}
+
+pub fn battery_state() -> BatteryState {
+    let state = LED_BATTERY_STATE.read();
+
+    BatteryState {
+        adapter_connected: state & (1 << 0) != 0,
+        charging: state & (1 << 1) != 0,
+        level: (state >> 2) & 0b111,
+    }
+}
+
+/// Signal strength of the current Wi-Fi connection, from `0` (none) to `3`.
+pub fn wifi_link_level() -> u8 {
+    WIFI_LINK_LEVEL.read()
+}
+
+pub fn wifi_mac_address() -> [u8; 6] {
+    WIFI_MACADDR.read()
+}
+
+/// Raw state of the network connection, as reported by `ac`.
+pub fn network_state() -> u8 {
+    NETWORK_STATE.read()
+}
+
+/// Whether wireless communication is enabled, see
+/// [`NwmExt::control_wireless_enabled`](crate::services::nwm::NwmExt::control_wireless_enabled).
+pub fn is_wireless_enabled() -> bool {
+    const WIRELESS_DISABLED: u8 = 7;
+
+    network_state() != WIRELESS_DISABLED
+}
+
+/// Whether headphones are plugged in.
+pub fn is_headset_connected() -> bool {
+    HEADSET_CONNECTED.read() != 0
+}
+
+/// Whether this is running on retail hardware, as opposed to a development unit.
+pub fn is_retail_hardware() -> bool {
+    RUNNING_HW.read() == 1
+}
@@ -0,0 +1,118 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! # Wall-clock time
+//!
+//! The RTC time in the [shared page](super::sharedpage) is only updated about once per second,
+//! so the time since its last update is measured with the [`SystemTick`] counter.
+
+use core::fmt;
+
+use super::{sharedpage, SystemTick};
+
+const MILLIS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+/// Days from 1900-01-01 to 1970-01-01.
+const DAYS_1900_TO_UNIX_EPOCH: u64 = 25_567;
+
+/// Milliseconds since 1900-01-01 00:00:00, in the time zone configured on the console.
+pub fn now_millis_since_1900() -> u64 {
+    let date_time = sharedpage::date_time();
+    let elapsed_ticks = SystemTick::now()
+        .count()
+        .saturating_sub(date_time.update_tick);
+    let elapsed_millis =
+        u128::from(elapsed_ticks) * 1000 / u128::from(SystemTick::TICKS_PER_SECOND);
+
+    date_time.milliseconds_since_1900 + elapsed_millis as u64
+}
+
+/// Milliseconds since the Unix epoch.
+///
+/// The console's clock is set to local time, so this is not UTC but offset by the time zone.
+pub fn now_unix_millis() -> u64 {
+    now_millis_since_1900().saturating_sub(DAYS_1900_TO_UNIX_EPOCH * MILLIS_PER_DAY)
+}
+
+/// Seconds since the Unix epoch, see [`now_unix_millis`].
+pub fn now_unix() -> u64 {
+    now_unix_millis() / 1000
+}
+
+/// A calendar date and time of day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub year: u16,
+    /// Month of the year, from 1 to 12.
+    pub month: u8,
+    /// Day of the month, from 1 to 31.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub millisecond: u16,
+}
+
+impl DateTime {
+    /// The current local date and time.
+    pub fn now() -> Self {
+        Self::from_millis_since_1900(now_millis_since_1900())
+    }
+
+    pub fn from_unix_millis(millis: u64) -> Self {
+        Self::from_millis_since_1900(millis + DAYS_1900_TO_UNIX_EPOCH * MILLIS_PER_DAY)
+    }
+
+    pub fn from_millis_since_1900(millis: u64) -> Self {
+        let days = millis / MILLIS_PER_DAY;
+        let millis_of_day = millis % MILLIS_PER_DAY;
+
+        let (year, month, day) = civil_from_days(days);
+        let seconds_of_day = millis_of_day / 1000;
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day / 60 % 60) as u8,
+            second: (seconds_of_day % 60) as u8,
+            millisecond: (millis_of_day % 1000) as u16,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.millisecond
+        )
+    }
+}
+
+/// Convert days since 1900-01-01 to `(year, month, day)` in the proleptic Gregorian calendar.
+fn civil_from_days(days: u64) -> (u16, u8, u8) {
+    // Shift the epoch to 0000-03-01, so leap days are at the end of each 400 year era.
+    const DAYS_0000_03_01_TO_1900: u64 = 693_901;
+
+    let days = days + DAYS_0000_03_01_TO_1900;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year as u16, month as u8, day as u8)
+}
@@ -0,0 +1,46 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The panic handler provided by the `panic-handler` feature.
+
+use crate::debug::{self, SvcDebugLog};
+use crate::ports::errf::{ErrF, ErrorInfo};
+use crate::result::{Level, Module, ResultCode, Summary};
+use crate::svc::{self, UserBreakReason};
+use crate::thread;
+
+use core::{fmt::Write, panic::PanicInfo};
+
+/// Reported to `err:f` for panics.  Description `0` is not used by the system for this module.
+const PANIC_RESULT: ResultCode =
+    ResultCode::new(Level::Fatal, Summary::Internal, Module::Application, 0);
+
+/// Log the panic, show it on the error display and terminate the process.
+///
+/// With the `unwind` feature, panics are unwound instead if something catches them.  Panics on
+/// spawned threads only terminate that thread, see [`thread::exit_panicking`].
+///
+/// If `err:f` is not reachable, break into an attached debugger instead.
+#[panic_handler]
+fn panic_handler(info: &PanicInfo) -> ! {
+    let _ = writeln!(SvcDebugLog, "[PANIC] {}", info);
+
+    #[cfg(feature = "unwind")]
+    crate::unwind::begin_panic(info);
+
+    let _ = write!(SvcDebugLog, "{}", debug::backtrace());
+
+    // Write out buffered records, e.g. of a `FileLogger`
+    log::logger().flush();
+
+    if thread::is_spawned() {
+        thread::exit_panicking()
+    }
+
+    let error = ErrorInfo::from_panic(PANIC_RESULT, info);
+    match ErrF::init().and_then(|errf| errf.throw(&error)) {
+        Ok(()) => svc::exit_process(),
+        Err(_) => svc::user_break(UserBreakReason::Panic),
+    }
+}
@@ -2,16 +2,19 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::debug::{FixedSizeBufferWriter, SvcDebugLog};
 use crate::ipc::IpcRequest;
-use crate::os::{OwnedHandle, BorrowedHandle};
-use crate::result::{Result, ResultCode};
-use crate::svc;
+use crate::os::{OwnedHandle, Process};
+use crate::result::{Level, Module, Result, ResultCode, Summary};
+use crate::svc::{self, UserBreakReason};
 
 use ctru_rt_macros::EnumCast;
 
 use log::debug;
 
+use core::fmt::{self, Write};
 use core::mem::{size_of, size_of_val};
+use core::panic::{Location, PanicInfo};
 
 extern "C" {
     #[link_name = "llvm.returnaddress"]
@@ -35,7 +38,7 @@ impl Default for ErrorType {
     }
 }
 
-#[derive(Debug, EnumCast)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
 #[enum_cast(value_type = "u8")]
 pub enum ExceptionType {
     PrefetchAbort,
@@ -44,6 +47,128 @@ pub enum ExceptionType {
     Vfp,
 }
 
+/// Registers of a thread at the time of an exception.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct CpuRegisters {
+    pub r: [u32; 13],
+    pub sp: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub cpsr: u32,
+}
+
+impl CpuRegisters {
+    /// Capture the registers of the calling code.
+    ///
+    /// The general purpose registers hold whatever the compiler left in them, `pc` points into
+    /// the caller.
+    #[cfg(target_arch = "arm")]
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let mut registers = Self::default();
+        let base = &mut registers as *mut Self;
+        unsafe {
+            core::arch::asm!(
+                "stm {base}, {{r0-r12}}",
+                "str sp, [{base}, #52]",
+                "str lr, [{base}, #56]",
+                "str pc, [{base}, #60]",
+                "mrs {cpsr}, cpsr",
+                "str {cpsr}, [{base}, #64]",
+                base = in(reg) base,
+                cpsr = out(reg) _,
+                options(nostack, preserves_flags),
+            )
+        };
+        registers
+    }
+
+    /// Off the 3DS, there are no registers to capture, so all are zero.
+    #[cfg(not(target_arch = "arm"))]
+    pub fn capture() -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct ExceptionInfo {
+    type_: u8,
+    _reserved: [u8; 3],
+    fsr: u32,
+    far: u32,
+    fpexc: u32,
+    fpinst: u32,
+    fpinst2: u32,
+}
+
+/// A CPU exception as reported by the kernel, see
+/// [`tls::set_exception_handler`](crate::tls::set_exception_handler).
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ExceptionContext {
+    info: ExceptionInfo,
+    registers: CpuRegisters,
+}
+
+impl ExceptionContext {
+    /// Describe an exception of `exception_type` with the given register dump, to report it with
+    /// [`ErrorInfo::from_exception`].
+    pub fn new(exception_type: ExceptionType, registers: CpuRegisters) -> Self {
+        Self {
+            info: ExceptionInfo {
+                type_: exception_type.to_value(),
+                _reserved: [0; 3],
+                fsr: 0,
+                far: 0,
+                fpexc: 0,
+                fpinst: 0,
+                fpinst2: 0,
+            },
+            registers,
+        }
+    }
+
+    /// Set the fault status and address of an abort.
+    pub fn with_fault(mut self, status: u32, address: u32) -> Self {
+        self.info.fsr = status;
+        self.info.far = address;
+        self
+    }
+
+    /// Copy the exception data the kernel passes to exception handlers.
+    pub(crate) unsafe fn read(info: *const u8, registers: *const CpuRegisters) -> Self {
+        Self {
+            info: (info as *const ExceptionInfo).read(),
+            registers: registers.read(),
+        }
+    }
+
+    pub fn exception_type(&self) -> core::result::Result<ExceptionType, u8> {
+        ExceptionType::from_value(self.info.type_)
+    }
+
+    /// The fault status register, describing the cause of an abort.
+    pub fn fault_status(&self) -> u32 {
+        self.info.fsr
+    }
+
+    /// The address an access faulted on, for data aborts.
+    pub fn fault_address(&self) -> u32 {
+        self.info.far
+    }
+
+    /// The VFP exception register, for VFP exceptions.
+    pub fn fpexc(&self) -> u32 {
+        self.info.fpexc
+    }
+
+    pub fn registers(&self) -> &CpuRegisters {
+        &self.registers
+    }
+}
+
 #[repr(C, align(4))]
 pub struct ErrorInfo {
     type_: ErrorType,
@@ -54,9 +179,12 @@ pub struct ErrorInfo {
     process_id: u32,
     title_id: u64,
     application_title_id: u64,
-    failure_message: [u8; 0x60],
+    /// A failure message, or the [`ExceptionContext`] of an exception.
+    data: [u8; 0x60],
 }
 
+const _: () = core::assert!(size_of::<ExceptionContext>() <= 0x60);
+
 impl ErrorInfo {
     const fn zeroed() -> Self {
         Self {
@@ -68,7 +196,7 @@ impl ErrorInfo {
             process_id: 0,
             title_id: 0,
             application_title_id: 0,
-            failure_message: [0; 0x60],
+            data: [0; 0x60],
         }
     }
 }
@@ -98,7 +226,7 @@ impl ErrorInfo {
     }
 
     fn current_process_id() -> u32 {
-        svc::get_process_id(BorrowedHandle::active_process()).unwrap_or(0)
+        Process::current().id().unwrap_or(0)
     }
 
     #[inline(never)]
@@ -129,7 +257,72 @@ impl ErrorInfo {
             result_code,
             pc_addr: unsafe { returnaddress(0) as u32 },
             process_id: Self::current_process_id(),
-            failure_message: Self::message_from(message),
+            data: Self::message_from(message),
+            ..Self::zeroed()
+        }
+    }
+
+    /// A failure message at `location`, truncated to fit.
+    #[inline(always)]
+    fn failure_message(location: Option<&Location>, message: fmt::Arguments) -> [u8; 0x60] {
+        let mut writer = FixedSizeBufferWriter::<0x5f>::new();
+        if let Some(location) = location {
+            let _ = write!(writer, "{}:{}: ", location.file(), location.line());
+        }
+        let _ = writer.write_fmt(message);
+
+        let mut data = [b'\0'; 0x60];
+        data[..writer.occupied().len()].copy_from_slice(writer.occupied());
+
+        data
+    }
+
+    /// Describe a panic, with its location and as much of its message as fits.
+    #[inline(never)]
+    pub fn from_panic(result_code: ResultCode, info: &PanicInfo) -> Self {
+        Self {
+            type_: ErrorType::Failure,
+            result_code,
+            pc_addr: unsafe { returnaddress(0) as u32 },
+            process_id: Self::current_process_id(),
+            data: Self::failure_message(info.location(), format_args!("{}", info.message())),
+            ..Self::zeroed()
+        }
+    }
+
+    /// Describe a failure at `location`, with as much of `message` as fits.
+    #[inline(never)]
+    pub fn from_failure(
+        result_code: ResultCode,
+        location: &Location,
+        message: fmt::Arguments,
+    ) -> Self {
+        Self {
+            type_: ErrorType::Failure,
+            result_code,
+            pc_addr: unsafe { returnaddress(0) as u32 },
+            process_id: Self::current_process_id(),
+            data: Self::failure_message(Some(location), message),
+            ..Self::zeroed()
+        }
+    }
+
+    /// Describe a CPU exception, including its register dump.
+    pub fn from_exception(context: &ExceptionContext) -> Self {
+        let mut data = [0; 0x60];
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                context as *const ExceptionContext as *const u8,
+                data.as_mut_ptr(),
+                size_of::<ExceptionContext>(),
+            )
+        };
+
+        Self {
+            type_: ErrorType::Exception,
+            pc_addr: context.registers.pc,
+            process_id: Self::current_process_id(),
+            data,
             ..Self::zeroed()
         }
     }
@@ -158,3 +351,103 @@ impl ErrF {
         Ok(())
     }
 }
+
+/// Log an exception and show it on the error display.
+///
+/// This is the exception handler installed by default.
+pub fn report_exception(context: &ExceptionContext) {
+    let _ = writeln!(
+        SvcDebugLog,
+        "[EXCEPTION] {:?} at pc = {:#010x}, address = {:#010x}, status = {:#x}",
+        context.exception_type(),
+        context.registers.pc,
+        context.info.far,
+        context.info.fsr,
+    );
+
+    if let Ok(errf) = ErrF::init() {
+        let _ = errf.throw(&ErrorInfo::from_exception(context));
+    }
+}
+
+/// Reported to `err:f` for failed [`assert!`]ions.
+const ASSERTION_RESULT: ResultCode =
+    ResultCode::new(Level::Fatal, Summary::InvalidState, Module::Application, 0);
+
+/// Log a failed assertion, show it on the error display and terminate the process.
+#[doc(hidden)]
+#[cold]
+#[inline(never)]
+#[track_caller]
+pub fn assertion_failed(message: fmt::Arguments) -> ! {
+    let location = Location::caller();
+    let _ = writeln!(SvcDebugLog, "[ASSERT] {}: {}", location, message);
+    log::logger().flush();
+
+    let error = ErrorInfo::from_failure(ASSERTION_RESULT, location, message);
+    match ErrF::init().and_then(|errf| errf.throw(&error)) {
+        Ok(()) => svc::exit_process(),
+        Err(_) => svc::user_break(UserBreakReason::Assert),
+    }
+}
+
+/// Like [`core::assert!`], but shows the failure on the error display instead of panicking.
+///
+/// ```ignore
+/// use ctru_rt::ports::errf;
+///
+/// errf::assert!(buffer.len() >= 4, "buffer too short: {}", buffer.len());
+/// ```
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __errf_assert {
+    ($condition: expr $(,)?) => {
+        if !$condition {
+            $crate::ports::errf::assertion_failed(format_args!(
+                "assertion failed: {}",
+                stringify!($condition)
+            ))
+        }
+    };
+    ($condition: expr, $($arg: tt)+) => {
+        if !$condition {
+            $crate::ports::errf::assertion_failed(format_args!($($arg)+))
+        }
+    };
+}
+
+/// Like [`core::assert_eq!`], but shows the failure on the error display instead of panicking.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __errf_assert_eq {
+    ($left: expr, $right: expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if !(*left == *right) {
+                    $crate::ports::errf::assertion_failed(format_args!(
+                        "assertion `left == right` failed: {:?} != {:?}",
+                        left, right
+                    ))
+                }
+            }
+        }
+    };
+    ($left: expr, $right: expr, $($arg: tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if !(*left == *right) {
+                    $crate::ports::errf::assertion_failed(format_args!(
+                        "{}: {:?} != {:?}",
+                        format_args!($($arg)+),
+                        left, right
+                    ))
+                }
+            }
+        }
+    };
+}
+
+#[doc(inline)]
+pub use crate::__errf_assert as assert;
+#[doc(inline)]
+pub use crate::__errf_assert_eq as assert_eq;
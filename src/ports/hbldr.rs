@@ -0,0 +1,89 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The homebrew loader built into Luma3DS, which loads a `.3dsx` the next time the current title
+//! starts.
+
+use crate::ipc::{IpcRequest, StaticBuffer};
+use crate::os::OwnedHandle;
+use crate::result::Result;
+use crate::svc;
+
+use alloc::vec::Vec;
+
+use log::debug;
+
+/// Size of the argument buffer passed to the loaded executable.
+pub const ARGUMENTS_SIZE: usize = 0x400;
+
+#[derive(Debug)]
+pub struct HbLdr {
+    port: OwnedHandle,
+}
+
+impl HbLdr {
+    pub fn init() -> Result<Self> {
+        debug!("Connecting to port `hb:ldr`...");
+        let port = svc::connect_to_port("hb:ldr\0")?;
+
+        Ok(Self { port })
+    }
+
+    /// Load the `.3dsx` at `path` on the SD card next, e.g. `sdmc:/3ds/app.3dsx`.
+    pub fn set_target(&self, path: &str) -> Result<()> {
+        let path = path.strip_prefix("sdmc:").unwrap_or(path);
+
+        let mut data = Vec::with_capacity(path.len() + 1);
+        data.extend_from_slice(path.as_bytes());
+        data.push(0);
+
+        let _ = IpcRequest::command(0x2)
+            .translate_parameter(StaticBuffer::from_bytes(&data, 0))
+            .dispatch(&self.port)?;
+
+        Ok(())
+    }
+
+    /// Pass `args` to the loaded executable, by convention starting with its path.
+    ///
+    /// Arguments that do not fit into [`ARGUMENTS_SIZE`] bytes are dropped.
+    pub fn set_arguments<'a, I>(&self, args: I) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let buffer = encode_arguments(args);
+
+        let _ = IpcRequest::command(0x3)
+            .translate_parameter(StaticBuffer::from_bytes(&buffer, 1))
+            .dispatch(&self.port)?;
+
+        Ok(())
+    }
+}
+
+/// Lay out `args` like the system argument list: the count, followed by NUL-terminated strings.
+fn encode_arguments<'a, I>(args: I) -> [u8; ARGUMENTS_SIZE]
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    const COUNT_SIZE: usize = core::mem::size_of::<u32>();
+
+    let mut buffer = [0; ARGUMENTS_SIZE];
+    let mut count: u32 = 0;
+    let mut position = COUNT_SIZE;
+
+    for arg in args {
+        let end = position + arg.len();
+        if end >= ARGUMENTS_SIZE {
+            break;
+        }
+
+        buffer[position..end].copy_from_slice(arg.as_bytes());
+        position = end + 1;
+        count += 1;
+    }
+
+    buffer[..COUNT_SIZE].copy_from_slice(&count.to_le_bytes());
+    buffer
+}
@@ -3,4 +3,6 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 pub mod errf;
+pub mod hbldr;
 pub mod srv;
+pub mod srv_pm;
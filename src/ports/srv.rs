@@ -3,14 +3,25 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::{
+    env,
     ipc::{IpcRequest, ThisProcessId},
     os::{AsHandle, OwnedHandle},
     result::{Result, ERROR_NOT_AUTHORIZED},
-    svc,
+    svc::{self, Timeout},
+    sync::{self, Event, LightMutex, ResetType},
+    thread::{JoinHandle, ThreadBuilder},
 };
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
 use ctru_rt_macros::EnumCast;
-use log::debug;
+use log::{debug, warn};
+
+/// Published when the system asks a process to terminate, e.g. because the console shuts down.
+pub const NOTIFICATION_TERMINATION_REQUEST: u32 = 0x100;
 
 #[derive(Debug, Copy, Clone, EnumCast)]
 #[enum_cast(value_type = "u32")]
@@ -38,6 +49,15 @@ impl Srv {
         Ok(srv)
     }
 
+    /// A session whose requests are answered by [`mock`](crate::ipc::mock).
+    #[cfg(test)]
+    pub(crate) fn mocked() -> Self {
+        Self {
+            handle: unsafe { OwnedHandle::new(0x5352_5600) }.unwrap(),
+            blocking_policy: BlockingPolicy::Blocking,
+        }
+    }
+
     pub fn blocking_policy(&self) -> BlockingPolicy {
         self.blocking_policy
     }
@@ -79,7 +99,16 @@ impl Srv {
         Ok(())
     }
 
+    /// Get a session to `service_name`.
+    ///
+    /// When launched from the homebrew loader, handles it acquired for the application are used
+    /// instead of asking `srv:`, which gives access to services restricted by the HOME menu.
     pub fn get_service_handle(&self, service_name: &str) -> Result<OwnedHandle> {
+        if let Some(handle) = env::service_override(service_name) {
+            debug!("Using the `{}` handle of the homebrew loader", service_name);
+            return svc::duplicate_handle(handle);
+        }
+
         let ((arg0, arg1), len) = unsafe { write_str_param(service_name) };
 
         let mut reply = IpcRequest::command(0x5)
@@ -205,3 +234,209 @@ impl AsHandle for Srv {
         self.handle.as_handle()
     }
 }
+
+type NotificationCallback = Arc<LightMutex<dyn FnMut(u32) + Send>>;
+
+/// State shared between a [`NotificationHandler`] and its pump thread.
+struct NotificationDispatch {
+    /// Notifications are delivered to the session that enabled them.
+    srv: Srv,
+    semaphore: OwnedHandle,
+    callbacks: LightMutex<Vec<(u32, NotificationCallback)>>,
+    termination_requested: AtomicBool,
+    stop: Event,
+}
+
+impl NotificationDispatch {
+    fn pump(&self) -> Result<()> {
+        loop {
+            // The semaphore counts the notifications pending for this session
+            let signaled = sync::wait_any(&[&self.semaphore, &self.stop], Timeout::forever())?;
+            if signaled == 1 {
+                return Ok(());
+            }
+
+            let notification_id = self.srv.receive_notification()?;
+            debug!("Received notification {:#x}", notification_id);
+
+            if notification_id == NOTIFICATION_TERMINATION_REQUEST {
+                self.termination_requested.store(true, Ordering::Release);
+            }
+
+            // Callbacks run without holding the list's lock, so that they can subscribe.
+            let callbacks: Vec<NotificationCallback> = self
+                .callbacks
+                .lock()
+                .iter()
+                .filter(|(id, _)| *id == notification_id)
+                .map(|(_, callback)| callback.clone())
+                .collect();
+            for callback in callbacks {
+                (callback.lock())(notification_id)
+            }
+        }
+    }
+}
+
+impl core::fmt::Debug for NotificationDispatch {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("NotificationDispatch")
+            .field("srv", &self.srv)
+            .field("semaphore", &self.semaphore)
+            .field("termination_requested", &self.termination_requested)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Receives notifications published through `srv:`, like the system's
+/// [termination request](NOTIFICATION_TERMINATION_REQUEST).
+///
+/// Notifications are received on a dedicated thread, which runs the callbacks registered with
+/// [`subscribe`](Self::subscribe).  It is stopped when the `NotificationHandler` is dropped.
+///
+/// ```ignore
+/// let notifications = NotificationHandler::init(0x30)?;
+/// while !notifications.is_termination_requested() {
+///     // ...
+/// }
+/// ```
+#[derive(Debug)]
+pub struct NotificationHandler {
+    dispatch: Arc<NotificationDispatch>,
+    pump: Option<JoinHandle<Result<()>>>,
+}
+
+impl NotificationHandler {
+    /// Open a session to `srv:` receiving notifications on a thread with the given `priority`.
+    ///
+    /// The session is subscribed to [`NOTIFICATION_TERMINATION_REQUEST`].
+    pub fn init(priority: i32) -> Result<Self> {
+        let srv = Srv::init()?;
+        let semaphore = srv.enable_notifications()?;
+        srv.subscribe(NOTIFICATION_TERMINATION_REQUEST)?;
+
+        let dispatch = Arc::new(NotificationDispatch {
+            srv,
+            semaphore,
+            callbacks: LightMutex::new(Vec::new()),
+            termination_requested: AtomicBool::new(false),
+            stop: Event::new(ResetType::OneShot)?,
+        });
+
+        let pump = {
+            let dispatch = dispatch.clone();
+            ThreadBuilder::default()
+                .with_priority(priority)
+                .with_stack_size(0x2000)
+                .spawn(move || {
+                    let result = dispatch.pump();
+                    if let Err(e) = result {
+                        warn!("Notification pump stopped: {:?}", e);
+                    }
+                    result
+                })?
+        };
+
+        Ok(Self {
+            dispatch,
+            pump: Some(pump),
+        })
+    }
+
+    /// Call `callback` whenever `notification_id` is published.
+    ///
+    /// Callbacks are run on the pump thread.
+    pub fn subscribe<F>(&self, notification_id: u32, callback: F) -> Result<()>
+    where
+        F: FnMut(u32) + Send + 'static,
+    {
+        // Hold the lock across the request, so that `srv:` is asked only once per ID
+        let mut callbacks = self.dispatch.callbacks.lock();
+        let subscribed = notification_id == NOTIFICATION_TERMINATION_REQUEST
+            || callbacks.iter().any(|(id, _)| *id == notification_id);
+        if !subscribed {
+            self.dispatch.srv.subscribe(notification_id)?;
+        }
+
+        callbacks.push((notification_id, Arc::new(LightMutex::new(callback))));
+
+        Ok(())
+    }
+
+    /// Call `callback` once the system asks this process to terminate.
+    pub fn on_termination_request<F>(&self, mut callback: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.dispatch.callbacks.lock().push((
+            NOTIFICATION_TERMINATION_REQUEST,
+            Arc::new(LightMutex::new(move |_| callback())),
+        ))
+    }
+
+    /// Whether the system asked this process to terminate.
+    ///
+    /// The application should then release its resources and exit.
+    pub fn is_termination_requested(&self) -> bool {
+        self.dispatch.termination_requested.load(Ordering::Acquire)
+    }
+
+    fn stop_pump(&mut self) -> Result<()> {
+        if let Some(pump) = self.pump.take() {
+            self.dispatch.stop.signal()?;
+
+            match pump.join()? {
+                Ok(result) => result?,
+                Err(panicked) => warn!("Notification pump: {}", panicked),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for NotificationHandler {
+    fn drop(&mut self) {
+        let _ = self.stop_pump();
+    }
+}
+
+#[cfg(all(test, feature = "mock-ipc"))]
+mod tests {
+    use super::*;
+
+    use crate::ipc::mock::{self, Reply};
+    use crate::result::ERROR_NOT_AUTHORIZED;
+
+    #[test]
+    fn get_service_handle_sends_name_and_policy() {
+        let _mock = mock::lock();
+        let srv = Srv::mocked();
+
+        mock::expect(0x5, Reply::success().handle(0x1234));
+        let handle = srv.get_service_handle("cfg:u").unwrap();
+        assert_eq!(handle.leak(), 0x1234);
+
+        let requests = mock::take_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].parameters,
+            [u32::from_le_bytes(*b"cfg:"), u32::from(b'u'), 5, 0]
+        );
+    }
+
+    #[test]
+    fn get_service_handle_alternatives_skips_denied() {
+        let _mock = mock::lock();
+        let srv = Srv::mocked();
+
+        mock::expect(0x5, Reply::error(ERROR_NOT_AUTHORIZED));
+        mock::expect(0x5, Reply::success().handle(0x1234));
+        let (handle, index) = srv
+            .get_service_handle_alternatives(&["cfg:i", "cfg:s", "cfg:u"])
+            .unwrap();
+
+        assert_eq!((handle.leak(), index), (0x1234, 1));
+        assert_eq!(mock::pending_replies(), 0);
+    }
+}
@@ -0,0 +1,113 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The interface of the service manager reserved for the process manager.
+//!
+//! Through it, processes are registered together with the services they may access, and
+//! notifications are published to processes other than the caller.  Using it requires access to
+//! `srv:pm`, which is usually only granted to system modules.
+
+use crate::ipc::{IpcRequest, StaticBuffer};
+use crate::os::{self, AsHandle, BorrowedHandle, OwnedHandle};
+use crate::ports::srv::Srv;
+use crate::result::Result;
+use crate::svc;
+
+use alloc::vec::Vec;
+
+use log::debug;
+
+/// Length of a service name in an access control list.
+const SERVICE_NAME_LENGTH: usize = 8;
+
+#[derive(Debug)]
+pub struct SrvPm {
+    handle: OwnedHandle,
+    /// Before system version 7.0, the commands are part of `srv:` and offset by this.
+    command_offset: u16,
+}
+
+impl SrvPm {
+    pub fn init(srv: &Srv) -> Result<Self> {
+        // Kernel version 2.39 shipped with system version 7.0
+        if os::version().is_at_least(2, 39) {
+            debug!("Connecting to port `srv:pm`...");
+            Ok(Self {
+                handle: svc::connect_to_port("srv:pm\0")?,
+                command_offset: 0,
+            })
+        } else {
+            Ok(Self {
+                handle: srv.get_service_handle("srv:pm")?,
+                command_offset: 0x400,
+            })
+        }
+    }
+
+    fn command(&self, id: u16) -> IpcRequest {
+        IpcRequest::command(self.command_offset + id)
+    }
+
+    /// Publish `notification_id` to the process `process` only.
+    pub fn publish_to_process(&self, notification_id: u32, process: BorrowedHandle) -> Result<()> {
+        let _ = self
+            .command(0x1)
+            .parameter(notification_id)
+            .translate_parameter(process)
+            .dispatch(&self.handle)?;
+
+        Ok(())
+    }
+
+    /// Publish `notification_id` to all processes.
+    pub fn publish_to_all(&self, notification_id: u32) -> Result<()> {
+        let _ = self
+            .command(0x2)
+            .parameter(notification_id)
+            .dispatch(&self.handle)?;
+
+        Ok(())
+    }
+
+    /// Register the process `process_id`, allowing it to access the services in `services`.
+    ///
+    /// Service names are truncated to 8 bytes.  A name may end in `*` to allow all services
+    /// starting with it.
+    pub fn register_process(&self, process_id: u32, services: &[&str]) -> Result<()> {
+        let mut access_control_list = Vec::with_capacity(services.len() * SERVICE_NAME_LENGTH);
+        for service in services {
+            let mut name = [0; SERVICE_NAME_LENGTH];
+            let length = service.len().min(SERVICE_NAME_LENGTH);
+            name[..length].copy_from_slice(&service.as_bytes()[..length]);
+            access_control_list.extend_from_slice(&name);
+        }
+
+        // The length of the list is given in words
+        let words = (access_control_list.len() / 4) as u32;
+
+        let _ = self
+            .command(0x3)
+            .parameters(&[process_id, words])
+            .translate_parameter(StaticBuffer::from_bytes(&access_control_list, 0))
+            .dispatch(&self.handle)?;
+
+        Ok(())
+    }
+
+    /// Unregister the process `process_id`, e.g. once it exited.
+    pub fn unregister_process(&self, process_id: u32) -> Result<()> {
+        let _ = self
+            .command(0x4)
+            .parameter(process_id)
+            .dispatch(&self.handle)?;
+
+        Ok(())
+    }
+}
+
+impl AsHandle for SrvPm {
+    fn as_handle(&self) -> BorrowedHandle<'_> {
+        self.handle.as_handle()
+    }
+}
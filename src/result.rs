@@ -136,8 +136,9 @@ pub trait ResultValue {
         Module::from_value(((self.value() >> 10) & 0b1111_1111) as u8)
     }
 
-    fn description(&self) -> ::core::result::Result<CommonDescription, u32> {
-        CommonDescription::from_value(self.value() & 0b11_1111_1111)
+    /// The description, interpreted according to the module that returned it.
+    fn description(&self) -> ::core::result::Result<KnownDescription, u32> {
+        KnownDescription::decode(self.module(), self.value() & 0b11_1111_1111)
     }
 }
 
@@ -195,6 +196,48 @@ macro_rules! result_value_dbg_fmt {
 result_value_dbg_fmt!(ResultCode);
 result_value_dbg_fmt!(ErrorCode);
 
+/// Write a named value, or the raw number if it is unknown.
+fn write_known<T: fmt::Debug, U: fmt::Display>(
+    f: &mut fmt::Formatter,
+    value: ::core::result::Result<T, U>,
+) -> fmt::Result {
+    match value {
+        Ok(known) => write!(f, "{:?}", known),
+        Err(unknown) => write!(f, "{}", unknown),
+    }
+}
+
+/// Format as e.g. `Fatal/OutOfResource/Application/OutOfMemory (0xd8e007f3)`.
+fn display_result_value<R: ResultValue>(value: &R, f: &mut fmt::Formatter) -> fmt::Result {
+    if value.is_ok() {
+        return write!(f, "Success ({:#010x})", value.value());
+    }
+
+    write_known(f, value.level())?;
+    f.write_str("/")?;
+    write_known(f, value.summary())?;
+    f.write_str("/")?;
+    write_known(f, value.module())?;
+    f.write_str("/")?;
+    write_known(f, value.description())?;
+    write!(f, " ({:#010x})", value.value())
+}
+
+impl fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        display_result_value(self, f)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        display_result_value(self, f)
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl core::error::Error for ErrorCode {}
+
 #[derive(Debug, Copy, Clone, PartialEq, EnumCast)]
 #[enum_cast(value_type = "u32")]
 pub enum Level {
@@ -334,7 +377,7 @@ pub trait Description {
     fn into_code(self) -> u32;
 }
 
-#[derive(Debug, EnumCast)]
+#[derive(Debug, Clone, Copy, PartialEq, EnumCast)]
 #[enum_cast(value_type = "u32")]
 pub enum CommonDescription {
     Success = 0,
@@ -370,6 +413,101 @@ impl Description for CommonDescription {
     }
 }
 
+/// Descriptions specific to the [`Os`](Module::Os) module, used by most kernel objects.
+///
+/// Results of the [`Kernel`](Module::Kernel) module itself only use [`CommonDescription`]s.
+#[derive(Debug, Clone, Copy, PartialEq, EnumCast)]
+#[enum_cast(value_type = "u32")]
+pub enum OsDescription {
+    SessionClosed = 26,
+    PortNameTooLong = 30,
+}
+
+impl Description for OsDescription {
+    fn into_code(self) -> u32 {
+        self.to_value()
+    }
+}
+
+/// Descriptions specific to the [`Fs`](Module::Fs) module.
+#[derive(Debug, Clone, Copy, PartialEq, EnumCast)]
+#[enum_cast(value_type = "u32")]
+pub enum FsDescription {
+    NotFound = 100,
+    FileNotFound = 120,
+    AlreadyExists = 190,
+    OutOfSpace = 210,
+}
+
+impl Description for FsDescription {
+    fn into_code(self) -> u32 {
+        self.to_value()
+    }
+}
+
+/// Descriptions specific to the [`Srv`](Module::Srv) module.
+#[derive(Debug, Clone, Copy, PartialEq, EnumCast)]
+#[enum_cast(value_type = "u32")]
+pub enum SrvDescription {
+    AccessDenied = 6,
+}
+
+impl Description for SrvDescription {
+    fn into_code(self) -> u32 {
+        self.to_value()
+    }
+}
+
+/// A description code looked up in the descriptions of its module, see
+/// [`ResultValue::description`].
+#[derive(Clone, Copy, PartialEq)]
+pub enum KnownDescription {
+    Common(CommonDescription),
+    Os(OsDescription),
+    Fs(FsDescription),
+    Srv(SrvDescription),
+}
+
+impl KnownDescription {
+    fn decode(
+        module: ::core::result::Result<Module, u8>,
+        code: u32,
+    ) -> ::core::result::Result<Self, u32> {
+        if let Ok(common) = CommonDescription::from_value(code) {
+            return Ok(Self::Common(common));
+        }
+
+        match module {
+            Ok(Module::Os) => OsDescription::from_value(code).map(Self::Os),
+            Ok(Module::Fs) => FsDescription::from_value(code).map(Self::Fs),
+            Ok(Module::Srv) => SrvDescription::from_value(code).map(Self::Srv),
+            _ => Err(code),
+        }
+    }
+}
+
+impl Description for KnownDescription {
+    fn into_code(self) -> u32 {
+        match self {
+            Self::Common(description) => description.into_code(),
+            Self::Os(description) => description.into_code(),
+            Self::Fs(description) => description.into_code(),
+            Self::Srv(description) => description.into_code(),
+        }
+    }
+}
+
+impl fmt::Debug for KnownDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Common(description) => description.fmt(f),
+            Self::Os(description) => description.fmt(f),
+            Self::Fs(description) => description.fmt(f),
+            Self::Srv(description) => description.fmt(f),
+        }
+    }
+}
+
 pub const ERROR_OUT_OF_MEMORY: ErrorCode = ErrorCode::new(
     Level::Fatal,
     Summary::OutOfResource,
@@ -382,3 +520,130 @@ pub const ERROR_NOT_AUTHORIZED: ErrorCode = ErrorCode::new(
     Module::Application,
     CommonDescription::NotAuthorized.to_value(),
 );
+/// Returned by the kernel when waiting on a synchronization object times out.
+pub const ERROR_TIMEOUT: ErrorCode = ErrorCode::new(
+    Level::Info,
+    Summary::StatusChanged,
+    Module::Os,
+    CommonDescription::Timeout.to_value(),
+);
+
+/// Maximum number of context messages an [`ErrorContext`] keeps.
+const CONTEXT_DEPTH: usize = 4;
+
+/// An [`ErrorCode`] together with descriptions of what was being done when it occurred.
+///
+/// Created with [`Context::context`].  Only the four innermost messages are kept, outer ones are
+/// counted but dropped.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    code: ErrorCode,
+    messages: [&'static str; CONTEXT_DEPTH],
+    depth: usize,
+}
+
+pub type ContextResult<T> = core::result::Result<T, ErrorContext>;
+
+impl ErrorContext {
+    pub const fn new(code: ErrorCode) -> Self {
+        Self {
+            code,
+            messages: [""; CONTEXT_DEPTH],
+            depth: 0,
+        }
+    }
+
+    /// The underlying error code.
+    pub const fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    /// Add a message describing an outer operation.
+    pub fn context(mut self, message: &'static str) -> Self {
+        if let Some(slot) = self.messages.get_mut(self.depth) {
+            *slot = message;
+        }
+        self.depth += 1;
+        self
+    }
+
+    /// The recorded messages, innermost first.
+    pub fn messages(&self) -> impl DoubleEndedIterator<Item = &'static str> + '_ {
+        self.messages[..self.depth.min(CONTEXT_DEPTH)]
+            .iter()
+            .copied()
+    }
+
+    /// The number of messages that did not fit and were dropped.
+    pub fn dropped(&self) -> usize {
+        self.depth.saturating_sub(CONTEXT_DEPTH)
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl core::error::Error for ErrorContext {}
+
+impl From<ErrorCode> for ErrorContext {
+    fn from(code: ErrorCode) -> Self {
+        Self::new(code)
+    }
+}
+
+impl From<ErrorContext> for ErrorCode {
+    fn from(context: ErrorContext) -> Self {
+        context.code
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.dropped() > 0 {
+            write!(f, "({} more): ", self.dropped())?;
+        }
+        for message in self.messages().rev() {
+            write!(f, "{}: ", message)?;
+        }
+        write!(f, "{}", self.code)
+    }
+}
+
+impl fmt::Debug for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut messages = [""; CONTEXT_DEPTH];
+        for (slot, message) in messages.iter_mut().zip(self.messages().rev()) {
+            *slot = message;
+        }
+
+        f.debug_struct("ErrorContext")
+            .field("code", &self.code)
+            .field("context", &&messages[..self.depth.min(CONTEXT_DEPTH)])
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+/// Attach context to failures, e.g. `map_shared_memory().context("mapping HID shared memory")?`.
+pub trait Context<T> {
+    fn context(self, message: &'static str) -> ContextResult<T>;
+}
+
+impl<T> Context<T> for Result<T> {
+    #[inline]
+    fn context(self, message: &'static str) -> ContextResult<T> {
+        self.map_err(|code| ErrorContext::new(code).context(message))
+    }
+}
+
+impl<T> Context<T> for ContextResult<T> {
+    #[inline]
+    fn context(self, message: &'static str) -> ContextResult<T> {
+        self.map_err(|context| context.context(message))
+    }
+}
+
+impl Context<()> for ResultCode {
+    #[inline]
+    fn context(self, message: &'static str) -> ContextResult<()> {
+        self.into_result().context(message)
+    }
+}
@@ -0,0 +1,152 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Starting up and shutting down the application.
+//!
+//! Before the entry point is called, the heap is set up and the constructors in `.init_array`
+//! run.  When the application exits, the hooks registered with [`at_exit`] run, then the
+//! destructors in `.fini_array`.
+
+use crate::svc;
+#[cfg(target_arch = "arm")]
+use crate::{env, heap};
+
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use log::debug;
+
+type ArrayFunction = unsafe extern "C" fn();
+
+extern "C" {
+    /// Where the homebrew loader called the application from, saved on startup.
+    static __ctru_rt_return_address: usize;
+    /// The stack pointer the loader called the application with, saved on startup.
+    static __ctru_rt_saved_stack: usize;
+
+    static __init_array_start: ArrayFunction;
+    static __init_array_end: ArrayFunction;
+    static __fini_array_start: ArrayFunction;
+    static __fini_array_end: ArrayFunction;
+}
+
+unsafe fn function_array(
+    start: &'static ArrayFunction,
+    end: &'static ArrayFunction,
+) -> &'static [ArrayFunction] {
+    let start = start as *const ArrayFunction;
+    let length = (end as *const ArrayFunction).offset_from(start) as usize;
+    core::slice::from_raw_parts(start, length)
+}
+
+/// Run the constructors in `.init_array`, in order.
+///
+/// # Safety
+///
+/// Must be called once, after the heap was initialized.
+pub(crate) unsafe fn run_init_array() {
+    for constructor in function_array(&__init_array_start, &__init_array_end) {
+        constructor()
+    }
+}
+
+/// Run the destructors in `.fini_array`, in reverse order.
+unsafe fn run_fini_array() {
+    let destructors = function_array(&__fini_array_start, &__fini_array_end);
+    for destructor in destructors.iter().rev() {
+        destructor()
+    }
+}
+
+const MAX_EXIT_HOOKS: usize = 32;
+
+static NEXT_EXIT_HOOK: AtomicUsize = AtomicUsize::new(0);
+static EXIT_HOOKS: [AtomicPtr<()>; MAX_EXIT_HOOKS] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_EXIT_HOOKS];
+
+/// Run `hook` when the application exits, e.g. to release resources held by the system.
+///
+/// Hooks run in reverse order of registration, each at most once, so resources are released in
+/// the reverse order they were acquired in.  The runtime registers its own hooks, e.g. to release
+/// GPU access rights.  Registering the same hook again does nothing.
+///
+/// # Panics
+///
+/// If more than 32 hooks are registered.
+pub fn at_exit(hook: fn()) {
+    let hook = hook as *mut ();
+    if EXIT_HOOKS
+        .iter()
+        .any(|registered| registered.load(Ordering::Acquire) == hook)
+    {
+        return;
+    }
+
+    let index = NEXT_EXIT_HOOK.fetch_add(1, Ordering::Relaxed);
+    if index >= MAX_EXIT_HOOKS {
+        panic!("All {} exit hooks are in use", MAX_EXIT_HOOKS);
+    }
+
+    EXIT_HOOKS[index].store(hook, Ordering::Release);
+}
+
+fn run_exit_hooks() {
+    let registered = NEXT_EXIT_HOOK.load(Ordering::Acquire).min(MAX_EXIT_HOOKS);
+
+    for hook in EXIT_HOOKS[..registered].iter().rev() {
+        let hook = hook.swap(core::ptr::null_mut(), Ordering::AcqRel);
+        if !hook.is_null() {
+            let hook = unsafe { core::mem::transmute::<*mut (), fn()>(hook) };
+            hook()
+        }
+    }
+}
+
+/// Exit the application, returning to the homebrew loader if it was launched from one.
+///
+/// This runs the [exit hooks](at_exit), which release what the runtime holds on behalf of the
+/// application like GPU access rights or the registration with APT, and the destructors in
+/// `.fini_array`.  Finally, the heap is returned to the system.  Values that are still alive are
+/// not dropped, and all other threads must have exited.  Returning from the entry point exits
+/// with code 0.
+pub fn exit(code: i32) -> ! {
+    debug!("Exiting with code {}", code);
+
+    run_exit_hooks();
+    unsafe { run_fini_array() };
+    log::logger().flush();
+
+    // The main thread might run on a stack allocated from the heap, so switch back to the
+    // loader's stack before unmapping it
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        core::arch::asm!(
+            "mov sp, {stack}",
+            "b {exit}",
+            stack = in(reg) __ctru_rt_saved_stack,
+            exit = sym exit_on_saved_stack,
+            in("r0") code,
+            options(noreturn),
+        )
+    }
+    #[cfg(not(target_arch = "arm"))]
+    svc::exit_process()
+}
+
+/// Return the heap to the system and leave, running on the stack saved on startup.
+#[cfg(target_arch = "arm")]
+unsafe extern "C" fn exit_on_saved_stack(code: i32) -> ! {
+    // Nothing may be allocated or logged from here on
+    let _ = heap::unmap();
+
+    if env::is_homebrew() {
+        core::arch::asm!(
+            "bx {return_address}",
+            return_address = in(reg) __ctru_rt_return_address,
+            in("r0") code,
+            options(noreturn),
+        )
+    }
+
+    svc::exit_process()
+}
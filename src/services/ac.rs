@@ -4,14 +4,31 @@
 
 use crate::ports::srv::Srv;
 use crate::{
-    ipc::IpcRequest,
-    os::OwnedHandle,
+    ipc::{IpcRequest, StaticBuffer, ThisProcessId},
+    os::{sharedpage, AsHandle, OwnedHandle},
     result::{CommonDescription, ErrorCode, Level, Module, Result, Summary},
+    svc::{Timeout, WaitOutcome},
+    sync::{Event, ResetType},
+    tls,
 };
 
+use ctru_rt_macros::EnumCast;
+
+const CONFIG_SIZE: usize = 0x200;
+const SSID_SIZE: usize = 0x20;
+
+const ERROR_INVALID_RESULT_VALUE: ErrorCode = ErrorCode::new(
+    Level::Fatal,
+    Summary::InvalidResultValue,
+    Module::Ac,
+    CommonDescription::InvalidResultValue.to_value(),
+);
+
 #[derive(Debug)]
 pub struct Ac {
     handle: OwnedHandle,
+    /// Signaled once a connection attempt started by [`Ac::connect_async`] completed.
+    connect_event: Event,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -27,15 +44,141 @@ impl WifiStatus {
     }
 }
 
+/// Encryption of the Wi-Fi network currently connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[enum_cast(value_type = "u32")]
+pub enum SecurityMode {
+    Open,
+    Wep40,
+    Wep104,
+    Wep128,
+    WpaTkip,
+    Wpa2Tkip,
+    WpaAes,
+    Wpa2Aes,
+}
+
+/// One of the three connection slots in the system settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[enum_cast(value_type = "u32")]
+pub enum WifiSlot {
+    First,
+    Second,
+    Third,
+}
+
+/// The name of a Wi-Fi network, which is not necessarily UTF-8.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Ssid {
+    data: [u8; SSID_SIZE],
+    len: usize,
+}
+
+impl Ssid {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        core::str::from_utf8(self.as_bytes()).ok()
+    }
+}
+
+impl core::fmt::Debug for Ssid {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.as_str() {
+            Some(ssid) => f.debug_tuple("Ssid").field(&ssid).finish(),
+            None => f.debug_tuple("Ssid").field(&self.as_bytes()).finish(),
+        }
+    }
+}
+
+/// Settings for a connection attempt.
+#[derive(Clone)]
+pub struct AcConfig([u8; CONFIG_SIZE]);
+
+impl core::fmt::Debug for AcConfig {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("AcConfig").finish_non_exhaustive()
+    }
+}
+
 impl Ac {
     pub fn init(srv: &Srv) -> Result<Self> {
         Ok(Self {
             handle: srv
                 .get_service_handle("ac:i")
                 .or_else(|_| srv.get_service_handle("ac:u"))?,
+            connect_event: Event::new(ResetType::OneShot)?,
         })
     }
 
+    /// The configuration connecting to any of the Wi-Fi slots set up in the system settings.
+    pub fn create_default_config(&self) -> Result<AcConfig> {
+        let mut config = AcConfig([0; CONFIG_SIZE]);
+        tls::get_thread_local_storage()
+            .static_buffer_descriptors()
+            .set(0, &mut config.0);
+
+        let _ = IpcRequest::command(0x1).dispatch(&self.handle)?;
+
+        Ok(config)
+    }
+
+    /// Start connecting with the [default configuration](Self::create_default_config).
+    ///
+    /// See [`wait_connected`](Self::wait_connected) for the outcome.
+    pub fn connect_async(&self) -> Result<()> {
+        let config = self.create_default_config()?;
+        self.connect_async_with(&config)
+    }
+
+    pub fn connect_async_with(&self, config: &AcConfig) -> Result<()> {
+        self.connect_event.clear()?;
+
+        let _ = IpcRequest::command(0x4)
+            .translate_parameter(ThisProcessId)
+            .translate_parameter(self.connect_event.as_handle())
+            .translate_parameter(StaticBuffer::from_bytes(&config.0, 1))
+            .dispatch(&self.handle)?;
+
+        Ok(())
+    }
+
+    /// Wait for the connection attempt started by [`connect_async`](Self::connect_async) to
+    /// complete, failing if no connection could be established.
+    ///
+    /// Returns [`WaitOutcome::TimedOut`] if it is still in progress after `timeout`.
+    pub fn wait_connected(&self, timeout: Timeout) -> Result<WaitOutcome> {
+        if self.connect_event.wait(timeout)?.is_timed_out() {
+            return Ok(WaitOutcome::TimedOut);
+        }
+
+        let _ = IpcRequest::command(0x5)
+            .translate_parameter(ThisProcessId)
+            .dispatch(&self.handle)?;
+
+        Ok(WaitOutcome::Signaled)
+    }
+
+    /// Close the connection, returning once it is down.
+    pub fn disconnect(&self) -> Result<()> {
+        let closed = Event::new(ResetType::OneShot)?;
+
+        let _ = IpcRequest::command(0x8)
+            .translate_parameter(ThisProcessId)
+            .translate_parameter(closed.as_handle())
+            .dispatch(&self.handle)?;
+
+        closed.wait(Timeout::forever())?;
+
+        let _ = IpcRequest::command(0x9)
+            .translate_parameter(ThisProcessId)
+            .dispatch(&self.handle)?;
+
+        Ok(())
+    }
+
     pub fn wifi_status(&self) -> Result<WifiStatus> {
         let mut reply = IpcRequest::command(0xd).dispatch(&self.handle)?;
 
@@ -43,16 +186,44 @@ impl Ac {
             0 => WifiStatus::NoConnection,
             1 => WifiStatus::Old3dsConnection,
             2 => WifiStatus::New3dsConnection,
-            _ => {
-                return Err(ErrorCode::new(
-                    Level::Fatal,
-                    Summary::InvalidResultValue,
-                    Module::Ac,
-                    CommonDescription::InvalidResultValue.to_value(),
-                ));
-            }
+            _ => return Err(ERROR_INVALID_RESULT_VALUE),
         };
 
         Ok(status)
     }
+
+    /// The slot of the connection in use.
+    pub fn connected_slot(&self) -> Result<WifiSlot> {
+        let mut reply = IpcRequest::command(0xf).dispatch(&self.handle)?;
+
+        WifiSlot::from_value(reply.read_word()).map_err(|_| ERROR_INVALID_RESULT_VALUE)
+    }
+
+    pub fn security_mode(&self) -> Result<SecurityMode> {
+        let mut reply = IpcRequest::command(0x33).dispatch(&self.handle)?;
+
+        SecurityMode::from_value(reply.read_word()).map_err(|_| ERROR_INVALID_RESULT_VALUE)
+    }
+
+    /// The name of the network currently connected to.
+    pub fn ssid(&self) -> Result<Ssid> {
+        let mut data = [0; SSID_SIZE];
+        tls::get_thread_local_storage()
+            .static_buffer_descriptors()
+            .set(0, &mut data);
+
+        let _ = IpcRequest::command(0x34).dispatch(&self.handle)?;
+
+        let mut reply = IpcRequest::command(0x35).dispatch(&self.handle)?;
+        let len = (reply.read_word() as usize).min(SSID_SIZE);
+
+        Ok(Ssid { data, len })
+    }
+
+    /// Signal strength of the current connection, from `0` (none) to `3`.
+    ///
+    /// Read from the [shared page](sharedpage::wifi_link_level), as the system settings do.
+    pub fn signal_strength(&self) -> u8 {
+        sharedpage::wifi_link_level()
+    }
 }
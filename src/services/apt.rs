@@ -4,14 +4,27 @@
 
 use core::marker::PhantomData;
 use core::ops::Deref;
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use core::time::Duration;
 
-use crate::ipc::{IpcParameter, IpcRequest};
+use crate::env::{self, RunFlags};
+use crate::ipc::{IpcParameter, IpcRequest, StaticBuffer};
 use crate::os::{AsHandle, OwnedHandle, BorrowedHandle};
+use crate::ports::hbldr::HbLdr;
 use crate::ports::srv::Srv;
 use crate::result::Result;
-use crate::sync::{Event, Mutex, OsMutex};
+use crate::services::gsp::gpu::{DisplayCapture, Gpu};
+use crate::services::gsp::gx::TransferFormat;
+use crate::svc::Timeout;
+use crate::sync::{Event, LightMutex, Mutex, OsMutex};
+use crate::thread;
+use crate::tls;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 
 use ctru_rt_macros::EnumCast;
+use log::debug;
 
 const APT_SERVICE_NAMES: [&str; 3] = ["APT:S", "APT:A", "APT:U"];
 
@@ -27,10 +40,15 @@ impl AppletAttributes {
         Self(self.0 | position.to_value())
     }
 
+    /// Keep APT from moving GPU rights on its own.
+    ///
+    /// There is no APT command to claim them: [`Gpu::init`] acquires them from GSP, and
+    /// [`AptLock`] hands them back and forth around sleep and applet transitions.
     const fn manual_gpu_rights(self) -> Self {
         Self(self.0 | (1 << 3))
     }
 
+    /// Keep APT from moving DSP rights on its own, which are claimed from the DSP service.
     const fn manual_dsp_rights(self) -> Self {
         Self(self.0 | (1 << 4))
     }
@@ -82,12 +100,225 @@ impl<'access, 'srv> Apt<'access, 'srv> {
         Ok((signal_event, resume_event))
     }
 
+    fn finalize(&self, app_id: AppId) -> Result<()> {
+        let _ = IpcRequest::command(0x04)
+            .parameter(app_id)
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
     fn enable(&self, attributes: AppletAttributes) -> Result<()> {
         let _ = IpcRequest::command(0x03)
             .parameter(attributes)
             .dispatch(&self.handle)?;
         Ok(())
     }
+
+    fn inquire_notification(&self, app_id: AppId) -> Result<u32> {
+        let mut reply = IpcRequest::command(0x0b)
+            .parameter(app_id)
+            .dispatch(&self.handle)?;
+
+        Ok(reply.read_word())
+    }
+
+    fn is_registered(&self, app_id: AppId) -> Result<bool> {
+        let mut reply = IpcRequest::command(0x09)
+            .parameter(app_id)
+            .dispatch(&self.handle)?;
+
+        Ok(reply.read_word() & 0xff != 0)
+    }
+
+    fn send_parameter(
+        &self,
+        source: AppId,
+        destination: AppId,
+        command: Command,
+        data: &[u8],
+    ) -> Result<()> {
+        let _ = IpcRequest::command(0x0c)
+            .parameter(source)
+            .parameter(destination)
+            .parameter(command.to_value())
+            .parameter(data.len())
+            .translate_parameter(None::<BorrowedHandle>)
+            .translate_parameter(StaticBuffer::from_bytes(data, 0))
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    /// Receive the parameter sent to `app_id`, copying its data into `buffer`.
+    fn receive_parameter(&self, app_id: AppId, buffer: &mut [u8]) -> Result<Parameter> {
+        let size = buffer.len();
+        tls::get_thread_local_storage()
+            .static_buffer_descriptors()
+            .set(0, buffer);
+
+        let mut reply = IpcRequest::command(0x0d)
+            .parameter(app_id)
+            .parameter(size)
+            .dispatch(&self.handle)?;
+
+        let _sender = reply.read_word();
+        let command = reply.read_word();
+        let size = reply.read_word() as usize;
+
+        let mut reply = reply.finish_results();
+        let handle = unsafe { reply.read_translate_result() };
+
+        Ok(Parameter {
+            command,
+            size,
+            handle,
+        })
+    }
+
+    fn prepare_to_start_library_applet(&self, app_id: AppId) -> Result<()> {
+        let _ = IpcRequest::command(0x18)
+            .parameter(app_id)
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    fn start_library_applet(&self, app_id: AppId, parameter: &[u8]) -> Result<()> {
+        let _ = IpcRequest::command(0x1e)
+            .parameter(app_id)
+            .parameter(parameter.len())
+            .translate_parameter(None::<BorrowedHandle>)
+            .translate_parameter(StaticBuffer::from_bytes(parameter, 0))
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    fn prepare_to_close_application(&self, cancel_preload: bool) -> Result<()> {
+        let _ = IpcRequest::command(0x22)
+            .parameter(u32::from(cancel_preload))
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    fn close_application(&self) -> Result<()> {
+        let _ = IpcRequest::command(0x27)
+            .parameter(0u32)
+            .translate_parameter(None::<BorrowedHandle>)
+            .translate_parameter(StaticBuffer::from_bytes(&[], 0))
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    fn prepare_to_do_application_jump(&self, target: &Chainload) -> Result<()> {
+        let _ = IpcRequest::command(0x31)
+            .parameter(u32::from(target.flags))
+            .parameter(target.title_id as u32)
+            .parameter((target.title_id >> 32) as u32)
+            .parameter(u32::from(target.media_type.to_value()))
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    fn do_application_jump(&self, parameter: &[u8], hmac: &[u8; 0x20]) -> Result<()> {
+        let _ = IpcRequest::command(0x32)
+            .parameter(parameter.len())
+            .parameter(hmac.len())
+            .translate_parameter(StaticBuffer::from_bytes(parameter, 0))
+            .translate_parameter(StaticBuffer::from_bytes(hmac, 2))
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    /// Unregister the application once it exits, as expected by the homebrew loader.
+    ///
+    /// If the system asked the application to close, it closes regardless of the run flags.
+    fn exit(&self, closing: bool, chainload: Option<Chainload>) -> Result<()> {
+        if closing {
+            return self.close_application();
+        }
+
+        let run_flags = env::system_runflags();
+        let chainload = chainload.or_else(|| {
+            run_flags
+                .contains(RunFlags::APT_CHAINLOAD)
+                .then_some(Chainload::SELF)
+        });
+
+        if let Some(target) = chainload {
+            const PARAMETER: [u8; 0x300] = [0; 0x300];
+            self.prepare_to_do_application_jump(&target)?;
+            self.do_application_jump(&PARAMETER, &[0; 0x20])
+        } else if run_flags.contains(RunFlags::APT_REINIT) {
+            self.finalize(AppId::Application)
+        } else {
+            self.prepare_to_close_application(true)?;
+            self.close_application()
+        }
+    }
+
+    fn prepare_to_jump_to_home_menu(&self) -> Result<()> {
+        let _ = IpcRequest::command(0x2b).dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    fn jump_to_home_menu(&self) -> Result<()> {
+        let _ = IpcRequest::command(0x2c)
+            .parameter(0u32)
+            .translate_parameter(None::<BorrowedHandle>)
+            .translate_parameter(StaticBuffer::from_bytes(&[], 0))
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    fn send_capture_buffer_info(&self, info: &CaptureBufferInfo) -> Result<()> {
+        let bytes = info.bytes();
+
+        let _ = IpcRequest::command(0x40)
+            .parameter(bytes.len())
+            .translate_parameter(StaticBuffer::from_bytes(&bytes, 0))
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    fn reply_sleep_query(&self, app_id: AppId, accept: bool) -> Result<()> {
+        let _ = IpcRequest::command(0x3e)
+            .parameter(app_id)
+            .parameter(u32::from(accept))
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    fn sleep_if_shell_closed(&self) -> Result<()> {
+        let _ = IpcRequest::command(0x3c).dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    fn reply_sleep_notification_complete(&self, app_id: AppId) -> Result<()> {
+        let _ = IpcRequest::command(0x3f)
+            .parameter(app_id)
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    fn notify_to_wait(&self, app_id: AppId) -> Result<()> {
+        let _ = IpcRequest::command(0x43)
+            .parameter(app_id)
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+
+    /// Reserve `percent` of the system core's CPU time for this application.
+    ///
+    /// This is required before spawning threads on [`Core::SysCore`](crate::thread::Core).
+    pub fn set_app_cpu_time_limit(&self, percent: u32) -> Result<()> {
+        set_app_cpu_time_limit(self.handle.as_handle(), percent)
+    }
+
+    pub fn app_cpu_time_limit(&self) -> Result<u32> {
+        let mut reply = IpcRequest::command(0x50)
+            .parameter(1u32)
+            .dispatch(&self.handle)?;
+
+        Ok(reply.read_word())
+    }
 }
 
 impl AsHandle for Apt<'_, '_> {
@@ -102,7 +333,7 @@ pub struct AptAccess<'srv> {
 }
 
 impl<'srv> AptAccess<'srv> {
-    fn aquire<'access>(&'access mut self) -> Result<Apt<'access, 'srv>> {
+    pub fn acquire<'access>(&'access mut self) -> Result<Apt<'access, 'srv>> {
         let (handle, matched_offset) = self.srv.get_service_handle_alternatives(&APT_SERVICE_NAMES[self.service_name_index..])?;
         self.service_name_index += matched_offset;
 
@@ -110,33 +341,642 @@ impl<'srv> AptAccess<'srv> {
     }
 }
 
+fn set_app_cpu_time_limit(apt: BorrowedHandle, percent: u32) -> Result<()> {
+    let _ = IpcRequest::command(0x4f)
+        .parameters(&[1, percent])
+        .dispatch(apt)?;
+    Ok(())
+}
+
+/// Like [`Apt::set_app_cpu_time_limit`], but without an [`AptLock`].
+///
+/// A single command needs no APT lock, see
+/// [`ThreadBuilder::with_syscore_time`](crate::thread::ThreadBuilder::with_syscore_time).
+pub(crate) fn reserve_syscore_time(srv: &Srv, percent: u32) -> Result<()> {
+    let (handle, _) = srv.get_service_handle_alternatives(&APT_SERVICE_NAMES)?;
+    set_app_cpu_time_limit(handle.as_handle(), percent)
+}
+
+/// Read the wireless reboot info into `buffer`.
+///
+/// The system sets it when rebooting into a title for local wireless play, like a Download Play
+/// child, to tell it which host to connect to.  Like [`reserve_syscore_time`], this needs no APT
+/// lock, so it can be read before initializing `ac` or `soc`.
+pub fn wireless_reboot_info(srv: &Srv, buffer: &mut [u8]) -> Result<()> {
+    let (handle, _) = srv.get_service_handle_alternatives(&APT_SERVICE_NAMES)?;
+
+    let size = buffer.len();
+    tls::get_thread_local_storage()
+        .static_buffer_descriptors()
+        .set(0, buffer);
+
+    let _ = IpcRequest::command(0x45)
+        .parameter(size)
+        .dispatch(&handle)?;
+    Ok(())
+}
+
+/// What the application should do next, see [`AptLock::main_loop_step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppState {
+    /// Continue with the next frame.
+    Running,
+    /// The HOME or power button was pressed, call [`AptLock::jump_to_home`].
+    HomeRequested,
+    /// The system asked the application to close.  Return from `main`.
+    Exiting,
+}
+
+/// Notifications sent through the signal event of an application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[enum_cast(value_type = "u32")]
+enum Signal {
+    None,
+    HomeButton,
+    HomeButton2,
+    SleepQuery,
+    SleepCancel,
+    SleepEnter,
+    SleepWakeup,
+    Shutdown,
+    PowerButton,
+    PowerButton2,
+    TrySleep,
+    OrderToClose,
+}
+
+/// Commands of parameters, e.g. those waking up an application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[enum_cast(value_type = "u32")]
+enum Command {
+    None,
+    Wakeup,
+    Request,
+    Response,
+    Exit,
+    Message,
+    HomeButtonOnce,
+    HomeButtonTwice,
+    DspSleep,
+    DspWakeup,
+    WakeupExit,
+    WakeupPause,
+    WakeupCancel,
+    WakeupCancelAll,
+    WakeupPowerButton,
+    WakeupJumpToHome,
+    RequestSysApplet,
+    WakeupLaunchApp,
+}
+
+/// Where the HOME menu finds the screen contents of the suspended application.
+#[derive(Debug, Clone, Copy)]
+struct CaptureBufferInfo {
+    size: u32,
+    is_3d: bool,
+    top: CaptureScreen,
+    bottom: CaptureScreen,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CaptureScreen {
+    left_offset: u32,
+    right_offset: u32,
+    format: u32,
+}
+
+impl CaptureBufferInfo {
+    /// Pixels in the capture of each screen, including the padding up to the next multiple of 256.
+    const TOP_PIXELS: u32 = 400 * 256;
+    const BOTTOM_PIXELS: u32 = 320 * 256;
+
+    fn new(capture: &DisplayCapture) -> Self {
+        let bytes_per_pixel = |format: core::result::Result<TransferFormat, u32>| {
+            format.map_or(4, |format| format.bytes_per_pixel())
+        };
+
+        let is_3d = capture.top.is_3d();
+        let top_size = bytes_per_pixel(capture.top.format()) * Self::TOP_PIXELS;
+        let bottom_size = bytes_per_pixel(capture.bottom.format()) * Self::BOTTOM_PIXELS;
+
+        let top_left = bottom_size;
+        let top_right = if is_3d { top_left + top_size } else { top_left };
+
+        Self {
+            size: top_right + top_size,
+            is_3d,
+            top: CaptureScreen {
+                left_offset: top_left,
+                right_offset: top_right,
+                format: capture.top.mode & 0b111,
+            },
+            bottom: CaptureScreen {
+                left_offset: 0,
+                right_offset: 0,
+                format: capture.bottom.mode & 0b111,
+            },
+        }
+    }
+
+    fn bytes(&self) -> [u8; 0x20] {
+        let words = [
+            self.size,
+            u32::from(self.is_3d),
+            self.top.left_offset,
+            self.top.right_offset,
+            self.top.format,
+            self.bottom.left_offset,
+            self.bottom.right_offset,
+            self.bottom.format,
+        ];
+
+        let mut bytes = [0; 0x20];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// A parameter sent to this application by another applet.
+#[derive(Debug)]
+struct Parameter {
+    command: u32,
+    /// Size of the data sent, which may exceed the buffer it was received into.
+    size: usize,
+    handle: Option<OwnedHandle>,
+}
+
+/// Where a title is installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[enum_cast(value_type = "u8")]
+pub enum MediaType {
+    Nand,
+    Sd,
+    GameCard,
+}
+
+/// The title to start once this application exits.
+#[derive(Debug, Clone, Copy)]
+struct Chainload {
+    flags: u8,
+    title_id: u64,
+    media_type: MediaType,
+}
+
+impl Chainload {
+    const FLAG_SELF: u8 = 1 << 1;
+
+    const SELF: Self = Self {
+        flags: Self::FLAG_SELF,
+        title_id: 0,
+        media_type: MediaType::Nand,
+    };
+}
+
+/// Bits of [`AptLock::flags`].
+const FLAG_HOME_REQUESTED: u32 = 1 << 0;
+const FLAG_POWER_BUTTON: u32 = 1 << 1;
+const FLAG_CLOSING: u32 = 1 << 2;
+const FLAG_HOME_DISALLOWED: u32 = 1 << 3;
+const FLAG_SLEEP_DISALLOWED: u32 = 1 << 4;
+
+const EXIT_NONE: u8 = 0;
+const EXIT_CLOSE: u8 = 1;
+const EXIT_CLOSING: u8 = 2;
+
+/// How [`rt::exit`](crate::rt::exit) has to close the application registered by an [`AptLock`].
+static CLOSE_ON_EXIT: AtomicU8 = AtomicU8::new(EXIT_NONE);
+
+fn close_on_exit() {
+    let closing = match CLOSE_ON_EXIT.swap(EXIT_NONE, Ordering::AcqRel) {
+        EXIT_NONE => return,
+        state => state == EXIT_CLOSING,
+    };
+
+    debug!("Closing application on exit");
+
+    // The lock owning the session to APT was never dropped, so open a new one
+    let srv = match Srv::init() {
+        Ok(srv) => srv,
+        Err(_) => return,
+    };
+    let mut access = AptAccess {
+        srv: &srv,
+        service_name_index: 0,
+    };
+
+    let _ = access.acquire().and_then(|apt| apt.exit(closing, None));
+}
+
+type SleepHook = Box<dyn FnMut() + Send>;
+
+#[derive(Default)]
+struct SleepHooks {
+    on_sleep: Vec<SleepHook>,
+    on_wake: Vec<SleepHook>,
+}
+
 pub struct AptLock<'srv> {
     access: Mutex<AptAccess<'srv>>,
+    signal_event: Event,
+    resume_event: Event,
+    flags: AtomicU32,
+    hooks: LightMutex<SleepHooks>,
+    chainload: LightMutex<Option<Chainload>>,
 }
 
 impl<'srv> AptLock<'srv> {
+    /// Register this application with APT and wait until it may run.
     pub fn init(srv: &'srv mut Srv) -> Result<Self> {
         let mut access = AptAccess {
             srv,
             service_name_index: 0,
         };
 
-        let apt = access.aquire()?;
+        let apt = access.acquire()?;
 
         const FLAGS: u16 = 0x0;
         let mutex = apt.get_lock(FLAGS)?;
 
-        let (_signal_event, _resume_event) = apt.init(
-            AppId::Application,
-            AppletAttributes::new()
-                .position(AppPosition::App)
-                .manual_gpu_rights()
-                .manual_dsp_rights(),
-        )?;
+        let attributes = AppletAttributes::new()
+            .position(AppPosition::App)
+            .manual_gpu_rights()
+            .manual_dsp_rights();
+
+        let (signal_event, resume_event) = apt.init(AppId::Application, attributes)?;
+        apt.enable(attributes)?;
+        drop(apt);
 
         let access = Mutex::const_new(mutex, access);
 
-        Ok(Self { access })
+        let this = Self {
+            access,
+            signal_event,
+            resume_event,
+            flags: AtomicU32::new(0),
+            hooks: LightMutex::new(SleepHooks::default()),
+            chainload: LightMutex::new(None),
+        };
+
+        if !env::system_runflags().contains(RunFlags::APT_WORKAROUND) {
+            this.with_apt(|apt| apt.notify_to_wait(AppId::Application))?;
+            this.wait_for_wakeup(&mut [])?;
+
+            CLOSE_ON_EXIT.store(EXIT_CLOSE, Ordering::Release);
+            crate::rt::at_exit(close_on_exit);
+        }
+
+        Ok(this)
+    }
+
+    fn with_apt<T, F: FnOnce(&Apt) -> Result<T>>(&self, f: F) -> Result<T> {
+        let mut access = self.access.lock();
+        let apt = access.acquire()?;
+        f(&apt)
+    }
+
+    fn set_flags(&self, flags: u32) {
+        self.flags.fetch_or(flags, Ordering::AcqRel);
+
+        if flags & FLAG_CLOSING != 0 {
+            let _ = CLOSE_ON_EXIT.compare_exchange(
+                EXIT_CLOSE,
+                EXIT_CLOSING,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+        }
+    }
+
+    fn clear_flags(&self, flags: u32) {
+        self.flags.fetch_and(!flags, Ordering::AcqRel);
+    }
+
+    /// Call `hook` right before the system goes to sleep, e.g. when the lid is closed.
+    ///
+    /// Hooks run on the thread calling [`main_loop_step`](Self::main_loop_step).
+    pub fn on_sleep<F>(&self, hook: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.hooks.lock().on_sleep.push(Box::new(hook))
+    }
+
+    /// Call `hook` once the system woke up from sleep.
+    pub fn on_wake<F>(&self, hook: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.hooks.lock().on_wake.push(Box::new(hook))
+    }
+
+    /// Run the hooks selected by `list`, without holding the lock so that they can register more.
+    fn run_hooks(&self, list: fn(&mut SleepHooks) -> &mut Vec<SleepHook>) {
+        let mut hooks = core::mem::take(list(&mut self.hooks.lock()));
+        for hook in &mut hooks {
+            hook()
+        }
+
+        let mut locked = self.hooks.lock();
+        let registered = list(&mut locked);
+        hooks.append(registered);
+        *registered = hooks;
+    }
+
+    /// Handle notifications from APT, and return what the application should do next.
+    ///
+    /// Call this once per frame.
+    pub fn main_loop_step(&self) -> Result<AppState> {
+        self.step(None)
+    }
+
+    /// Like [`main_loop_step`](Self::main_loop_step), but also release the GPU while the system
+    /// sleeps.
+    ///
+    /// The GPU is released after the [`on_sleep`](Self::on_sleep) hooks ran, and reacquired before
+    /// the [`on_wake`](Self::on_wake) hooks run.
+    pub fn main_loop_step_with_gpu(&self, gpu: &mut Gpu) -> Result<AppState> {
+        self.step(Some(gpu))
+    }
+
+    fn step(&self, mut gpu: Option<&mut Gpu>) -> Result<AppState> {
+        while self.signal_event.wait(Timeout::none())?.is_signaled() {
+            self.handle_signal(gpu.as_deref_mut())?;
+        }
+
+        Ok(self.state())
+    }
+
+    fn state(&self) -> AppState {
+        let flags = self.flags.load(Ordering::Acquire);
+        if flags & FLAG_CLOSING != 0 {
+            AppState::Exiting
+        } else if flags & (FLAG_HOME_REQUESTED | FLAG_POWER_BUTTON) != 0 {
+            AppState::HomeRequested
+        } else {
+            AppState::Running
+        }
+    }
+
+    fn handle_signal(&self, gpu: Option<&mut Gpu>) -> Result<()> {
+        let signal = self.with_apt(|apt| apt.inquire_notification(AppId::Application))?;
+
+        match Signal::from_value(signal) {
+            Ok(Signal::HomeButton | Signal::HomeButton2) => {
+                if self.is_home_allowed() {
+                    self.set_flags(FLAG_HOME_REQUESTED)
+                } else {
+                    debug!("Ignoring HOME button while disallowed")
+                }
+            }
+            Ok(Signal::PowerButton) => self.set_flags(FLAG_POWER_BUTTON),
+            Ok(Signal::PowerButton2) => self.clear_flags(FLAG_POWER_BUTTON),
+            Ok(Signal::SleepQuery) => {
+                let accept = self.is_sleep_allowed();
+                self.with_apt(|apt| apt.reply_sleep_query(AppId::Application, accept))?
+            }
+            Ok(Signal::SleepEnter) => {
+                self.run_hooks(|hooks| &mut hooks.on_sleep);
+                if let Some(gpu) = gpu {
+                    gpu.prepare_for_sleep()?;
+                }
+                self.with_apt(|apt| apt.reply_sleep_notification_complete(AppId::Application))?
+            }
+            Ok(Signal::SleepWakeup) => {
+                if let Some(gpu) = gpu {
+                    gpu.resume_after_sleep()?;
+                }
+                self.run_hooks(|hooks| &mut hooks.on_wake);
+            }
+            Ok(Signal::Shutdown | Signal::OrderToClose) => self.set_flags(FLAG_CLOSING),
+            Ok(Signal::None | Signal::SleepCancel | Signal::TrySleep) => {}
+            Err(signal) => debug!("Ignoring unknown APT signal {}", signal),
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the next parameter sent to this application.
+    fn receive(&self, buffer: &mut [u8]) -> Result<Parameter> {
+        self.resume_event.wait(Timeout::forever())?;
+        self.with_apt(|apt| apt.receive_parameter(AppId::Application, buffer))
+    }
+
+    /// Wait until this application is woken up, receiving the data sent along into `buffer`.
+    fn wait_for_wakeup(&self, buffer: &mut [u8]) -> Result<AppState> {
+        loop {
+            let command = self.receive(buffer)?.command;
+
+            match Command::from_value(command) {
+                Ok(
+                    Command::Wakeup
+                    | Command::WakeupExit
+                    | Command::WakeupPause
+                    | Command::WakeupLaunchApp,
+                ) => break,
+                Ok(Command::WakeupJumpToHome) => {
+                    self.set_flags(FLAG_HOME_REQUESTED);
+                    break;
+                }
+                Ok(Command::WakeupPowerButton) => {
+                    self.set_flags(FLAG_POWER_BUTTON);
+                    break;
+                }
+                Ok(Command::WakeupCancel | Command::WakeupCancelAll) => {
+                    self.set_flags(FLAG_CLOSING);
+                    break;
+                }
+                _ => debug!("Ignoring APT parameter {} while waiting", command),
+            }
+        }
+
+        Ok(self.state())
+    }
+
+    /// Suspend this application and show the HOME menu, returning once the application resumes.
+    ///
+    /// The screen contents are handed to the HOME menu, and the GPU is released while suspended.
+    /// Once resumed, the GPU is reacquired with its VRAM restored, but the framebuffers have to be
+    /// presented again.
+    pub fn jump_to_home(&self, gpu: &mut Gpu) -> Result<AppState> {
+        self.clear_flags(FLAG_HOME_REQUESTED | FLAG_POWER_BUTTON);
+
+        self.with_apt(|apt| apt.prepare_to_jump_to_home_menu())?;
+
+        let capture = CaptureBufferInfo::new(&gpu.import_display_capture_info()?);
+        self.with_apt(|apt| apt.send_capture_buffer_info(&capture))?;
+
+        gpu.prepare_for_sleep()?;
+        self.with_apt(|apt| apt.jump_to_home_menu())?;
+
+        let state = self.wait_for_wakeup(&mut [])?;
+        gpu.resume_after_sleep()?;
+
+        Ok(state)
+    }
+
+    /// Whether pressing HOME returns [`AppState::HomeRequested`], the default.
+    pub fn is_home_allowed(&self) -> bool {
+        self.flags.load(Ordering::Acquire) & FLAG_HOME_DISALLOWED == 0
+    }
+
+    /// Ignore the HOME button while not `allowed`, e.g. while saving.
+    pub fn set_home_allowed(&self, allowed: bool) {
+        if allowed {
+            self.clear_flags(FLAG_HOME_DISALLOWED)
+        } else {
+            self.set_flags(FLAG_HOME_DISALLOWED)
+        }
+    }
+
+    /// Whether the system may go to sleep when the lid is closed, the default.
+    pub fn is_sleep_allowed(&self) -> bool {
+        self.flags.load(Ordering::Acquire) & FLAG_SLEEP_DISALLOWED == 0
+    }
+
+    /// Keep the system awake while not `allowed`.
+    ///
+    /// Once allowed again, the system goes to sleep if the lid was closed in the meantime.
+    pub fn set_sleep_allowed(&self, allowed: bool) -> Result<()> {
+        if allowed {
+            let previous = self.flags.fetch_and(!FLAG_SLEEP_DISALLOWED, Ordering::AcqRel);
+            if previous & FLAG_SLEEP_DISALLOWED != 0 {
+                self.with_apt(|apt| apt.sleep_if_shell_closed())?;
+            }
+        } else {
+            self.set_flags(FLAG_SLEEP_DISALLOWED);
+            // Turn down a query that is still pending
+            self.with_apt(|apt| apt.reply_sleep_query(AppId::Application, false))?;
+        }
+
+        Ok(())
+    }
+
+    /// Disallow HOME and sleep until the returned guard is dropped, which restores the previous
+    /// settings.
+    pub fn lock_home_and_sleep(&self) -> Result<HomeSleepLock<'_, 'srv>> {
+        let guard = HomeSleepLock {
+            apt: self,
+            home_allowed: self.is_home_allowed(),
+            sleep_allowed: self.is_sleep_allowed(),
+        };
+
+        self.set_home_allowed(false);
+        self.set_sleep_allowed(false)?;
+
+        Ok(guard)
+    }
+
+    /// Start the title `title_id` instead of returning to the HOME menu once this application exits,
+    /// i.e. when this `AptLock` is dropped.
+    pub fn chainload(&self, title_id: u64, media_type: MediaType) {
+        *self.chainload.lock() = Some(Chainload {
+            flags: 0,
+            title_id,
+            media_type,
+        })
+    }
+
+    /// Restart this title once this application exits.
+    pub fn chainload_self(&self) {
+        *self.chainload.lock() = Some(Chainload::SELF)
+    }
+
+    /// Start the `.3dsx` at `path` with `args` once this application exits.
+    ///
+    /// This restarts the current title with the homebrew loader of Luma3DS set up to load `path`,
+    /// just like the Homebrew Launcher does.  The loaded executable receives `path` as its first
+    /// argument.
+    pub fn chainload_3dsx(&self, path: &str, args: &[&str]) -> Result<()> {
+        let loader = HbLdr::init()?;
+        loader.set_target(path)?;
+        loader.set_arguments(core::iter::once(path).chain(args.iter().copied()))?;
+
+        self.chainload_self();
+
+        Ok(())
+    }
+
+    /// Cancel a previous [`chainload`](Self::chainload).
+    pub fn cancel_chainload(&self) {
+        self.chainload.lock().take();
+    }
+
+    /// Hand the screen capture to the library applet `applet` about to be started.
+    fn transfer_screen(&self, gpu: &mut Gpu, applet: AppId) -> Result<()> {
+        const REGISTER_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+        let capture = CaptureBufferInfo::new(&gpu.import_display_capture_info()?);
+
+        while !self.with_apt(|apt| apt.is_registered(applet))? {
+            thread::sleep(REGISTER_POLL_INTERVAL);
+        }
+
+        self.with_apt(|apt| {
+            apt.send_parameter(
+                AppId::Application,
+                applet,
+                Command::Request,
+                &capture.bytes(),
+            )
+        })?;
+
+        while self.receive(&mut [])?.command != Command::Response.to_value() {}
+
+        Ok(())
+    }
+
+    /// Suspend this application and run the library applet `applet`, returning once it exits.
+    ///
+    /// The applet receives `parameter` and overwrites it with its result.  The GPU is handled like
+    /// in [`jump_to_home`](Self::jump_to_home).
+    pub(crate) fn run_library_applet(
+        &self,
+        gpu: &mut Gpu,
+        applet: AppId,
+        parameter: &mut [u8],
+    ) -> Result<AppState> {
+        self.with_apt(|apt| apt.prepare_to_start_library_applet(applet))?;
+        self.transfer_screen(gpu, applet)?;
+
+        gpu.prepare_for_sleep()?;
+        self.with_apt(|apt| apt.start_library_applet(applet, parameter))?;
+
+        let state = self.wait_for_wakeup(parameter)?;
+        gpu.resume_after_sleep()?;
+
+        Ok(state)
+    }
+}
+
+impl Drop for AptLock<'_> {
+    fn drop(&mut self) {
+        CLOSE_ON_EXIT.store(EXIT_NONE, Ordering::Release);
+
+        if env::system_runflags().contains(RunFlags::APT_WORKAROUND) {
+            return;
+        }
+
+        let closing = self.flags.load(Ordering::Acquire) & FLAG_CLOSING != 0;
+        let chainload = self.chainload.get_mut().take();
+
+        let _ = self.with_apt(|apt| apt.exit(closing, chainload));
+    }
+}
+
+/// Keeps HOME and sleep disallowed, see [`AptLock::lock_home_and_sleep`].
+#[must_use = "HOME and sleep are allowed again when the lock is dropped"]
+pub struct HomeSleepLock<'apt, 'srv> {
+    apt: &'apt AptLock<'srv>,
+    home_allowed: bool,
+    sleep_allowed: bool,
+}
+
+impl Drop for HomeSleepLock<'_, '_> {
+    fn drop(&mut self) {
+        self.apt.set_home_allowed(self.home_allowed);
+        let _ = self.apt.set_sleep_allowed(self.sleep_allowed);
     }
 }
 
@@ -148,9 +988,9 @@ impl<'srv> Deref for AptLock<'srv> {
     }
 }
 
-#[derive(Debug, EnumCast)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
 #[enum_cast(value_type = "u16")]
-enum AppId {
+pub(crate) enum AppId {
     HomeMenu = 0x101,
     Camera = 0x110,
     FriendsList = 0x112,
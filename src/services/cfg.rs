@@ -0,0 +1,164 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::ipc::{IpcRequest, MappedBuffer};
+use crate::os::OwnedHandle;
+use crate::ports::srv::Srv;
+use crate::result::{CommonDescription, ErrorCode, Level, Module, Result, Summary};
+
+use ctru_rt_macros::EnumCast;
+
+const CFG_SERVICE_NAMES: [&str; 3] = ["cfg:i", "cfg:s", "cfg:u"];
+
+/// Services that can write the configuration, a prefix of [`CFG_SERVICE_NAMES`].
+const CFG_WRITE_SERVICES: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[enum_cast(value_type = "u8")]
+pub enum SystemModel {
+    Old3ds,
+    Old3dsXl,
+    New3ds,
+    Old2ds,
+    New3dsXl,
+    New2dsXl,
+}
+
+impl SystemModel {
+    pub const fn is_new_3ds(self) -> bool {
+        matches!(self, Self::New3ds | Self::New3dsXl | Self::New2dsXl)
+    }
+}
+
+#[derive(Debug)]
+pub struct Cfg {
+    handle: OwnedHandle,
+    service_index: usize,
+}
+
+impl Cfg {
+    pub fn init(srv: &Srv) -> Result<Self> {
+        let (handle, service_index) = srv.get_service_handle_alternatives(&CFG_SERVICE_NAMES)?;
+
+        Ok(Self {
+            handle,
+            service_index,
+        })
+    }
+
+    /// Read the configuration block `id` into `data`, which has to match the block's size.
+    pub fn get_config_block(&self, id: u32, data: &mut [u8]) -> Result<()> {
+        let size = data.len();
+
+        let _ = IpcRequest::command(0x01)
+            .parameter(size)
+            .parameter(id)
+            .translate_parameter(MappedBuffer::write(data))
+            .dispatch(&self.handle)?;
+
+        Ok(())
+    }
+
+    /// Get write access to the configuration, which only the `cfg:s` and `cfg:i` services grant.
+    pub fn writer(&self) -> Option<CfgWrite<'_>> {
+        (self.service_index < CFG_WRITE_SERVICES).then_some(CfgWrite { cfg: self })
+    }
+
+    pub fn system_model(&self) -> Result<SystemModel> {
+        let mut reply = IpcRequest::command(0x05).dispatch(&self.handle)?;
+
+        SystemModel::from_value((reply.read_word() & 0xff) as u8).map_err(|_| {
+            ErrorCode::new(
+                Level::Fatal,
+                Summary::InvalidResultValue,
+                Module::Config,
+                CommonDescription::InvalidResultValue.to_value(),
+            )
+        })
+    }
+}
+
+/// Writes the system configuration, see [`Cfg::writer`].
+///
+/// Changes are kept in memory until [`save`](Self::save)d, and are lost on reboot otherwise.
+#[derive(Debug)]
+pub struct CfgWrite<'cfg> {
+    cfg: &'cfg Cfg,
+}
+
+impl CfgWrite<'_> {
+    /// Overwrite the configuration block `id` with `data`, which has to match the block's size.
+    pub fn set_config_block(&self, id: u32, data: &[u8]) -> Result<()> {
+        let _ = IpcRequest::command(0x402)
+            .parameter(id)
+            .parameter(data.len())
+            .translate_parameter(MappedBuffer::read(data))
+            .dispatch(&self.cfg.handle)?;
+
+        Ok(())
+    }
+
+    /// Write the configuration to the NAND.
+    pub fn save(&self) -> Result<()> {
+        let _ = IpcRequest::command(0x403).dispatch(&self.cfg.handle)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock-ipc"))]
+mod tests {
+    use super::*;
+
+    use crate::ipc::mock::{self, Reply};
+    use crate::result::ERROR_NOT_AUTHORIZED;
+
+    fn init(denied: usize) -> Cfg {
+        for _ in 0..denied {
+            mock::expect(0x5, Reply::error(ERROR_NOT_AUTHORIZED));
+        }
+        mock::expect(0x5, Reply::success().handle(0x1234));
+
+        let cfg = Cfg::init(&Srv::mocked()).unwrap();
+        mock::take_requests();
+        cfg
+    }
+
+    #[test]
+    fn system_model_reads_low_byte() {
+        let _mock = mock::lock();
+        let cfg = init(2);
+
+        mock::expect(0x5, Reply::success().word(0xab02));
+        assert_eq!(cfg.system_model(), Ok(SystemModel::New3ds));
+    }
+
+    #[test]
+    fn get_config_block_sends_size_and_id() {
+        let _mock = mock::lock();
+        let cfg = init(2);
+
+        let mut data = [0; 4];
+        mock::expect(0x1, Reply::success());
+        cfg.get_config_block(0x50005, &mut data).unwrap();
+
+        assert_eq!(mock::take_requests()[0].parameters, [4, 0x50005]);
+    }
+
+    #[test]
+    fn writer_requires_write_access() {
+        let _mock = mock::lock();
+
+        assert!(init(2).writer().is_none());
+
+        let cfg = init(1);
+        mock::expect(0x402, Reply::success());
+        cfg.writer()
+            .unwrap()
+            .set_config_block(0x50005, &[0; 4])
+            .unwrap();
+
+        assert_eq!(mock::take_requests()[0].parameters, [0x50005, 4]);
+    }
+}
@@ -0,0 +1,269 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Files on the SD card and other archives, through the `fs:USER` service.
+
+use crate::ipc::{IpcRequest, MappedBuffer, StaticBuffer, ThisProcessId};
+use crate::os::OwnedHandle;
+use crate::ports::srv::Srv;
+use crate::result::Result;
+
+use core::ops::BitOr;
+
+use alloc::vec::Vec;
+
+use ctru_rt_macros::{service, EnumCast};
+
+const PATH_EMPTY: u32 = 1;
+const PATH_ASCII: u32 = 3;
+
+/// No transaction, the only kind supported.
+const TRANSACTION: u32 = 0;
+
+/// A path as passed to the service, including its NUL terminator.
+struct Path {
+    kind: u32,
+    data: Vec<u8>,
+}
+
+impl Path {
+    fn empty() -> Self {
+        Self {
+            kind: PATH_EMPTY,
+            data: alloc::vec![0],
+        }
+    }
+
+    fn ascii(path: &str) -> Self {
+        let mut data = Vec::with_capacity(path.len() + 1);
+        data.extend_from_slice(path.as_bytes());
+        data.push(0);
+
+        Self {
+            kind: PATH_ASCII,
+            data,
+        }
+    }
+
+    fn words(&self) -> [u32; 2] {
+        [self.kind, self.data.len() as u32]
+    }
+}
+
+fn u64_words(value: u64) -> [u32; 2] {
+    [value as u32, (value >> 32) as u32]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[non_exhaustive]
+#[enum_cast(value_type = "u32")]
+pub enum ArchiveId {
+    Sdmc = 9,
+}
+
+/// How to open a [`File`], combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OpenFlags(u32);
+
+impl OpenFlags {
+    pub const READ: Self = Self(1 << 0);
+    pub const WRITE: Self = Self(1 << 1);
+    pub const CREATE: Self = Self(1 << 2);
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(other)
+    }
+}
+
+#[derive(Debug)]
+pub struct Fs {
+    handle: OwnedHandle,
+}
+
+impl Fs {
+    pub fn init(srv: &Srv) -> Result<Self> {
+        let handle = srv.get_service_handle("fs:USER")?;
+
+        let _ = IpcRequest::command(0x801)
+            .translate_parameter(ThisProcessId)
+            .dispatch(&handle)?;
+
+        Ok(Self { handle })
+    }
+
+    pub fn open_archive(&self, id: ArchiveId) -> Result<Archive<'_>> {
+        let path = Path::empty();
+
+        let mut reply = IpcRequest::command(0x80c)
+            .parameter(id.to_value())
+            .parameters(&path.words())
+            .translate_parameter(StaticBuffer::from_bytes(&path.data, 0))
+            .dispatch(&self.handle)?;
+
+        let handle = u64::from(reply.read_word()) | u64::from(reply.read_word()) << 32;
+
+        Ok(Archive { fs: self, handle })
+    }
+}
+
+/// An open archive, in which paths are absolute like `/3ds/app.log`.
+#[derive(Debug)]
+pub struct Archive<'fs> {
+    fs: &'fs Fs,
+    handle: u64,
+}
+
+impl Archive<'_> {
+    /// Open the file at `path`, or create it with [`OpenFlags::CREATE`].
+    pub fn open_file(&self, path: &str, flags: OpenFlags) -> Result<File> {
+        const ATTRIBUTES: u32 = 0;
+
+        let path = Path::ascii(path);
+
+        let mut reply = IpcRequest::command(0x802)
+            .parameter(TRANSACTION)
+            .parameters(&u64_words(self.handle))
+            .parameters(&path.words())
+            .parameters(&[flags.bits(), ATTRIBUTES])
+            .translate_parameter(StaticBuffer::from_bytes(&path.data, 0))
+            .dispatch(&self.fs.handle)?
+            .finish_results();
+
+        let handle = unsafe { reply.read_handle() };
+
+        Ok(File { handle })
+    }
+
+    pub fn delete_file(&self, path: &str) -> Result<()> {
+        let path = Path::ascii(path);
+
+        let _ = IpcRequest::command(0x804)
+            .parameter(TRANSACTION)
+            .parameters(&u64_words(self.handle))
+            .parameters(&path.words())
+            .translate_parameter(StaticBuffer::from_bytes(&path.data, 0))
+            .dispatch(&self.fs.handle)?;
+
+        Ok(())
+    }
+
+    /// Rename the file at `from` to `to`, both in this archive.
+    pub fn rename_file(&self, from: &str, to: &str) -> Result<()> {
+        let (from, to) = (Path::ascii(from), Path::ascii(to));
+
+        let _ = IpcRequest::command(0x805)
+            .parameter(TRANSACTION)
+            .parameters(&u64_words(self.handle))
+            .parameters(&from.words())
+            .parameters(&u64_words(self.handle))
+            .parameters(&to.words())
+            .translate_parameter(StaticBuffer::from_bytes(&from.data, 1))
+            .translate_parameter(StaticBuffer::from_bytes(&to.data, 2))
+            .dispatch(&self.fs.handle)?;
+
+        Ok(())
+    }
+
+    pub fn create_directory(&self, path: &str) -> Result<()> {
+        const ATTRIBUTES: u32 = 0;
+
+        let path = Path::ascii(path);
+
+        let _ = IpcRequest::command(0x809)
+            .parameter(TRANSACTION)
+            .parameters(&u64_words(self.handle))
+            .parameters(&path.words())
+            .parameter(ATTRIBUTES)
+            .translate_parameter(StaticBuffer::from_bytes(&path.data, 0))
+            .dispatch(&self.fs.handle)?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Archive<'_> {
+    fn drop(&mut self) {
+        let _ = IpcRequest::command(0x80e)
+            .parameters(&u64_words(self.handle))
+            .dispatch(&self.fs.handle);
+    }
+}
+
+/// An open file, closed on drop.
+#[derive(Debug)]
+pub struct File {
+    handle: OwnedHandle,
+}
+
+impl File {
+    /// Read into `buffer` from `offset`, returning the number of bytes read.
+    pub fn read(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let read = self.read_raw(offset, buffer.len(), MappedBuffer::write(buffer))?;
+
+        Ok(read as usize)
+    }
+
+    /// Write `data` at `offset`, returning the number of bytes written.
+    pub fn write(&self, offset: u64, data: &[u8], flush: bool) -> Result<usize> {
+        let written = self.write_raw(
+            offset,
+            data.len(),
+            u32::from(flush),
+            MappedBuffer::read(data),
+        )?;
+
+        Ok(written as usize)
+    }
+}
+
+service! {
+    impl File {
+        #[command(0x802)]
+        fn read_raw(
+            &self,
+            offset: u64,
+            size: usize,
+            #[translate] buffer: MappedBuffer<'_>,
+        ) -> u32;
+
+        #[command(0x803)]
+        fn write_raw(
+            &self,
+            offset: u64,
+            size: usize,
+            flush: u32,
+            #[translate] data: MappedBuffer<'_>,
+        ) -> u32;
+
+        #[command(0x804)]
+        pub fn size(&self) -> u64;
+
+        #[command(0x805)]
+        pub fn set_size(&self, size: u64);
+
+        #[command(0x808)]
+        fn close(&self);
+
+        #[command(0x809)]
+        pub fn flush(&self);
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
@@ -2,20 +2,28 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::ipc::{IpcRequest, StaticBuffer};
+use crate::ipc::IpcRequest;
 use crate::os::mem::MemoryPermission;
 use crate::os::{
     sharedmem::{MappedBlock, SharedMemoryMapper},
-    AsHandle, OwnedHandle, BorrowedHandle,
+    AsHandle, OwnedHandle, BorrowedHandle, Process, RawHandle, CLOSED_HANDLE,
 };
 use crate::ports::srv::Srv;
 use crate::result::{ErrorCode, Result};
 use crate::svc::Timeout;
-use crate::sync::{Event, ResetType};
+use crate::sync::{Event, LightEvent, LightMutex, ResetType};
+use crate::thread::{self, JoinHandle, ThreadBuilder};
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::gx::{CommandQueue, GxCommand, QueueFull, TransferFormat};
+use super::regs::{self, FramebufferGeometry, LcdSync, Registers};
 
 use log::{debug, trace, warn};
 
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
 use ctru_rt_macros::EnumCast;
 
@@ -46,12 +54,43 @@ impl Screen {
         }
     }
 
+    /// Offset of this screen's LCD framebuffer setup registers.
+    pub(crate) const fn framebuffer_setup_register(&self) -> u32 {
+        match self {
+            Self::Top => 0x40_0400,
+            Self::Bottom => 0x40_0500,
+        }
+    }
+
     pub(crate) const fn dimensions_register(&self) -> u32 {
         let dim = self.dimensions();
         (dim.height as u32) << 16 | (dim.width as u32)
     }
 }
 
+/// Screens to fill with black, see [`Gpu::set_lcd_force_blank`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ForceBlank(u8);
+
+impl ForceBlank {
+    /// Display both screens normally.
+    pub const fn none() -> Self {
+        Self(0)
+    }
+
+    pub const fn both() -> Self {
+        Self::none().screen(Screen::Top).screen(Screen::Bottom)
+    }
+
+    pub const fn screen(self, screen: Screen) -> Self {
+        Self(self.0 | 1 << screen.to_value())
+    }
+
+    pub const fn is_blanked(self, screen: Screen) -> bool {
+        self.0 & 1 << screen.to_value() != 0
+    }
+}
+
 #[repr(packed)]
 struct InterruptHeader {
     current_index: u8,
@@ -256,6 +295,10 @@ struct InterruptInfo {
     event_buf: *const u32,
 }
 
+// SAFETY: `event_buf` points into GSP shared memory, which the interrupt pump thread may access
+// until it is stopped by `Gpu::drop`.
+unsafe impl Send for InterruptInfo {}
+
 impl InterruptInfo {
     fn header(&self) -> &AtomicU32 {
         unsafe { &*(self.event_buf.offset(0) as *const AtomicU32) }
@@ -283,6 +326,35 @@ impl InterruptInfo {
         }
     }
 
+    fn pop_interrupt(&self) -> Option<InterruptEvent> {
+        let mut header = self.load_header(Ordering::Acquire);
+        loop {
+            if header.events_total == 0 {
+                return None;
+            }
+
+            let event = unsafe { self.read_event(&header) }?;
+
+            let acknowledged = InterruptHeader {
+                current_index: if header.current_index >= 0x34 {
+                    0
+                } else {
+                    header.current_index + 1
+                },
+                events_total: header.events_total - 1,
+                error: 0,
+                _unused: header._unused,
+            };
+
+            match self.store_header(header, acknowledged, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return Some(event),
+                Err(updated) => {
+                    header = updated;
+                }
+            }
+        }
+    }
+
     unsafe fn read_event(&self, header: &InterruptHeader) -> Option<InterruptEvent> {
         let index = header.current_index;
         let block_idx = usize::from(index / 4);
@@ -324,34 +396,7 @@ impl Sharedmem {
     }
 
     fn pop_interrupt(&self) -> Option<InterruptEvent> {
-        let info = self.interrupt_info();
-
-        let mut header = info.load_header(Ordering::Acquire);
-        loop {
-            if header.events_total == 0 {
-                return None;
-            }
-
-            let event = unsafe { info.read_event(&header) }?;
-
-            let acknowledged = InterruptHeader {
-                current_index: if header.current_index >= 0x34 {
-                    0
-                } else {
-                    header.current_index + 1
-                },
-                events_total: header.events_total - 1,
-                error: 0,
-                _unused: header._unused,
-            };
-
-            match info.store_header(header, acknowledged, Ordering::AcqRel, Ordering::Acquire) {
-                Ok(_) => return Some(event),
-                Err(updated) => {
-                    header = updated;
-                }
-            }
-        }
+        self.interrupt_info().pop_interrupt()
     }
 
     unsafe fn framebuffer_info_for(&mut self, screen: Screen) -> FramebufferInfo {
@@ -368,6 +413,21 @@ impl Sharedmem {
         FramebufferInfo { info }
     }
 
+    fn command_queue(&mut self) -> CommandQueue {
+        const QUEUE_BASE: isize = 0x200;
+        const SIZE: isize = 0x80;
+
+        unsafe {
+            let base = self
+                .shared_memory
+                .as_mut_ptr()
+                .offset(QUEUE_BASE)
+                .offset(self.gsp_module_thread_index as isize * SIZE);
+
+            CommandQueue::new(base)
+        }
+    }
+
     pub fn present_buffer(
         &mut self,
         screen: Screen,
@@ -383,19 +443,126 @@ impl Sharedmem {
     }
 }
 
+/// The framebuffer a screen displays, see [`Gpu::import_display_capture_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenCapture {
+    pub left: *const u8,
+    pub right: *const u8,
+    /// The framebuffer mode, as passed to [`Gpu::present_buffer`].
+    pub mode: u32,
+    pub stride: u32,
+}
+
+impl ScreenCapture {
+    const MODE_3D: u32 = 1 << 5;
+
+    pub fn format(&self) -> core::result::Result<TransferFormat, u32> {
+        TransferFormat::from_value(self.mode & 0b111)
+    }
+
+    pub fn is_3d(&self) -> bool {
+        self.mode & Self::MODE_3D != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayCapture {
+    pub top: ScreenCapture,
+    pub bottom: ScreenCapture,
+}
+
+type InterruptCallback = Box<dyn FnMut(InterruptEvent) + Send>;
+
+/// State shared between a [`Gpu`] and its interrupt pump thread.
+struct InterruptDispatch {
+    /// Events received by the pump, but not yet returned from [`Gpu::next_event`].
+    occurred: AtomicU32,
+    received: LightEvent,
+    callbacks: LightMutex<Vec<(InterruptEvent, InterruptCallback)>>,
+    stop: AtomicBool,
+}
+
+impl InterruptDispatch {
+    fn new() -> Self {
+        Self {
+            occurred: AtomicU32::new(0),
+            received: LightEvent::new(ResetType::OneShot),
+            callbacks: LightMutex::new(Vec::new()),
+            stop: AtomicBool::new(false),
+        }
+    }
+
+    fn pump(&self, gpu_events: &Event, info: &InterruptInfo) -> Result<()> {
+        loop {
+            gpu_events.wait(Timeout::forever())?;
+
+            if self.stop.load(Ordering::Acquire) {
+                return Ok(());
+            }
+
+            let mut events = InterruptEventSet::empty();
+            while let Some(event) = info.pop_interrupt() {
+                events.add(event)
+            }
+
+            self.occurred.fetch_or(events.0, Ordering::AcqRel);
+            self.received.signal()?;
+
+            // Run callbacks without holding the lock, so registering one never waits for them.
+            let mut callbacks = core::mem::take(&mut *self.callbacks.lock());
+            for (event, callback) in callbacks.iter_mut() {
+                if events.contains(*event) {
+                    callback(*event)
+                }
+            }
+
+            let mut registered = self.callbacks.lock();
+            callbacks.append(&mut registered);
+            *registered = callbacks;
+        }
+    }
+
+    fn next_event(&self) -> Result<InterruptEventSet> {
+        loop {
+            let occurred = self.occurred.swap(0, Ordering::AcqRel);
+            if occurred != 0 {
+                return Ok(InterruptEventSet(occurred));
+            }
+
+            self.received.wait()?;
+        }
+    }
+}
+
+impl core::fmt::Debug for InterruptDispatch {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("InterruptDispatch")
+            .field("occurred", &self.occurred)
+            .field("stop", &self.stop)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 pub struct Gpu {
     access: AccessRightsToken,
     sharedmem: Sharedmem,
+    dispatch: Arc<InterruptDispatch>,
+    pump: Option<JoinHandle<Result<()>>>,
 }
 
 impl Gpu {
+    /// Acquire GPU access rights and set up the interrupt relay queue.
+    ///
+    /// The application registers with APT to manage GPU rights itself, so this is what claims
+    /// them.  See [`AptLock::main_loop_step_with_gpu`](crate::services::apt::AptLock::main_loop_step_with_gpu)
+    /// for releasing them while the system sleeps.
     pub fn init(srv: &Srv) -> Result<Self> {
         let service_handle = srv.get_service_handle("gsp::Gpu")?;
 
         const ACCESS_FLAGS: u8 = 0x00;
         let mut access =
-            Self::aquire_access(service_handle, BorrowedHandle::active_process(), ACCESS_FLAGS)?;
+            Self::aquire_access(service_handle, Process::current().as_handle(), ACCESS_FLAGS)?;
 
         const QUEUE_FLAGS: u8 = 0x01;
         let gsp_relay_queue = Self::register_interrupt_relay_queue(&mut access, QUEUE_FLAGS)?;
@@ -403,6 +570,8 @@ impl Gpu {
         Ok(Self {
             access,
             sharedmem: gsp_relay_queue,
+            dispatch: Arc::new(InterruptDispatch::new()),
+            pump: None,
         })
     }
 
@@ -416,7 +585,13 @@ impl Gpu {
             .translate_parameter(owner_process)
             .dispatch(&service_handle)?;
 
-        Ok(AccessRightsToken { service_handle })
+        let mut access = AccessRightsToken {
+            service_handle,
+            held: false,
+        };
+        access.set_held(true);
+
+        Ok(access)
     }
 
     fn register_interrupt_relay_queue(
@@ -460,131 +635,169 @@ impl Gpu {
         SharedMemoryMapper::global().map(
             shared_memory_handle,
             0x1000,
-            MemoryPermission::Rw,
-            MemoryPermission::DontCare,
+            MemoryPermission::RW,
+            MemoryPermission::DONT_CARE,
         )
     }
 
     fn init_hardware(access: &mut AccessRightsToken) -> Result<()> {
-        const GPU_REG_BASE: u32 = 0x40_0000;
-        const INIT_VALUES: &[(u32, u32, Option<u32>)] = &[
-            (0x1000, 0, None),
-            (0x1080, 0x12345678, None),
-            (0x10C0, 0xFFFFFFF0, None),
-            (0x10D0, 1, None),
-            // (0x1914, 1, None), // homebrew addition: make sure GPUREG_START_DRAW_FUNC0 starts off in configuration mode
-            // Top screen LCD configuration, see https://www.3dbrew.org/wiki/GPU/External_Registers#LCD_Source_Framebuffer_Setup
-
-            // Top screen sync registers:
-            (0x0400, 0x1C2, None),
-            (0x0404, 0xD1, None),
-            (0x0408, 0x1C1, None),
-            (0x040C, 0x1C1, None),
-            (0x0410, 0, None),
-            (0x0414, 0xCF, None),
-            (0x0418, 0xD1, None),
-            (0x041C, (0x1C5 << 16) | 0x1C1, None),
-            (0x0420, 0x10000, None),
-            (0x0424, 0x19D, None),
-            (0x0428, 2, None),
-            (0x042C, 0x192, None),
-            (0x0430, 0x192, None),
-            (0x0434, 0x192, None),
-            (0x0438, 1, None),
-            (0x043C, 2, None),
-            (0x0440, (0x196 << 16) | 0x192, None),
-            (0x0444, 0, None),
-            (0x0448, 0, None),
-            // Top screen fb geometry
-            (0x045C, (400 << 16) | 240, None), // dimensions
-            (0x0460, (0x1C1 << 16) | 0xD1, None),
-            (0x0464, (0x192 << 16) | 2, None),
-            // Top screen framebuffer format (initial)
-            (0x0470, 0x80340, None),
-            // Top screen unknown reg @ 0x9C
-            (0x049C, 0, None),
-            // Bottom screen LCD configuration
-
-            // Bottom screen sync registers:
-            (0x0500, 0x1C2, None),
-            (0x0504, 0xD1, None),
-            (0x0508, 0x1C1, None),
-            (0x050C, 0x1C1, None),
-            (0x0510, 0xCD, None),
-            (0x0514, 0xCF, None),
-            (0x0518, 0xD1, None),
-            (0x051C, (0x1C5 << 16) | 0x1C1, None),
-            (0x0520, 0x10000, None),
-            (0x0524, 0x19D, None),
-            (0x0528, 0x52, None),
-            (0x052C, 0x192, None),
-            (0x0530, 0x192, None),
-            (0x0534, 0x4F, None),
-            (0x0538, 0x50, None),
-            (0x053C, 0x52, None),
-            (0x0540, (0x198 << 16) | 0x194, None),
-            (0x0544, 0, None),
-            (0x0548, 0x11, None),
-            // Bottom screen fb geometry
-            (0x055C, Screen::Bottom.dimensions_register(), None), // dimensions
-            (0x0560, (0x1C1 << 16) | 0xD1, None),
-            (0x0564, (0x192 << 16) | 0x52, None),
-            // Bottom screen framebuffer format (initial)
-            (0x0570, 0x80300, None),
-            // Bottom screen unknown reg @ 0x9C
-            (0x059C, 0, None),
-            // Initial, blank framebuffer (top left A/B, bottom A/B, top right A/B)
-            (0x0468, 0x18300000, None),
-            (0x046C, 0x18300000, None),
-            (0x0568, 0x18300000, None),
-            (0x056C, 0x18300000, None),
-            (0x0494, 0x18300000, None),
-            (0x0498, 0x18300000, None),
-            // Framebuffer select: A
-            (0x0478, 1, None),
-            (0x0578, 1, None),
-            // Clear DMA transfer (PPF) "transfer finished" bit
-            (0x0C18, 0, Some(0xFF00)),
-            // GX_GPU_CLK |= 0x70000 (value is 0x100 when gsp starts, enough to at least display framebuffers & have memory fill work)
-            // This enables the clock to some GPU components
-            (0x0004, 0x70100, None),
-            // Clear Memory Fill (PSC0 and PSC1) "busy" and "finished" bits
-            (0x001C, 0, Some(0xFF)),
-            (0x002C, 0, Some(0xFF)),
-            // More init registers
-            (0x0050, 0x22221200, None),
-            (0x0054, 0xFF2, Some(0xFFFF)),
-            // Enable some LCD clocks (?) (unsure)
-            (0x0474, 0x10501, None),
-            (0x0574, 0x10501, None),
-        ];
-
-        for (register_offset, value, mask) in INIT_VALUES {
-            let register = GPU_REG_BASE + register_offset;
-            trace!(
-                "Writing GSP register: {:#08x} = {:#08x} (mask: {:08x?})",
-                register,
-                value,
-                mask
-            );
-            match mask {
-                None => {
-                    write_graphics_register(access.service_handle.as_handle(), register, value)?
+        const BLANK_FRAMEBUFFER: u32 = 0x1830_0000;
+
+        let registers = Registers::new(access.as_handle());
+
+        // SAFETY: None of the raw writes starts a memory fill or transfer.
+        unsafe {
+            registers.write(regs::IRQ_ACK, 0)?;
+            registers.write(regs::IRQ_CMP, 0x12345678)?;
+            registers.write(regs::IRQ_MASK, 0xffff_fff0)?;
+            registers.write(regs::IRQ_AUTOSTOP, 1)?;
+        }
+
+        for (screen, format) in [(Screen::Top, 0x80340), (Screen::Bottom, 0x80300)] {
+            registers.set_lcd_sync(screen, &LcdSync::for_screen(screen))?;
+            registers.set_framebuffer_geometry(screen, &FramebufferGeometry::for_screen(screen))?;
+            registers.set_framebuffer_format(screen, format)?;
+            unsafe { registers.write(regs::framebuffer_setup(screen, 0x9c), 0) }?;
+        }
+
+        registers.set_left_framebuffers(Screen::Top, [BLANK_FRAMEBUFFER; 2])?;
+        registers.set_left_framebuffers(Screen::Bottom, [BLANK_FRAMEBUFFER; 2])?;
+        registers.set_right_framebuffers([BLANK_FRAMEBUFFER; 2])?;
+        // Framebuffer A
+        registers.select_framebuffer(Screen::Top, 1)?;
+        registers.select_framebuffer(Screen::Bottom, 1)?;
+
+        registers.clear_display_transfer_status()?;
+        // 0x100 when GSP starts, enough to display framebuffers and have memory fills work
+        registers.set_gpu_clock(0x70100)?;
+        registers.clear_memory_fill_status()?;
+
+        // Purpose unknown
+        unsafe {
+            registers.write(regs::GPU_EXTERNAL + 0x50, 0x22221200)?;
+            registers.write_masked(regs::GPU_EXTERNAL + 0x54, 0xff2, 0xffff)?;
+        }
+
+        for screen in [Screen::Top, Screen::Bottom] {
+            let lcd_clocks = regs::framebuffer_setup(screen, regs::framebuffer::LCD_CLOCKS);
+            unsafe { registers.write(lcd_clocks, 0x10501) }?;
+        }
+
+        Ok(())
+    }
+
+    pub fn next_event(&mut self) -> Result<InterruptEventSet> {
+        match self.pump {
+            Some(_) => self.dispatch.next_event(),
+            None => self.sharedmem.wait_event(),
+        }
+    }
+
+    /// Wait for the next VBlank of the top screen.
+    pub fn wait_vblank_top(&mut self) -> Result<()> {
+        self.wait_for_all(&[InterruptEvent::VBlank0])
+    }
+
+    /// Wait for the next VBlank of the bottom screen.
+    pub fn wait_vblank_bottom(&mut self) -> Result<()> {
+        self.wait_for_all(&[InterruptEvent::VBlank1])
+    }
+
+    /// Call `callback` whenever `event` occurs.
+    ///
+    /// Callbacks are run on the interrupt pump thread, see
+    /// [`start_interrupt_pump`](Self::start_interrupt_pump).
+    pub fn on_interrupt<F>(&mut self, event: InterruptEvent, callback: F)
+    where
+        F: FnMut(InterruptEvent) + Send + 'static,
+    {
+        self.dispatch
+            .callbacks
+            .lock()
+            .push((event, Box::new(callback)))
+    }
+
+    /// Receive GPU interrupts on a dedicated thread with the given `priority`.
+    ///
+    /// The thread runs callbacks registered with [`on_interrupt`](Self::on_interrupt), and
+    /// forwards all events to [`next_event`](Self::next_event).  It is stopped when the `Gpu` is
+    /// dropped.
+    pub fn start_interrupt_pump(&mut self, priority: i32) -> Result<()> {
+        if self.pump.is_some() {
+            return Ok(());
+        }
+
+        let gpu_events = self.sharedmem.gpu_events.duplicate()?;
+        let info = self.sharedmem.interrupt_info();
+        let dispatch = self.dispatch.clone();
+
+        let pump = ThreadBuilder::default()
+            .with_priority(priority)
+            .with_stack_size(0x2000)
+            .spawn(move || {
+                let result = dispatch.pump(&gpu_events, &info);
+                if let Err(e) = result {
+                    warn!("GPU interrupt pump stopped: {:?}", e);
                 }
-                Some(mask) => write_graphics_register_masked(
-                    access.service_handle.as_handle(),
-                    register,
-                    value,
-                    mask,
-                )?,
+                result
+            })?;
+
+        self.pump = Some(pump);
+
+        Ok(())
+    }
+
+    fn stop_interrupt_pump(&mut self) -> Result<()> {
+        if let Some(pump) = self.pump.take() {
+            self.dispatch.stop.store(true, Ordering::Release);
+            self.sharedmem.gpu_events.signal()?;
+
+            match pump.join()? {
+                Ok(result) => result?,
+                Err(panicked) => warn!("GPU interrupt pump: {}", panicked),
             }
         }
 
         Ok(())
     }
 
-    pub fn next_event(&mut self) -> Result<InterruptEventSet> {
-        self.sharedmem.wait_event()
+    /// Wait until each of `events` occurred at least once.
+    pub(super) fn wait_for_all(&mut self, events: &[InterruptEvent]) -> Result<()> {
+        let mut pending = InterruptEventSet::empty();
+        events.iter().for_each(|event| pending.add(*event));
+
+        while pending.0 != 0 {
+            let occurred = self.next_event()?;
+            pending.0 &= !occurred.0;
+        }
+
+        Ok(())
+    }
+
+    /// Append `command` to the GX command queue, and make sure the GSP processes it.
+    pub(super) fn submit_gx_command(&mut self, command: &GxCommand) -> Result<()> {
+        let mut queue = self.sharedmem.command_queue();
+
+        let was_empty = loop {
+            match queue.push(command) {
+                Ok(was_empty) => break was_empty,
+                Err(QueueFull) => {
+                    trace!("GX command queue is full, waiting for the GSP to catch up");
+                    thread::yield_now()
+                }
+            }
+        };
+
+        if was_empty {
+            self.trigger_command_queue()?;
+        }
+
+        Ok(())
+    }
+
+    fn trigger_command_queue(&mut self) -> Result<()> {
+        let _ = IpcRequest::command(0x0c).dispatch(&self.access)?;
+        Ok(())
     }
 
     pub fn present_buffer(
@@ -600,24 +813,151 @@ impl Gpu {
             .present_buffer(screen, active_fb, fb0, fb1, stride, mode)
     }
 
-    pub fn set_lcd_force_blank(&mut self, flags: u8) -> Result<()> {
+    /// Query the framebuffers currently displayed on both screens.
+    pub fn import_display_capture_info(&mut self) -> Result<DisplayCapture> {
+        let mut reply = IpcRequest::command(0x18).dispatch(&self.access)?;
+
+        let mut read_screen = || ScreenCapture {
+            left: reply.read_word() as *const u8,
+            right: reply.read_word() as *const u8,
+            mode: reply.read_word(),
+            stride: reply.read_word(),
+        };
+
+        let top = read_screen();
+        let bottom = read_screen();
+
+        Ok(DisplayCapture { top, bottom })
+    }
+
+    /// Load the color lookup table that is applied to every pixel sent to `screen`.
+    ///
+    /// Entry `i` maps color channel intensity `i` to a new color, encoded as `0x00BBGGRR`.  The
+    /// identity table is `lut[i] = i * 0x010101`.
+    pub fn set_color_lut(&mut self, screen: Screen, lut: &[u32; 256]) -> Result<()> {
+        self.registers().set_color_lut(screen, lut)
+    }
+
+    /// Write the GPU's external registers, e.g. to tweak the LCD timing.
+    pub fn registers(&mut self) -> Registers<'_> {
+        Registers::new(self.access.as_handle())
+    }
+
+    /// Save the VRAM area used by the system and release GPU access rights.
+    ///
+    /// Call this before the system enters sleep mode or another applet takes over the screens,
+    /// and [`resume_after_sleep`](Self::resume_after_sleep) once control returns.
+    pub fn prepare_for_sleep(&mut self) -> Result<()> {
+        let _ = IpcRequest::command(0x19).dispatch(&self.access)?;
+        self.access.release()
+    }
+
+    /// Reacquire GPU access rights and restore the VRAM area saved by
+    /// [`prepare_for_sleep`](Self::prepare_for_sleep).
+    pub fn resume_after_sleep(&mut self) -> Result<()> {
+        const ACCESS_FLAGS: u8 = 0x00;
+        self.access.reaquire(ACCESS_FLAGS)?;
+        let _ = IpcRequest::command(0x1a).dispatch(&self.access)?;
+        Ok(())
+    }
+
+    /// Write back the CPU data cache covering `size` bytes at `ptr` to memory.
+    ///
+    /// This is required after writing to buffers that the GPU reads, e.g. framebuffers or sources
+    /// of GX commands.
+    pub fn flush_data_cache(&mut self, ptr: *const u8, size: usize) -> Result<()> {
+        self.data_cache_operation(0x08, ptr, size)
+    }
+
+    /// Discard the CPU data cache covering `size` bytes at `ptr`.
+    ///
+    /// This is required before reading buffers that the GPU wrote to.
+    pub fn invalidate_data_cache(&mut self, ptr: *const u8, size: usize) -> Result<()> {
+        self.data_cache_operation(0x09, ptr, size)
+    }
+
+    fn data_cache_operation(&mut self, command: u16, ptr: *const u8, size: usize) -> Result<()> {
+        let _ = IpcRequest::command(command)
+            .parameters(&[ptr as u32, size as u32])
+            .translate_parameter(Process::current().as_handle())
+            .dispatch(&self.access)?;
+        Ok(())
+    }
+
+    /// Fill the screens selected by `blank` with black, and display the others normally.
+    pub fn set_lcd_force_blank(&mut self, blank: ForceBlank) -> Result<()> {
         let _ = IpcRequest::command(0x0b)
-            .parameter(flags as u32)
+            .parameter(u32::from(blank.0))
+            .dispatch(&self.access)?;
+        Ok(())
+    }
+
+    /// Turn the 3D LED on or off.
+    ///
+    /// The LED is only lit while stereoscopic 3D is active, but applications rendering in 2D only
+    /// can force it off.
+    pub fn set_3d_led(&mut self, enabled: bool) -> Result<()> {
+        let _ = IpcRequest::command(0x1c)
+            .parameter(u32::from(!enabled))
             .dispatch(&self.access)?;
         Ok(())
     }
 }
 
+impl Drop for Gpu {
+    fn drop(&mut self) {
+        let _ = self.stop_interrupt_pump();
+    }
+}
+
+/// The session holding GPU access rights, which [`rt::exit`](crate::rt::exit) releases.
+static HELD_RIGHTS: AtomicU32 = AtomicU32::new(CLOSED_HANDLE);
+
+fn release_rights_on_exit() {
+    let handle: RawHandle = HELD_RIGHTS.swap(CLOSED_HANDLE, Ordering::AcqRel);
+    if handle != CLOSED_HANDLE {
+        debug!("Releasing GPU access rights on exit");
+        let _ = IpcRequest::command(0x17).dispatch(BorrowedHandle::new(handle));
+    }
+}
+
 #[derive(Debug)]
 #[must_use = "GPU access rights must be released properly"]
 struct AccessRightsToken {
     service_handle: OwnedHandle,
+    held: bool,
 }
 
 impl AccessRightsToken {
+    fn set_held(&mut self, held: bool) {
+        self.held = held;
+
+        let handle = match held {
+            true => self.service_handle.as_handle().handle,
+            false => CLOSED_HANDLE,
+        };
+        HELD_RIGHTS.store(handle, Ordering::Release);
+        crate::rt::at_exit(release_rights_on_exit);
+    }
+
+    fn reaquire(&mut self, flags: u8) -> Result<()> {
+        if !self.held {
+            debug!("Reacquiring GPU access rights");
+            let _ = IpcRequest::command(0x16)
+                .parameter(u32::from(flags))
+                .translate_parameter(Process::current().as_handle())
+                .dispatch(&self.service_handle)?;
+            self.set_held(true);
+        }
+        Ok(())
+    }
+
     fn release(&mut self) -> Result<()> {
-        debug!("Releasing GPU access rights");
-        let _ = IpcRequest::command(0x17).dispatch(&self.service_handle)?;
+        if self.held {
+            debug!("Releasing GPU access rights");
+            let _ = IpcRequest::command(0x17).dispatch(&self.service_handle)?;
+            self.set_held(false);
+        }
         Ok(())
     }
 }
@@ -634,33 +974,112 @@ impl Drop for AccessRightsToken {
     }
 }
 
-fn write_graphics_register(
-    service_handle: BorrowedHandle,
-    register_offset: u32,
-    value: &u32,
-) -> Result<()> {
-    let data = core::slice::from_ref(value);
-    let _ = IpcRequest::command(0x01)
-        .parameters(&[register_offset, 4])
-        .translate_parameter(StaticBuffer::new(data, 0))
-        .dispatch(service_handle)?;
-
-    Ok(())
-}
-
-fn write_graphics_register_masked(
-    service_handle: BorrowedHandle,
-    register_offset: u32,
-    value: &u32,
-    mask: &u32,
-) -> Result<()> {
-    let value = core::slice::from_ref(value);
-    let mask = core::slice::from_ref(mask);
-    let _ = IpcRequest::command(0x02)
-        .parameters(&[register_offset, 4])
-        .translate_parameter(StaticBuffer::new(value, 0))
-        .translate_parameter(StaticBuffer::new(mask, 1))
-        .dispatch(service_handle)?;
-
-    Ok(())
+/// Error returned when a [`CommandList`] runs out of space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandListFull;
+
+/// Builder for a list of PICA200 register writes, to be run with [`Gpu::process_command_list`].
+///
+/// The list is written to a caller-provided buffer, which has to be in linear memory and aligned
+/// to 16 bytes.
+#[derive(Debug)]
+pub struct CommandList<'b> {
+    buffer: &'b mut [u32],
+    len: usize,
+}
+
+impl<'b> CommandList<'b> {
+    const REGISTER_FINALIZE: u16 = 0x0010;
+    const FINALIZE_MAGIC: u32 = 0x1234_5678;
+    const MASK_ALL: u8 = 0xf;
+
+    pub fn new(buffer: &'b mut [u32]) -> Self {
+        debug_assert!(
+            buffer.as_ptr() as usize & 0xf == 0,
+            "Command list buffer must be aligned to 16 bytes"
+        );
+
+        Self { buffer, len: 0 }
+    }
+
+    fn push(
+        &mut self,
+        register: u16,
+        mask: u8,
+        values: &[u32],
+        consecutive: bool,
+    ) -> core::result::Result<&mut Self, CommandListFull> {
+        let (first, extra) = values.split_first().ok_or(CommandListFull)?;
+        if extra.len() > 0xff {
+            return Err(CommandListFull);
+        }
+
+        let header = u32::from(register)
+            | u32::from(mask & 0xf) << 16
+            | (extra.len() as u32) << 20
+            | u32::from(consecutive) << 31;
+
+        // Each command occupies an even number of words.
+        let words = 2 + extra.len() + (extra.len() & 1);
+        let command = self
+            .buffer
+            .get_mut(self.len..self.len + words)
+            .ok_or(CommandListFull)?;
+
+        command[0] = *first;
+        command[1] = header;
+        command[2..2 + extra.len()].copy_from_slice(extra);
+        command[2 + extra.len()..].fill(0);
+
+        self.len += words;
+        Ok(self)
+    }
+
+    /// Write `value` to `register`.
+    pub fn write(
+        &mut self,
+        register: u16,
+        value: u32,
+    ) -> core::result::Result<&mut Self, CommandListFull> {
+        self.push(register, Self::MASK_ALL, &[value], false)
+    }
+
+    /// Write the bytes of `value` selected by the lower four bits of `mask` to `register`.
+    pub fn write_masked(
+        &mut self,
+        register: u16,
+        value: u32,
+        mask: u8,
+    ) -> core::result::Result<&mut Self, CommandListFull> {
+        self.push(register, mask, &[value], false)
+    }
+
+    /// Write all `values` to `register`, one after another.
+    pub fn write_repeated(
+        &mut self,
+        register: u16,
+        values: &[u32],
+    ) -> core::result::Result<&mut Self, CommandListFull> {
+        self.push(register, Self::MASK_ALL, values, false)
+    }
+
+    /// Write `values` to consecutive registers, starting at `first_register`.
+    pub fn write_consecutive(
+        &mut self,
+        first_register: u16,
+        values: &[u32],
+    ) -> core::result::Result<&mut Self, CommandListFull> {
+        self.push(first_register, Self::MASK_ALL, values, true)
+    }
+
+    /// Terminate the list, padding it to a multiple of 16 bytes.
+    pub fn finish(mut self) -> core::result::Result<&'b [u32], CommandListFull> {
+        self.write(Self::REGISTER_FINALIZE, Self::FINALIZE_MAGIC)?;
+        if self.len & 0b11 != 0 {
+            self.write(Self::REGISTER_FINALIZE, Self::FINALIZE_MAGIC)?;
+        }
+
+        let Self { buffer, len } = self;
+        Ok(&buffer[..len])
+    }
 }
@@ -0,0 +1,400 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! # GX commands
+//!
+//! Commands for the GPU's DMA and fill units are passed to the GSP through a queue in its shared
+//! memory.  Each command blocks until the GPU signals its completion with an
+//! [`InterruptEvent`](super::gpu::InterruptEvent).
+
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::gpu::{Gpu, InterruptEvent};
+use crate::result::{CommonDescription, ErrorCode, Level, Module, Result, Summary};
+
+use ctru_rt_macros::EnumCast;
+
+use log::debug;
+
+const QUEUE_SLOTS: u8 = 15;
+
+/// Returned when a buffer is smaller than the dimensions of a [display
+/// transfer](Gpu::display_transfer) require.
+pub const ERROR_BUFFER_TOO_SMALL: ErrorCode = ErrorCode::new(
+    Level::Usage,
+    Summary::WrongArgument,
+    Module::Application,
+    CommonDescription::InvalidSize.to_value(),
+);
+
+/// A raw GX command, as stored in the command queue.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct GxCommand([u32; 8]);
+
+#[derive(Debug)]
+pub(crate) struct QueueFull;
+
+/// The GX command queue of this process in GSP shared memory.
+///
+/// The first word is a header (current index, number of pending commands, status, error),
+/// followed by [`QUEUE_SLOTS`] commands of 8 words each, starting at word 8.
+pub(crate) struct CommandQueue {
+    base: *mut u32,
+}
+
+impl CommandQueue {
+    /// # Safety
+    ///
+    /// `base` must point to a command queue in mapped GSP shared memory.
+    pub(crate) unsafe fn new(base: *mut u32) -> Self {
+        Self { base }
+    }
+
+    fn header(&self) -> &AtomicU32 {
+        unsafe { &*(self.base as *const AtomicU32) }
+    }
+
+    /// Append `command` to the queue.
+    ///
+    /// Returns whether the queue was empty before, in which case the GSP has to be told to
+    /// process it.
+    pub(crate) fn push(&mut self, command: &GxCommand) -> core::result::Result<bool, QueueFull> {
+        let header = self.header();
+        let mut current = header.load(Ordering::Acquire);
+
+        loop {
+            let [index, total, status, error] = current.to_le_bytes();
+            if total >= QUEUE_SLOTS {
+                return Err(QueueFull);
+            }
+
+            let slot = usize::from((index + total) % QUEUE_SLOTS);
+            unsafe {
+                self.base
+                    .add(8 * (1 + slot))
+                    .cast::<[u32; 8]>()
+                    .write_volatile(command.0)
+            };
+
+            let updated = u32::from_le_bytes([index, total + 1, status, error]);
+            match header.compare_exchange(current, updated, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return Ok(total == 0),
+                Err(new) => current = new,
+            }
+        }
+    }
+}
+
+/// Pixel format of the input or output of a [display transfer](Gpu::display_transfer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[enum_cast(value_type = "u32")]
+pub enum TransferFormat {
+    RGBA8,
+    RGB8,
+    RGB565,
+    RGB5A1,
+    RGBA4,
+}
+
+impl TransferFormat {
+    pub const fn bytes_per_pixel(&self) -> u32 {
+        match self {
+            Self::RGBA8 => 4,
+            Self::RGB8 => 3,
+            Self::RGB565 | Self::RGB5A1 | Self::RGBA4 => 2,
+        }
+    }
+}
+
+/// Downscaling applied by a [display transfer](Gpu::display_transfer), for anti-aliasing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[enum_cast(value_type = "u32")]
+pub enum TransferScaling {
+    None,
+    /// Average pairs of horizontally adjacent pixels.
+    X,
+    /// Average 2x2 blocks of pixels.
+    XY,
+}
+
+/// Flags controlling a [display transfer](Gpu::display_transfer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferFlags(u32);
+
+impl TransferFlags {
+    const FLIP_VERTICALLY: u32 = 1 << 0;
+    const TILED_OUTPUT: u32 = 1 << 1;
+    const RAW_COPY: u32 = 1 << 3;
+
+    /// Convert tiled `input` to linear `output`, as needed for framebuffers.
+    pub const fn new(input: TransferFormat, output: TransferFormat) -> Self {
+        Self(input.to_value() << 8 | output.to_value() << 12)
+    }
+
+    pub const fn flip_vertically(self) -> Self {
+        Self(self.0 | Self::FLIP_VERTICALLY)
+    }
+
+    /// Convert linear input to tiled output instead.
+    pub const fn tiled_output(self) -> Self {
+        Self(self.0 | Self::TILED_OUTPUT)
+    }
+
+    pub const fn scaling(self, scaling: TransferScaling) -> Self {
+        Self(self.0 & !(0b11 << 24) | scaling.to_value() << 24)
+    }
+
+    /// Bytes per pixel of the format stored at `shift`.
+    fn bytes_per_pixel(self, shift: u32) -> usize {
+        TransferFormat::from_value(self.0 >> shift & 0b111)
+            .map_or(4, |format| format.bytes_per_pixel() as usize)
+    }
+}
+
+/// Dimensions of a [display transfer](Gpu::display_transfer) buffer, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferDimensions {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl TransferDimensions {
+    pub const fn new(width: u16, height: u16) -> Self {
+        Self { width, height }
+    }
+
+    const fn encode(self) -> u32 {
+        (self.height as u32) << 16 | self.width as u32
+    }
+
+    const fn size(self, bytes_per_pixel: usize) -> usize {
+        self.width as usize * self.height as usize * bytes_per_pixel
+    }
+}
+
+/// Layout of the lines copied by a [texture copy](Gpu::texture_copy).
+///
+/// After each `width` bytes, `gap` bytes are skipped.  Both are counted in units of 16 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CopyLine {
+    pub width: u16,
+    pub gap: u16,
+}
+
+impl CopyLine {
+    pub const fn new(width: u16, gap: u16) -> Self {
+        Self { width, gap }
+    }
+
+    const fn encode(self) -> u32 {
+        (self.gap as u32) << 16 | self.width as u32
+    }
+
+    /// The most bytes that can be copied in lines of this layout within `len` bytes.
+    const fn data_size(self, len: usize) -> usize {
+        let width = self.width as usize * 16;
+        let period = width + self.gap as usize * 16;
+        if width == 0 {
+            return len;
+        }
+
+        // Lines are only skipped between, not after them.
+        let lines = (len + period - width) / period;
+        lines * width + len.saturating_sub(lines * period)
+    }
+}
+
+/// Number of bits per value written by [`Gpu::memory_fill`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillWidth {
+    Bits16,
+    Bits24,
+    Bits32,
+}
+
+impl FillWidth {
+    const fn control(self) -> u32 {
+        const TRIGGER: u32 = 1;
+
+        let width = match self {
+            Self::Bits16 => 0,
+            Self::Bits24 => 1,
+            Self::Bits32 => 2,
+        };
+
+        TRIGGER | width << 8
+    }
+}
+
+/// A buffer in VRAM or linear memory to be filled by [`Gpu::memory_fill`].
+#[derive(Debug)]
+pub struct FillBuffer<'b> {
+    start: *mut u8,
+    end: *mut u8,
+    value: u32,
+    width: FillWidth,
+    _buffer: PhantomData<&'b mut [u8]>,
+}
+
+impl<'b> FillBuffer<'b> {
+    pub fn new(buffer: &'b mut [u8], value: u32, width: FillWidth) -> Self {
+        let range = buffer.as_mut_ptr_range();
+
+        Self {
+            start: range.start,
+            end: range.end,
+            value,
+            width,
+            _buffer: PhantomData,
+        }
+    }
+
+    fn encode(buffer: &Option<Self>) -> [u32; 4] {
+        match buffer {
+            Some(buffer) => [
+                buffer.start as u32,
+                buffer.value,
+                buffer.end as u32,
+                buffer.width.control(),
+            ],
+            None => [0; 4],
+        }
+    }
+}
+
+impl Gpu {
+    /// Fill up to two buffers with a constant value in hardware.
+    ///
+    /// Blocks until both fills are done.
+    pub fn memory_fill(
+        &mut self,
+        first: Option<FillBuffer<'_>>,
+        second: Option<FillBuffer<'_>>,
+    ) -> Result<()> {
+        const MEMORY_FILL: u32 = 0x02;
+
+        let [start0, value0, end0, control0] = FillBuffer::encode(&first);
+        let [start1, value1, end1, control1] = FillBuffer::encode(&second);
+
+        let command = GxCommand([
+            MEMORY_FILL,
+            start0,
+            value0,
+            end0,
+            start1,
+            value1,
+            end1,
+            control0 | control1 << 16,
+        ]);
+
+        debug!("Submitting memory fill: {:08x?}", command);
+        self.submit_gx_command(&command)?;
+
+        match (first, second) {
+            (Some(_), Some(_)) => self.wait_for_all(&[InterruptEvent::PSC0, InterruptEvent::PSC1]),
+            (Some(_), None) => self.wait_for_all(&[InterruptEvent::PSC0]),
+            (None, Some(_)) => self.wait_for_all(&[InterruptEvent::PSC1]),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Copy `input` to `output` while converting between tiled and linear layout and pixel
+    /// formats, as well as optionally downscaling.
+    ///
+    /// Fails with [`ERROR_BUFFER_TOO_SMALL`] if either buffer is smaller than its dimensions in
+    /// the format given by `flags`.  Blocks until the transfer is done.
+    pub fn display_transfer(
+        &mut self,
+        input: &[u8],
+        input_dimensions: TransferDimensions,
+        output: &mut [u8],
+        output_dimensions: TransferDimensions,
+        flags: TransferFlags,
+    ) -> Result<()> {
+        const DISPLAY_TRANSFER: u32 = 0x03;
+
+        if input.len() < input_dimensions.size(flags.bytes_per_pixel(8))
+            || output.len() < output_dimensions.size(flags.bytes_per_pixel(12))
+        {
+            return Err(ERROR_BUFFER_TOO_SMALL);
+        }
+
+        let command = GxCommand([
+            DISPLAY_TRANSFER,
+            input.as_ptr() as u32,
+            output.as_mut_ptr() as u32,
+            input_dimensions.encode(),
+            output_dimensions.encode(),
+            flags.0,
+            0,
+            0,
+        ]);
+
+        debug!("Submitting display transfer: {:08x?}", command);
+        self.submit_gx_command(&command)?;
+
+        self.wait_for_all(&[InterruptEvent::PPF])
+    }
+
+    /// Let the GPU execute a list of register writes, such as one built with
+    /// [`CommandList`](super::gpu::CommandList).
+    ///
+    /// `list` has to reside in linear memory.  If `flush` is set, the GSP flushes the data cache
+    /// for `list` first.  Blocks until the GPU finished processing the list.
+    pub fn process_command_list(&mut self, list: &[u32], flush: bool) -> Result<()> {
+        const PROCESS_COMMAND_LIST: u32 = 0x01;
+
+        let command = GxCommand([
+            PROCESS_COMMAND_LIST,
+            list.as_ptr() as u32,
+            core::mem::size_of_val(list) as u32,
+            0,
+            0,
+            0,
+            0,
+            u32::from(flush),
+        ]);
+
+        debug!("Submitting command list: {:08x?}", command);
+        self.submit_gx_command(&command)?;
+
+        self.wait_for_all(&[InterruptEvent::P3D])
+    }
+
+    /// Copy the bytes of `input` to `output` without any conversion.
+    ///
+    /// Lines of the input and output can be spaced out independently, see [`CopyLine`].  Copies as
+    /// many bytes as fit into both buffers, in multiples of 16.  Blocks until the copy is done.
+    pub fn texture_copy(
+        &mut self,
+        input: &[u8],
+        input_line: CopyLine,
+        output: &mut [u8],
+        output_line: CopyLine,
+    ) -> Result<()> {
+        const TEXTURE_COPY: u32 = 0x04;
+
+        let size = input_line
+            .data_size(input.len())
+            .min(output_line.data_size(output.len()))
+            & !0xf;
+
+        let command = GxCommand([
+            TEXTURE_COPY,
+            input.as_ptr() as u32,
+            output.as_mut_ptr() as u32,
+            size as u32,
+            input_line.encode(),
+            output_line.encode(),
+            TransferFlags::RAW_COPY,
+            0,
+        ]);
+
+        debug!("Submitting texture copy: {:08x?}", command);
+        self.submit_gx_command(&command)?;
+
+        self.wait_for_all(&[InterruptEvent::PPF])
+    }
+}
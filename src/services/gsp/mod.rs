@@ -3,3 +3,5 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 pub mod gpu;
+pub mod gx;
+pub mod regs;
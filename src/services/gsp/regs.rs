@@ -0,0 +1,350 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! # GPU registers
+//!
+//! The GPU's external registers configure the LCDs, the framebuffers they display and the
+//! clocks of the GPU's units.  They are written through GSP while holding GPU access rights, see
+//! [`Gpu::registers`](super::gpu::Gpu::registers).  Addresses are relative to `0x1EB00000`, as
+//! expected by GSP.
+//!
+//! The values written by [`Gpu::init`](super::gpu::Gpu::init) are available as constants, e.g.
+//! [`LcdSync::TOP`], to derive modified timings from.  Wrong timings can leave a screen blank
+//! until it is configured again.
+//!
+//! See <https://www.3dbrew.org/wiki/GPU/External_Registers>.
+
+use super::gpu::Screen;
+
+use crate::ipc::{IpcRequest, StaticBuffer};
+use crate::os::BorrowedHandle;
+use crate::result::Result;
+
+use log::trace;
+
+/// Start of the GPU's external registers.
+pub const GPU_EXTERNAL: u32 = 0x40_0000;
+
+/// Enables the clocks of the GPU's units.
+pub const CLOCK: u32 = GPU_EXTERNAL + 0x0004;
+/// Control registers of the two memory fill units.
+pub const MEMORY_FILL_CONTROL: [u32; 2] = [GPU_EXTERNAL + 0x001c, GPU_EXTERNAL + 0x002c];
+/// Control register of the display transfer unit.
+pub const DISPLAY_TRANSFER_CONTROL: u32 = GPU_EXTERNAL + 0x0c18;
+
+pub const IRQ_ACK: u32 = GPU_EXTERNAL + 0x1000;
+pub const IRQ_CMP: u32 = GPU_EXTERNAL + 0x1080;
+pub const IRQ_MASK: u32 = GPU_EXTERNAL + 0x10c0;
+pub const IRQ_AUTOSTOP: u32 = GPU_EXTERNAL + 0x10d0;
+
+/// Offsets into the LCD framebuffer setup registers of a screen, see [`framebuffer_setup`].
+pub mod framebuffer {
+    /// The [`LcdSync`](super::LcdSync) registers.
+    pub const SYNC: u32 = 0x00;
+    /// The [`FramebufferGeometry`](super::FramebufferGeometry) registers.
+    pub const GEOMETRY: u32 = 0x5c;
+    /// Addresses of the left framebuffers A and B.
+    pub const LEFT_ADDRESSES: u32 = 0x68;
+    pub const FORMAT: u32 = 0x70;
+    /// Believed to enable clocks of the LCD.
+    pub const LCD_CLOCKS: u32 = 0x74;
+    /// Selects the framebuffer displayed, A or B.
+    pub const SELECT: u32 = 0x78;
+    pub const COLOR_LUT_INDEX: u32 = 0x80;
+    pub const COLOR_LUT_DATA: u32 = 0x84;
+    pub const STRIDE: u32 = 0x90;
+    /// Addresses of the right framebuffers A and B, only used by the top screen.
+    pub const RIGHT_ADDRESSES: u32 = 0x94;
+}
+
+/// Address of the framebuffer setup register at `offset` for `screen`.
+pub const fn framebuffer_setup(screen: Screen, offset: u32) -> u32 {
+    screen.framebuffer_setup_register() + offset
+}
+
+/// GSP writes at most this many bytes per request.
+const MAX_WRITE_SIZE: usize = 0x80;
+
+/// Timing of the signals driving an LCD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LcdSync {
+    /// Horizontal timing, in pixel clocks.
+    pub horizontal: [u32; 9],
+    /// Vertical timing, in lines.
+    pub vertical: [u32; 10],
+}
+
+impl LcdSync {
+    pub const TOP: Self = Self {
+        horizontal: [
+            0x1c2,
+            0xd1,
+            0x1c1,
+            0x1c1,
+            0,
+            0xcf,
+            0xd1,
+            (0x1c5 << 16) | 0x1c1,
+            0x10000,
+        ],
+        vertical: [
+            0x19d,
+            2,
+            0x192,
+            0x192,
+            0x192,
+            1,
+            2,
+            (0x196 << 16) | 0x192,
+            0,
+            0,
+        ],
+    };
+
+    pub const BOTTOM: Self = Self {
+        horizontal: [
+            0x1c2,
+            0xd1,
+            0x1c1,
+            0x1c1,
+            0xcd,
+            0xcf,
+            0xd1,
+            (0x1c5 << 16) | 0x1c1,
+            0x10000,
+        ],
+        vertical: [
+            0x19d,
+            0x52,
+            0x192,
+            0x192,
+            0x4f,
+            0x50,
+            0x52,
+            (0x198 << 16) | 0x194,
+            0,
+            0x11,
+        ],
+    };
+
+    pub const fn for_screen(screen: Screen) -> Self {
+        match screen {
+            Screen::Top => Self::TOP,
+            Screen::Bottom => Self::BOTTOM,
+        }
+    }
+
+    fn to_words(self) -> [u32; 19] {
+        let mut words = [0; 19];
+        words[..9].copy_from_slice(&self.horizontal);
+        words[9..].copy_from_slice(&self.vertical);
+        words
+    }
+}
+
+/// Size of a framebuffer and the part of the LCD timing it is displayed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferGeometry {
+    /// Width of the framebuffer, i.e. the height of the screen.
+    pub width: u16,
+    pub height: u16,
+    /// First and last pixel clock the framebuffer is displayed in.
+    pub horizontal: (u16, u16),
+    /// First and last line the framebuffer is displayed in.
+    pub vertical: (u16, u16),
+}
+
+impl FramebufferGeometry {
+    pub const TOP: Self = Self {
+        width: 240,
+        height: 400,
+        horizontal: (0xd1, 0x1c1),
+        vertical: (2, 0x192),
+    };
+
+    pub const BOTTOM: Self = Self {
+        width: 240,
+        height: 320,
+        horizontal: (0xd1, 0x1c1),
+        vertical: (0x52, 0x192),
+    };
+
+    pub const fn for_screen(screen: Screen) -> Self {
+        match screen {
+            Screen::Top => Self::TOP,
+            Screen::Bottom => Self::BOTTOM,
+        }
+    }
+
+    fn to_words(self) -> [u32; 3] {
+        let pair = |low: u16, high: u16| u32::from(high) << 16 | u32::from(low);
+        [
+            pair(self.width, self.height),
+            pair(self.horizontal.0, self.horizontal.1),
+            pair(self.vertical.0, self.vertical.1),
+        ]
+    }
+}
+
+/// Writes the GPU's external registers through GSP.
+#[derive(Debug)]
+pub struct Registers<'a> {
+    access: BorrowedHandle<'a>,
+}
+
+impl<'a> Registers<'a> {
+    /// `access` must be a GSP session holding GPU access rights.
+    pub(crate) fn new(access: BorrowedHandle<'a>) -> Self {
+        Self { access }
+    }
+
+    /// Write `value` to `register`.
+    ///
+    /// # Safety
+    ///
+    /// The registers control DMA engines like the memory fill and display transfer units, which
+    /// write to arbitrary physical memory.  The write must not make the GPU access memory in use.
+    pub unsafe fn write(&self, register: u32, value: u32) -> Result<()> {
+        self.write_words(register, &[value])
+    }
+
+    /// Write consecutive registers starting at `register`.
+    ///
+    /// # Safety
+    ///
+    /// See [`write`](Self::write).
+    pub unsafe fn write_words(&self, register: u32, values: &[u32]) -> Result<()> {
+        const WORDS_PER_WRITE: usize = MAX_WRITE_SIZE / 4;
+
+        for (i, chunk) in values.chunks(WORDS_PER_WRITE).enumerate() {
+            let register = register + (i * MAX_WRITE_SIZE) as u32;
+            trace!("Writing GPU registers: {:#08x} = {:#x?}", register, chunk);
+
+            let _ = IpcRequest::command(0x01)
+                .parameters(&[register, (chunk.len() * 4) as u32])
+                .translate_parameter(StaticBuffer::new(chunk, 0))
+                .dispatch(self.access)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the bits of `value` selected by `mask` to `register`.
+    ///
+    /// # Safety
+    ///
+    /// See [`write`](Self::write).
+    pub unsafe fn write_masked(&self, register: u32, value: u32, mask: u32) -> Result<()> {
+        trace!(
+            "Writing GPU register: {:#08x} = {:#08x} (mask: {:#08x})",
+            register,
+            value,
+            mask
+        );
+
+        let value = [value];
+        let mask = [mask];
+        let _ = IpcRequest::command(0x02)
+            .parameters(&[register, 4])
+            .translate_parameter(StaticBuffer::new(&value, 0))
+            .translate_parameter(StaticBuffer::new(&mask, 1))
+            .dispatch(self.access)?;
+
+        Ok(())
+    }
+
+    // The setters below configure the LCDs, enable clocks or clear status bits, none of which
+    // make the GPU write to memory.
+
+    pub fn set_lcd_sync(&self, screen: Screen, sync: &LcdSync) -> Result<()> {
+        unsafe {
+            self.write_words(
+                framebuffer_setup(screen, framebuffer::SYNC),
+                &sync.to_words(),
+            )
+        }
+    }
+
+    pub fn set_framebuffer_geometry(
+        &self,
+        screen: Screen,
+        geometry: &FramebufferGeometry,
+    ) -> Result<()> {
+        unsafe {
+            self.write_words(
+                framebuffer_setup(screen, framebuffer::GEOMETRY),
+                &geometry.to_words(),
+            )
+        }
+    }
+
+    /// Set the raw framebuffer format, including the color format in the lowest three bits.
+    pub fn set_framebuffer_format(&self, screen: Screen, format: u32) -> Result<()> {
+        unsafe { self.write(framebuffer_setup(screen, framebuffer::FORMAT), format) }
+    }
+
+    /// Set the physical addresses of the left framebuffers A and B.
+    pub fn set_left_framebuffers(&self, screen: Screen, addresses: [u32; 2]) -> Result<()> {
+        unsafe {
+            self.write_words(
+                framebuffer_setup(screen, framebuffer::LEFT_ADDRESSES),
+                &addresses,
+            )
+        }
+    }
+
+    /// Set the physical addresses of the right framebuffers A and B of the top screen.
+    pub fn set_right_framebuffers(&self, addresses: [u32; 2]) -> Result<()> {
+        unsafe {
+            self.write_words(
+                framebuffer_setup(Screen::Top, framebuffer::RIGHT_ADDRESSES),
+                &addresses,
+            )
+        }
+    }
+
+    /// Set the raw framebuffer select register, see [`framebuffer::SELECT`].
+    pub fn select_framebuffer(&self, screen: Screen, select: u32) -> Result<()> {
+        unsafe { self.write(framebuffer_setup(screen, framebuffer::SELECT), select) }
+    }
+
+    pub fn set_framebuffer_stride(&self, screen: Screen, stride: u32) -> Result<()> {
+        unsafe { self.write(framebuffer_setup(screen, framebuffer::STRIDE), stride) }
+    }
+
+    /// Load the color lookup table of `screen`, see [`Gpu::set_color_lut`](super::gpu::Gpu::set_color_lut).
+    pub fn set_color_lut(&self, screen: Screen, lut: &[u32; 256]) -> Result<()> {
+        // Writing the index resets the table position, each write to the data register advances it.
+        unsafe {
+            self.write(framebuffer_setup(screen, framebuffer::COLOR_LUT_INDEX), 0)?;
+            for &entry in lut {
+                self.write(
+                    framebuffer_setup(screen, framebuffer::COLOR_LUT_DATA),
+                    entry,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Enable the clocks of the GPU's units, see [`CLOCK`].
+    pub fn set_gpu_clock(&self, clock: u32) -> Result<()> {
+        unsafe { self.write(CLOCK, clock) }
+    }
+
+    /// Clear the "busy" and "finished" bits of both memory fill units.
+    pub fn clear_memory_fill_status(&self) -> Result<()> {
+        for control in MEMORY_FILL_CONTROL {
+            unsafe { self.write_masked(control, 0, 0xff) }?;
+        }
+
+        Ok(())
+    }
+
+    /// Clear the "finished" bit of the display transfer unit.
+    pub fn clear_display_transfer_status(&self) -> Result<()> {
+        unsafe { self.write_masked(DISPLAY_TRANSFER_CONTROL, 0, 0xff00) }
+    }
+}
@@ -11,6 +11,8 @@ use crate::{
     },
     ports::srv::Srv,
     result::Result,
+    svc::{Timeout, WaitOutcome},
+    sync::Event,
 };
 
 use log::debug;
@@ -27,7 +29,7 @@ impl SharedMemory {
     fn new(memory_handle: OwnedHandle) -> Result<Self> {
         const SIZE: usize = 0x2b0;
         const SELF_PERM: MemoryPermission = MemoryPermission::R;
-        const HID_PERM: MemoryPermission = MemoryPermission::DontCare;
+        const HID_PERM: MemoryPermission = MemoryPermission::DONT_CARE;
         let sharedmem =
             SharedMemoryMapper::global().map(memory_handle, SIZE, SELF_PERM, HID_PERM)?;
 
@@ -40,6 +42,11 @@ impl SharedMemory {
         (self.sharedmem.as_ptr().offset(offset) as *const T).read_volatile()
     }
 
+    unsafe fn read_bytes<T>(&self, byte_offset: usize) -> T {
+        let ptr = self.sharedmem.as_ptr() as *const u8;
+        (ptr.add(byte_offset) as *const T).read_volatile()
+    }
+
     fn current_update(&self) -> SystemTick {
         let tick_count = unsafe { self.read(0) };
 
@@ -77,6 +84,69 @@ impl SharedMemory {
     fn pad_released(&self, index: u32) -> u32 {
         unsafe { self.pad_state(index).offset(2).read_volatile() }
     }
+
+    fn pad_circle_pad(&self, index: u32) -> (i16, i16) {
+        let position = unsafe { self.pad_state(index).offset(3).read_volatile() };
+        (position as i16, (position >> 16) as i16)
+    }
+
+    fn pad_entry(&self, index: u32, tick: SystemTick) -> PadSample {
+        PadSample {
+            tick,
+            held: KeyPad::new(self.pad_current(index)),
+            pressed: KeyPad::new(self.pad_pressed(index)),
+            released: KeyPad::new(self.pad_released(index)),
+            circle_pad: self.pad_circle_pad(index),
+        }
+    }
+
+    /// Word offset of the touch screen section.
+    const TOUCH: isize = 0xa8 / 4;
+
+    fn touch_update(&self) -> SystemTick {
+        let tick_count = unsafe { self.read(Self::TOUCH) };
+
+        SystemTick::new(tick_count)
+    }
+
+    fn touch_index(&self) -> u32 {
+        let idx: u32 = unsafe { self.read(Self::TOUCH + 4) };
+        idx & 0b0111
+    }
+
+    /// Word offset of the gyroscope section.
+    const GYROSCOPE: isize = 0x158 / 4;
+
+    fn gyroscope_index(&self) -> u32 {
+        let idx: u32 = unsafe { self.read(Self::GYROSCOPE + 4) };
+        idx & 0b1_1111
+    }
+
+    fn gyroscope_entry(&self, index: u32) -> RawAngularRate {
+        debug_assert!(index < 32);
+        let entry = (Self::GYROSCOPE as usize) * 4 + 0x18 + index as usize * 6;
+        let (x, z, y) = unsafe {
+            (
+                self.read_bytes(entry),
+                self.read_bytes(entry + 2),
+                self.read_bytes(entry + 4),
+            )
+        };
+
+        RawAngularRate { x, y, z }
+    }
+
+    fn touch_entry(&self, index: u32) -> Touch {
+        debug_assert!(index < 8);
+        let entry = Self::TOUCH + 8 + (index * 2) as isize;
+        let (position, valid): (u32, u32) = unsafe { (self.read(entry), self.read(entry + 1)) };
+
+        Touch {
+            x: position as u16,
+            y: (position >> 16) as u16,
+            pressed: valid & 1 != 0,
+        }
+    }
 }
 
 impl Drop for SharedMemory {
@@ -91,10 +161,16 @@ impl Drop for SharedMemory {
 pub struct Hid {
     service_handle: OwnedHandle,
     sharedmem: SharedMemory,
-    pads: (OwnedHandle, OwnedHandle),
-    accelerometer: OwnedHandle,
-    gyroscope: OwnedHandle,
-    debugpad: OwnedHandle,
+    pads: (Event, Event),
+    accelerometer: Event,
+    gyroscope: Event,
+    debugpad: Event,
+    /// Keys held during the previous [`Hid::scan_input`].
+    previous_keys: KeyPad,
+    /// Pad entry read by the previous [`Hid::scan_input`].
+    previous_index: u32,
+    /// Tick of the pad entry read by the previous [`Hid::scan_input`].
+    previous_update: u64,
 }
 
 impl Hid {
@@ -114,23 +190,94 @@ impl Hid {
         // It's important to map memory last: if this fails, all handles above are dropped properly
         let sharedmem = SharedMemory::new(memory_handle)?;
 
+        let [pad0, pad1, accelerometer, gyroscope, debugpad] =
+            [pad0, pad1, accelerometer, gyroscope, debugpad]
+                .map(|handle| unsafe { Event::from_handle(handle) });
+
         debug!("HID initialized!");
         Ok(Self {
             service_handle,
-            sharedmem,
             pads: (pad0, pad1),
             accelerometer,
             gyroscope,
             debugpad,
+            previous_keys: KeyPad::new(0),
+            previous_index: sharedmem.current_index(),
+            previous_update: sharedmem.current_update().count(),
+            sharedmem,
         })
     }
 
+    /// The event HID signals whenever it stored new samples of `source`.
+    pub fn event(&self, source: HidEvent) -> &Event {
+        match source {
+            HidEvent::Pad0 => &self.pads.0,
+            HidEvent::Pad1 => &self.pads.1,
+            HidEvent::Accelerometer => &self.accelerometer,
+            HidEvent::Gyroscope => &self.gyroscope,
+            HidEvent::DebugPad => &self.debugpad,
+        }
+    }
+
+    /// Block until HID stored new samples of the keys, circle pad and touch screen.
+    ///
+    /// Returns [`WaitOutcome::TimedOut`] if there were none within `timeout`.
+    pub fn wait_for_input(&self, timeout: Timeout) -> Result<WaitOutcome> {
+        let outcome = self.pads.0.wait(timeout)?;
+        if outcome.is_signaled() {
+            // The event is sticky, so it stays signaled until cleared
+            self.pads.0.clear()?;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Position of the volume slider, from `0` (muted) to [`MAX_SOUND_VOLUME`].
+    ///
+    /// See [`sharedpage`](crate::os::sharedpage) for the 3D slider and headset state.
+    pub fn sound_volume(&self) -> Result<u8> {
+        let mut reply = IpcRequest::command(0x17).dispatch(&self.service_handle)?;
+
+        Ok(reply.read_word() as u8)
+    }
+
     fn enable_accelerometer(&self) -> Result<()> {
-        IpcRequest::command(0xa)
+        IpcRequest::command(0x11)
+            .dispatch(&self.service_handle)
+            .map(drop)
+    }
+
+    /// Start sampling the gyroscope, returning the parameters to convert its samples.
+    pub fn enable_gyroscope(&self) -> Result<GyroscopeCalibration> {
+        let _ = IpcRequest::command(0x13).dispatch(&self.service_handle)?;
+
+        let mut reply = IpcRequest::command(0x15).dispatch(&self.service_handle)?;
+        let dps_per_unit = f32::from_bits(reply.read_word());
+
+        let mut reply = IpcRequest::command(0x16).dispatch(&self.service_handle)?;
+        let words: [u32; 5] = core::array::from_fn(|_| reply.read_word());
+
+        Ok(GyroscopeCalibration::new(dps_per_unit, &words))
+    }
+
+    pub fn disable_gyroscope(&self) -> Result<()> {
+        IpcRequest::command(0x14)
             .dispatch(&self.service_handle)
             .map(drop)
     }
 
+    /// The most recent sample of the gyroscope, see [`enable_gyroscope`](Self::enable_gyroscope).
+    pub fn gyroscope_raw(&self) -> RawAngularRate {
+        let index = self.sharedmem.gyroscope_index();
+
+        self.sharedmem.gyroscope_entry(index)
+    }
+
+    /// The most recent angular rate measured by the gyroscope.
+    pub fn angular_rate(&self, calibration: &GyroscopeCalibration) -> AngularRate {
+        calibration.convert(self.gyroscope_raw())
+    }
+
     pub fn last_keypad(&self) -> KeyPad {
         debug!("tick (low): {:?}", self.sharedmem.current_update());
         let index = self.sharedmem.current_index();
@@ -139,9 +286,309 @@ impl Hid {
 
         KeyPad::new(pad)
     }
+
+    /// Snapshot the keys, to find out which changed since the previous call.
+    ///
+    /// Call this once per frame.  Keys pressed and released again between two calls are reported
+    /// as pressed.
+    pub fn scan_input(&mut self) -> Input {
+        let index = self.sharedmem.current_index();
+        let updated = self.sharedmem.current_update().count();
+
+        // Catch presses shorter than a frame in the entries added since the previous scan
+        let added = self.pad_entries_since(index, updated);
+        let pressed = (0..added).fold(0, |pressed, age| {
+            pressed | self.sharedmem.pad_pressed((index + 8 - age) % 8)
+        });
+
+        let held = self.sharedmem.pad_current(index);
+        let previous = self.previous_keys.bits();
+        self.previous_keys = KeyPad::new(held);
+        self.previous_index = index;
+        self.previous_update = updated;
+
+        Input {
+            held: KeyPad::new(held),
+            down: KeyPad::new((held | pressed) & !previous),
+            up: KeyPad::new(previous & !held),
+        }
+    }
+
+    /// Number of pad entries HID stored since the previous scan, at most the length of its ring.
+    fn pad_entries_since(&self, index: u32, updated: u64) -> u32 {
+        if updated == self.previous_update {
+            return 0;
+        }
+
+        // The index alone cannot tell whether the ring wrapped around, the ticks can
+        let interval = updated.saturating_sub(self.sharedmem.last_update().count());
+        let elapsed = updated.saturating_sub(self.previous_update);
+        if interval != 0 && (elapsed + interval / 2) / interval >= 8 {
+            return 8;
+        }
+
+        match (index + 8 - self.previous_index) % 8 {
+            0 => 8,
+            added => added,
+        }
+    }
+
+    /// The position of the circle pad, with positive `y` pointing up.
+    ///
+    /// HID applies the system's calibration, so the values range to about
+    /// [`CIRCLE_PAD_MAX`] in each direction.
+    pub fn circle_pad(&self) -> (i16, i16) {
+        let index = self.sharedmem.current_index();
+
+        self.sharedmem.pad_circle_pad(index)
+    }
+
+    /// All samples of the keys and circle pad HID keeps, see [`PadHistory`].
+    pub fn pad_history(&self) -> PadHistory {
+        let index = self.sharedmem.current_index();
+        let updated = self.sharedmem.current_update();
+        let interval = updated
+            .count()
+            .saturating_sub(self.sharedmem.last_update().count());
+
+        let samples = core::array::from_fn(|i| {
+            let age = (PadHistory::LENGTH - 1 - i) as u32;
+            let tick = updated.count().saturating_sub(u64::from(age) * interval);
+            self.sharedmem
+                .pad_entry((index + 8 - age) % 8, SystemTick::new(tick))
+        });
+
+        PadHistory { updated, samples }
+    }
+
+    /// The most recent sample of the touch screen.
+    pub fn touch(&self) -> Touch {
+        let index = self.sharedmem.touch_index();
+
+        self.sharedmem.touch_entry(index)
+    }
+
+    /// The recent samples of the touch screen, see [`TouchHistory`].
+    pub fn touch_history(&self) -> TouchHistory {
+        let index = self.sharedmem.touch_index();
+        let updated = self.sharedmem.touch_update();
+
+        let mut samples = [Touch::RELEASED; TouchHistory::LENGTH];
+        for (age, sample) in samples.iter_mut().rev().enumerate() {
+            *sample = self.sharedmem.touch_entry((index + 8 - age as u32) % 8);
+        }
+
+        TouchHistory { updated, samples }
+    }
+}
+
+/// Highest [`Hid::sound_volume`].
+pub const MAX_SOUND_VOLUME: u8 = 0x3f;
+
+/// Distance from the center at which the circle pad reports being fully tilted along an axis.
+pub const CIRCLE_PAD_MAX: i16 = 156;
+
+/// Center a circle pad `position` that lies within `radius` of the center, to ignore the drift of
+/// a resting stick.
+pub fn dead_zone(position: (i16, i16), radius: i16) -> (i16, i16) {
+    let (x, y) = (i32::from(position.0), i32::from(position.1));
+    let radius = i32::from(radius);
+
+    if x * x + y * y < radius * radius {
+        (0, 0)
+    } else {
+        position
+    }
+}
+
+/// Scale a circle pad `position` to `-1.0..=1.0` along both axes.
+pub fn normalize(position: (i16, i16)) -> (f32, f32) {
+    let scale = |value: i16| (f32::from(value) / f32::from(CIRCLE_PAD_MAX)).clamp(-1.0, 1.0);
+
+    (scale(position.0), scale(position.1))
+}
+
+/// A sample of the gyroscope, in the units of the sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RawAngularRate {
+    /// Roll
+    pub x: i16,
+    /// Pitch
+    pub y: i16,
+    /// Yaw
+    pub z: i16,
+}
+
+/// Rotation around each axis in degrees per second, see [`RawAngularRate`] for the axes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AngularRate {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// Calibration of a gyroscope axis stored in the system: its readings at rest and when turned
+/// either way at a fixed rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AxisCalibration {
+    pub zero: i16,
+    pub positive: i16,
+    pub negative: i16,
+}
+
+/// Returned by [`Hid::enable_gyroscope`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GyroscopeCalibration {
+    /// Degrees per second of one unit of a [`RawAngularRate`].
+    pub dps_per_unit: f32,
+    pub x: AxisCalibration,
+    pub y: AxisCalibration,
+    pub z: AxisCalibration,
+}
+
+impl GyroscopeCalibration {
+    fn new(dps_per_unit: f32, words: &[u32; 5]) -> Self {
+        let half = |i: usize| (words[i / 2] >> (16 * (i % 2))) as i16;
+        let axis = |i: usize| AxisCalibration {
+            zero: half(3 * i),
+            positive: half(3 * i + 1),
+            negative: half(3 * i + 2),
+        };
+
+        Self {
+            dps_per_unit,
+            x: axis(0),
+            y: axis(1),
+            z: axis(2),
+        }
+    }
+
+    pub fn convert(&self, raw: RawAngularRate) -> AngularRate {
+        AngularRate {
+            x: f32::from(raw.x) * self.dps_per_unit,
+            y: f32::from(raw.y) * self.dps_per_unit,
+            z: f32::from(raw.z) * self.dps_per_unit,
+        }
+    }
+}
+
+/// A sample of the touch screen, in pixels of the bottom screen from its top left corner.
+///
+/// HID applies the system's calibration before storing samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Touch {
+    pub x: u16,
+    pub y: u16,
+    pub pressed: bool,
 }
 
-#[derive(Clone, Copy)]
+impl Touch {
+    const RELEASED: Self = Self {
+        x: 0,
+        y: 0,
+        pressed: false,
+    };
+
+    /// The position touched, if any.
+    pub fn position(&self) -> Option<(u16, u16)> {
+        self.pressed.then_some((self.x, self.y))
+    }
+}
+
+/// The ring buffer of touch screen samples HID keeps.
+///
+/// HID adds a sample about every 4 ms, so the samples cover a bit more than the last frame.
+#[derive(Debug, Clone, Copy)]
+pub struct TouchHistory {
+    /// When the newest sample was taken.
+    pub updated: SystemTick,
+    /// Samples from oldest to newest.
+    pub samples: [Touch; Self::LENGTH],
+}
+
+impl TouchHistory {
+    pub const LENGTH: usize = 8;
+
+    pub fn latest(&self) -> Touch {
+        self.samples[Self::LENGTH - 1]
+    }
+}
+
+/// A sample of the keys and circle pad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PadSample {
+    /// When the sample was taken, see [`PadHistory`].
+    pub tick: SystemTick,
+    pub held: KeyPad,
+    /// Keys pressed since the previous sample.
+    pub pressed: KeyPad,
+    /// Keys released since the previous sample.
+    pub released: KeyPad,
+    pub circle_pad: (i16, i16),
+}
+
+/// The ring buffer of pad samples HID keeps.
+///
+/// HID adds a sample about every 4 ms, but only stores when the two newest samples were taken.
+/// The ticks of older samples are extrapolated from the interval between those two.
+#[derive(Debug, Clone, Copy)]
+pub struct PadHistory {
+    /// When the newest sample was taken.
+    pub updated: SystemTick,
+    /// Samples from oldest to newest.
+    pub samples: [PadSample; Self::LENGTH],
+}
+
+impl PadHistory {
+    pub const LENGTH: usize = 8;
+
+    pub fn latest(&self) -> PadSample {
+        self.samples[Self::LENGTH - 1]
+    }
+
+    /// The samples taken after `tick`, from oldest to newest, e.g. those since the previous frame.
+    pub fn since(&self, tick: SystemTick) -> &[PadSample] {
+        let first = self.samples.partition_point(|sample| sample.tick <= tick);
+        &self.samples[first..]
+    }
+}
+
+/// Events signaled by HID, see [`Hid::event`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HidEvent {
+    Pad0,
+    Pad1,
+    Accelerometer,
+    Gyroscope,
+    DebugPad,
+}
+
+/// The keys at one [`Hid::scan_input`], compared to the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    held: KeyPad,
+    down: KeyPad,
+    up: KeyPad,
+}
+
+impl Input {
+    /// Keys that are pressed right now.
+    pub const fn keys_held(&self) -> KeyPad {
+        self.held
+    }
+
+    /// Keys that were pressed since the previous scan.
+    pub const fn keys_down(&self) -> KeyPad {
+        self.down
+    }
+
+    /// Keys that were released since the previous scan.
+    pub const fn keys_up(&self) -> KeyPad {
+        self.up
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
 pub struct KeyPad(u32);
 
 #[doc(hidden)]
@@ -159,6 +606,14 @@ impl KeyPad {
         Self(bits)
     }
 
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
     _keypad_key! {a, 0}
     _keypad_key! {b, 1}
     _keypad_key! {select, 2}
@@ -4,8 +4,12 @@
 
 pub mod ac;
 pub mod apt;
+pub mod cfg;
+pub mod fs;
 pub mod gsp;
 pub mod hid;
+pub mod nwm;
+pub mod ptm;
 pub mod soc;
 
 pub trait Service: Default {}
@@ -0,0 +1,37 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! # Network wireless manager
+//!
+//! `nwm::EXT` controls the wireless hardware as a whole, like the wireless switch of the Old 3DS
+//! or the toggle in the HOME Menu.  Whether wireless is enabled can be read without a session, see
+//! [`sharedpage::is_wireless_enabled`](crate::os::sharedpage::is_wireless_enabled).
+
+use crate::ipc::IpcRequest;
+use crate::os::OwnedHandle;
+use crate::ports::srv::Srv;
+use crate::result::Result;
+
+#[derive(Debug)]
+pub struct NwmExt {
+    handle: OwnedHandle,
+}
+
+impl NwmExt {
+    pub fn init(srv: &Srv) -> Result<Self> {
+        Ok(Self {
+            handle: srv.get_service_handle("nwm::EXT")?,
+        })
+    }
+
+    /// Enable or disable all wireless communication.
+    ///
+    /// Disabling it drops the connections of `ac` and `soc`.
+    pub fn control_wireless_enabled(&self, enabled: bool) -> Result<()> {
+        let _ = IpcRequest::command(0x8)
+            .parameter(u32::from(enabled))
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::ipc::IpcRequest;
+use crate::os::OwnedHandle;
+use crate::ports::srv::Srv;
+use crate::result::Result;
+
+/// CPU configuration of a New 3DS, see [`PtmSysm::configure_new_3ds_cpu`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct New3dsCpuConfig(u8);
+
+impl New3dsCpuConfig {
+    /// Run at 268 MHz without L2 cache, like an Old 3DS.
+    pub const fn legacy() -> Self {
+        Self(0)
+    }
+
+    /// Run at 804 MHz.
+    pub const fn high_clock(self) -> Self {
+        Self(self.0 | 1 << 0)
+    }
+
+    pub const fn l2_cache(self) -> Self {
+        Self(self.0 | 1 << 1)
+    }
+}
+
+#[derive(Debug)]
+pub struct PtmSysm {
+    handle: OwnedHandle,
+}
+
+impl PtmSysm {
+    pub fn init(srv: &Srv) -> Result<Self> {
+        Ok(Self {
+            handle: srv.get_service_handle("ptm:sysm")?,
+        })
+    }
+
+    /// Set the clock rate and L2 cache state of a New 3DS.
+    pub fn configure_new_3ds_cpu(&self, config: New3dsCpuConfig) -> Result<()> {
+        let _ = IpcRequest::command(0x818)
+            .parameter(u32::from(config.0))
+            .dispatch(&self.handle)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Reading and writing connected sockets through `embedded-io`.
+
+use super::{Errno, OwnedSocketFd, SocketError};
+
+use embedded_io::{ErrorKind, ErrorType, Read, Write};
+
+impl embedded_io::Error for SocketError {
+    fn kind(&self) -> ErrorKind {
+        let errno = match self {
+            Self::SocketErr(errno) => *errno,
+            Self::SystemErr(_) | Self::WouldBlock => return ErrorKind::Other,
+        };
+
+        match errno {
+            Errno::ENOENT => ErrorKind::NotFound,
+            Errno::EACCES | Errno::EPERM => ErrorKind::PermissionDenied,
+            Errno::ECONNREFUSED => ErrorKind::ConnectionRefused,
+            Errno::ECONNRESET => ErrorKind::ConnectionReset,
+            Errno::ECONNABORTED => ErrorKind::ConnectionAborted,
+            Errno::ENOTCONN => ErrorKind::NotConnected,
+            Errno::EADDRINUSE => ErrorKind::AddrInUse,
+            Errno::EADDRNOTAVAIL => ErrorKind::AddrNotAvailable,
+            Errno::EPIPE => ErrorKind::BrokenPipe,
+            Errno::EEXIST => ErrorKind::AlreadyExists,
+            Errno::EINVAL => ErrorKind::InvalidInput,
+            Errno::ETIMEDOUT => ErrorKind::TimedOut,
+            Errno::EINTR => ErrorKind::Interrupted,
+            Errno::EOPNOTSUPP | Errno::ENOTSUP | Errno::EAFNOSUPPORT => ErrorKind::Unsupported,
+            Errno::ENOMEM | Errno::ENOBUFS => ErrorKind::OutOfMemory,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorType for OwnedSocketFd<'_> {
+    type Error = SocketError;
+}
+
+impl Read for OwnedSocketFd<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        self.soc.recv(self, buffer)
+    }
+}
+
+impl Write for OwnedSocketFd<'_> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+        self.soc.send(self, data)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
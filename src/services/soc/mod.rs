@@ -0,0 +1,1175 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::ports::srv::Srv;
+use crate::{
+    heap::PageAlignedBuffer,
+    ipc::{IpcParameter, IpcRequest, IpcResult, MappedBuffer, StaticBuffer, ThisProcessId},
+    os::{mem::MemoryPermission, AsHandle, OwnedHandle},
+    result::{ErrorCode as SystemErrorCode, Result as SystemResult},
+    svc::{self, Timeout},
+    tls,
+};
+
+use core::{fmt, marker::PhantomData, net::Ipv4Addr, ops::BitOr};
+
+use alloc::vec::Vec;
+
+use ctru_rt_macros::EnumCast;
+use log::debug;
+
+#[cfg(feature = "embedded-io")]
+mod io;
+#[cfg(feature = "embedded-nal")]
+mod nal;
+
+#[derive(Debug)]
+pub struct Soc {
+    handle: OwnedHandle,
+    buffer: PageAlignedBuffer,
+    buffer_handle: OwnedHandle,
+}
+
+impl Soc {
+    pub fn init(srv: &Srv, buffer: PageAlignedBuffer) -> SystemResult<Self> {
+        debug!("Creating memory block for buffer {:?}", buffer);
+        let buffer_handle = unsafe {
+            svc::create_memory_block(
+                buffer.as_ptr().unwrap().as_ptr() as usize,
+                buffer.size(),
+                MemoryPermission::NONE,
+                MemoryPermission::RW,
+            )?
+        };
+        debug!("Got buffer handle: {:?}", buffer_handle);
+        let handle = srv.get_service_handle("soc:U")?;
+
+        debug!("Got service handle: {:?}", handle);
+        let _reply = IpcRequest::command(0x1)
+            .parameter(buffer.size())
+            .translate_parameter(ThisProcessId)
+            .translate_parameter(buffer_handle.as_handle())
+            .dispatch(&handle)?;
+
+        debug!("Initialized!");
+        Ok(Self {
+            handle,
+            buffer,
+            buffer_handle,
+        })
+    }
+
+    /// Create a socket, which is closed when the returned descriptor is dropped.
+    pub fn socket(
+        &self,
+        domain: Domain,
+        socket_type: Type,
+        protocol: Protocol,
+    ) -> Result<OwnedSocketFd<'_>> {
+        let mut reply = IpcRequest::command(0x2)
+            .parameters(&[
+                domain.to_value(),
+                socket_type.to_value(),
+                protocol.to_value(),
+            ])
+            .translate_parameter(ThisProcessId)
+            .dispatch(&self.handle)?;
+
+        let fd = SocketError::into_length(reply.read_result())?;
+
+        Ok(OwnedSocketFd::new(self, fd as u32))
+    }
+
+    /// Close a socket that is not owned by an [`OwnedSocketFd`].
+    pub fn close(&self, fd: SocketFd<'_>) -> Result<()> {
+        self.close_fd(fd.0)
+    }
+
+    fn close_fd(&self, fd: u32) -> Result<()> {
+        let mut reply = IpcRequest::command(0xb)
+            .parameter(fd)
+            .translate_parameter(ThisProcessId)
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_result(reply.read_result())
+    }
+
+    /// Shut down receiving, sending or both on a connected socket, which stays open.
+    pub fn shutdown(&self, fd: &SocketFd<'_>, how: Shutdown) -> Result<()> {
+        let mut reply = IpcRequest::command(0xc)
+            .parameter(fd)
+            .parameter(how.to_value())
+            .translate_parameter(ThisProcessId)
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_result(reply.read_result())
+    }
+
+    pub fn listen(&self, fd: &SocketFd<'_>, backlog: isize) -> Result<()> {
+        let mut reply = IpcRequest::command(0x3)
+            .parameter(fd)
+            .parameter(backlog as u32)
+            .translate_parameter(ThisProcessId)
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_result(reply.read_result())
+    }
+
+    /// Wait for a connection on a listening socket, returning its socket and the peer's address.
+    pub fn accept(&self, fd: &SocketFd<'_>) -> Result<(OwnedSocketFd<'_>, SocketAddrV4)> {
+        let mut address = [0; ADDRESS_SIZE];
+
+        let tls = tls::get_thread_local_storage();
+        let mut buffer_descriptors = tls.static_buffer_descriptors();
+
+        buffer_descriptors.set(0, &mut address);
+
+        let mut reply = IpcRequest::command(0x4)
+            .parameter(fd)
+            .parameter(address.len())
+            .translate_parameter(ThisProcessId)
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        let accepted = SocketError::into_length(reply.read_result())?;
+
+        let accepted = OwnedSocketFd::new(self, accepted as u32);
+
+        Ok((accepted, SocketAddrV4::decode(&address)?))
+    }
+
+    pub fn bind(&self, fd: &SocketFd<'_>, address: impl Into<SocketAddrV4>) -> Result<()> {
+        let address = address.into().encode();
+
+        let mut reply = IpcRequest::command(0x5)
+            .parameter(fd)
+            .parameter(address.len())
+            .translate_parameter(ThisProcessId)
+            .translate_parameter(StaticBuffer::from_bytes(&address, 0))
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_result(reply.read_result())
+    }
+
+    pub fn connect(&self, fd: &SocketFd<'_>, address: impl Into<SocketAddrV4>) -> Result<()> {
+        let address = address.into().encode();
+
+        let mut reply = IpcRequest::command(0x6)
+            .parameter(fd)
+            .parameter(address.len())
+            .translate_parameter(ThisProcessId)
+            .translate_parameter(StaticBuffer::from_bytes(&address, 0))
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_result(reply.read_result())
+    }
+
+    /// Receive into `buffer` from a connected socket, returning the number of bytes received.
+    ///
+    /// Returns `0` once the peer closed the connection.
+    pub fn recv(&self, fd: &SocketFd<'_>, buffer: &mut [u8]) -> Result<usize> {
+        self.recv_with_address(fd, buffer, &mut [])
+    }
+
+    /// Receive a datagram into `buffer`, returning its length and the address it was sent from.
+    ///
+    /// The rest of a datagram longer than `buffer` is discarded.
+    pub fn recvfrom(&self, fd: &SocketFd<'_>, buffer: &mut [u8]) -> Result<(usize, SocketAddrV4)> {
+        let mut address = [0; ADDRESS_SIZE];
+        let length = self.recv_with_address(fd, buffer, &mut address)?;
+
+        Ok((length, SocketAddrV4::decode(&address)?))
+    }
+
+    fn recv_with_address(
+        &self,
+        fd: &SocketFd<'_>,
+        buffer: &mut [u8],
+        address: &mut [u8],
+    ) -> Result<usize> {
+        const FLAGS: u32 = 0;
+
+        let parameters = [fd.0, buffer.len() as u32, FLAGS, address.len() as u32];
+
+        let tls = tls::get_thread_local_storage();
+        let mut buffer_descriptors = tls.static_buffer_descriptors();
+
+        let mut reply = if buffer.len() < MAX_STATIC_TRANSFER {
+            buffer_descriptors.set(0, buffer);
+            buffer_descriptors.set(1, address);
+
+            IpcRequest::command(0x8)
+                .parameters(&parameters)
+                .translate_parameter(ThisProcessId)
+                .dispatch(&self.handle)
+        } else {
+            buffer_descriptors.set(0, address);
+
+            IpcRequest::command(0x7)
+                .parameters(&parameters)
+                .translate_parameter(ThisProcessId)
+                .translate_parameter(MappedBuffer::write(buffer))
+                .dispatch(&self.handle)
+        }
+        .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_length(reply.read_result())
+    }
+
+    /// Send `data` on a connected socket, returning the number of bytes sent.
+    ///
+    /// Data smaller than [`MAX_STATIC_TRANSFER`] is copied into the request, larger data is
+    /// mapped into the socket service.
+    pub fn send(&self, fd: &SocketFd<'_>, data: &[u8]) -> Result<usize> {
+        self.send_with_address(fd, data, &[])
+    }
+
+    /// Send `data` as a datagram to `address`, returning the number of bytes sent.
+    pub fn sendto(
+        &self,
+        fd: &SocketFd<'_>,
+        data: &[u8],
+        address: impl Into<SocketAddrV4>,
+    ) -> Result<usize> {
+        self.send_with_address(fd, data, &address.into().encode())
+    }
+
+    fn send_with_address(&self, fd: &SocketFd<'_>, data: &[u8], address: &[u8]) -> Result<usize> {
+        const FLAGS: u32 = 0;
+
+        let parameters = [fd.0, data.len() as u32, FLAGS, address.len() as u32];
+
+        let mut reply = if data.len() < MAX_STATIC_TRANSFER {
+            IpcRequest::command(0xa)
+                .parameters(&parameters)
+                .translate_parameter(ThisProcessId)
+                .translate_parameter(StaticBuffer::from_bytes(data, 2))
+                .translate_parameter(StaticBuffer::from_bytes(address, 1))
+                .dispatch(&self.handle)
+        } else {
+            IpcRequest::command(0x9)
+                .parameters(&parameters)
+                .translate_parameter(ThisProcessId)
+                .translate_parameter(StaticBuffer::from_bytes(address, 1))
+                .translate_parameter(MappedBuffer::read(data))
+                .dispatch(&self.handle)
+        }
+        .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_length(reply.read_result())
+    }
+
+    /// Wait until any of `fds` is ready for its requested events, or `timeout` passed.
+    ///
+    /// Returns the number of sockets that are ready, which is `0` on timeout, and sets their
+    /// [`ready`](PollFd::ready) events.  To wake up a thread waiting here, include a datagram
+    /// socket bound to the loopback address and send to it.
+    pub fn poll(&self, fds: &mut [PollFd<'_, '_>], timeout: impl Into<Timeout>) -> Result<usize> {
+        let request: Vec<u32> = fds
+            .iter()
+            .flat_map(|fd| [fd.fd.0, fd.events.bits(), 0])
+            .collect();
+        let mut reply_fds = alloc::vec![0u32; request.len()];
+
+        let tls = tls::get_thread_local_storage();
+        tls.static_buffer_descriptors().set(0, &mut reply_fds);
+
+        let mut reply = IpcRequest::command(0x14)
+            .parameter(fds.len())
+            .parameter(timeout.into().milliseconds() as u32)
+            .translate_parameter(ThisProcessId)
+            .translate_parameter(StaticBuffer::new(&request, 10))
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        let ready = SocketError::into_length(reply.read_result())?;
+
+        for (fd, words) in fds.iter_mut().zip(reply_fds.chunks_exact(3)) {
+            fd.ready = PollFlags(words[2]);
+        }
+
+        Ok(ready)
+    }
+
+    /// Read the value of `option` into `value`, returning its length.
+    pub fn get_sockopt_bytes(
+        &self,
+        fd: &SocketFd<'_>,
+        option: SocketOption,
+        value: &mut [u8],
+    ) -> Result<usize> {
+        let size = value.len();
+
+        let tls = tls::get_thread_local_storage();
+        tls.static_buffer_descriptors().set(0, value);
+
+        let mut reply = IpcRequest::command(0x11)
+            .parameters(&[fd.0, option.level, option.name, size as u32])
+            .translate_parameter(ThisProcessId)
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_result(reply.read_result())?;
+
+        Ok((reply.read_word() as usize).min(size))
+    }
+
+    pub fn set_sockopt_bytes(
+        &self,
+        fd: &SocketFd<'_>,
+        option: SocketOption,
+        value: &[u8],
+    ) -> Result<()> {
+        let mut reply = IpcRequest::command(0x12)
+            .parameters(&[fd.0, option.level, option.name, value.len() as u32])
+            .translate_parameter(ThisProcessId)
+            .translate_parameter(StaticBuffer::from_bytes(value, 9))
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_result(reply.read_result())
+    }
+
+    /// Read an option with an integer value, like [`SocketOption::SO_RCVBUF`].
+    pub fn get_sockopt(&self, fd: &SocketFd<'_>, option: SocketOption) -> Result<i32> {
+        let mut value = [0; 4];
+        self.get_sockopt_bytes(fd, option, &mut value)?;
+
+        Ok(i32::from_le_bytes(value))
+    }
+
+    /// Set an option with an integer value, where flags like [`SocketOption::SO_REUSEADDR`] are
+    /// enabled by any value other than `0`.
+    pub fn set_sockopt(&self, fd: &SocketFd<'_>, option: SocketOption, value: i32) -> Result<()> {
+        self.set_sockopt_bytes(fd, option, &value.to_le_bytes())
+    }
+
+    fn fcntl(&self, fd: &SocketFd<'_>, command: u32, argument: u32) -> Result<u32> {
+        let mut reply = IpcRequest::command(0x13)
+            .parameters(&[fd.0, command, argument])
+            .translate_parameter(ThisProcessId)
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_length(reply.read_result()).map(|flags| flags as u32)
+    }
+
+    /// Make operations on the socket fail with [`SocketError::WouldBlock`] instead of waiting.
+    pub fn set_nonblocking(&self, fd: &SocketFd<'_>, nonblocking: bool) -> Result<()> {
+        const F_GETFL: u32 = 3;
+        const F_SETFL: u32 = 4;
+        const O_NONBLOCK: u32 = 0x4;
+
+        let flags = self.fcntl(fd, F_GETFL, 0)?;
+        let flags = match nonblocking {
+            true => flags | O_NONBLOCK,
+            false => flags & !O_NONBLOCK,
+        };
+        self.fcntl(fd, F_SETFL, flags).map(drop)
+    }
+
+    /// Look up the IPv4 addresses of `name` with DNS.
+    pub fn gethostbyname(&self, name: &str) -> Result<Vec<Ipv4Addr>> {
+        let mut name_data = Vec::with_capacity(name.len() + 1);
+        name_data.extend_from_slice(name.as_bytes());
+        name_data.push(0);
+
+        let mut host = alloc::vec![0; HOSTENT_SIZE];
+
+        let tls = tls::get_thread_local_storage();
+        tls.static_buffer_descriptors().set(0, &mut host);
+
+        let mut reply = IpcRequest::command(0xd)
+            .parameter(name_data.len())
+            .translate_parameter(StaticBuffer::from_bytes(&name_data, 3))
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_result(reply.read_result())?;
+
+        let read_i16 = |at: usize| i16::from_le_bytes([host[at], host[at + 1]]);
+        let length = read_i16(hostent::LENGTH) as usize;
+        let count = (read_i16(hostent::ADDRESS_COUNT) as usize).min(hostent::MAX_ADDRESSES);
+
+        if read_i16(hostent::ADDRESS_TYPE) as u32 != Domain::AfInet.to_value() || length != 4 {
+            return Err(SocketError::SocketErr(Errno::EAFNOSUPPORT));
+        }
+
+        let addresses = host[hostent::ADDRESSES..]
+            .chunks_exact(hostent::ADDRESS_SIZE)
+            .take(count)
+            .map(|address| Ipv4Addr::new(address[0], address[1], address[2], address[3]))
+            .collect();
+
+        Ok(addresses)
+    }
+
+    /// The addresses of `host` at `port`, where `host` is either a domain name or an IPv4 address
+    /// like `"10.0.0.1"`.
+    ///
+    /// ```ignore
+    /// let address = soc.resolve("example.com", 80)?.next().ok_or(NotFound)?;
+    /// soc.connect(&fd, address)?;
+    /// ```
+    pub fn resolve(&self, host: &str, port: u16) -> Result<impl Iterator<Item = SocketAddrV4>> {
+        let addresses = match host.parse() {
+            Ok(ip) => alloc::vec![ip],
+            Err(_) => self.gethostbyname(host)?,
+        };
+
+        Ok(addresses
+            .into_iter()
+            .map(move |ip| SocketAddrV4::new(ip, port)))
+    }
+
+    /// The IPv4 address of this console.
+    pub fn gethostid(&self) -> Result<Ipv4Addr> {
+        let mut reply = IpcRequest::command(0x16).dispatch(&self.handle)?;
+
+        // In network byte order
+        Ok(Ipv4Addr::from(reply.read_word().to_ne_bytes()))
+    }
+
+    /// The address of the remote end of a connected socket.
+    pub fn getpeername(&self, fd: &SocketFd<'_>) -> Result<SocketAddrV4> {
+        self.socket_address(0x17, fd)
+    }
+
+    /// The local address a socket is bound to.
+    pub fn getsockname(&self, fd: &SocketFd<'_>) -> Result<SocketAddrV4> {
+        self.socket_address(0x18, fd)
+    }
+
+    fn socket_address(&self, command: u16, fd: &SocketFd<'_>) -> Result<SocketAddrV4> {
+        let mut address = [0; ADDRESS_SIZE];
+
+        let tls = tls::get_thread_local_storage();
+        tls.static_buffer_descriptors().set(0, &mut address);
+
+        let mut reply = IpcRequest::command(command)
+            .parameter(fd)
+            .parameter(address.len())
+            .translate_parameter(ThisProcessId)
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_result(reply.read_result())?;
+
+        SocketAddrV4::decode(&address)
+    }
+
+    /// Read the configuration of the network interface into `value`, returning its length.
+    pub fn get_network_opt_bytes(&self, option: NetworkOption, value: &mut [u8]) -> Result<usize> {
+        const SOL_CONFIG: u32 = 0xfffe;
+
+        let size = value.len();
+
+        let tls = tls::get_thread_local_storage();
+        tls.static_buffer_descriptors().set(0, value);
+
+        let mut reply = IpcRequest::command(0x1a)
+            .parameters(&[SOL_CONFIG, option.to_value(), size as u32])
+            .dispatch(&self.handle)
+            .map_err(SocketError::SystemErr)?;
+
+        SocketError::into_result(reply.read_result())?;
+
+        Ok((reply.read_word() as usize).min(size))
+    }
+
+    pub fn mac_address(&self) -> Result<[u8; 6]> {
+        let mut mac = [0; 6];
+        self.get_network_opt_bytes(NetworkOption::MacAddress, &mut mac)?;
+
+        Ok(mac)
+    }
+
+    /// Address, subnet mask and broadcast address of the network interface.
+    pub fn ip_info(&self) -> Result<IpInfo> {
+        let mut info = [0; 12];
+        self.get_network_opt_bytes(NetworkOption::IpInfo, &mut info)?;
+
+        Ok(IpInfo {
+            address: read_ipv4(&info[0..]),
+            netmask: read_ipv4(&info[4..]),
+            broadcast: read_ipv4(&info[8..]),
+        })
+    }
+
+    /// The gateway of the default route, if there is one.
+    pub fn gateway(&self) -> Result<Option<Ipv4Addr>> {
+        const ENTRY_SIZE: usize = 0x18;
+        const MAX_ENTRIES: usize = 32;
+
+        let mut table = alloc::vec![0; ENTRY_SIZE * MAX_ENTRIES];
+        let length = self.get_network_opt_bytes(NetworkOption::RoutingTable, &mut table)?;
+
+        // Entries are destination, netmask, gateway, flags and a timestamp
+        let gateway = table[..length]
+            .chunks_exact(ENTRY_SIZE)
+            .find(|entry| read_ipv4(&entry[0..]).is_unspecified())
+            .map(|entry| read_ipv4(&entry[8..]));
+
+        Ok(gateway)
+    }
+
+    /// The DNS servers used to [resolve](Self::resolve) host names.
+    pub fn dns_servers(&self) -> Result<Vec<Ipv4Addr>> {
+        const ENTRY_SIZE: usize = 0x14;
+        const MAX_ENTRIES: usize = 8;
+
+        let mut table = [0; ENTRY_SIZE * MAX_ENTRIES];
+        let length = self.get_network_opt_bytes(NetworkOption::DnsTable, &mut table)?;
+
+        // Entries are the address family, the address and padding
+        let servers = table[..length]
+            .chunks_exact(ENTRY_SIZE)
+            .filter(|entry| read_u32(&entry[0..]) == Domain::AfInet.to_value())
+            .map(|entry| read_ipv4(&entry[4..]))
+            .collect();
+
+        Ok(servers)
+    }
+
+    fn shutdown_service(&self) -> SystemResult<()> {
+        IpcRequest::command(0x19)
+            .dispatch(&self.handle)
+            .map(drop)
+    }
+
+    pub fn reclaim(mut self) -> SystemResult<PageAlignedBuffer> {
+        self.shutdown_service()?;
+        let buffer = core::mem::take(&mut self.buffer);
+
+        drop(self);
+
+        Ok(buffer)
+    }
+}
+
+impl Drop for Soc {
+    fn drop(&mut self) {
+        let _ = self.shutdown_service();
+    }
+}
+
+/// Transfers of at least this many bytes are passed in mapped instead of static buffers.
+pub const MAX_STATIC_TRANSFER: usize = 0x2000;
+
+/// Size of `struct sockaddr` of the socket service.
+const ADDRESS_SIZE: usize = 0x1c;
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn read_ipv4(bytes: &[u8]) -> Ipv4Addr {
+    Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])
+}
+
+/// Addresses of the network interface, see [`Soc::ip_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpInfo {
+    pub address: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    pub broadcast: Ipv4Addr,
+}
+
+/// Configuration of the network interface to [read](Soc::get_network_opt_bytes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[non_exhaustive]
+#[enum_cast(value_type = "u32")]
+pub enum NetworkOption {
+    MacAddress = 0x1004,
+    ArpTable = 0x3002,
+    IpInfo = 0x4003,
+    IpMtu = 0x4004,
+    RoutingTable = 0x4006,
+    UdpNumber = 0x8002,
+    UdpTable = 0x8003,
+    TcpNumber = 0x9002,
+    TcpTable = 0x9003,
+    DnsTable = 0xb003,
+    DhcpLeaseTime = 0xc001,
+}
+
+/// Size of `struct hostent` as filled in by the socket service.
+const HOSTENT_SIZE: usize = 0x1a88;
+
+/// Offsets into `struct hostent`.
+mod hostent {
+    pub const ADDRESS_TYPE: usize = 0x0;
+    pub const LENGTH: usize = 0x2;
+    pub const ADDRESS_COUNT: usize = 0x4;
+    pub const ADDRESSES: usize = 0x1908;
+
+    pub const ADDRESS_SIZE: usize = 0x10;
+    pub const MAX_ADDRESSES: usize = 24;
+}
+
+/// An IPv4 address and port, as passed to the socket service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SocketAddrV4 {
+    ip: Ipv4Addr,
+    port: u16,
+}
+
+impl SocketAddrV4 {
+    /// Length of `struct sockaddr_in` of the socket service.
+    const ENCODED_SIZE: u8 = 8;
+
+    pub const fn new(ip: Ipv4Addr, port: u16) -> Self {
+        Self { ip, port }
+    }
+
+    pub const fn ip(&self) -> Ipv4Addr {
+        self.ip
+    }
+
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    fn encode(&self) -> [u8; Self::ENCODED_SIZE as usize] {
+        let family = Domain::AfInet.to_value() as u8;
+        let [port_hi, port_lo] = self.port.to_be_bytes();
+        let [a, b, c, d] = self.ip.octets();
+
+        [Self::ENCODED_SIZE, family, port_hi, port_lo, a, b, c, d]
+    }
+
+    fn decode(address: &[u8; ADDRESS_SIZE]) -> Result<Self> {
+        match address {
+            [length, family, port_hi, port_lo, a, b, c, d, ..]
+                if *length >= Self::ENCODED_SIZE
+                    && u32::from(*family) == Domain::AfInet.to_value() =>
+            {
+                Ok(Self::new(
+                    Ipv4Addr::new(*a, *b, *c, *d),
+                    u16::from_be_bytes([*port_hi, *port_lo]),
+                ))
+            }
+            _ => Err(SocketError::SocketErr(Errno::EAFNOSUPPORT)),
+        }
+    }
+}
+
+impl From<core::net::SocketAddrV4> for SocketAddrV4 {
+    fn from(address: core::net::SocketAddrV4) -> Self {
+        Self::new(*address.ip(), address.port())
+    }
+}
+
+impl From<SocketAddrV4> for core::net::SocketAddrV4 {
+    fn from(address: SocketAddrV4) -> Self {
+        Self::new(address.ip, address.port)
+    }
+}
+
+impl From<(Ipv4Addr, u16)> for SocketAddrV4 {
+    fn from((ip, port): (Ipv4Addr, u16)) -> Self {
+        Self::new(ip, port)
+    }
+}
+
+impl fmt::Display for SocketAddrV4 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.ip, self.port)
+    }
+}
+
+/// An option of a socket, passed to [`Soc::set_sockopt`].
+///
+/// The constants use the numbering of the socket service, which differs from other systems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SocketOption {
+    level: u32,
+    name: u32,
+}
+
+impl SocketOption {
+    pub const SOL_SOCKET: u32 = 0xffff;
+    pub const SOL_TCP: u32 = 6;
+
+    pub const SO_DEBUG: Self = Self::new(Self::SOL_SOCKET, 0x0001);
+    pub const SO_ACCEPTCONN: Self = Self::new(Self::SOL_SOCKET, 0x0002);
+    pub const SO_REUSEADDR: Self = Self::new(Self::SOL_SOCKET, 0x0004);
+    pub const SO_KEEPALIVE: Self = Self::new(Self::SOL_SOCKET, 0x0008);
+    pub const SO_DONTROUTE: Self = Self::new(Self::SOL_SOCKET, 0x0010);
+    pub const SO_BROADCAST: Self = Self::new(Self::SOL_SOCKET, 0x0020);
+    pub const SO_LINGER: Self = Self::new(Self::SOL_SOCKET, 0x0080);
+    pub const SO_OOBINLINE: Self = Self::new(Self::SOL_SOCKET, 0x0100);
+    pub const SO_SNDBUF: Self = Self::new(Self::SOL_SOCKET, 0x1001);
+    pub const SO_RCVBUF: Self = Self::new(Self::SOL_SOCKET, 0x1002);
+    pub const SO_SNDLOWAT: Self = Self::new(Self::SOL_SOCKET, 0x1003);
+    pub const SO_RCVLOWAT: Self = Self::new(Self::SOL_SOCKET, 0x1004);
+    pub const SO_TYPE: Self = Self::new(Self::SOL_SOCKET, 0x1008);
+    /// The pending error of the socket, an [`Errno`] code.  Reading it clears it.
+    pub const SO_ERROR: Self = Self::new(Self::SOL_SOCKET, 0x1009);
+
+    /// Send small segments right away instead of coalescing them.
+    pub const TCP_NODELAY: Self = Self::new(Self::SOL_TCP, 0x2001);
+    pub const TCP_MAXSEG: Self = Self::new(Self::SOL_TCP, 0x2002);
+
+    pub const fn new(level: u32, name: u32) -> Self {
+        Self { level, name }
+    }
+}
+
+/// Events of a socket to [`poll`](Soc::poll) for, combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PollFlags(u32);
+
+impl PollFlags {
+    pub const NONE: Self = Self(0);
+    /// Data can be read.
+    pub const IN: Self = Self(0x01);
+    /// Urgent data can be read.
+    pub const PRI: Self = Self(0x02);
+    /// The peer closed the connection.  Reported without being requested.
+    pub const HUP: Self = Self(0x04);
+    /// Reported without being requested.
+    pub const ERR: Self = Self(0x08);
+    /// Data can be written.
+    pub const OUT: Self = Self(0x10);
+    /// The socket is not open.  Reported without being requested.
+    pub const NVAL: Self = Self(0x20);
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for PollFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(other)
+    }
+}
+
+/// A socket and the events to [`poll`](Soc::poll) it for.
+#[derive(Debug)]
+pub struct PollFd<'fd, 's> {
+    fd: &'fd SocketFd<'s>,
+    events: PollFlags,
+    ready: PollFlags,
+}
+
+impl<'fd, 's> PollFd<'fd, 's> {
+    pub fn new(fd: &'fd SocketFd<'s>, events: PollFlags) -> Self {
+        Self {
+            fd,
+            events,
+            ready: PollFlags::NONE,
+        }
+    }
+
+    pub fn fd(&self) -> &'fd SocketFd<'s> {
+        self.fd
+    }
+
+    /// The events the socket was ready for in the last [`poll`](Soc::poll).
+    pub fn ready(&self) -> PollFlags {
+        self.ready
+    }
+}
+
+#[derive(Debug, EnumCast)]
+#[non_exhaustive]
+#[enum_cast(value_type = "u32")]
+pub enum Domain {
+    AfInet = 2,
+}
+
+impl Default for Domain {
+    fn default() -> Self {
+        Self::AfInet
+    }
+}
+
+#[derive(Debug, EnumCast)]
+#[non_exhaustive]
+#[enum_cast(value_type = "u32")]
+pub enum Type {
+    Stream = 1,
+    Datagram = 2,
+}
+
+#[derive(Debug, EnumCast)]
+#[non_exhaustive]
+#[enum_cast(value_type = "u32")]
+pub enum Protocol {
+    Default = 0,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Self::Default
+    }
+}
+
+#[derive(Debug)]
+pub struct PosixReturnValue(u32);
+
+impl IpcResult for PosixReturnValue {
+    fn decode(result: u32) -> Self {
+        Self(result)
+    }
+}
+
+impl PosixReturnValue {
+    pub fn check(ret: u32) -> Result<()> {
+        SocketError::into_result(Self(ret))
+    }
+
+    /// The error of a negative return value.
+    pub fn errno(&self) -> Option<Errno> {
+        match self.0 as i32 {
+            0.. => None,
+            ret => Some(Errno(ret.unsigned_abs())),
+        }
+    }
+}
+
+/// An error of a socket operation.
+///
+/// The socket service numbers errors differently than newlib and other C libraries, the constants
+/// are named like their POSIX counterparts.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Errno(u32);
+
+macro_rules! errno {
+    ($($name: ident = $value: expr),* $(,)?) => {
+        impl Errno {
+            $(pub const $name: Self = Self($value);)*
+
+            /// The POSIX name of the error, like `"ECONNREFUSED"`.
+            pub const fn name(self) -> Option<&'static str> {
+                match self.0 {
+                    $($value => Some(stringify!($name)),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+errno! {
+    E2BIG = 1,
+    EACCES = 2,
+    EADDRINUSE = 3,
+    EADDRNOTAVAIL = 4,
+    EAFNOSUPPORT = 5,
+    EAGAIN = 6,
+    EALREADY = 7,
+    EBADF = 8,
+    EBADMSG = 9,
+    EBUSY = 10,
+    ECANCELED = 11,
+    ECHILD = 12,
+    ECONNABORTED = 13,
+    ECONNREFUSED = 14,
+    ECONNRESET = 15,
+    EDEADLK = 16,
+    EDESTADDRREQ = 17,
+    EDOM = 18,
+    EDQUOT = 19,
+    EEXIST = 20,
+    EFAULT = 21,
+    EFBIG = 22,
+    EHOSTUNREACH = 23,
+    EIDRM = 24,
+    EILSEQ = 25,
+    EINPROGRESS = 26,
+    EINTR = 27,
+    EINVAL = 28,
+    EIO = 29,
+    EISCONN = 30,
+    EISDIR = 31,
+    ELOOP = 32,
+    EMFILE = 33,
+    EMLINK = 34,
+    EMSGSIZE = 35,
+    EMULTIHOP = 36,
+    ENAMETOOLONG = 37,
+    ENETDOWN = 38,
+    ENETRESET = 39,
+    ENETUNREACH = 40,
+    ENFILE = 41,
+    ENOBUFS = 42,
+    ENODATA = 43,
+    ENODEV = 44,
+    ENOENT = 45,
+    ENOEXEC = 46,
+    ENOLCK = 47,
+    ENOLINK = 48,
+    ENOMEM = 49,
+    ENOMSG = 50,
+    ENOPROTOOPT = 51,
+    ENOSPC = 52,
+    ENOSR = 53,
+    ENOSTR = 54,
+    ENOSYS = 55,
+    ENOTCONN = 56,
+    ENOTDIR = 57,
+    ENOTEMPTY = 58,
+    ENOTSOCK = 59,
+    ENOTSUP = 60,
+    ENOTTY = 61,
+    ENXIO = 62,
+    EOPNOTSUPP = 63,
+    EOVERFLOW = 64,
+    EPERM = 65,
+    EPIPE = 66,
+    EPROTO = 67,
+    EPROTONOSUPPORT = 68,
+    EPROTOTYPE = 69,
+    ERANGE = 70,
+    EROFS = 71,
+    ESPIPE = 72,
+    ESRCH = 73,
+    ESTALE = 74,
+    ETIME = 75,
+    ETIMEDOUT = 76,
+}
+
+impl Errno {
+    /// The error number as used by the socket service.
+    pub const fn code(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Debug for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => f.debug_tuple("Errno").field(&self.0).finish(),
+        }
+    }
+}
+
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "error {}", self.0),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SocketFd<'s>(u32, PhantomData<&'s u32>);
+
+impl<'s> IpcParameter for &SocketFd<'s> {
+    fn encode(&self) -> u32 {
+        self.0
+    }
+}
+
+impl<'s> IpcResult for SocketFd<'s> {
+    fn decode(result: u32) -> Self {
+        SocketFd(result, PhantomData)
+    }
+}
+
+/// A socket descriptor that is closed on drop.
+#[derive(Debug)]
+pub struct OwnedSocketFd<'s> {
+    soc: &'s Soc,
+    fd: SocketFd<'s>,
+}
+
+impl<'s> OwnedSocketFd<'s> {
+    fn new(soc: &'s Soc, fd: u32) -> Self {
+        Self {
+            soc,
+            fd: SocketFd(fd, PhantomData),
+        }
+    }
+
+    /// Close the socket, reporting failure unlike dropping it.
+    pub fn close(self) -> Result<()> {
+        let soc = self.soc;
+        soc.close(self.into_raw())
+    }
+
+    /// Release ownership, leaving the socket open until [`Soc::close`]d.
+    pub fn into_raw(self) -> SocketFd<'s> {
+        let fd = SocketFd(self.fd.0, PhantomData);
+        core::mem::forget(self);
+        fd
+    }
+}
+
+impl<'s> core::ops::Deref for OwnedSocketFd<'s> {
+    type Target = SocketFd<'s>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.fd
+    }
+}
+
+impl Drop for OwnedSocketFd<'_> {
+    fn drop(&mut self) {
+        let _ = self.soc.close_fd(self.fd.0);
+    }
+}
+
+/// Which directions to [`shutdown`](Soc::shutdown).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCast)]
+#[enum_cast(value_type = "u32")]
+pub enum Shutdown {
+    Read = 0,
+    Write = 1,
+    Both = 2,
+}
+
+#[derive(Debug)]
+pub enum SocketError {
+    SystemErr(SystemErrorCode),
+    SocketErr(Errno),
+    /// The socket is [non-blocking](Soc::set_nonblocking) and the operation would have to wait.
+    WouldBlock,
+}
+
+impl From<SystemErrorCode> for SocketError {
+    fn from(e: SystemErrorCode) -> Self {
+        SocketError::SystemErr(e)
+    }
+}
+
+impl From<Errno> for SocketError {
+    fn from(e: Errno) -> Self {
+        SocketError::SocketErr(e)
+    }
+}
+
+impl fmt::Display for SocketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SystemErr(e) => write!(f, "socket service failed: {}", e),
+            Self::SocketErr(errno) => write!(f, "socket operation failed with {}", errno),
+            Self::WouldBlock => write!(f, "socket operation would block"),
+        }
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl core::error::Error for SocketError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::SystemErr(e) => Some(e),
+            Self::SocketErr(_) | Self::WouldBlock => None,
+        }
+    }
+}
+
+type Result<T> = ::core::result::Result<T, SocketError>;
+
+impl SocketError {
+    fn into_result(rv: PosixReturnValue) -> Result<()> {
+        Self::into_length(rv).map(drop)
+    }
+
+    /// Negative return values are errors, others are byte counts or descriptors.
+    fn into_length(rv: PosixReturnValue) -> Result<usize> {
+        match rv.errno() {
+            Some(Errno::EAGAIN) => Err(SocketError::WouldBlock),
+            Some(errno) => Err(SocketError::SocketErr(errno)),
+            None => Ok(rv.0 as usize),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock-ipc"))]
+mod tests {
+    use super::*;
+
+    use crate::ipc::mock::{self, Reply};
+
+    fn init() -> Soc {
+        Soc {
+            handle: unsafe { OwnedHandle::new(0x1234) }.unwrap(),
+            buffer: PageAlignedBuffer::null(),
+            buffer_handle: unsafe { OwnedHandle::new(0x5678) }.unwrap(),
+        }
+    }
+
+    #[test]
+    fn socket_is_closed_on_drop() {
+        let _mock = mock::lock();
+        let soc = init();
+
+        mock::expect(0x2, Reply::success().word(3));
+        let fd = soc
+            .socket(Domain::AfInet, Type::Stream, Protocol::Default)
+            .unwrap();
+        assert_eq!(fd.0, 3);
+
+        mock::expect(0xb, Reply::success().word(0));
+        drop(fd);
+
+        let requests = mock::take_requests();
+        assert_eq!(requests[0].parameters, [2, 1, 0]);
+        assert_eq!(requests[1].parameters, [3]);
+
+        mock::expect(0x19, Reply::success());
+    }
+
+    #[test]
+    fn negative_return_values_are_errors() {
+        let _mock = mock::lock();
+        let soc = init();
+
+        mock::expect(0x2, Reply::success().word(-(Errno::EAGAIN.0 as i32) as u32));
+        assert!(matches!(
+            soc.socket(Domain::AfInet, Type::Datagram, Protocol::Default),
+            Err(SocketError::WouldBlock)
+        ));
+
+        mock::expect(
+            0x2,
+            Reply::success().word(-(Errno::ECONNREFUSED.0 as i32) as u32),
+        );
+        assert!(matches!(
+            soc.socket(Domain::AfInet, Type::Datagram, Protocol::Default),
+            Err(SocketError::SocketErr(Errno::ECONNREFUSED))
+        ));
+
+        mock::expect(0x19, Reply::success());
+    }
+
+    #[test]
+    fn gethostid_is_in_network_byte_order() {
+        let _mock = mock::lock();
+        let soc = init();
+
+        mock::expect(
+            0x16,
+            Reply::success().word(u32::from_ne_bytes([192, 168, 0, 2])),
+        );
+        assert_eq!(soc.gethostid().unwrap(), Ipv4Addr::new(192, 168, 0, 2));
+
+        mock::expect(0x19, Reply::success());
+    }
+}
@@ -0,0 +1,163 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The TCP and UDP stacks of `embedded-nal`, implemented for `&Soc`.
+//!
+//! ```ignore
+//! let mut stack = &soc;
+//! let mut socket = TcpClientStack::socket(&mut stack)?;
+//! nb::block!(stack.connect(&mut socket, address))?;
+//! ```
+//!
+//! Operations block unless the socket was made [non-blocking](Soc::set_nonblocking).  Only IPv4
+//! addresses are supported.
+
+use super::{Domain, Errno, OwnedSocketFd, Protocol, Soc, SocketAddrV4, SocketError, Type};
+
+use core::net::{Ipv4Addr, SocketAddr};
+
+use embedded_nal::{
+    TcpClientStack, TcpError, TcpErrorKind, TcpFullStack, UdpClientStack, UdpFullStack,
+};
+
+/// Connections waiting to be accepted by a listening socket.
+const LISTEN_BACKLOG: isize = 8;
+
+fn v4(address: SocketAddr) -> Result<SocketAddrV4, SocketError> {
+    match address {
+        SocketAddr::V4(address) => Ok(address.into()),
+        SocketAddr::V6(_) => Err(SocketError::SocketErr(Errno::EAFNOSUPPORT)),
+    }
+}
+
+fn nb<T>(result: Result<T, SocketError>) -> nb::Result<T, SocketError> {
+    result.map_err(|e| match e {
+        SocketError::WouldBlock => nb::Error::WouldBlock,
+        e => nb::Error::Other(e),
+    })
+}
+
+impl TcpError for SocketError {
+    fn kind(&self) -> TcpErrorKind {
+        match self {
+            Self::SocketErr(Errno::EPIPE | Errno::ECONNRESET | Errno::ENOTCONN) => {
+                TcpErrorKind::PipeClosed
+            }
+            _ => TcpErrorKind::Other,
+        }
+    }
+}
+
+impl<'s> TcpClientStack for &'s Soc {
+    type TcpSocket = OwnedSocketFd<'s>;
+    type Error = SocketError;
+
+    fn socket(&mut self) -> Result<Self::TcpSocket, Self::Error> {
+        Soc::socket(self, Domain::AfInet, Type::Stream, Protocol::Default)
+    }
+
+    fn connect(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        remote: SocketAddr,
+    ) -> nb::Result<(), Self::Error> {
+        match Soc::connect(self, socket, v4(remote)?) {
+            // A non-blocking connection is established in the background
+            Err(SocketError::SocketErr(Errno::EINPROGRESS | Errno::EALREADY)) => {
+                Err(nb::Error::WouldBlock)
+            }
+            Err(SocketError::SocketErr(Errno::EISCONN)) => Ok(()),
+            result => nb(result),
+        }
+    }
+
+    fn send(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &[u8],
+    ) -> nb::Result<usize, Self::Error> {
+        nb(Soc::send(self, socket, buffer))
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, Self::Error> {
+        nb(Soc::recv(self, socket, buffer))
+    }
+
+    fn close(&mut self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+        socket.close()
+    }
+}
+
+impl TcpFullStack for &Soc {
+    fn bind(&mut self, socket: &mut Self::TcpSocket, local_port: u16) -> Result<(), Self::Error> {
+        Soc::bind(self, socket, (Ipv4Addr::UNSPECIFIED, local_port))
+    }
+
+    fn listen(&mut self, socket: &mut Self::TcpSocket) -> Result<(), Self::Error> {
+        Soc::listen(self, socket, LISTEN_BACKLOG)
+    }
+
+    fn accept(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+    ) -> nb::Result<(Self::TcpSocket, SocketAddr), Self::Error> {
+        let (accepted, address) = nb(Soc::accept(self, socket))?;
+
+        Ok((accepted, SocketAddr::V4(address.into())))
+    }
+}
+
+impl<'s> UdpClientStack for &'s Soc {
+    type UdpSocket = OwnedSocketFd<'s>;
+    type Error = SocketError;
+
+    fn socket(&mut self) -> Result<Self::UdpSocket, Self::Error> {
+        Soc::socket(self, Domain::AfInet, Type::Datagram, Protocol::Default)
+    }
+
+    fn connect(
+        &mut self,
+        socket: &mut Self::UdpSocket,
+        remote: SocketAddr,
+    ) -> Result<(), Self::Error> {
+        Soc::connect(self, socket, v4(remote)?)
+    }
+
+    fn send(&mut self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+        nb(Soc::send(self, socket, buffer)).map(drop)
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut Self::UdpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<(usize, SocketAddr), Self::Error> {
+        let (length, address) = nb(Soc::recvfrom(self, socket, buffer))?;
+
+        Ok((length, SocketAddr::V4(address.into())))
+    }
+
+    fn close(&mut self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+        socket.close()
+    }
+}
+
+impl UdpFullStack for &Soc {
+    fn bind(&mut self, socket: &mut Self::UdpSocket, local_port: u16) -> Result<(), Self::Error> {
+        Soc::bind(self, socket, (Ipv4Addr::UNSPECIFIED, local_port))
+    }
+
+    fn send_to(
+        &mut self,
+        socket: &mut Self::UdpSocket,
+        remote: SocketAddr,
+        buffer: &[u8],
+    ) -> nb::Result<(), Self::Error> {
+        nb(Soc::sendto(self, socket, buffer, v4(remote)?)).map(drop)
+    }
+}
@@ -5,13 +5,16 @@
 use crate::os::reslimit::LimitType;
 use crate::{
     os::{
-        mem::{MemoryOperation, MemoryPermission, QueryResult},
-        BorrowedHandle, OwnedHandle, RawHandle
+        mem::{
+            MemoryOperation, MemoryPermission, MemoryState, QueryResult, ERROR_INVALID_MEMORY_INFO,
+        },
+        BorrowedHandle, OwnedHandle, RawHandle, SystemTick, CLOSED_HANDLE,
     },
-    result::Result,
+    result::{Result, ERROR_TIMEOUT},
     sync::{ArbitrationType, ResetType},
 };
 
+#[cfg(target_arch = "arm")]
 use core::arch::asm;
 use core::{
     convert::{TryFrom, TryInto},
@@ -66,10 +69,58 @@ into_register_implicit! {
     i32 as i32,
     usize as usize,
     bool as u32,
-    MemoryPermission as u32,
     unsafe extern "C" fn(usize) as u32,
 }
 
+/// A 64-bit value passed in two registers, marked `#[split]` in calls to `svc!`.
+pub trait IntoRegisterPair {
+    /// The low and high half of the value.
+    unsafe fn into_register_pair(self) -> (u32, u32);
+}
+
+/// A 64-bit value returned in two registers, marked `#[split]` in calls to `svc!`.
+pub trait FromRegisterPair {
+    unsafe fn from_register_pair(low: u32, high: u32) -> Self;
+}
+
+impl IntoRegisterPair for u64 {
+    unsafe fn into_register_pair(self) -> (u32, u32) {
+        (self as u32, (self >> 32) as u32)
+    }
+}
+
+impl IntoRegisterPair for i64 {
+    unsafe fn into_register_pair(self) -> (u32, u32) {
+        (self as u64).into_register_pair()
+    }
+}
+
+impl IntoRegisterPair for Timeout {
+    unsafe fn into_register_pair(self) -> (u32, u32) {
+        (self.reg_low(), self.reg_high())
+    }
+}
+
+impl FromRegisterPair for u64 {
+    unsafe fn from_register_pair(low: u32, high: u32) -> Self {
+        u64::from(high) << 32 | u64::from(low)
+    }
+}
+
+impl FromRegisterPair for i64 {
+    unsafe fn from_register_pair(low: u32, high: u32) -> Self {
+        u64::from_register_pair(low, high) as i64
+    }
+}
+
+impl IntoRegister for MemoryPermission {
+    type Register = u32;
+
+    unsafe fn into_register(self) -> u32 {
+        self.bits()
+    }
+}
+
 impl<T> IntoRegister for *mut T {
     type Register = *mut T;
     unsafe fn into_register(self) -> *mut T {
@@ -100,9 +151,8 @@ pub unsafe fn query_memory(addr: usize) -> Result<QueryResult> {
     let (base_process_virtual_address, size, permission, state, page_flags) =
         svc!(0x02: (_, _, addr) -> (usize, usize, u32, u32, u32))?;
 
-    // TODO: yeah, let's hope the kernel always returns valid values here
-    let permission = core::mem::transmute(permission);
-    let state = core::mem::transmute(state);
+    let permission = MemoryPermission::try_from(permission)?;
+    let state = MemoryState::try_from(state).map_err(|_| ERROR_INVALID_MEMORY_INFO)?;
 
     Ok(QueryResult {
         base_process_virtual_address,
@@ -135,10 +185,8 @@ pub fn exit_thread() -> ! {
 
 /// Pause the current thread for the specified duration.
 pub fn sleep_thread(duration: Timeout) {
-    let (ns_high, ns_low) = (duration.reg_high(), duration.reg_low());
-
     unsafe {
-        let _ = svc!(0x0a: (ns_low, ns_high));
+        let _ = svc!(0x0a: (#[split] duration));
     }
 }
 
@@ -146,6 +194,10 @@ pub fn get_thread_priority(handle: BorrowedHandle) -> Result<i32> {
     unsafe { svc!(0x0b: (_, handle) -> i32) }
 }
 
+pub fn set_thread_priority(handle: BorrowedHandle, priority: i32) -> Result<()> {
+    unsafe { svc!(0x0c: (handle, priority)) }
+}
+
 pub fn create_mutex(initially_locked: bool) -> Result<OwnedHandle> {
     unsafe { svc!(0x13: (initially_locked) -> OwnedHandle) }
 }
@@ -201,18 +253,52 @@ pub fn arbitrate_address(
     value: i32,
     timeout: Timeout,
 ) -> Result<()> {
-    let (ns_low, ns_high) = (timeout.reg_low(), timeout.reg_high());
-    unsafe { svc!(0x22: (handle, address, arbitration_type, value, ns_low, ns_high)) }
+    unsafe { svc!(0x22: (handle, address, arbitration_type, value, #[split] timeout)) }
 }
 
+#[cfg(target_arch = "arm")]
 pub unsafe fn close_handle(handle: RawHandle) -> Result<()> {
     svc!(0x23: (handle))
 }
 
-pub fn wait_synchronization(handle: BorrowedHandle, timeout: Timeout) -> Result<()> {
-    let (ns_high, ns_low) = (timeout.reg_high(), timeout.reg_low());
+/// Off the 3DS, handles only come from scripted IPC replies, so there is nothing to close.
+#[cfg(not(target_arch = "arm"))]
+pub unsafe fn close_handle(_handle: RawHandle) -> Result<()> {
+    Ok(())
+}
+
+/// How a wait for a synchronization object ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WaitOutcome {
+    Signaled,
+    TimedOut,
+}
 
-    unsafe { svc!(0x24: (handle, _, ns_high, ns_low)) }
+impl WaitOutcome {
+    pub const fn is_signaled(self) -> bool {
+        matches!(self, Self::Signaled)
+    }
+
+    pub const fn is_timed_out(self) -> bool {
+        matches!(self, Self::TimedOut)
+    }
+}
+
+/// Wait until `handle` is signaled, or `timeout` expires.
+pub fn wait_synchronization(handle: BorrowedHandle, timeout: Timeout) -> Result<WaitOutcome> {
+    match unsafe { svc!(0x24: (handle, _, #[split] timeout)) } {
+        Ok(()) => Ok(WaitOutcome::Signaled),
+        Err(e) if e == ERROR_TIMEOUT => Ok(WaitOutcome::TimedOut),
+        Err(e) => Err(e),
+    }
+}
+
+/// Wait until `handle` is signaled, or the system tick counter reaches `deadline`.
+pub fn wait_synchronization_deadline(
+    handle: BorrowedHandle,
+    deadline: SystemTick,
+) -> Result<WaitOutcome> {
+    wait_synchronization(handle, Timeout::until(deadline))
 }
 
 pub fn wait_synchronization_many(
@@ -220,7 +306,8 @@ pub fn wait_synchronization_many(
     wait_all: bool,
     timeout: Timeout,
 ) -> Result<i32> {
-    let (ns_high, ns_low) = (timeout.reg_high(), timeout.reg_low());
+    // The halves are not in adjacent registers, which `#[split]` can not express
+    let (ns_low, ns_high) = unsafe { timeout.into_register_pair() };
     let num_handles = handles.len();
     let handles: *const BorrowedHandle = handles.as_ptr();
 
@@ -239,7 +326,7 @@ pub fn wait_synchronization_all(handles: &[BorrowedHandle], timeout: Timeout) ->
 }
 
 pub fn wait_synchronization_any(handles: &[BorrowedHandle], timeout: Timeout) -> Result<usize> {
-    let signaled = wait_synchronization_many(handles, WAIT_ALL, timeout)?;
+    let signaled = wait_synchronization_many(handles, WAIT_FIRST, timeout)?;
 
     match usize::try_from(signaled) {
         Ok(index) => Ok(index),
@@ -257,18 +344,23 @@ pub fn duplicate_handle(handle: BorrowedHandle) -> Result<OwnedHandle> {
 }
 
 pub fn get_system_tick_count() -> u64 {
-    let tick_low: u32;
-    let tick_high: u32;
+    #[cfg(target_arch = "arm")]
+    {
+        let tick_low: u32;
+        let tick_high: u32;
+        unsafe {
+            asm!("svc 0x28", lateout("r0") tick_high, lateout("r1") tick_low);
+        }
+        (tick_high as u64) << 32 | tick_low as u64
+    }
+    #[cfg(not(target_arch = "arm"))]
     unsafe {
-        asm!("svc 0x28", lateout("r0") tick_high, lateout("r1") tick_low);
+        unsupported(0x28)
     }
-    (tick_high as u64) << 32 | tick_low as u64
 }
 
 pub unsafe fn get_system_info(sysinfo_type: u32, param: i32) -> Result<i64> {
-    let (out_low, out_high) = svc!(0x2a: (_, sysinfo_type, param) -> (u32, u32))?;
-
-    Ok(((out_high as i64) << 32) | out_low as i64)
+    svc!(0x2a: (_, sysinfo_type, param) -> #[split] i64)
 }
 
 pub fn connect_to_port(port_name: &str) -> Result<OwnedHandle> {
@@ -277,12 +369,20 @@ pub fn connect_to_port(port_name: &str) -> Result<OwnedHandle> {
     unsafe { svc!(0x2d: (_, port_name) -> OwnedHandle) }
 }
 
+#[cfg(not(feature = "mock-ipc"))]
 #[inline]
 pub unsafe fn send_sync_request(handle: BorrowedHandle, command_buffer: *mut u32) -> Result<*mut u32> {
     svc!(0x32: (handle))?;
     Ok(command_buffer)
 }
 
+#[cfg(feature = "mock-ipc")]
+pub use crate::ipc::mock::send_sync_request;
+
+pub fn open_process(process_id: u32) -> Result<OwnedHandle> {
+    unsafe { svc!(0x33: (_, process_id) -> OwnedHandle) }
+}
+
 pub fn get_process_id(process_handle: BorrowedHandle) -> Result<u32> {
     unsafe { svc!(0x35: (_, process_handle) -> u32) }
 }
@@ -332,6 +432,10 @@ pub enum UserBreakReason {
     UnloadRo = 4,
 }
 
+pub fn terminate_process(process_handle: BorrowedHandle) -> Result<()> {
+    unsafe { svc!(0x76: (process_handle)) }
+}
+
 pub fn user_break(reason: UserBreakReason) -> ! {
     let reason = reason as u32;
     unsafe { svc!(0x3c: (reason) -> !) }
@@ -341,6 +445,16 @@ pub fn user_break(reason: UserBreakReason) -> ! {
     }
 }
 
+/// Like [`user_break`], but an attached debugger may resume execution.
+///
+/// Without a debugger, the process is terminated.
+pub fn user_break_resumable(reason: UserBreakReason) {
+    let reason = reason as u32;
+    unsafe {
+        let _ = svc!(0x3c: (reason));
+    }
+}
+
 #[inline(always)]
 #[doc(hidden)]
 pub fn output_debug_bytes(bytes: &[u8]) {
@@ -355,8 +469,37 @@ pub fn output_debug_string(message: &str) {
     output_debug_bytes(message.as_bytes())
 }
 
+/// Reply to the last request received on `reply_target` and wait for the next one on `handles`.
+///
+/// Returns the index into `handles` of the object that was signaled or received a request.
+/// Pass `None` as `reply_target` when there is no pending request to reply to, e.g. on the first
+/// iteration of a server loop.
+pub fn reply_and_receive(
+    handles: &[BorrowedHandle],
+    reply_target: Option<BorrowedHandle>,
+) -> Result<usize> {
+    let num_handles = handles.len();
+    let handles: *const BorrowedHandle = handles.as_ptr();
+    let reply_target = reply_target.map_or(CLOSED_HANDLE, |target| target.handle);
+
+    let index = unsafe { svc!(0x4f: (_, handles, num_handles, reply_target) -> usize) }?;
+
+    Ok(index)
+}
+
 pub fn stop_point() {
-    unsafe { asm!("svc 0xff") }
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        asm!("svc 0xff")
+    }
+}
+
+/// Stands in for the SVCs issued by `svc!` on targets other than the 3DS.
+#[cfg(not(target_arch = "arm"))]
+#[doc(hidden)]
+#[track_caller]
+pub unsafe fn unsupported<T>(svc_num: u8) -> T {
+    unimplemented!("SVC {:#04x} is only available on the 3DS", svc_num)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -379,6 +522,22 @@ impl Timeout {
         Self::from_nanoseconds(0)
     }
 
+    /// The time left until the system tick counter reaches `deadline`, none if it has passed.
+    pub fn until(deadline: SystemTick) -> Self {
+        let ticks = deadline.count().saturating_sub(SystemTick::now().count());
+        Self::from(crate::os::duration_from_ticks(ticks))
+    }
+
+    /// The timeout in milliseconds rounded up, or `-1` if it is negative or does not fit.
+    pub(crate) fn milliseconds(self) -> i32 {
+        match self.0 {
+            ..=-1 => -1,
+            nanoseconds => ((nanoseconds as u64).div_ceil(1_000_000))
+                .try_into()
+                .unwrap_or(-1),
+        }
+    }
+
     #[inline]
     pub(crate) const fn reg_high(self) -> u32 {
         ((self.0 as u64) >> 32) as u32
@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Wait and wake operations on 32-bit words, backed by the process-wide address arbiter.
+//!
+//! These are the building blocks of the locks in [`sync`](super).  A thread waits while a word
+//! holds some value, and whoever changes the word wakes it up:
+//!
+//! ```ignore
+//! static READY: AtomicI32 = AtomicI32::new(0);
+//!
+//! // Waiting thread
+//! while READY.load(Ordering::Acquire) == 0 {
+//!     futex::wait_if_less_than(&READY, 1)?;
+//! }
+//!
+//! // Signaling thread
+//! READY.store(1, Ordering::Release);
+//! futex::wake_all(&READY)?;
+//! ```
+//!
+//! Like all futexes, waits can return spuriously, so the word must be checked again after each.
+
+use crate::result::{Result, ERROR_TIMEOUT};
+use crate::svc::{Timeout, WaitOutcome};
+
+use core::sync::atomic::AtomicI32;
+
+use super::ArbitrationType;
+
+/// Put the current thread to sleep if `*word < value`.
+///
+/// The comparison and going to sleep happen atomically with respect to [`wake`].
+pub fn wait_if_less_than(word: &AtomicI32, value: i32) -> Result<()> {
+    arbitrate(
+        word,
+        ArbitrationType::WaitIfLessThan,
+        value,
+        Timeout::none(),
+    )
+}
+
+/// Like [`wait_if_less_than`], but wake up after `timeout` if not woken before.
+pub fn wait_timeout(word: &AtomicI32, value: i32, timeout: Timeout) -> Result<WaitOutcome> {
+    let result = arbitrate(word, ArbitrationType::WaitIfLessThanTimeout, value, timeout);
+
+    match result {
+        Ok(()) => Ok(WaitOutcome::Signaled),
+        Err(e) if e == ERROR_TIMEOUT => Ok(WaitOutcome::TimedOut),
+        Err(e) => Err(e),
+    }
+}
+
+/// Wake up at most `waiters` threads sleeping on `word`.
+pub fn wake(word: &AtomicI32, waiters: usize) -> Result<()> {
+    let waiters = i32::try_from(waiters).unwrap_or(i32::MAX);
+    arbitrate(word, ArbitrationType::Signal, waiters, Timeout::none())
+}
+
+/// Wake up all threads sleeping on `word`.
+pub fn wake_all(word: &AtomicI32) -> Result<()> {
+    arbitrate(word, ArbitrationType::Signal, -1, Timeout::none())
+}
+
+#[cfg(not(all(test, not(target_arch = "arm"))))]
+fn arbitrate(
+    word: &AtomicI32,
+    arbitration_type: ArbitrationType,
+    value: i32,
+    timeout: Timeout,
+) -> Result<()> {
+    super::ARBITER.arbitrate(word, arbitration_type, value, timeout)
+}
+
+/// Stands in for the address arbiter in tests on the host.
+///
+/// All words share one condition variable, so every wake up wakes all waiters.
+#[cfg(all(test, not(target_arch = "arm")))]
+fn arbitrate(
+    word: &AtomicI32,
+    arbitration_type: ArbitrationType,
+    value: i32,
+    timeout: Timeout,
+) -> Result<()> {
+    use core::sync::atomic::Ordering;
+    use std::sync::{Condvar, Mutex};
+    use std::time::Duration;
+
+    static LOCK: Mutex<()> = Mutex::new(());
+    static WOKEN: Condvar = Condvar::new();
+
+    let guard = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let ArbitrationType::Signal = arbitration_type {
+        WOKEN.notify_all();
+        return Ok(());
+    }
+
+    if word.load(Ordering::SeqCst) < value {
+        match arbitration_type {
+            ArbitrationType::WaitIfLessThanTimeout if timeout.milliseconds() >= 0 => {
+                let timeout = Duration::from_millis(timeout.milliseconds() as u64);
+                let _ = WOKEN.wait_timeout(guard, timeout);
+            }
+            _ => drop(WOKEN.wait(guard)),
+        }
+    }
+
+    Ok(())
+}
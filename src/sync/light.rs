@@ -0,0 +1,217 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Synchronization primitives that do not consume any kernel handles.
+//!
+//! Both [`LightLock`] and [`LightEvent`] are plain 32-bit words that threads sleep on using the
+//! process-wide address arbiter.  They can be created in `const` contexts and are cheap enough to
+//! be used for fine-grained locking.
+
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use lock_api::{GuardSend, RawMutex};
+
+use super::{futex, ResetType};
+use crate::result::Result;
+
+pub type LightMutex<T> = lock_api::Mutex<LightLock, T>;
+pub type LightMutexGuard<'a, T> = lock_api::MutexGuard<'a, LightLock, T>;
+
+/// A mutex backed by the address arbiter.
+///
+/// A positive value `n` means the lock is free and `n - 1` threads are waiting for it, a negative
+/// value `-n` means it is held and `n - 1` threads are waiting.  Zero is treated as "free, no
+/// waiters".
+#[derive(Debug)]
+pub struct LightLock {
+    state: AtomicI32,
+}
+
+impl LightLock {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicI32::new(1),
+        }
+    }
+
+    #[inline]
+    fn normalize(state: i32) -> i32 {
+        if state == 0 {
+            1
+        } else {
+            state
+        }
+    }
+}
+
+impl Default for LightLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl RawMutex for LightLock {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self::new();
+
+    type GuardMarker = GuardSend;
+
+    fn lock(&self) {
+        // Either take the lock, or register as a waiter.
+        let previous = self
+            .state
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                let state = Self::normalize(state);
+                Some(if state < 0 { state - 1 } else { -state })
+            })
+            .map(Self::normalize)
+            .unwrap_or_else(|state| state);
+
+        if previous > 0 {
+            return;
+        }
+
+        loop {
+            futex::wait_if_less_than(&self.state, 0).expect("Failed to wait for light lock");
+
+            // Take the lock and stop being a waiter at the same time.
+            let acquired = self
+                .state
+                .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                    let state = Self::normalize(state);
+                    if state < 0 {
+                        None
+                    } else {
+                        Some(-(state - 1))
+                    }
+                })
+                .is_ok();
+
+            if acquired {
+                return;
+            }
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        self.state
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                let state = Self::normalize(state);
+                if state < 0 {
+                    None
+                } else {
+                    Some(-state)
+                }
+            })
+            .is_ok()
+    }
+
+    unsafe fn unlock(&self) {
+        let previous = self
+            .state
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |state| Some(-state))
+            .unwrap_or_else(|state| state);
+
+        let waiters = -previous - 1;
+        if waiters > 0 {
+            futex::wake(&self.state, 1).expect("Failed to wake up light lock waiter")
+        }
+    }
+
+    fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) < 0
+    }
+}
+
+const CLEARED_STICKY: i32 = -2;
+const CLEARED_ONESHOT: i32 = -1;
+const SIGNALED_ONESHOT: i32 = 0;
+const SIGNALED_STICKY: i32 = 1;
+
+/// An event backed by the address arbiter.
+///
+/// Only [`ResetType::OneShot`] and [`ResetType::Sticky`] events are supported.
+#[derive(Debug)]
+pub struct LightEvent {
+    state: AtomicI32,
+    lock: LightLock,
+}
+
+impl LightEvent {
+    pub const fn new(reset_type: ResetType) -> Self {
+        let state = match reset_type {
+            ResetType::OneShot => CLEARED_ONESHOT,
+            ResetType::Sticky => CLEARED_STICKY,
+            ResetType::Pulse => panic!("Light events cannot be pulse events"),
+        };
+
+        Self {
+            state: AtomicI32::new(state),
+            lock: LightLock::new(),
+        }
+    }
+
+    /// Signal the event.
+    ///
+    /// A one-shot event wakes up a single waiter, a sticky event wakes up all waiters and stays
+    /// signaled until it is [cleared](Self::clear).
+    pub fn signal(&self) -> Result<()> {
+        match self.state.load(Ordering::Acquire) {
+            CLEARED_ONESHOT => {
+                self.state.store(SIGNALED_ONESHOT, Ordering::Release);
+                futex::wake(&self.state, 1)
+            }
+            CLEARED_STICKY => {
+                self.lock.lock();
+                self.state.store(SIGNALED_STICKY, Ordering::Release);
+                let result = futex::wake_all(&self.state);
+                unsafe { self.lock.unlock() };
+                result
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn clear(&self) {
+        match self.state.load(Ordering::Acquire) {
+            SIGNALED_STICKY => {
+                self.lock.lock();
+                self.state.store(CLEARED_STICKY, Ordering::Release);
+                unsafe { self.lock.unlock() };
+            }
+            SIGNALED_ONESHOT => self.state.store(CLEARED_ONESHOT, Ordering::Release),
+            _ => {}
+        }
+    }
+
+    /// Consume a pending signal of a one-shot event.
+    fn try_reset(&self) -> bool {
+        self.state
+            .compare_exchange(
+                SIGNALED_ONESHOT,
+                CLEARED_ONESHOT,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    /// Check whether the event is signaled without blocking.
+    ///
+    /// A signaled one-shot event is cleared by this call.
+    pub fn try_wait(&self) -> bool {
+        self.state.load(Ordering::Acquire) == SIGNALED_STICKY || self.try_reset()
+    }
+
+    /// Block until the event is signaled.
+    pub fn wait(&self) -> Result<()> {
+        loop {
+            match self.state.load(Ordering::Acquire) {
+                SIGNALED_STICKY => return Ok(()),
+                SIGNALED_ONESHOT if self.try_reset() => return Ok(()),
+                _ => futex::wait_if_less_than(&self.state, SIGNALED_ONESHOT)?,
+            }
+        }
+    }
+}
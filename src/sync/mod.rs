@@ -3,13 +3,24 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::os::{AsHandle, BorrowedHandle, OwnedHandle, RawHandle, SystemTick, CLOSED_HANDLE};
-use crate::result::Result;
-use crate::svc::{self, Timeout};
+use crate::result::{CommonDescription, ErrorCode, Level, Module, Result, Summary};
+use crate::svc::{self, Timeout, WaitOutcome};
 
 use core::sync::atomic::{AtomicU32, Ordering};
 
 use lock_api::{GuardNoSend, RawMutex, RawMutexTimed};
 
+use ::spin::Lazy;
+
+pub mod futex;
+mod light;
+mod once;
+mod rwlock;
+
+pub use light::{LightEvent, LightLock, LightMutex, LightMutexGuard};
+pub use once::{LazyLock, Once, OnceCell};
+pub use rwlock::{OsRwLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
 #[repr(u32)]
 #[derive(Debug)]
 pub enum ResetType {
@@ -34,10 +45,16 @@ impl Event {
         Self { handle }
     }
 
-    pub fn wait(&self, timeout: Timeout) -> Result<()> {
+    /// Wait until the event is signaled, or `timeout` expires.
+    pub fn wait(&self, timeout: Timeout) -> Result<WaitOutcome> {
         svc::wait_synchronization(self.as_handle(), timeout)
     }
 
+    /// Wait until the event is signaled, or the system tick counter reaches `deadline`.
+    pub fn wait_deadline(&self, deadline: SystemTick) -> Result<WaitOutcome> {
+        svc::wait_synchronization_deadline(self.as_handle(), deadline)
+    }
+
     pub fn clear(&self) -> Result<()> {
         svc::clear_event(self.as_handle())
     }
@@ -47,20 +64,15 @@ impl Event {
     }
 
     pub fn wait_all(events: &[Self], timeout: Timeout) -> Result<()> {
-        // SAFETY: Both `Event` and `WeakHandle` have the same layout.
-        // Therefore it is safe to transmute &[Event] into &[WeakHandle].
-        //
-        // There are the following #[repr(transparent)]-chains:
-        // * Event -> Handle -> Option<NonZeroU32> (-> u32, niche optimization)
-        // * WeakHandle -> u32
-        let handles: &[BorrowedHandle] = unsafe { core::mem::transmute(events) };
-        svc::wait_synchronization_all(handles, timeout)
+        with_wait_handles(events.iter().map(Event::as_handle), |handles| {
+            svc::wait_synchronization_all(handles, timeout)
+        })
     }
 
     pub fn wait_any(events: &[Self], timeout: Timeout) -> Result<usize> {
-        // SAFETY: See comment in Self::wait_all.
-        let handles: &[BorrowedHandle] = unsafe { core::mem::transmute(events) };
-        svc::wait_synchronization_any(handles, timeout)
+        with_wait_handles(events.iter().map(Event::as_handle), |handles| {
+            svc::wait_synchronization_any(handles, timeout)
+        })
     }
 
     pub fn duplicate(&self) -> Result<Self> {
@@ -75,6 +87,77 @@ impl AsHandle for Event {
     }
 }
 
+impl AsWaitHandle for Event {
+    fn as_wait_handle(&self) -> BorrowedHandle<'_> {
+        self.as_handle()
+    }
+}
+
+/// Kernel objects that a thread can wait on until they are signaled.
+pub trait AsWaitHandle {
+    fn as_wait_handle(&self) -> BorrowedHandle<'_>;
+}
+
+/// Raw handles to waitable objects that have no dedicated type, such as server ports or timers.
+impl AsWaitHandle for OwnedHandle {
+    fn as_wait_handle(&self) -> BorrowedHandle<'_> {
+        self.as_handle()
+    }
+}
+
+impl<T: AsWaitHandle + ?Sized> AsWaitHandle for &T {
+    fn as_wait_handle(&self) -> BorrowedHandle<'_> {
+        (**self).as_wait_handle()
+    }
+}
+
+/// The most objects a single call to [`wait_any`] or [`wait_all`] can wait on.
+pub const MAX_WAIT_OBJECTS: usize = 64;
+
+/// Returned when waiting on more than [`MAX_WAIT_OBJECTS`] objects at once.
+pub const ERROR_TOO_MANY_WAIT_OBJECTS: ErrorCode = ErrorCode::new(
+    Level::Usage,
+    Summary::WrongArgument,
+    Module::Application,
+    CommonDescription::OutOfRange.to_value(),
+);
+
+/// Collect `handles` into a buffer on the stack and pass them to `wait`.
+fn with_wait_handles<'a, R>(
+    handles: impl ExactSizeIterator<Item = BorrowedHandle<'a>>,
+    wait: impl FnOnce(&[BorrowedHandle<'a>]) -> Result<R>,
+) -> Result<R> {
+    let count = handles.len();
+    if count > MAX_WAIT_OBJECTS {
+        return Err(ERROR_TOO_MANY_WAIT_OBJECTS);
+    }
+
+    let mut buffer = [BorrowedHandle::active_thread(); MAX_WAIT_OBJECTS];
+    for (slot, handle) in buffer.iter_mut().zip(handles) {
+        *slot = handle;
+    }
+
+    wait(&buffer[..count])
+}
+
+/// Wait until any of `objects` is signaled, and return its index.
+///
+/// Fails with [`ERROR_TOO_MANY_WAIT_OBJECTS`] if there are more than [`MAX_WAIT_OBJECTS`].
+pub fn wait_any(objects: &[&dyn AsWaitHandle], timeout: Timeout) -> Result<usize> {
+    with_wait_handles(objects.iter().map(|o| o.as_wait_handle()), |handles| {
+        svc::wait_synchronization_any(handles, timeout)
+    })
+}
+
+/// Wait until all of `objects` are signaled.
+///
+/// Fails with [`ERROR_TOO_MANY_WAIT_OBJECTS`] if there are more than [`MAX_WAIT_OBJECTS`].
+pub fn wait_all(objects: &[&dyn AsWaitHandle], timeout: Timeout) -> Result<()> {
+    with_wait_handles(objects.iter().map(|o| o.as_wait_handle()), |handles| {
+        svc::wait_synchronization_all(handles, timeout)
+    })
+}
+
 #[derive(Debug)]
 struct AtomicHandle(AtomicU32);
 
@@ -161,9 +244,8 @@ impl OsMutex {
         }
     }
 
-    pub unsafe fn lock(&self, timeout: Timeout) -> Result<()> {
-        svc::wait_synchronization(self.handle.as_handle(), timeout)?;
-        Ok(())
+    pub unsafe fn lock(&self, timeout: Timeout) -> Result<WaitOutcome> {
+        svc::wait_synchronization(self.handle.as_handle(), timeout)
     }
 
     pub unsafe fn unlock(&self) -> Result<()> {
@@ -196,13 +278,13 @@ unsafe impl RawMutex for OsMutex {
 
     fn lock(&self) {
         let handle = self.get();
-        svc::wait_synchronization(handle, Timeout::forever())
-            .expect("Failed to lock mutex with infinite timeout")
+        let _ = svc::wait_synchronization(handle, Timeout::forever())
+            .expect("Failed to lock mutex with infinite timeout");
     }
 
     fn try_lock(&self) -> bool {
         let handle = self.get();
-        svc::wait_synchronization(handle, Timeout::none()).is_ok()
+        svc::wait_synchronization(handle, Timeout::none()).is_ok_and(WaitOutcome::is_signaled)
     }
 
     unsafe fn unlock(&self) {
@@ -218,13 +300,12 @@ unsafe impl RawMutexTimed for OsMutex {
 
     fn try_lock_for(&self, timeout: Self::Duration) -> bool {
         let handle = self.get();
-        svc::wait_synchronization(handle, timeout).is_ok()
+        svc::wait_synchronization(handle, timeout).is_ok_and(WaitOutcome::is_signaled)
     }
 
     fn try_lock_until(&self, deadline: Self::Instant) -> bool {
-        let now = SystemTick::now();
-        let timeout = Timeout::from_nanoseconds((deadline.count() - now.count()).max(0) as i64);
-        self.try_lock_for(timeout)
+        let handle = self.get();
+        svc::wait_synchronization_deadline(handle, deadline).is_ok_and(WaitOutcome::is_signaled)
     }
 }
 
@@ -273,102 +354,51 @@ impl AddressArbiter {
             timeout,
         )
     }
-
-    fn wake_up<T: Sized>(&self, address: &T, num_waiters: usize, timeout: Timeout) -> Result<()> {
-        self.arbitrate(
-            address,
-            ArbitrationType::Signal,
-            num_waiters as i32,
-            timeout,
-        )
-    }
-
-    fn wake_up_all<T: Sized>(&self, address: &mut T, timeout: Timeout) -> Result<()> {
-        self.arbitrate(address, ArbitrationType::Signal, -1, timeout)
-    }
-
-    fn wait_if_less_than<T: Ord + Sized + Into<i32>>(&self, address: &T, value: T) -> Result<()> {
-        self.arbitrate(
-            address,
-            ArbitrationType::WaitIfLessThan,
-            value.into(),
-            Timeout::none(),
-        )
-    }
 }
 
+static ARBITER: Lazy<AddressArbiter> =
+    Lazy::new(move || AddressArbiter::new().expect("Could not initialize address arbiter"));
+
 pub mod spin {
     use crate::result::Result;
-    use crate::svc::Timeout;
 
-    use ::spin::{Lazy, RwLock};
+    use core::sync::atomic::{AtomicI32, Ordering};
 
-    use super::AddressArbiter;
-
-    static ARBITER: Lazy<AddressArbiter> =
-        Lazy::new(move || AddressArbiter::new().expect("Could not initialize address arbiter"));
-
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
-    #[repr(i32)]
-    enum State {
-        Cleared = 0,
-        Signaled = 1,
-    }
+    use super::futex;
 
-    impl From<State> for i32 {
-        fn from(state: State) -> Self {
-            state as i32
-        }
-    }
+    const CLEARED: i32 = 0;
+    const SIGNALED: i32 = 1;
 
     #[derive(Debug)]
-    pub struct StickyEvent(RwLock<State>);
+    pub struct StickyEvent(AtomicI32);
 
     impl StickyEvent {
         pub const fn new() -> Self {
-            Self(RwLock::new(State::Cleared))
+            Self(AtomicI32::new(CLEARED))
         }
 
         pub fn signal(&self) -> Result<()> {
-            let lock = self.0.upgradeable_read();
-            match *lock {
-                State::Signaled => Ok(()),
-                State::Cleared => {
-                    let mut lock = lock.upgrade();
-                    let state = &mut *lock;
-                    *state = State::Signaled;
-                    ARBITER.wake_up_all(state, Timeout::forever())?;
-                    Ok(())
-                }
+            match self.0.swap(SIGNALED, Ordering::Release) {
+                CLEARED => futex::wake_all(&self.0),
+                _ => Ok(()),
             }
         }
 
         pub fn clear(&self) {
-            let lock = self.0.upgradeable_read();
-            match *lock {
-                State::Cleared => {}
-                State::Signaled => {
-                    *lock.upgrade() = State::Cleared;
-                }
-            }
+            self.0.store(CLEARED, Ordering::Release)
         }
 
         pub fn wait(&self) {
-            let state = &*self.0.read();
-            match state {
-                State::Cleared => {
-                    ARBITER.wait_if_less_than(state, State::Signaled).ok();
-                }
-                State::Signaled => {}
+            while self.0.load(Ordering::Acquire) == CLEARED {
+                futex::wait_if_less_than(&self.0, SIGNALED)
+                    .expect("Failed to wait for sticky event");
             }
         }
 
         pub fn try_wait(&self) -> core::result::Result<(), StickyEventClearedError> {
-            let state = self.0.read();
-
-            match *state {
-                State::Cleared => Err(StickyEventClearedError),
-                State::Signaled => Ok(()),
+            match self.0.load(Ordering::Acquire) {
+                CLEARED => Err(StickyEventClearedError),
+                _ => Ok(()),
             }
         }
     }
@@ -0,0 +1,353 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use super::futex;
+
+// Waiting threads sleep while the state is less than `INCOMPLETE`, i.e. only while an initializer
+// is running.
+const RUNNING: i32 = 0;
+const INCOMPLETE: i32 = 1;
+const COMPLETE: i32 = 2;
+
+/// Run a one-time initialization routine.
+///
+/// Threads that call [`Once::call_once`] while another thread runs the initializer are put to
+/// sleep on the address arbiter until it finishes.
+pub struct Once {
+    state: AtomicI32,
+}
+
+/// Resets a [`Once`] if its initializer does not return, so that waiting threads can retry.
+struct CompletionGuard<'a> {
+    state: &'a AtomicI32,
+    set_to: i32,
+}
+
+impl Drop for CompletionGuard<'_> {
+    fn drop(&mut self) {
+        self.state.store(self.set_to, Ordering::Release);
+        futex::wake_all(self.state).expect("Failed to wake up threads waiting for initialization")
+    }
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicI32::new(INCOMPLETE),
+        }
+    }
+
+    #[inline]
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Run `f` if no other call to `call_once` has completed yet.
+    ///
+    /// When this function returns, exactly one initializer has run to completion.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        self.call_once_fallible(|| {
+            f();
+            true
+        })
+    }
+
+    /// Like [`call_once`](Self::call_once), but `f` returning `false` does not complete the
+    /// `Once`, so the next call runs its initializer.
+    fn call_once_fallible<F: FnOnce() -> bool>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+
+        self.call_once_slow(f)
+    }
+
+    #[cold]
+    fn call_once_slow<F: FnOnce() -> bool>(&self, f: F) {
+        let mut f = Some(f);
+
+        loop {
+            match self.state.compare_exchange(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    let mut guard = CompletionGuard {
+                        state: &self.state,
+                        set_to: INCOMPLETE,
+                    };
+
+                    if f.take().is_some_and(|f| f()) {
+                        guard.set_to = COMPLETE;
+                    }
+                    return;
+                }
+                Err(COMPLETE) => return,
+                Err(_) => self.wait_while_running(),
+            }
+        }
+    }
+}
+
+impl Once {
+    /// Sleep until the initializer that is running finishes, or return spuriously.
+    fn wait_while_running(&self) {
+        futex::wait_if_less_than(&self.state, INCOMPLETE)
+            .expect("Failed to wait for initialization")
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Once {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Once")
+            .field("completed", &self.is_completed())
+            .finish()
+    }
+}
+
+/// A cell that can be written to only once, from any thread.
+pub struct OnceCell<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Send for OnceCell<T> {}
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> Self {
+        Self {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        if self.once.is_completed() {
+            // SAFETY: `value` is written exactly once, before `once` is marked as completed.
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.once.is_completed() {
+            Some(unsafe { (*self.value.get()).assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Get the contents of the cell, initializing it with `init` if it is empty.
+    ///
+    /// If several threads race to initialize the cell, only one `init` is run.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, init: F) -> &T {
+        self.once.call_once(|| unsafe {
+            (*self.value.get()).write(init());
+        });
+
+        unsafe { self.get_unchecked() }
+    }
+
+    /// Like [`get_or_init`](Self::get_or_init), but leave the cell empty if `init` fails.
+    pub fn get_or_try_init<E, F: FnOnce() -> core::result::Result<T, E>>(
+        &self,
+        init: F,
+    ) -> core::result::Result<&T, E> {
+        let mut error = None;
+        self.once.call_once_fallible(|| match init() {
+            Ok(value) => {
+                unsafe { (*self.value.get()).write(value) };
+                true
+            }
+            Err(e) => {
+                error = Some(e);
+                false
+            }
+        });
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(unsafe { self.get_unchecked() }),
+        }
+    }
+
+    /// Store `value` in the cell, or give it back if the cell was already initialized.
+    pub fn set(&self, value: T) -> core::result::Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    pub fn into_inner(mut self) -> Option<T> {
+        self.take()
+    }
+
+    pub fn take(&mut self) -> Option<T> {
+        if self.once.is_completed() {
+            self.once = Once::new();
+            Some(unsafe { (*self.value.get()).assume_init_read() })
+        } else {
+            None
+        }
+    }
+
+    unsafe fn get_unchecked(&self) -> &T {
+        (*self.value.get()).assume_init_ref()
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<T> for OnceCell<T> {
+    fn from(value: T) -> Self {
+        let cell = Self::new();
+        let _ = cell.set(value);
+        cell
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceCell").field(value).finish(),
+            None => f.write_str("OnceCell(<uninit>)"),
+        }
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            unsafe { (*self.value.get()).assume_init_drop() }
+        }
+    }
+}
+
+/// A value that is initialized on first access.
+pub struct LazyLock<T, F = fn() -> T> {
+    cell: OnceCell<T>,
+    init: Cell<Option<F>>,
+}
+
+// SAFETY: `init` is only ever accessed from within `Once::call_once`.
+unsafe impl<T: Send + Sync, F: Send> Sync for LazyLock<T, F> {}
+
+impl<T, F: FnOnce() -> T> LazyLock<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceCell::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| match this.init.take() {
+            Some(init) => init(),
+            None => panic!("LazyLock instance has previously been poisoned"),
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for LazyLock<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Self::force(self)
+    }
+}
+
+impl<T: Default> Default for LazyLock<T> {
+    fn default() -> Self {
+        Self::new(T::default)
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for LazyLock<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.cell.get() {
+            Some(value) => f.debug_tuple("LazyLock").field(value).finish(),
+            None => f.write_str("LazyLock(<uninit>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Run `f` on another thread, failing if it does not finish in time.
+    fn finishes<F: FnOnce() + Send + 'static>(f: F) {
+        let (done, finished) = mpsc::channel();
+        thread::spawn(move || {
+            f();
+            done.send(()).unwrap();
+        });
+
+        finished
+            .recv_timeout(Duration::from_secs(10))
+            .expect("A thread waiting for initialization was not woken up");
+    }
+
+    #[test]
+    fn waiting_after_failed_initializer_returns() {
+        finishes(|| {
+            let once = Once::new();
+            once.call_once_fallible(|| false);
+
+            // A thread that saw the initializer running, but went to sleep after it failed
+            once.wait_while_running();
+        });
+    }
+
+    #[test]
+    fn failed_initializer_wakes_up_waiters() {
+        finishes(|| {
+            static CELL: OnceCell<u32> = OnceCell::new();
+            let (fail, failing) = mpsc::channel();
+
+            let first = thread::spawn(move || {
+                CELL.get_or_try_init(|| Err(failing.recv().unwrap()))
+                    .copied()
+            });
+            while CELL.once.state.load(Ordering::Acquire) != RUNNING {
+                thread::yield_now();
+            }
+
+            let second = thread::spawn(|| *CELL.get_or_try_init(|| Ok::<_, ()>(1)).unwrap());
+            thread::sleep(Duration::from_millis(50));
+            fail.send(()).unwrap();
+
+            assert_eq!(first.join().unwrap(), Err(()));
+            assert_eq!(second.join().unwrap(), 1);
+        });
+    }
+}
@@ -0,0 +1,154 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use lock_api::{GuardSend, RawRwLock, RawRwLockDowngrade};
+
+use super::futex;
+
+pub type RwLock<T> = lock_api::RwLock<OsRwLock, T>;
+pub type RwLockReadGuard<'a, T> = lock_api::RwLockReadGuard<'a, OsRwLock, T>;
+pub type RwLockWriteGuard<'a, T> = lock_api::RwLockWriteGuard<'a, OsRwLock, T>;
+
+const UNLOCKED: i32 = 0;
+const WRITE_LOCKED: i32 = -1;
+const MAX_READERS: i32 = i32::MAX;
+
+/// A reader-writer lock built on the address arbiter.
+///
+/// Unlike [`OsMutex`](super::OsMutex), this lock does not occupy a kernel handle per instance, so
+/// it can be used in `static`s without any lazy initialization.
+///
+/// `state` holds the number of readers, or [`WRITE_LOCKED`] while a writer holds the lock.
+/// Readers sleep on `state` directly (waiting while it is negative).  Writers cannot express
+/// "wait while non-zero" with the arbiter, so they sleep on `writer_wakeups` instead, which is
+/// bumped every time the lock becomes free.
+///
+/// The lock prefers readers: a steady stream of readers can starve writers.
+#[derive(Debug)]
+pub struct OsRwLock {
+    state: AtomicI32,
+    writer_wakeups: AtomicI32,
+}
+
+impl OsRwLock {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicI32::new(UNLOCKED),
+            writer_wakeups: AtomicI32::new(0),
+        }
+    }
+
+    #[inline]
+    const fn next_wakeup(wakeups: i32) -> i32 {
+        // Keep the counter non-negative, so that "less than the next value" is always satisfiable.
+        if wakeups == i32::MAX {
+            0
+        } else {
+            wakeups + 1
+        }
+    }
+
+    fn wake_writer(&self) {
+        let _ = self
+            .writer_wakeups
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |wakeups| {
+                Some(Self::next_wakeup(wakeups))
+            });
+
+        futex::wake(&self.writer_wakeups, 1).expect("Failed to wake up waiting writer")
+    }
+
+    fn wake_readers(&self) {
+        futex::wake_all(&self.state).expect("Failed to wake up waiting readers")
+    }
+}
+
+impl Default for OsRwLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl RawRwLock for OsRwLock {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self::new();
+
+    type GuardMarker = GuardSend;
+
+    fn lock_shared(&self) {
+        while !self.try_lock_shared() {
+            futex::wait_if_less_than(&self.state, UNLOCKED)
+                .expect("Failed to wait for writer to release lock")
+        }
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state < UNLOCKED || state == MAX_READERS {
+                return false;
+            }
+
+            match self.state.compare_exchange_weak(
+                state,
+                state + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(updated) => state = updated,
+            }
+        }
+    }
+
+    unsafe fn unlock_shared(&self) {
+        if self.state.fetch_sub(1, Ordering::Release) == 1 {
+            self.wake_writer()
+        }
+    }
+
+    fn lock_exclusive(&self) {
+        loop {
+            let wakeups = self.writer_wakeups.load(Ordering::Acquire);
+
+            if self.try_lock_exclusive() {
+                return;
+            }
+
+            futex::wait_if_less_than(&self.writer_wakeups, Self::next_wakeup(wakeups))
+                .expect("Failed to wait for lock release")
+        }
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        self.state
+            .compare_exchange(UNLOCKED, WRITE_LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.state.store(UNLOCKED, Ordering::Release);
+
+        self.wake_readers();
+        self.wake_writer()
+    }
+
+    fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) != UNLOCKED
+    }
+
+    fn is_locked_exclusive(&self) -> bool {
+        self.state.load(Ordering::Relaxed) == WRITE_LOCKED
+    }
+}
+
+unsafe impl RawRwLockDowngrade for OsRwLock {
+    unsafe fn downgrade(&self) {
+        self.state.store(1, Ordering::Release);
+
+        self.wake_readers()
+    }
+}
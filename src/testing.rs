@@ -0,0 +1,249 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Running tests on the console or in an emulator.
+//!
+//! Functions marked with [`#[ctru_test]`](crate::ctru_test) are collected by the linker, and run
+//! by [`run`], which reports the results in the [TAP] format:
+//!
+//! ```ignore
+//! #[ctru_test]
+//! fn srv_knows_cfg() -> ctru_rt::result::Result<()> {
+//!     let srv = Srv::init()?;
+//!     Cfg::init(&srv)?;
+//!     Ok(())
+//! }
+//!
+//! #[entry(services(log))]
+//! fn main() {
+//!     testing::run_and_exit(&mut SvcDebugLog)
+//! }
+//! ```
+//!
+//! [`SvcDebugLog`](crate::debug::SvcDebugLog) writes to the host once [`link`](crate::debug::link)
+//! is connected, otherwise to the kernel's debug output, which emulators like Citra print to their
+//! log.  Reports can also be sent to a TCP socket with [`SocketOutput`].
+//!
+//! Tests must be defined in the application crate, as the linker drops unreferenced test cases of
+//! libraries.  Without the `unwind` feature, a panicking test ends the run.
+//!
+//! [TAP]: https://testanything.org/tap-version-13-specification.html
+
+use crate::env;
+use crate::services::soc::{Domain, OwnedSocketFd, Protocol, Soc, SocketError, Type};
+
+use alloc::string::String;
+use core::fmt;
+use core::net::SocketAddrV4;
+
+/// A test registered with [`#[ctru_test]`](crate::ctru_test).
+#[derive(Debug)]
+pub struct TestCase {
+    /// The path of the test function, including its module.
+    pub name: &'static str,
+    pub ignored: bool,
+    #[doc(hidden)]
+    pub function: fn() -> Result<(), Failure>,
+}
+
+impl TestCase {
+    /// Run the test, catching panics if unwinding is supported.
+    pub fn run(&self) -> Result<(), Failure> {
+        #[cfg(feature = "unwind")]
+        {
+            use crate::unwind::{catch_unwind, AssertUnwindSafe};
+
+            catch_unwind(AssertUnwindSafe(self.function)).unwrap_or_else(|payload| {
+                let message = match payload.downcast::<&'static str>() {
+                    Ok(message) => String::from(*message),
+                    Err(payload) => match payload.downcast::<String>() {
+                        Ok(message) => *message,
+                        Err(_) => String::from("Box<dyn Any>"),
+                    },
+                };
+
+                Err(Failure::new(alloc::format!("panicked: {}", message)))
+            })
+        }
+
+        #[cfg(not(feature = "unwind"))]
+        (self.function)()
+    }
+}
+
+/// Why a test failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    message: String,
+}
+
+impl Failure {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// What a test function may return.
+pub trait TestResult {
+    fn into_result(self) -> Result<(), Failure>;
+}
+
+impl TestResult for () {
+    fn into_result(self) -> Result<(), Failure> {
+        Ok(())
+    }
+}
+
+impl<E: fmt::Debug> TestResult for Result<(), E> {
+    fn into_result(self) -> Result<(), Failure> {
+        self.map_err(|e| Failure::new(alloc::format!("{:?}", e)))
+    }
+}
+
+extern "C" {
+    static __ctru_rt_tests_start: u8;
+    static __ctru_rt_tests_end: u8;
+}
+
+/// All tests of the application, in link order.
+pub fn tests() -> &'static [TestCase] {
+    unsafe {
+        let start = &__ctru_rt_tests_start as *const u8 as *const TestCase;
+        let end = &__ctru_rt_tests_end as *const u8 as *const TestCase;
+        core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+    }
+}
+
+/// The tests selected by the arguments the application was started with.
+///
+/// Each argument after the executable path selects the tests whose name contains it.  Without
+/// arguments, all tests are selected.
+pub fn selected_tests() -> impl Iterator<Item = &'static TestCase> {
+    let filters: alloc::vec::Vec<_> = env::args().skip(1).collect();
+
+    tests()
+        .iter()
+        .filter(move |test| filters.is_empty() || filters.iter().any(|f| test.name.contains(&**f)))
+}
+
+/// How many tests passed, failed or were ignored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+}
+
+impl Summary {
+    pub fn is_success(&self) -> bool {
+        self.failed == 0
+    }
+
+    /// 0 if no test failed, 1 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        if self.is_success() {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+/// Run the [selected tests](selected_tests), reporting each result to `output` as it finishes.
+///
+/// Each line of the report is written at once, so it is not interleaved with log messages.
+pub fn run(output: &mut impl fmt::Write) -> Result<Summary, fmt::Error> {
+    let tests: alloc::vec::Vec<_> = selected_tests().collect();
+    let mut summary = Summary::default();
+
+    writeln!(output, "TAP version 13")?;
+    writeln!(output, "1..{}", tests.len())?;
+
+    for (number, test) in (1..).zip(tests) {
+        if test.ignored {
+            summary.ignored += 1;
+            writeln!(output, "ok {} - {} # SKIP ignored", number, test.name)?;
+            continue;
+        }
+
+        match test.run() {
+            Ok(()) => {
+                summary.passed += 1;
+                writeln!(output, "ok {} - {}", number, test.name)?;
+            }
+            Err(failure) => {
+                summary.failed += 1;
+                writeln!(output, "not ok {} - {}", number, test.name)?;
+                for line in failure.message().lines() {
+                    writeln!(output, "# {}", line)?;
+                }
+            }
+        }
+    }
+
+    writeln!(
+        output,
+        "# passed {}, failed {}, ignored {}",
+        summary.passed, summary.failed, summary.ignored
+    )?;
+
+    Ok(summary)
+}
+
+/// [`run`] the tests, then [`exit`](crate::rt::exit) with the [exit code](Summary::exit_code).
+///
+/// Exits with code 2 if the report could not be written.
+pub fn run_and_exit(output: &mut impl fmt::Write) -> ! {
+    let code = match run(output) {
+        Ok(summary) => summary.exit_code(),
+        Err(_) => 2,
+    };
+
+    crate::rt::exit(code)
+}
+
+/// Writes a report to a TCP connection, e.g. to a CI runner listening on the host.
+pub struct SocketOutput<'s> {
+    soc: &'s Soc,
+    fd: OwnedSocketFd<'s>,
+}
+
+impl<'s> SocketOutput<'s> {
+    pub fn connect(soc: &'s Soc, address: SocketAddrV4) -> Result<Self, SocketError> {
+        let fd = soc.socket(Domain::AfInet, Type::Stream, Protocol::Default)?;
+        soc.connect(&fd, address)?;
+
+        Ok(Self { soc, fd })
+    }
+}
+
+impl fmt::Write for SocketOutput<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut bytes = s.as_bytes();
+        while !bytes.is_empty() {
+            match self.soc.send(&self.fd, bytes) {
+                Ok(sent) if sent > 0 => bytes = &bytes[sent.min(bytes.len())..],
+                _ => return Err(fmt::Error),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> fmt::Result {
+        self.write_str(&alloc::fmt::format(args))
+    }
+}
@@ -3,14 +3,26 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::early_debug;
-use crate::os::{AsHandle, OwnedHandle};
-use crate::result::Result;
-use crate::svc::{self, Timeout};
+use crate::env;
+use crate::os::reslimit::LimitType;
+use crate::os::{self, AsHandle, BorrowedHandle, OwnedHandle, Process};
+use crate::result::{self, CommonDescription, ErrorCode, Level, Module, Summary};
+use crate::services::apt;
+use crate::svc::{self, Timeout, WaitOutcome};
+use crate::sync::{futex, AsWaitHandle, LightLock, LightMutex};
+use crate::tls::{self, get_thread_local_storage};
+use crate::Services;
+
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::sync::atomic::{AtomicI32, AtomicU8, AtomicUsize, Ordering};
+use core::time::Duration;
 
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use alloc::{self, alloc::Layout};
 
-use log::debug;
+use log::{debug, warn};
 
 unsafe extern "C" fn _ctru_rt_thread_start(argument: usize) {
     early_debug!("We are in _ctru_rt_thread_start(0x{:08x})!", argument);
@@ -18,19 +30,34 @@ unsafe extern "C" fn _ctru_rt_thread_start(argument: usize) {
 
     early_debug!("Got a packet: entry_point={:p}", packet.entry_point);
 
-    (packet.entry_point)();
+    // Free the packet before running the entry point, which might not return.
+    let ThreadPacket {
+        entry_point,
+        memory,
+    } = *packet;
+
+    ThreadVars::install(Some(memory));
+
+    #[cfg(feature = "unwind")]
+    if crate::unwind::catch_unwind(crate::unwind::AssertUnwindSafe(entry_point)).is_err() {
+        exit_panicking()
+    }
+    #[cfg(not(feature = "unwind"))]
+    entry_point();
 
     svc::exit_thread();
 }
 
 struct ThreadPacket {
     entry_point: Box<dyn FnOnce()>,
+    memory: ThreadMemory<()>,
 }
 
 impl ThreadPacket {
-    pub(crate) fn new(entry_point: impl FnOnce() + Send + 'static) -> Box<Self> {
+    fn new<T>(entry_point: impl FnOnce() + Send + 'static, memory: &ThreadMemory<T>) -> Box<Self> {
         Box::new(Self {
             entry_point: Box::new(entry_point),
+            memory: memory.erase(),
         })
     }
 
@@ -43,26 +70,267 @@ impl ThreadPacket {
     }
 }
 
+/// Handed to the spawned thread to store its return value.
 #[derive(Debug)]
-struct ReturnValue<T>(*mut T);
+struct ReturnValue<T>(ThreadMemory<T>);
 
 impl<T> ReturnValue<T> {
-    fn new(ptr: *mut T) -> Self {
-        Self(ptr)
+    fn new(memory: &ThreadMemory<T>) -> Self {
+        Self(ThreadMemory { ..*memory })
     }
 
     unsafe fn store(self, return_value: T) {
-        core::ptr::write(self.0, return_value)
+        let memory = self.0;
+        core::ptr::write(memory.return_value, return_value);
+
+        if memory.state().swap(FINISHED, Ordering::AcqRel) == DETACHED {
+            // Nobody is going to join this thread.  The detacher frees its memory once it exited.
+            core::ptr::drop_in_place(memory.return_value);
+        }
     }
 }
 
 unsafe impl<T> Send for ReturnValue<T> where T: Send + 'static {}
 
+const RUNNING: u8 = 0;
+const DETACHED: u8 = 1;
+const FINISHED: u8 = 2;
+const PANICKED: u8 = 3;
+
+/// Per-thread bookkeeping, stored at the start of the thread local storage.
+#[derive(Debug)]
+#[repr(C)]
+struct ThreadVars {
+    magic: u32,
+    parker: AtomicI32,
+    memory: Option<ThreadMemory<()>>,
+}
+
+impl ThreadVars {
+    const MAGIC: u32 = u32::from_be_bytes(*b"CRT0");
+
+    fn install(memory: Option<ThreadMemory<()>>) {
+        tls::reset_exception_handler();
+        tls::init_user_slots();
+
+        let vars = get_thread_local_storage().thread_vars() as *mut ThreadVars;
+        unsafe {
+            vars.write(ThreadVars {
+                magic: Self::MAGIC,
+                parker: AtomicI32::new(EMPTY),
+                memory,
+            })
+        }
+    }
+
+    /// Get the bookkeeping of the current thread.
+    ///
+    /// Threads that were not started by this crate get theirs on first use.
+    fn current() -> &'static ThreadVars {
+        let vars = get_thread_local_storage().thread_vars() as *const ThreadVars;
+
+        unsafe {
+            if (*vars).magic != Self::MAGIC {
+                Self::install(None)
+            }
+
+            &*vars
+        }
+    }
+}
+
+const _: () = assert!(core::mem::size_of::<ThreadVars>() <= tls::THREAD_VARS_SIZE);
+
+pub(crate) fn init_main_thread() {
+    ThreadVars::install(None);
+    tls::set_exception_handler(crate::ports::errf::report_exception);
+
+    configure_main_thread_priority(env::requested_main_thread_priority());
+    MAIN_THREAD_STACK_SIZE.store(env::requested_main_thread_stack_size(), Ordering::Relaxed);
+}
+
+/// Size of the stack the entry point runs on, or 0 to keep the one set up by the loader.
+static MAIN_THREAD_STACK_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Stands in for a main thread priority that was not configured.
+const LOADER_PRIORITY: i32 = -1;
+
+/// The priority requested for the main thread, and once applied, the one it runs at.
+static MAIN_THREAD_PRIORITY: AtomicI32 = AtomicI32::new(LOADER_PRIORITY);
+
+/// Run the entry point on a stack of `stack_size` bytes allocated from the heap.
+///
+/// Called by [`#[entry]`](crate::entry) before the heap is initialized.  Without a size, the
+/// size linked into the executable's header is used.
+#[doc(hidden)]
+pub fn configure_main_thread_stack(stack_size: Option<usize>) {
+    if let Some(stack_size) = stack_size {
+        MAIN_THREAD_STACK_SIZE.store(stack_size, Ordering::Relaxed)
+    }
+}
+
+/// Run the entry point at `priority`.
+///
+/// Called by [`#[entry]`](crate::entry) before the heap is initialized.  Without a priority, the
+/// priority linked into the executable's header is used, if any.
+#[doc(hidden)]
+pub fn configure_main_thread_priority(priority: Option<i32>) {
+    if let Some(priority) = priority {
+        MAIN_THREAD_PRIORITY.store(priority, Ordering::Relaxed)
+    }
+}
+
+pub(crate) fn main_thread_priority() -> i32 {
+    MAIN_THREAD_PRIORITY.load(Ordering::Relaxed)
+}
+
+/// Switch the main thread to the configured priority, if any, and record the one it runs at.
+fn apply_main_thread_priority() {
+    let thread = BorrowedHandle::active_thread();
+    let priority = MAIN_THREAD_PRIORITY.load(Ordering::Relaxed);
+
+    if priority != LOADER_PRIORITY {
+        if svc::set_thread_priority(thread, priority).is_ok() {
+            return;
+        }
+        early_debug!("Failed to set main thread priority to {:#x}", priority);
+    }
+
+    if let Ok(current) = svc::get_thread_priority(thread) {
+        MAIN_THREAD_PRIORITY.store(current, Ordering::Relaxed);
+    }
+}
+
+/// Call `entry_point` on the configured main thread stack, at the configured priority.
+///
+/// # Safety
+///
+/// The heap must be initialized.
+#[cfg(target_arch = "arm")]
+pub(crate) unsafe fn run_main(entry_point: unsafe extern "C" fn()) {
+    apply_main_thread_priority();
+
+    let stack_size = MAIN_THREAD_STACK_SIZE.load(Ordering::Relaxed);
+    if stack_size == 0 {
+        return entry_point();
+    }
+
+    let layout = Layout::from_size_align(align_to(stack_size, 8), 8).unwrap();
+    let stack = alloc::alloc::alloc(layout);
+    if stack.is_null() {
+        alloc::alloc::handle_alloc_error(layout)
+    }
+
+    // r4 is callee-saved, so it still holds the loader's stack pointer once the entry returns.
+    core::arch::asm!(
+        "mov r4, sp",
+        "mov sp, {stack_top}",
+        "blx {entry_point}",
+        "mov sp, r4",
+        stack_top = in(reg) stack.add(layout.size()),
+        entry_point = in(reg) entry_point,
+        out("r4") _,
+        clobber_abi("C"),
+    );
+
+    alloc::alloc::dealloc(stack, layout)
+}
+
+/// Error returned when joining a thread that panicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Panicked;
+
+impl fmt::Display for Panicked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("thread panicked")
+    }
+}
+
+/// The outcome of a thread: its return value, or [`Panicked`].
+pub type Result<T> = core::result::Result<T, Panicked>;
+
+/// Terminate the current thread after a panic.
+///
+/// Call this from your `#[panic_handler]` instead of [`svc::exit_thread`]: joining a spawned
+/// thread that exited this way returns `Err(Panicked)`.
+pub fn exit_panicking() -> ! {
+    if let Some(memory) = &ThreadVars::current().memory {
+        memory.state().store(PANICKED, Ordering::Release);
+    }
+
+    svc::exit_thread()
+}
+
+/// Whether the current thread was started by [`spawn`], as opposed to the main thread.
+pub(crate) fn is_spawned() -> bool {
+    ThreadVars::current().memory.is_some()
+}
+
+const PARKED: i32 = -1;
+const EMPTY: i32 = 0;
+const NOTIFIED: i32 = 1;
+
+/// A handle to a thread, used to [unpark](Thread::unpark) it.
+#[derive(Debug, Clone)]
+pub struct Thread {
+    parker: *const AtomicI32,
+}
+
+// SAFETY: The parker lives in the thread local storage of the thread, which is accessible from
+// every thread in the process.
+unsafe impl Send for Thread {}
+unsafe impl Sync for Thread {}
+
+impl Thread {
+    /// Make the token of this thread available, waking it up if it is [parked](park).
+    ///
+    /// If the thread has exited, this has no effect beyond (at worst) a spurious wakeup of
+    /// whichever thread reuses its thread local storage.
+    pub fn unpark(&self) {
+        let parker = unsafe { &*self.parker };
+
+        if parker.swap(NOTIFIED, Ordering::Release) == PARKED {
+            futex::wake(parker, 1).expect("Failed to unpark thread")
+        }
+    }
+}
+
+/// Get a handle to the calling thread.
+pub fn current() -> Thread {
+    Thread {
+        parker: &ThreadVars::current().parker,
+    }
+}
+
+/// Block until the current thread's token is made available by [`Thread::unpark`].
+///
+/// Consumes the token if it is already available.  Like in `std`, this can return spuriously.
+pub fn park() {
+    let parker = &ThreadVars::current().parker;
+
+    // NOTIFIED => EMPTY: consume the token, EMPTY => PARKED: go to sleep.
+    if parker.fetch_sub(1, Ordering::Acquire) == NOTIFIED {
+        return;
+    }
+
+    loop {
+        futex::wait_if_less_than(parker, EMPTY).expect("Failed to park thread");
+
+        if parker
+            .compare_exchange(NOTIFIED, EMPTY, Ordering::Acquire, Ordering::Acquire)
+            .is_ok()
+        {
+            return;
+        }
+    }
+}
+
 #[derive(Debug)]
 struct ThreadMemory<T> {
     allocated: *mut u8,
     stack_top: *mut u8,
     return_value: *mut T,
+    state: *const AtomicU8,
     layout: Layout,
 }
 
@@ -75,60 +343,396 @@ impl<T> ThreadMemory<T> {
         let stack_size = layout.size();
 
         let (layout, rv_offset) = layout.extend(Layout::new::<T>()).unwrap();
+        let (layout, state_offset) = layout.extend(Layout::new::<AtomicU8>()).unwrap();
+        let layout = StackPool::pooled_layout(layout);
 
-        let allocated = unsafe { alloc::alloc::alloc(layout) };
+        let allocated = match StackPool::take(layout) {
+            Some(allocated) => allocated,
+            None => unsafe { alloc::alloc::alloc(layout) },
+        };
+        if allocated.is_null() {
+            alloc::alloc::handle_alloc_error(layout)
+        }
 
         let stack_top = unsafe { allocated.add(stack_size) };
         let return_value = unsafe { allocated.add(rv_offset) as *mut T };
+        let state = unsafe {
+            let state = allocated.add(state_offset) as *mut AtomicU8;
+            state.write(AtomicU8::new(RUNNING));
+            state as *const AtomicU8
+        };
 
         Self {
             allocated,
             stack_top,
             return_value,
+            state,
             layout,
         }
     }
 
+    fn erase(&self) -> ThreadMemory<()> {
+        ThreadMemory {
+            allocated: self.allocated,
+            stack_top: self.stack_top,
+            return_value: self.return_value as *mut (),
+            state: self.state,
+            layout: self.layout,
+        }
+    }
+
+    fn state(&self) -> &AtomicU8 {
+        unsafe { &*self.state }
+    }
+
+    /// Return this memory to the stack pool, or free it.
     unsafe fn dealloc(self) {
-        alloc::alloc::dealloc(self.allocated, self.layout)
+        if !StackPool::put(self.allocated, self.layout) {
+            alloc::alloc::dealloc(self.allocated, self.layout)
+        }
+    }
+}
+
+static DETACHED_THREADS: LightMutex<DetachedThreads> =
+    LightMutex::const_new(LightLock::new(), DetachedThreads::new());
+
+/// Threads detached while still running, whose memory is freed once they exited.
+///
+/// A thread cannot free the stack it is running on, so this happens whenever a thread is spawned
+/// or detached.
+struct DetachedThreads {
+    threads: Vec<(OwnedHandle, ThreadMemory<()>)>,
+}
+
+// SAFETY: The list owns the memory of threads that will never touch it again once they exited.
+unsafe impl Send for DetachedThreads {}
+
+impl DetachedThreads {
+    const fn new() -> Self {
+        Self {
+            threads: Vec::new(),
+        }
     }
+
+    fn push(handle: OwnedHandle, memory: ThreadMemory<()>) {
+        let mut detached = DETACHED_THREADS.lock();
+        detached.reap();
+        detached.threads.push((handle, memory));
+    }
+
+    /// Free the memory of all threads that have exited.
+    fn reap(&mut self) {
+        self.threads.retain(|(handle, memory)| {
+            let exited = svc::wait_synchronization(handle.as_handle(), Timeout::none())
+                .is_ok_and(WaitOutcome::is_signaled);
+            if exited {
+                // SAFETY: The thread exited, and dropped its return value before doing so.
+                unsafe { ThreadMemory { ..*memory }.dealloc() }
+            }
+
+            !exited
+        })
+    }
+}
+
+/// Memory of threads is pooled in multiples of this size.
+const STACK_POOL_GRANULARITY: usize = 0x400;
+const STACK_POOL_MAX_SIZE: usize = 0x10000;
+const STACK_POOL_BUCKETS: usize = STACK_POOL_MAX_SIZE / STACK_POOL_GRANULARITY;
+
+/// Maximum number of blocks pooled per size, 0 if the pool is disabled.
+static STACK_POOL_LIMIT: AtomicUsize = AtomicUsize::new(0);
+static STACK_POOL: LightMutex<StackPool> =
+    LightMutex::const_new(LightLock::new(), StackPool::new());
+
+/// Memory of joined threads kept for new threads, see [`set_stack_pool_limit`].
+///
+/// Each bucket is a list of blocks of the same size, linked through their first word.
+struct StackPool {
+    buckets: [(*mut u8, usize); STACK_POOL_BUCKETS],
+}
+
+// SAFETY: The pool owns the blocks it holds.
+unsafe impl Send for StackPool {}
+
+impl StackPool {
+    const fn new() -> Self {
+        Self {
+            buckets: [(core::ptr::null_mut(), 0); STACK_POOL_BUCKETS],
+        }
+    }
+
+    fn bucket(layout: Layout) -> Option<usize> {
+        let size = layout.size();
+        let pooled = layout.align() == 8
+            && size.is_multiple_of(STACK_POOL_GRANULARITY)
+            && (1..=STACK_POOL_MAX_SIZE).contains(&size);
+
+        pooled.then(|| size / STACK_POOL_GRANULARITY - 1)
+    }
+
+    /// Round `layout` up to the size of a bucket, if the pool is enabled.
+    fn pooled_layout(layout: Layout) -> Layout {
+        if STACK_POOL_LIMIT.load(Ordering::Relaxed) == 0 || layout.size() > STACK_POOL_MAX_SIZE {
+            return layout;
+        }
+
+        Layout::from_size_align(
+            align_to(layout.size(), STACK_POOL_GRANULARITY),
+            layout.align(),
+        )
+        .unwrap_or(layout)
+    }
+
+    fn take(layout: Layout) -> Option<*mut u8> {
+        let bucket = Self::bucket(layout)?;
+        let (head, count) = &mut STACK_POOL.lock().buckets[bucket];
+        if head.is_null() {
+            return None;
+        }
+
+        let block = *head;
+        *head = unsafe { (block as *mut *mut u8).read() };
+        *count -= 1;
+
+        Some(block)
+    }
+
+    /// Keep `block` for reuse, unless the pool for its size is full.
+    ///
+    /// # Safety
+    ///
+    /// `block` must have been allocated with `layout`, and must not be used afterwards if this
+    /// returns `true`.
+    unsafe fn put(block: *mut u8, layout: Layout) -> bool {
+        let limit = STACK_POOL_LIMIT.load(Ordering::Relaxed);
+        let bucket = match Self::bucket(layout) {
+            Some(bucket) => bucket,
+            None => return false,
+        };
+
+        let (head, count) = &mut STACK_POOL.lock().buckets[bucket];
+        if *count >= limit {
+            return false;
+        }
+
+        (block as *mut *mut u8).write(*head);
+        *head = block;
+        *count += 1;
+
+        true
+    }
+
+    /// Free blocks beyond `limit` in each bucket.
+    fn truncate(&mut self, limit: usize) {
+        for (bucket, (head, count)) in self.buckets.iter_mut().enumerate() {
+            let layout = Layout::from_size_align((bucket + 1) * STACK_POOL_GRANULARITY, 8).unwrap();
+
+            while *count > limit {
+                let block = *head;
+                unsafe {
+                    *head = (block as *mut *mut u8).read();
+                    alloc::alloc::dealloc(block, layout);
+                }
+                *count -= 1;
+            }
+        }
+    }
+}
+
+/// Keep the memory of up to `limit` joined threads of each stack size for new threads.
+///
+/// Applications that frequently spawn short-lived threads fragment the heap less this way.  Stack
+/// sizes are rounded up to a multiple of 1 KiB, and stacks larger than 64 KiB are never pooled.
+/// A `limit` of 0 disables the pool and frees all pooled memory, which is the default.
+pub fn set_stack_pool_limit(limit: usize) {
+    STACK_POOL_LIMIT.store(limit, Ordering::Relaxed);
+    STACK_POOL.lock().truncate(limit)
 }
 
+/// An owned permission to join on a thread.
+///
+/// Dropping a `JoinHandle` [detaches](JoinHandle::detach) the thread.
 #[derive(Debug)]
-#[must_use = "Dropping a JoinHandle leaks the associated thread and its resources"]
+#[must_use = "Dropping a JoinHandle detaches the associated thread"]
 pub struct JoinHandle<T> {
-    handle: OwnedHandle,
+    handle: ManuallyDrop<OwnedHandle>,
     memory: ThreadMemory<T>,
 }
 
+// SAFETY: the return value is only ever accessed by the owner of the handle, after the thread
+// has finished.
+unsafe impl<T: Send> Send for JoinHandle<T> {}
+
+impl<T> JoinHandle<T> {
+    fn into_parts(self) -> (OwnedHandle, ThreadMemory<T>) {
+        let this = ManuallyDrop::new(self);
+
+        // SAFETY: `this` is never used or dropped again.
+        unsafe {
+            (
+                ManuallyDrop::into_inner(core::ptr::read(&this.handle)),
+                core::ptr::read(&this.memory),
+            )
+        }
+    }
+
+    /// Let the thread run to completion on its own.
+    ///
+    /// The thread drops its return value once it finishes.  Its stack is freed after it exited,
+    /// the next time a thread is spawned or detached.
+    pub fn detach(self) {
+        drop(self)
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        // SAFETY: `self.handle` is not used again.
+        let handle = unsafe { ManuallyDrop::take(&mut self.handle) };
+
+        let state = self.memory.state().swap(DETACHED, Ordering::AcqRel);
+        if let FINISHED | PANICKED = state {
+            // The thread exited before we could detach it, so it expects to be joined.  Wait for
+            // it to leave its stack, then clean up in its stead.
+            let _ = svc::wait_synchronization(handle.as_handle(), Timeout::forever());
+
+            unsafe {
+                if state == FINISHED {
+                    core::ptr::drop_in_place(self.memory.return_value);
+                }
+                ThreadMemory { ..self.memory }.dealloc();
+            }
+        } else {
+            // The thread still runs on its stack, which it cannot free on its own.
+            DetachedThreads::push(handle, self.memory.erase());
+        }
+    }
+}
+
 impl<T> JoinHandle<T>
 where
     T: Send + 'static,
 {
-    pub fn join(self) -> Result<T> {
-        let Self { handle, memory } = self;
-        svc::wait_synchronization(handle.as_handle(), Timeout::forever())?;
+    /// Wait for the thread to finish.
+    ///
+    /// Returns `Ok(Err(Panicked))` if the thread [panicked](exit_panicking).
+    pub fn join(self) -> result::Result<Result<T>> {
+        svc::wait_synchronization(self.handle.as_handle(), Timeout::forever())?;
+
+        Ok(unsafe { self.take_return_value() })
+    }
+
+    /// Wait at most `timeout` for the thread to finish.
+    ///
+    /// Returns the thread's return value, or hands back the `JoinHandle` if the thread is still
+    /// running.
+    pub fn join_timeout(
+        self,
+        timeout: Timeout,
+    ) -> result::Result<core::result::Result<Result<T>, Self>> {
+        match svc::wait_synchronization(self.handle.as_handle(), timeout)? {
+            WaitOutcome::Signaled => Ok(Ok(unsafe { self.take_return_value() })),
+            WaitOutcome::TimedOut => Ok(Err(self)),
+        }
+    }
+
+    /// Join the thread if it has already finished, without blocking.
+    #[inline]
+    pub fn try_join(self) -> result::Result<core::result::Result<Result<T>, Self>> {
+        self.join_timeout(Timeout::none())
+    }
+
+    /// # Safety
+    ///
+    /// The thread must have exited.
+    unsafe fn take_return_value(self) -> Result<T> {
+        let (_handle, memory) = self.into_parts();
 
         // SAFETY: The thread using this memory exited.
         // We own the only pointer to the location of the return value.
-        let return_value = unsafe { memory.return_value.read() };
+        let return_value = match memory.state().load(Ordering::Acquire) {
+            FINISHED => Ok(memory.return_value.read()),
+            _ => Err(Panicked),
+        };
 
         // SAFETY: The thread using this memory exited, so we have exclusive access and are free to
         // deallocate it.
-        unsafe { memory.dealloc() };
+        memory.dealloc();
 
-        Ok(return_value)
+        return_value
     }
 
     pub fn is_running(&self) -> bool {
-        svc::wait_synchronization(self.handle.as_handle(), Timeout::none()).is_err()
+        !svc::wait_synchronization(self.handle.as_handle(), Timeout::none())
+            .is_ok_and(WaitOutcome::is_signaled)
+    }
+}
+
+/// Signaled once the thread has exited.
+impl<T> AsWaitHandle for JoinHandle<T> {
+    fn as_wait_handle(&self) -> BorrowedHandle<'_> {
+        self.handle.as_handle()
+    }
+}
+
+/// The processor a thread runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Core {
+    /// The ideal processor of the current process.
+    Default,
+    /// Any processor the kernel sees fit.
+    Any,
+    /// Core 0, the application core.
+    AppCore,
+    /// Core 1, the system core.
+    ///
+    /// Applications only get CPU time on this core once they have set a limit using
+    /// [`Apt::set_app_cpu_time_limit`](crate::services::apt::Apt::set_app_cpu_time_limit), or
+    /// through [`ThreadBuilder::with_syscore_time`].
+    SysCore,
+    /// Core 2, only present on New 3DS models.
+    Core2,
+    /// Core 3, only present on New 3DS models.
+    Core3,
+}
+
+impl Core {
+    pub const fn processor_id(self) -> i32 {
+        match self {
+            Self::Default => -2,
+            Self::Any => -1,
+            Self::AppCore => 0,
+            Self::SysCore => 1,
+            Self::Core2 => 2,
+            Self::Core3 => 3,
+        }
     }
 }
+
+/// Returned when spawning a thread on [`Core::SysCore`] before the application was granted CPU
+/// time on it.
+pub const ERROR_SYSCORE_CPU_TIME_UNSET: ErrorCode = ErrorCode::new(
+    Level::Usage,
+    Summary::InvalidState,
+    Module::Application,
+    CommonDescription::NotAuthorized.to_value(),
+);
+
+/// Returned when spawning a thread on [`Core::Core2`] or [`Core::Core3`] on models without them.
+pub const ERROR_CORE_UNAVAILABLE: ErrorCode = ErrorCode::new(
+    Level::Usage,
+    Summary::NotSupported,
+    Module::Application,
+    CommonDescription::OutOfRange.to_value(),
+);
+
 #[derive(Debug)]
 pub struct ThreadBuilder {
     priority: i32,
     stack_size: usize,
-    processor_id: i32,
+    processor: Core,
+    syscore_time: Option<u32>,
 }
 
 const fn align_to(value: usize, aligment: usize) -> usize {
@@ -141,7 +745,8 @@ impl Default for ThreadBuilder {
         Self {
             priority: 0x30,
             stack_size: 0x1000,
-            processor_id: -2,
+            processor: Core::Default,
+            syscore_time: None,
         }
     }
 }
@@ -151,26 +756,92 @@ impl ThreadBuilder {
         Self { priority, ..self }
     }
 
-    pub fn spawn<F, T>(self, f: F) -> Result<JoinHandle<T>>
+    pub fn with_stack_size(self, stack_size: usize) -> Self {
+        Self { stack_size, ..self }
+    }
+
+    pub fn with_processor_id(self, processor: Core) -> Self {
+        Self { processor, ..self }
+    }
+
+    /// Reserve `percent` of the system core's CPU time through APT when spawning on
+    /// [`Core::SysCore`], unless the application already reserved some.
+    ///
+    /// # Panics
+    ///
+    /// If `percent` is not within 5 to 89, the range APT accepts.
+    pub fn with_syscore_time(self, percent: u32) -> Self {
+        assert!(
+            (5..=89).contains(&percent),
+            "System core CPU time of {}% is out of range",
+            percent
+        );
+
+        Self {
+            syscore_time: Some(percent),
+            ..self
+        }
+    }
+
+    fn check_processor(&self) -> result::Result<()> {
+        match self.processor {
+            Core::SysCore => self.check_syscore_time(),
+            Core::Core2 | Core::Core3 => {
+                if !os::is_new_3ds(Services::get()?.srv())? {
+                    warn!("Cannot spawn thread on {:?}: not a New 3DS", self.processor);
+                    return Err(ERROR_CORE_UNAVAILABLE);
+                }
+
+                Ok(())
+            }
+            Core::Default | Core::Any | Core::AppCore => Ok(()),
+        }
+    }
+
+    fn check_syscore_time(&self) -> result::Result<()> {
+        let process = Process::current();
+        let limits = process.resource_limits()?;
+        if limits.get(LimitType::CpuTime).limit()? > 0 {
+            return Ok(());
+        }
+
+        match self.syscore_time {
+            Some(percent) => {
+                debug!("Reserving {}% of the system core's CPU time", percent);
+                apt::reserve_syscore_time(Services::get()?.srv(), percent)
+            }
+            None => {
+                warn!("Cannot spawn thread on the system core: no CPU time limit was set via APT");
+                Err(ERROR_SYSCORE_CPU_TIME_UNSET)
+            }
+        }
+    }
+
+    pub fn spawn<F, T>(self, f: F) -> result::Result<JoinHandle<T>>
     where
         F: FnOnce() -> T,
         F: Send + 'static,
         T: Send + 'static,
     {
+        self.check_processor()?;
+
+        DETACHED_THREADS.lock().reap();
+
         let thread_memory = ThreadMemory::allocate(self.stack_size);
 
-        let return_value = ReturnValue::new(thread_memory.return_value);
+        let return_value = ReturnValue::new(&thread_memory);
 
         let wrapper = move || unsafe {
             let rv: T = f();
+            tls::destroy_user_slots();
             return_value.store(rv)
         };
-        let packet = ThreadPacket::new(wrapper);
+        let packet = ThreadPacket::new(wrapper, &thread_memory);
         let argument = ThreadPacket::into_argument(packet);
 
         debug!(
             "Launching thread: priority={}, argument={:p}, mem_start={:p}, stack_top={:p}, return_value={:p}, processor_id={}",
-            self.priority, argument as *const (), thread_memory.allocated, thread_memory.stack_top,  thread_memory.return_value, self.processor_id
+            self.priority, argument as *const (), thread_memory.allocated, thread_memory.stack_top,  thread_memory.return_value, self.processor.processor_id()
         );
 
         let handle = unsafe {
@@ -179,18 +850,30 @@ impl ThreadBuilder {
                 _ctru_rt_thread_start,
                 argument,
                 thread_memory.stack_top,
-                self.processor_id,
-            )?
+                self.processor.processor_id(),
+            )
+        };
+
+        let handle = match handle {
+            Ok(handle) => handle,
+            Err(e) => {
+                // SAFETY: The thread was never started, so nobody else owns the packet or memory.
+                unsafe {
+                    drop(ThreadPacket::from_argument(argument));
+                    thread_memory.dealloc();
+                }
+                return Err(e);
+            }
         };
 
         Ok(JoinHandle {
-            handle,
+            handle: ManuallyDrop::new(handle),
             memory: thread_memory,
         })
     }
 }
 
-pub fn spawn<F, T>(f: F) -> Result<JoinHandle<T>>
+pub fn spawn<F, T>(f: F) -> result::Result<JoinHandle<T>>
 where
     F: FnOnce() -> T,
     F: Send + 'static,
@@ -198,3 +881,13 @@ where
 {
     ThreadBuilder::default().spawn(f)
 }
+
+/// Give up the remainder of this thread's time slice to other threads of the same priority.
+pub fn yield_now() {
+    svc::sleep_thread(Timeout::none())
+}
+
+/// Put the current thread to sleep for at least `duration`.
+pub fn sleep(duration: Duration) {
+    svc::sleep_thread(duration.into())
+}
@@ -2,7 +2,26 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::ports::errf::{self, CpuRegisters, ExceptionContext};
+use crate::svc;
+use crate::sync::OnceCell;
+
 use core::marker::PhantomData;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use alloc::boxed::Box;
+
+/// Bytes at the start of thread local storage reserved for the runtime's bookkeeping.
+pub(crate) const THREAD_VARS_SIZE: usize = 0x40;
+
+/// The exception handler entry point, its stack and where to store the exception data, read by
+/// the kernel, followed by the handler invoked by the entry point.
+const EXCEPTION_HANDLER_OFFSET: usize = THREAD_VARS_SIZE;
+const EXCEPTION_HANDLER_SIZE: usize = 0x10;
+
+/// Number of [`UserSlot`]s available per process.
+pub const NUM_USER_SLOTS: usize =
+    (0x80 - EXCEPTION_HANDLER_OFFSET - EXCEPTION_HANDLER_SIZE) / core::mem::size_of::<*mut u8>();
 
 /// TODO: figure out how to trick this code into giving me thread local access
 struct AccessToken;
@@ -12,6 +31,29 @@ static mut THREAD_ACCESS_TOKEN: AccessToken = AccessToken;
 pub struct ThreadLocalStorage(*mut u8);
 
 impl ThreadLocalStorage {
+    /// Start of the runtime's per-thread bookkeeping.
+    ///
+    /// The first `0x40` bytes of thread local storage are not used by the kernel or IPC.  The
+    /// kernel reads the words after them as the thread's exception handler.
+    #[inline]
+    pub(crate) fn thread_vars(&self) -> *mut u8 {
+        self.0
+    }
+
+    #[inline]
+    fn exception_handler(&self) -> *mut [usize; 4] {
+        unsafe { self.0.add(EXCEPTION_HANDLER_OFFSET) as *mut [usize; 4] }
+    }
+
+    #[inline]
+    fn user_slots(&self) -> *mut [*mut u8; NUM_USER_SLOTS] {
+        unsafe {
+            self.0
+                .add(EXCEPTION_HANDLER_OFFSET + EXCEPTION_HANDLER_SIZE)
+                as *mut [*mut u8; NUM_USER_SLOTS]
+        }
+    }
+
     #[inline]
     pub fn command_buffer(&self) -> *mut u32 {
         unsafe { self.0.add(0x80) as *mut u32 }
@@ -23,6 +65,7 @@ impl ThreadLocalStorage {
     }
 }
 
+#[cfg(target_arch = "arm")]
 #[inline]
 pub fn get_thread_local_storage() -> ThreadLocalStorage {
     let data: *mut u8;
@@ -35,6 +78,23 @@ pub fn get_thread_local_storage() -> ThreadLocalStorage {
     ThreadLocalStorage(data)
 }
 
+/// Stands in for thread local storage on targets other than the 3DS.
+///
+/// The storage is shared by all threads, so only one thread may use it at a time.
+#[cfg(not(target_arch = "arm"))]
+pub fn get_thread_local_storage() -> ThreadLocalStorage {
+    use core::cell::UnsafeCell;
+
+    #[repr(C, align(8))]
+    struct Storage(UnsafeCell<[u8; 0x200]>);
+
+    unsafe impl Sync for Storage {}
+
+    static STORAGE: Storage = Storage(UnsafeCell::new([0; 0x200]));
+
+    ThreadLocalStorage(STORAGE.0.get() as *mut u8)
+}
+
 #[repr(packed)]
 struct Descriptor<'a> {
     flags: u32,
@@ -68,3 +128,179 @@ impl<'a> StaticBufferDescriptors<'a> {
         }
     }
 }
+
+/// Run the exception handler on the stack of the faulting thread, and store the exception data
+/// there.
+const ON_FAULTING_STACK: usize = 1;
+
+/// The handler set on the main thread, which the kernel also enters for threads without their own.
+static MAIN_THREAD_EXCEPTION_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+/// Handle CPU exceptions of the current thread, like data aborts, with `handler`.
+///
+/// Set on the main thread, this is the handler of all threads without their own.  After the
+/// handler returns, the process exits.  By default, the main thread uses
+/// [`errf::report_exception`].
+pub fn set_exception_handler(handler: fn(&ExceptionContext)) {
+    if !crate::thread::is_spawned() {
+        MAIN_THREAD_EXCEPTION_HANDLER.store(handler as usize, Ordering::Release);
+    }
+
+    unsafe {
+        get_thread_local_storage()
+            .exception_handler()
+            .write_volatile([
+                exception_entry as *const () as usize,
+                ON_FAULTING_STACK,
+                ON_FAULTING_STACK,
+                handler as usize,
+            ])
+    }
+}
+
+/// Remove the exception handler of the current thread, falling back to the main thread's.
+pub fn clear_exception_handler() {
+    if !crate::thread::is_spawned() {
+        MAIN_THREAD_EXCEPTION_HANDLER.store(0, Ordering::Release);
+    }
+
+    reset_exception_handler()
+}
+
+/// Remove the exception handler of a thread that is being set up.
+pub(crate) fn reset_exception_handler() {
+    unsafe {
+        get_thread_local_storage()
+            .exception_handler()
+            .write_volatile([0; 4])
+    }
+}
+
+/// Entered by the kernel on exceptions, with the stack pointing below the exception data.
+extern "C" fn exception_entry(info: *const u8, registers: *const CpuRegisters) -> ! {
+    let context = unsafe { ExceptionContext::read(info, registers) };
+
+    let handler = match unsafe { (*get_thread_local_storage().exception_handler())[3] } {
+        0 => MAIN_THREAD_EXCEPTION_HANDLER.load(Ordering::Acquire),
+        handler => handler,
+    };
+    let handler = match handler {
+        0 => errf::report_exception,
+        handler => unsafe { core::mem::transmute::<usize, fn(&ExceptionContext)>(handler) },
+    };
+    handler(&context);
+
+    svc::exit_process()
+}
+
+static NEXT_USER_SLOT: AtomicUsize = AtomicUsize::new(0);
+static USER_SLOT_DESTRUCTORS: [AtomicPtr<()>; NUM_USER_SLOTS] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; NUM_USER_SLOTS];
+
+/// A value that is stored separately for each thread.
+///
+/// Slots are backed by the otherwise unused part of each thread's thread local storage, so there
+/// are only [`NUM_USER_SLOTS`] of them per process.  A slot is assigned on first use, and the value
+/// for each thread is initialized lazily.  Use [`thread_local!`](crate::thread_local) to declare one.
+///
+/// Values are dropped when a thread spawned by [`thread`](crate::thread) returns.
+#[derive(Debug)]
+pub struct UserSlot<T: 'static> {
+    index: OnceCell<usize>,
+    init: fn() -> T,
+}
+
+// SAFETY: Every thread only ever accesses its own copy of the value.
+unsafe impl<T: 'static> Sync for UserSlot<T> {}
+
+impl<T: 'static> UserSlot<T> {
+    pub const fn new(init: fn() -> T) -> Self {
+        Self {
+            index: OnceCell::new(),
+            init,
+        }
+    }
+
+    /// The slot assigned to this value, assigning the next free one on first use.
+    ///
+    /// Threads racing to assign it wait for the first one, so no slot is lost.
+    fn index(&self) -> usize {
+        *self.index.get_or_init(|| {
+            let index = NEXT_USER_SLOT.fetch_add(1, Ordering::Relaxed);
+            if index >= NUM_USER_SLOTS {
+                panic!(
+                    "All {} thread local storage slots are in use",
+                    NUM_USER_SLOTS
+                );
+            }
+
+            USER_SLOT_DESTRUCTORS[index].store(drop_slot::<T> as *mut (), Ordering::Release);
+            index
+        })
+    }
+
+    /// Access the current thread's value, initializing it first if necessary.
+    pub fn with<R, F: FnOnce(&T) -> R>(&'static self, f: F) -> R {
+        let index = self.index();
+        let slot = unsafe { &mut (*get_thread_local_storage().user_slots())[index] };
+
+        if slot.is_null() {
+            *slot = Box::into_raw(Box::new((self.init)())) as *mut u8;
+        }
+
+        f(unsafe { &*(*slot as *const T) })
+    }
+}
+
+unsafe fn drop_slot<T>(value: *mut u8) {
+    drop(Box::from_raw(value as *mut T))
+}
+
+/// Mark all user slots of the current thread as uninitialized.
+pub(crate) fn init_user_slots() {
+    unsafe {
+        get_thread_local_storage()
+            .user_slots()
+            .write([core::ptr::null_mut(); NUM_USER_SLOTS])
+    }
+}
+
+/// Drop all values stored in user slots of the current thread.
+pub(crate) fn destroy_user_slots() {
+    let slots = unsafe { &mut *get_thread_local_storage().user_slots() };
+
+    for (slot, destructor) in slots.iter_mut().zip(USER_SLOT_DESTRUCTORS.iter()) {
+        let value = *slot;
+        *slot = core::ptr::null_mut();
+        let destructor = destructor.load(Ordering::Acquire);
+
+        if !value.is_null() && !destructor.is_null() {
+            let destructor: unsafe fn(*mut u8) = unsafe { core::mem::transmute(destructor) };
+            unsafe { destructor(value) }
+        }
+    }
+}
+
+/// Declare values that are stored separately for each thread, backed by a [`UserSlot`].
+///
+/// ```ignore
+/// ctru_rt::thread_local! {
+///     static COUNTER: core::cell::Cell<u32> = core::cell::Cell::new(0);
+/// }
+///
+/// COUNTER.with(|counter| counter.set(counter.get() + 1));
+/// ```
+#[macro_export]
+macro_rules! thread_local {
+    () => {};
+    ($(#[$attr: meta])* $vis: vis static $name: ident: $t: ty = $init: expr; $($rest: tt)*) => {
+        $(#[$attr])*
+        $vis static $name: $crate::tls::UserSlot<$t> = $crate::tls::UserSlot::new({
+            fn __init() -> $t {
+                $init
+            }
+            __init
+        });
+        $crate::thread_local!($($rest)*);
+    };
+}
@@ -0,0 +1,537 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Unwinding as described by the Exception Handling ABI for the ARM Architecture (EHABI).
+//!
+//! Every function has an entry in `.ARM.exidx`, which either marks it as not unwindable, holds a
+//! few unwind opcodes directly, or points into `.ARM.extab` for a personality routine, more
+//! opcodes and language specific data.
+
+use super::personality::{__aeabi_unwind_cpp_pr0, __aeabi_unwind_cpp_pr1, __aeabi_unwind_cpp_pr2};
+use super::Exception;
+use crate::early_debug;
+use crate::svc::{self, UserBreakReason};
+
+use core::arch::global_asm;
+use core::mem::MaybeUninit;
+use core::ptr::addr_of;
+
+const SP: usize = 13;
+const LR: usize = 14;
+const PC: usize = 15;
+
+/// Register contents of a frame during unwinding.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub(crate) struct Context {
+    core: [u32; 16],
+    vfp: [u64; 16],
+}
+
+impl Context {
+    pub(crate) fn set_register(&mut self, register: usize, value: u32) {
+        self.core[register] = value;
+    }
+
+    pub(crate) fn pc(&self) -> u32 {
+        self.core[PC]
+    }
+
+    pub(crate) fn set_pc(&mut self, pc: u32) {
+        self.core[PC] = pc;
+    }
+
+    fn sp(&self) -> u32 {
+        self.core[SP]
+    }
+
+    unsafe fn pop(&mut self) -> u32 {
+        let value = (self.core[SP] as *const u32).read();
+        self.core[SP] += 4;
+        value
+    }
+
+    /// Pop the core registers in `mask`, lowest first.
+    unsafe fn pop_core(&mut self, mask: u16) {
+        let mut popped_sp = None;
+        for register in (0..16).filter(|r| mask & (1 << r) != 0) {
+            let value = self.pop();
+            if register == SP {
+                popped_sp = Some(value);
+            } else {
+                self.core[register] = value;
+            }
+        }
+
+        if let Some(sp) = popped_sp {
+            self.core[SP] = sp;
+        }
+    }
+
+    /// Pop `count` double registers starting at `first`.  Registers saved by `FSTMX` are followed
+    /// by a padding word.
+    unsafe fn pop_vfp(&mut self, first: u8, count: u8, fstmx: bool) -> Result<(), UnwindError> {
+        let registers = self
+            .vfp
+            .get_mut(usize::from(first)..usize::from(first + count))
+            .ok_or(UnwindError)?;
+
+        for register in registers {
+            let sp = self.core[SP] as *const u32;
+            *register = u64::from(sp.read()) | u64::from(sp.add(1).read()) << 32;
+            self.core[SP] += 8;
+        }
+
+        if fstmx {
+            self.core[SP] += 4;
+        }
+
+        Ok(())
+    }
+}
+
+/// Passed to personality routines to tell them what to do with a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub(crate) enum State {
+    /// Search for a handler, without running any code.
+    VirtualUnwindFrame = 0,
+    /// Run cleanups or handlers of the frame.
+    UnwindFrameStarting = 1,
+    /// The frame's cleanup has finished, continue with its caller.
+    UnwindFrameResume = 2,
+}
+
+/// Returned by personality routines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub(crate) enum Reason {
+    EndOfStack = 5,
+    HandlerFound = 6,
+    InstallContext = 7,
+    ContinueUnwind = 8,
+    Failure = 9,
+}
+
+pub(crate) type Personality = unsafe extern "C" fn(State, *mut Exception, *mut Context) -> Reason;
+
+/// The unwind tables are malformed, or use unsupported instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UnwindError;
+
+/// Decode a 31 bit offset relative to `word`.
+unsafe fn prel31(word: *const u32) -> u32 {
+    let offset = ((word.read() << 1) as i32) >> 1;
+    (word as u32).wrapping_add(offset as u32)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Entry {
+    /// Compact unwind opcodes stored directly in the index table.
+    Inline(u32),
+    /// An entry in `.ARM.extab`.
+    Table(*const u32),
+}
+
+/// The unwind information of the function a frame belongs to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Frame {
+    /// Start address of the function.
+    pub(crate) function: u32,
+    entry: Entry,
+}
+
+impl Frame {
+    const CANT_UNWIND: u32 = 0x1;
+    const COMPACT: u32 = 0x8000_0000;
+
+    /// Look up the function containing the return address `pc`.
+    fn find(pc: u32) -> Option<Self> {
+        extern "C" {
+            static __exidx_start: [u32; 2];
+            static __exidx_end: [u32; 2];
+        }
+
+        let index = unsafe {
+            let start = addr_of!(__exidx_start);
+            let end = addr_of!(__exidx_end);
+            core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+        };
+
+        // The return address might already belong to the next function if the call was the last
+        // instruction, so look up the call instruction itself.
+        let pc = (pc & !1).wrapping_sub(2);
+        let found = index.partition_point(|entry| unsafe { prel31(&entry[0]) } <= pc);
+        let entry = index.get(found.checked_sub(1)?)?;
+
+        let function = unsafe { prel31(&entry[0]) };
+        let entry = match entry[1] {
+            Self::CANT_UNWIND => return None,
+            word if word & Self::COMPACT != 0 => Entry::Inline(word),
+            _ => Entry::Table(unsafe { prel31(&entry[1]) } as *const u32),
+        };
+
+        Some(Self { function, entry })
+    }
+
+    fn personality(&self) -> Option<Personality> {
+        let word = match self.entry {
+            Entry::Inline(word) => word,
+            Entry::Table(table) => unsafe { table.read() },
+        };
+
+        if word & Self::COMPACT == 0 {
+            let Entry::Table(table) = self.entry else {
+                return None;
+            };
+            let address = unsafe { prel31(table) } as usize;
+            return Some(unsafe { core::mem::transmute::<usize, Personality>(address) });
+        }
+
+        match (word >> 24) & 0xf {
+            0 => Some(__aeabi_unwind_cpp_pr0),
+            1 if matches!(self.entry, Entry::Table(_)) => Some(__aeabi_unwind_cpp_pr1),
+            2 if matches!(self.entry, Entry::Table(_)) => Some(__aeabi_unwind_cpp_pr2),
+            _ => None,
+        }
+    }
+
+    fn opcodes(&self) -> Opcodes {
+        match self.entry {
+            Entry::Inline(word) => Opcodes::new(word, 3, core::ptr::null(), 0),
+            Entry::Table(table) => unsafe {
+                let word = table.read();
+                match word >> 24 {
+                    0x80 => Opcodes::new(word, 3, core::ptr::null(), 0),
+                    0x81 | 0x82 => Opcodes::new(word, 2, table.add(1), (word >> 16) & 0xff),
+                    _ => {
+                        let word = table.add(1).read();
+                        Opcodes::new(word, 3, table.add(2), word >> 24)
+                    }
+                }
+            },
+        }
+    }
+
+    /// The language specific data following the opcodes of a generic personality routine.
+    pub(crate) fn language_specific_data(&self) -> Option<*const u8> {
+        match self.entry {
+            Entry::Table(table) if unsafe { table.read() } & Self::COMPACT == 0 => unsafe {
+                let words = table.add(1).read() >> 24;
+                Some(table.add(2 + words as usize) as *const u8)
+            },
+            _ => None,
+        }
+    }
+
+    /// Restore the registers of the caller by executing the frame's unwind opcodes.
+    pub(crate) unsafe fn unwind(&self, context: &mut Context) -> Result<(), UnwindError> {
+        let mut opcodes = self.opcodes();
+        let mut wrote_pc = false;
+
+        while let Some(opcode) = opcodes.next() {
+            match opcode {
+                0x00..=0x3f => context.core[SP] += (u32::from(opcode) << 2) + 4,
+                0x40..=0x7f => context.core[SP] -= (u32::from(opcode & 0x3f) << 2) + 4,
+                0x80..=0x8f => {
+                    let mask = u16::from(opcode & 0xf) << 8 | u16::from(opcodes.operand()?);
+                    if mask == 0 {
+                        // Refuse to unwind
+                        return Err(UnwindError);
+                    }
+                    context.pop_core(mask << 4);
+                    wrote_pc |= mask & (1 << (PC - 4)) != 0;
+                }
+                0x90..=0x9f => match usize::from(opcode & 0xf) {
+                    SP | PC => return Err(UnwindError),
+                    register => context.core[SP] = context.core[register],
+                },
+                0xa0..=0xaf => {
+                    let mut mask = ((1 << ((opcode & 0x7) + 1)) - 1) << 4;
+                    if opcode & 0x8 != 0 {
+                        mask |= 1 << LR;
+                    }
+                    context.pop_core(mask);
+                }
+                0xb0 => break,
+                0xb1 => match opcodes.operand()? {
+                    mask @ 0x01..=0x0f => context.pop_core(mask.into()),
+                    _ => return Err(UnwindError),
+                },
+                0xb2 => context.core[SP] += 0x204 + (opcodes.uleb128()? << 2),
+                0xb3 => {
+                    let operand = opcodes.operand()?;
+                    context.pop_vfp(operand >> 4, (operand & 0xf) + 1, true)?
+                }
+                0xb8..=0xbf => context.pop_vfp(8, (opcode & 0x7) + 1, true)?,
+                0xc9 => {
+                    let operand = opcodes.operand()?;
+                    context.pop_vfp(operand >> 4, (operand & 0xf) + 1, false)?
+                }
+                0xd0..=0xd7 => context.pop_vfp(8, (opcode & 0x7) + 1, false)?,
+                // Spare opcodes, iWMMXt and the upper VFPv3 registers, none of which exist here.
+                _ => return Err(UnwindError),
+            }
+        }
+
+        if !wrote_pc {
+            context.core[PC] = context.core[LR];
+        }
+
+        Ok(())
+    }
+}
+
+/// The bytes of a sequence of unwind opcodes, packed most significant first into words.
+struct Opcodes {
+    word: u32,
+    bytes_left: u32,
+    next_word: *const u32,
+    words_left: u32,
+}
+
+impl Opcodes {
+    fn new(word: u32, bytes_left: u32, next_word: *const u32, words_left: u32) -> Self {
+        Self {
+            word,
+            bytes_left,
+            next_word,
+            words_left,
+        }
+    }
+
+    fn operand(&mut self) -> Result<u8, UnwindError> {
+        self.next().ok_or(UnwindError)
+    }
+
+    fn uleb128(&mut self) -> Result<u32, UnwindError> {
+        let mut value = 0;
+        for shift in (0..32).step_by(7) {
+            let byte = self.operand()?;
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(UnwindError)
+    }
+}
+
+impl Iterator for Opcodes {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.bytes_left == 0 {
+            if self.words_left == 0 {
+                return None;
+            }
+
+            self.word = unsafe { self.next_word.read() };
+            self.next_word = self.next_word.wrapping_add(1);
+            self.words_left -= 1;
+            self.bytes_left = 4;
+        }
+
+        self.bytes_left -= 1;
+        Some((self.word >> (self.bytes_left * 8)) as u8)
+    }
+}
+
+/// Hand the current frame of `context` to its personality routine.
+unsafe fn step(
+    frame: Frame,
+    state: State,
+    exception: *mut Exception,
+    context: &mut Context,
+) -> Reason {
+    let (sp, pc) = (context.sp(), context.pc());
+
+    let personality = match frame.personality() {
+        Some(personality) => personality,
+        None => return Reason::Failure,
+    };
+
+    (*exception).frame = Some(frame);
+    let reason = personality(state, exception, context);
+
+    // Callers live further up the stack, anything else would never terminate.
+    let stuck = context.sp() < sp || (context.sp(), context.pc()) == (sp, pc);
+    match reason {
+        Reason::ContinueUnwind if stuck => Reason::Failure,
+        reason => reason,
+    }
+}
+
+/// Search for a handler, then unwind to it.
+///
+/// Only returns if there is no handler, without running any cleanups.
+unsafe extern "C" fn raise_exception(exception: *mut Exception, context: *mut Context) -> Reason {
+    let context = &mut *context;
+    let mut search = context.clone();
+
+    loop {
+        let frame = match Frame::find(search.pc()) {
+            Some(frame) => frame,
+            None => return Reason::EndOfStack,
+        };
+
+        match step(frame, State::VirtualUnwindFrame, exception, &mut search) {
+            Reason::ContinueUnwind => continue,
+            Reason::HandlerFound => break,
+            reason => return reason,
+        }
+    }
+
+    unwind_phase(exception, context, State::UnwindFrameStarting)
+}
+
+/// Continue unwinding after a cleanup finished, called at the end of landing pads.
+unsafe extern "C" fn resume_exception(exception: *mut Exception, context: *mut Context) -> ! {
+    let context = &mut *context;
+    context.set_pc((*exception).resume_pc);
+
+    unwind_phase(exception, context, State::UnwindFrameResume)
+}
+
+/// Run cleanups until the handler is reached.
+unsafe fn unwind_phase(exception: *mut Exception, context: &mut Context, mut state: State) -> ! {
+    loop {
+        let pc = context.pc();
+        let frame = match Frame::find(pc) {
+            Some(frame) => frame,
+            None => break,
+        };
+
+        match step(frame, state, exception, context) {
+            Reason::ContinueUnwind => state = State::UnwindFrameStarting,
+            Reason::InstallContext => {
+                (*exception).resume_pc = pc;
+                _ctru_rt_unwind_install(context)
+            }
+            _ => break,
+        }
+    }
+
+    // Cleanups have already run for some frames, so there is nothing sensible to return to.
+    early_debug!("Failed to unwind to a handler found earlier");
+    svc::user_break(UserBreakReason::Panic)
+}
+
+/// Start unwinding, see [`raise_exception`].
+pub(crate) unsafe fn raise(exception: *mut Exception) -> Reason {
+    _ctru_rt_unwind_raise(exception)
+}
+
+/// Report the return address of every frame above the caller, innermost first, until `record`
+/// returns `false`.
+#[inline(never)]
+pub(crate) fn trace(mut record: impl FnMut(u32) -> bool) {
+    let mut context = unsafe {
+        let mut context = MaybeUninit::<Context>::uninit();
+        _ctru_rt_unwind_capture(context.as_mut_ptr());
+        context.assume_init()
+    };
+
+    while let Some(frame) = Frame::find(context.pc()) {
+        let (sp, pc) = (context.sp(), context.pc());
+        if unsafe { frame.unwind(&mut context) }.is_err()
+            || context.sp() < sp
+            || (context.sp(), context.pc()) == (sp, pc)
+            || !record(context.pc())
+        {
+            break;
+        }
+    }
+}
+
+// Exceptions are only passed by pointer, and only to Rust code.
+#[allow(improper_ctypes)]
+extern "C-unwind" {
+    // Frames calling this must remain unwindable.
+    fn _ctru_rt_unwind_raise(exception: *mut Exception) -> Reason;
+}
+
+extern "C" {
+    fn _ctru_rt_unwind_capture(context: *mut Context);
+    fn _ctru_rt_unwind_install(context: *const Context) -> !;
+}
+
+// Entry points capture the registers of their caller in a `Context` on the stack, as if the call
+// had just returned.  Only registers preserved across calls matter, but saving all of them is
+// simpler.
+global_asm!(
+    r#"
+    .macro CTRU_RT_CAPTURE_CONTEXT
+        sub sp, sp, #192
+        stmia sp, {{r0-r12}}
+        add r1, sp, #192
+        str r1, [sp, #52]
+        str lr, [sp, #56]
+        str lr, [sp, #60]
+        add r1, sp, #64
+        vstmia r1, {{d0-d15}}
+        mov r1, sp
+    .endm
+
+    .section .text._ctru_rt_unwind_raise,"ax",%progbits
+    .global _ctru_rt_unwind_raise
+    .type _ctru_rt_unwind_raise,%function
+    .arm
+    .p2align 2
+_ctru_rt_unwind_raise:
+    CTRU_RT_CAPTURE_CONTEXT
+    bl {raise}
+    ldr lr, [sp, #56]
+    add sp, sp, #192
+    bx lr
+    .size _ctru_rt_unwind_raise, . - _ctru_rt_unwind_raise
+
+    .section .text._Unwind_Resume,"ax",%progbits
+    .global _Unwind_Resume
+    .type _Unwind_Resume,%function
+    .arm
+    .p2align 2
+_Unwind_Resume:
+    CTRU_RT_CAPTURE_CONTEXT
+    bl {resume}
+    .size _Unwind_Resume, . - _Unwind_Resume
+
+    .section .text._ctru_rt_unwind_capture,"ax",%progbits
+    .global _ctru_rt_unwind_capture
+    .type _ctru_rt_unwind_capture,%function
+    .arm
+    .p2align 2
+_ctru_rt_unwind_capture:
+    stmia r0, {{r0-r12}}
+    str sp, [r0, #52]
+    str lr, [r0, #56]
+    str lr, [r0, #60]
+    add r1, r0, #64
+    vstmia r1, {{d0-d15}}
+    bx lr
+    .size _ctru_rt_unwind_capture, . - _ctru_rt_unwind_capture
+
+    .section .text._ctru_rt_unwind_install,"ax",%progbits
+    .global _ctru_rt_unwind_install
+    .type _ctru_rt_unwind_install,%function
+    .arm
+    .p2align 2
+_ctru_rt_unwind_install:
+    add r1, r0, #64
+    vldmia r1, {{d0-d15}}
+    @ Only the target stack can hold pc while all other registers are restored.  It lies above
+    @ the context and everything still in use.
+    ldr r1, [r0, #52]
+    ldr r2, [r0, #60]
+    str r2, [r1, #-4]
+    ldr lr, [r0, #56]
+    mov sp, r1
+    ldmia r0, {{r0-r12}}
+    ldr pc, [sp, #-4]
+    .size _ctru_rt_unwind_install, . - _ctru_rt_unwind_install
+    "#,
+    raise = sym raise_exception,
+    resume = sym resume_exception,
+);
@@ -0,0 +1,127 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Stack unwinding for applications built with `panic = "unwind"`.
+//!
+//! To unwind, the `#[panic_handler]` has to call [`begin_panic`], which only returns if the
+//! panic can not be caught.  The panic handler of the `panic-handler` feature already does this.
+//! Panics are then caught by [`catch_unwind`], and by spawned threads, which report them as
+//! [`Panicked`](crate::thread::Panicked) when joined.  Values on the stack are dropped on the way.
+
+pub(crate) mod ehabi;
+mod personality;
+
+use crate::early_debug;
+use crate::svc::{self, UserBreakReason};
+
+use core::any::Any;
+use core::mem::ManuallyDrop;
+use core::panic::PanicInfo;
+
+pub use core::panic::{AssertUnwindSafe, RefUnwindSafe, UnwindSafe};
+
+use alloc::boxed::Box;
+use alloc::string::ToString;
+
+/// Identifies exceptions raised by this crate.
+const EXCEPTION_CLASS: [u8; 8] = *b"CTR\0RUST";
+
+/// An exception in flight, passed to landing pads.
+#[repr(C)]
+pub(crate) struct Exception {
+    class: [u8; 8],
+    /// The frame currently handed to a personality routine.
+    frame: Option<ehabi::Frame>,
+    /// Where the landing pad currently running was entered from.
+    resume_pc: u32,
+    payload: Box<dyn Any + Send>,
+}
+
+/// Unwind with `payload`, returning only if nothing would catch it.
+fn raise(payload: Box<dyn Any + Send>) -> Box<dyn Any + Send> {
+    let exception = Box::into_raw(Box::new(Exception {
+        class: EXCEPTION_CLASS,
+        frame: None,
+        resume_pc: 0,
+        payload,
+    }));
+
+    let reason = unsafe { ehabi::raise(exception) };
+    early_debug!("Not unwinding: {:?}", reason);
+
+    unsafe { Box::from_raw(exception) }.payload
+}
+
+/// Take the payload of an exception caught by a landing pad.
+unsafe fn take_payload(exception: *mut u8) -> Box<dyn Any + Send> {
+    let exception = Box::from_raw(exception as *mut Exception);
+    if exception.class != EXCEPTION_CLASS {
+        early_debug!("Caught a foreign exception");
+        svc::user_break(UserBreakReason::Panic)
+    }
+
+    exception.payload
+}
+
+/// Unwind the stack for `info`, with its message as payload.
+///
+/// Call this from the `#[panic_handler]`.  Returns if the panic can not unwind, or if nothing
+/// would catch it, leaving the stack untouched for reporting the panic.
+pub fn begin_panic(info: &PanicInfo) {
+    if !info.can_unwind() || !crate::heap::initialized() {
+        return;
+    }
+
+    let payload: Box<dyn Any + Send> = match info.message().as_str() {
+        Some(message) => Box::new(message),
+        None => Box::new(info.message().to_string()),
+    };
+
+    drop(raise(payload))
+}
+
+/// Unwind with `payload` without invoking the panic handler.
+///
+/// If nothing catches it, the current thread exits as [panicked](crate::thread::exit_panicking).
+pub fn resume_unwind(payload: Box<dyn Any + Send>) -> ! {
+    drop(raise(payload));
+    crate::thread::exit_panicking()
+}
+
+/// Invoke `f`, returning the payload of the panic if it unwinds.
+pub fn catch_unwind<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Result<R, Box<dyn Any + Send>> {
+    union Data<F, R> {
+        f: ManuallyDrop<F>,
+        r: ManuallyDrop<R>,
+        p: ManuallyDrop<Box<dyn Any + Send>>,
+    }
+
+    fn call<F: FnOnce() -> R, R>(data: *mut u8) {
+        unsafe {
+            let data = &mut *(data as *mut Data<F, R>);
+            let f = ManuallyDrop::take(&mut data.f);
+            data.r = ManuallyDrop::new(f());
+        }
+    }
+
+    fn catch<F: FnOnce() -> R, R>(data: *mut u8, exception: *mut u8) {
+        unsafe {
+            let data = &mut *(data as *mut Data<F, R>);
+            data.p = ManuallyDrop::new(take_payload(exception));
+        }
+    }
+
+    let mut data = Data {
+        f: ManuallyDrop::new(f),
+    };
+    let data_ptr = &mut data as *mut Data<F, R> as *mut u8;
+
+    unsafe {
+        if core::intrinsics::catch_unwind(call::<F, R>, data_ptr, catch::<F, R>) == 0 {
+            Ok(ManuallyDrop::into_inner(data.r))
+        } else {
+            Err(ManuallyDrop::into_inner(data.p))
+        }
+    }
+}
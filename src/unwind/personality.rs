@@ -0,0 +1,246 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Personality routines, which decide what happens to a frame while unwinding.
+
+use super::ehabi::{Context, Reason, State, UnwindError};
+use super::Exception;
+
+use core::mem::size_of;
+
+unsafe fn continue_unwind(exception: *mut Exception, context: *mut Context) -> Reason {
+    match (*exception).frame {
+        Some(frame) if frame.unwind(&mut *context).is_ok() => Reason::ContinueUnwind,
+        _ => Reason::Failure,
+    }
+}
+
+/// Personality routine for frames with a short sequence of unwind opcodes and nothing to clean up.
+#[no_mangle]
+pub unsafe extern "C" fn __aeabi_unwind_cpp_pr0(
+    _state: State,
+    exception: *mut Exception,
+    context: *mut Context,
+) -> Reason {
+    continue_unwind(exception, context)
+}
+
+/// Personality routine for frames with a long sequence of unwind opcodes.
+///
+/// Its cleanup descriptors are only generated for C++ and ignored.
+#[no_mangle]
+pub unsafe extern "C" fn __aeabi_unwind_cpp_pr1(
+    _state: State,
+    exception: *mut Exception,
+    context: *mut Context,
+) -> Reason {
+    continue_unwind(exception, context)
+}
+
+/// See [`__aeabi_unwind_cpp_pr1`].
+#[no_mangle]
+pub unsafe extern "C" fn __aeabi_unwind_cpp_pr2(
+    _state: State,
+    exception: *mut Exception,
+    context: *mut Context,
+) -> Reason {
+    continue_unwind(exception, context)
+}
+
+/// Personality routine of all Rust functions with landing pads.
+#[lang = "eh_personality"]
+unsafe extern "C" fn rust_eh_personality(
+    state: State,
+    exception: *mut Exception,
+    context: *mut Context,
+) -> Reason {
+    let frame = match (*exception).frame {
+        Some(frame) if state != State::UnwindFrameResume => frame,
+        _ => return continue_unwind(exception, context),
+    };
+
+    let action = match frame.language_specific_data() {
+        Some(lsda) => match find_action(lsda, frame.function, (*context).pc()) {
+            Ok(action) => action,
+            Err(UnwindError) => return Reason::Failure,
+        },
+        None => Action::None,
+    };
+
+    match (state, action) {
+        (_, Action::None) | (State::VirtualUnwindFrame, Action::Cleanup(_)) => {
+            continue_unwind(exception, context)
+        }
+        (State::VirtualUnwindFrame, Action::Catch(_)) => Reason::HandlerFound,
+        (_, Action::Cleanup(landing_pad) | Action::Catch(landing_pad)) => {
+            let context = &mut *context;
+            context.set_register(0, exception as u32);
+            context.set_register(1, 0);
+            context.set_pc(landing_pad);
+            Reason::InstallContext
+        }
+        (_, Action::Terminate) => Reason::Failure,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    /// Nothing to do in this frame.
+    None,
+    /// Run the landing pad, then continue unwinding.
+    Cleanup(u32),
+    /// Stop unwinding at the landing pad.
+    Catch(u32),
+    /// The call may not unwind.
+    Terminate,
+}
+
+const DW_EH_PE_OMIT: u8 = 0xff;
+const DW_EH_PE_INDIRECT: u8 = 0x80;
+
+const DW_EH_PE_ABSPTR: u8 = 0x00;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_FUNCREL: u8 = 0x40;
+
+const DW_EH_PE_ULEB128: u8 = 0x01;
+const DW_EH_PE_UDATA2: u8 = 0x02;
+const DW_EH_PE_UDATA4: u8 = 0x03;
+const DW_EH_PE_SLEB128: u8 = 0x09;
+const DW_EH_PE_SDATA2: u8 = 0x0a;
+const DW_EH_PE_SDATA4: u8 = 0x0b;
+
+/// Reads the DWARF encoded language specific data (`.gcc_except_table`).
+struct Reader(*const u8);
+
+impl Reader {
+    unsafe fn read<T: Copy>(&mut self) -> T {
+        let value = (self.0 as *const T).read_unaligned();
+        self.0 = self.0.add(size_of::<T>());
+        value
+    }
+
+    unsafe fn uleb128(&mut self) -> u32 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte: u8 = self.read();
+            if shift < 32 {
+                value |= u32::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    unsafe fn sleb128(&mut self) -> i32 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte: u8 = self.read();
+            if shift < 32 {
+                value |= i32::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                if shift < 32 && byte & 0x40 != 0 {
+                    value |= !0 << shift;
+                }
+                return value;
+            }
+        }
+    }
+
+    unsafe fn encoded(&mut self, encoding: u8, function: u32) -> Result<u32, UnwindError> {
+        let base = match encoding & 0x70 {
+            DW_EH_PE_ABSPTR => 0,
+            DW_EH_PE_PCREL => self.0 as u32,
+            DW_EH_PE_FUNCREL => function,
+            _ => return Err(UnwindError),
+        };
+
+        let value = match encoding & 0x0f {
+            DW_EH_PE_ABSPTR | DW_EH_PE_UDATA4 | DW_EH_PE_SDATA4 => self.read::<u32>(),
+            DW_EH_PE_ULEB128 => self.uleb128(),
+            DW_EH_PE_UDATA2 => self.read::<u16>().into(),
+            DW_EH_PE_SLEB128 => self.sleb128() as u32,
+            DW_EH_PE_SDATA2 => i32::from(self.read::<i16>()) as u32,
+            _ => return Err(UnwindError),
+        };
+
+        let value = base.wrapping_add(value);
+        if encoding & DW_EH_PE_INDIRECT != 0 {
+            Ok((value as *const u32).read())
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Offsets in the call site table are plain numbers relative to the function.
+    unsafe fn offset(&mut self, encoding: u8) -> Result<u32, UnwindError> {
+        if encoding == DW_EH_PE_OMIT || encoding & 0xf0 != 0 {
+            return Err(UnwindError);
+        }
+
+        self.encoded(encoding, 0)
+    }
+}
+
+/// Find what to do for the call returning to `pc` in `function`.
+unsafe fn find_action(lsda: *const u8, function: u32, pc: u32) -> Result<Action, UnwindError> {
+    let mut reader = Reader(lsda);
+
+    let landing_pad_base = match reader.read::<u8>() {
+        DW_EH_PE_OMIT => function,
+        encoding => reader.encoded(encoding, function)?,
+    };
+
+    if reader.read::<u8>() != DW_EH_PE_OMIT {
+        // Type tables are only used for C++ `catch` clauses.
+        let _type_table_offset = reader.uleb128();
+    }
+
+    let call_site_encoding = reader.read::<u8>();
+    let call_site_table_length = reader.uleb128();
+    let action_table = reader.0.add(call_site_table_length as usize);
+
+    // Look up the call instruction, not the instruction the call returns to.
+    let pc = (pc & !1) - 1;
+
+    while reader.0 < action_table {
+        let start = reader.offset(call_site_encoding)?;
+        let length = reader.offset(call_site_encoding)?;
+        let landing_pad = reader.offset(call_site_encoding)?;
+        let action = reader.uleb128();
+
+        // Call sites are sorted by their start address
+        if pc < function + start {
+            break;
+        }
+
+        if pc < function + start + length {
+            if landing_pad == 0 {
+                return Ok(Action::None);
+            }
+
+            let landing_pad = landing_pad_base + landing_pad;
+            if action == 0 {
+                return Ok(Action::Cleanup(landing_pad));
+            }
+
+            // Rust only emits catch-all clauses, but a cleanup might be listed as well.
+            let type_index = Reader(action_table.add(action as usize - 1)).sleb128();
+            return Ok(match type_index {
+                0 => Action::Cleanup(landing_pad),
+                _ => Action::Catch(landing_pad),
+            });
+        }
+    }
+
+    // Calls without an entry are not allowed to unwind
+    Ok(Action::Terminate)
+}